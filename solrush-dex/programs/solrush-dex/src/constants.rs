@@ -10,12 +10,74 @@ pub const LP_MINT_SEED: &[u8] = b"lp_mint";
 pub const POSITION_SEED: &[u8] = b"position";
 pub const LIMIT_ORDER_SEED: &[u8] = b"limit_order";
 pub const RUSH_CONFIG_SEED: &[u8] = b"rush_config";
+pub const AMM_CONFIG_SEED: &[u8] = b"amm_config";
 
 pub const MIN_INITIAL_DEPOSIT: u64 = 1000;
+/// LP permanently locked out of the first deposit; never minted to anyone.
+pub const MINIMUM_LIQUIDITY: u64 = 1000;
+/// Minimum geometric-mean LP (`sqrt(amount_a * amount_b)`) for the deposit that bootstraps a pool.
+pub const MIN_INITIAL_LIQUIDITY: u64 = 100_000;
 pub const MAX_SLIPPAGE_BPS: u64 = 5000;
 pub const RATIO_TOLERANCE_BPS: u64 = 100;
+pub const MAX_MARKET_SPREAD_BPS: u16 = 1000;
+pub const DEFAULT_FLASH_FEE_BPS: u16 = 9;
+pub const MAX_FLASH_FEE_BPS: u16 = 1000;
+/// Per-pool swap fees set through `set_pool_fee` are expressed over this denominator.
+pub const FEE_BPS_DENOMINATOR: u64 = 10_000;
+/// Default protocol-wide band for per-pool swap fees.
+pub const DEFAULT_MIN_FEE_BPS: u16 = 1;
+pub const DEFAULT_MAX_FEE_BPS: u16 = 100;
+/// Capacity of the pool-creation mint whitelist on `AmmConfig`.
+pub const MAX_WHITELISTED_MINTS: usize = 64;
+/// Upper bound on the StableSwap amplification coefficient `A`.
+pub const MAX_AMPLIFICATION_COEFFICIENT: u64 = 1_000_000;
 
 pub const MAX_LIMIT_ORDER_EXPIRY_DAYS: i64 = 30;
+/// Seconds past `expires_at` a limit order stays executable, absorbing cluster clock drift.
+pub const LIMIT_ORDER_EXPIRY_GRACE_SECS: i64 = 30;
+/// `AmmConfig::min_partial_fill_bps` until governance changes it, and while no AMM config exists.
+pub const DEFAULT_MIN_PARTIAL_FILL_BPS: u16 = 1_000;
+/// Cap on a limit order's keeper `execution_reward_bps`.
+pub const MAX_EXECUTION_REWARD_BPS: u16 = 100;
+
+/// Partner-token reward programs a pool can run next to RUSH.
+pub const MAX_REWARD_PROGRAMS: usize = 2;
+pub const DEFAULT_MIN_CLAIM_INTERVAL_SECS: i64 = 60;
+pub const MAX_MIN_CLAIM_INTERVAL_SECS: i64 = 7 * 86400;
+/// Fixed-point scale of `LiquidityPool::acc_reward_per_share`.
+pub const ACC_REWARD_PRECISION: u128 = 1_000_000_000_000_000_000;
+
+/// Fixed-point scale of the prices summed into the pool's TWAP accumulators.
+pub const TWAP_PRICE_PRECISION: u128 = 1_000_000_000_000;
+/// Number of accumulator snapshots kept per pool for `get_twap`.
+pub const TWAP_OBSERVATION_SLOTS: usize = 8;
+/// Minimum spacing between stored snapshots; with the slot count this bounds the TWAP window.
+pub const TWAP_OBSERVATION_INTERVAL_SECS: i64 = 300;
+/// TWAP window a deposit's spot price is compared against for the LP alignment boost.
+pub const ALIGNMENT_TWAP_WINDOW_SECS: i64 = TWAP_OBSERVATION_INTERVAL_SECS;
+/// TWAP window limit orders trigger on, so a swap just before execution can't fire them.
+pub const LIMIT_ORDER_TWAP_WINDOW_SECS: i64 = 60;
+/// Limit orders don't execute while spot is further than this from that TWAP, in bps.
+pub const MAX_LIMIT_ORDER_TWAP_DEVIATION_BPS: u64 = 200;
+/// Cap on `LiquidityPool::dynamic_fee_bps`: the fee moves by at most a quarter either way.
+pub const MAX_DYNAMIC_FEE_BPS: u16 = 2_500;
+/// TWAP window a dynamic-fee swap's move of the spot price is measured against.
+pub const DYNAMIC_FEE_TWAP_WINDOW_SECS: i64 = TWAP_OBSERVATION_INTERVAL_SECS;
+/// Cap on `LiquidityPool::alignment_boost_bps` (2x rewards at most).
+pub const MAX_ALIGNMENT_BOOST_BPS: u16 = 10_000;
+/// Longest LP lock `lock_position` accepts; it earns `MAX_LOCK_BOOST_BPS`.
+pub const MAX_LOCK_DAYS: u64 = 360;
+/// Reward multiplier of a `MAX_LOCK_DAYS` lock (4x); shorter locks scale down linearly to 1x.
+pub const MAX_LOCK_BOOST_BPS: u16 = 40_000;
+
+/// Default insurance seed (quote atomic units) before a perps market can activate.
+pub const DEFAULT_MIN_INSURANCE_SEED: u64 = 5_000_000;
+/// Capacity of the market registry on `PerpsGlobalState`.
+pub const MAX_PERPS_MARKETS: usize = 32;
+/// Default `PerpsMarket::max_oracle_staleness_secs` for new markets.
+pub const DEFAULT_MAX_ORACLE_STALENESS_SECS: u32 = 60;
+/// Keeper fee for executing a take-profit / stop-loss, in bps of the closed notional.
+pub const TRIGGER_KEEPER_FEE_BPS: u16 = 10;
 
 pub fn is_valid_pair(token_a_mint: &Pubkey, token_b_mint: &Pubkey) -> bool {
     token_a_mint != token_b_mint
@@ -28,3 +90,43 @@ pub fn get_canonical_pair(mint_a: Pubkey, mint_b: Pubkey) -> (Pubkey, Pubkey) {
         (mint_b, mint_a)
     }
 }
+
+/// Derive the LP token mint PDA for a pool: `[b"lp_mint", pool]`.
+pub fn derive_lp_mint(pool: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[LP_MINT_SEED, pool.as_ref()], &crate::ID)
+}
+
+/// True when `mint` is the LP mint of the pool that is its mint authority.
+pub fn is_pool_lp_mint(mint: &Pubkey, mint_authority: Option<Pubkey>) -> bool {
+    match mint_authority {
+        Some(authority) => derive_lp_mint(&authority).0 == *mint,
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_derive_lp_mint_matches_pda() {
+        let pool = Pubkey::new_unique();
+        let (expected, bump) =
+            Pubkey::find_program_address(&[b"lp_mint", pool.as_ref()], &crate::ID);
+        assert_eq!(derive_lp_mint(&pool), (expected, bump));
+    }
+
+    #[test]
+    fn test_lp_mint_detected_as_circular() {
+        let pool = Pubkey::new_unique();
+        let (lp_mint, _) = derive_lp_mint(&pool);
+        assert!(is_pool_lp_mint(&lp_mint, Some(pool)));
+    }
+
+    #[test]
+    fn test_regular_mint_not_lp_mint() {
+        let mint = Pubkey::new_unique();
+        assert!(!is_pool_lp_mint(&mint, Some(Pubkey::new_unique())));
+        assert!(!is_pool_lp_mint(&mint, None));
+    }
+}
@@ -86,4 +86,100 @@ pub enum CustomError {
     CloseAmountExceedsPosition,
     #[msg("Initial margin violation — insufficient equity to increase position")]
     InsufficientMargin,
+    #[msg("Pool is locked — re-entrant call rejected")]
+    PoolLocked,
+    #[msg("Reward claim too soon — minimum claim interval not elapsed")]
+    ClaimTooSoon,
+    #[msg("Invalid market risk parameters")]
+    InvalidRiskParams,
+    #[msg("LP token mints cannot be used as pool tokens")]
+    LpMintAsPoolToken,
+    #[msg("Market is not active")]
+    MarketInactive,
+    #[msg("Insurance fund below the minimum seed required for activation")]
+    InsuranceSeedNotMet,
+    #[msg("Invalid StableSwap amplification coefficient")]
+    InvalidAmplification,
+    #[msg("Protocol fee account required when a protocol fee is set")]
+    MissingProtocolFeeAccount,
+    #[msg("Pool is paused")]
+    PoolPaused,
+    #[msg("Pool is not paused")]
+    PoolNotPaused,
+    #[msg("Maximum number of perps markets reached")]
+    MarketLimitReached,
+    #[msg("Exact-output swaps are not supported on StableSwap pools")]
+    ExactOutUnsupported,
+    #[msg("Flash loan must be repaid later in the same transaction")]
+    FlashLoanNotRepaid,
+    #[msg("Token mint is not whitelisted for pool creation")]
+    MintNotAllowed,
+    #[msg("Mint whitelist is full")]
+    WhitelistFull,
+    #[msg("Not enough price history for the requested TWAP window")]
+    TwapWindowUnavailable,
+    #[msg("Initial deposit too small to cover the locked minimum liquidity")]
+    InitialLiquidityTooLow,
+    #[msg("Authority token accounts required to seed initial liquidity")]
+    MissingSeedTokenAccount,
+    #[msg("Position still holds size, collateral or unpaid funding")]
+    PositionNotEmpty,
+    #[msg("Price impact exceeds the allowed maximum")]
+    PriceImpactTooHigh,
+    #[msg("Single-sided deposits are not supported on StableSwap pools")]
+    SingleSidedUnsupported,
+    #[msg("Orders are not on opposite sides of the same pool at crossing prices")]
+    OrdersDoNotCross,
+    #[msg("Pool has no liquidity yet")]
+    PoolNotFunded,
+    #[msg("Fee-on-transfer tokens are not supported by this instruction")]
+    TransferFeeUnsupported,
+    #[msg("Liquidations are disabled")]
+    LiquidationsDisabled,
+    #[msg("Take-profit and stop-loss prices are on the wrong side of each other")]
+    InvalidTriggerPrice,
+    #[msg("No position trigger is met at the current price")]
+    TriggerNotMet,
+    #[msg("Open would push the long/short open interest imbalance over the market cap")]
+    OiImbalanceExceeded,
+    #[msg("Market has no uncovered bad debt to deleverage")]
+    AdlNotRequired,
+    #[msg("Position is not a profitable counterparty of the bad debt")]
+    AdlIneligiblePosition,
+    #[msg("No RUSH pool pairs RUSH with one of this pool's tokens")]
+    NoRushPool,
+    #[msg("Every open position in other markets must be passed to value account margin")]
+    IncompleteMarginAccounts,
+    #[msg("Margin mode differs from the open position's")]
+    MarginModeMismatch,
+    #[msg("Reduce-only order would increase or flip the position")]
+    ReduceOnlyViolation,
+    #[msg("Trade would push market open interest over its cap")]
+    OpenInterestCapExceeded,
+    #[msg("Batch accounts must be (order, vault, owner token account, owner) groups")]
+    IncompleteOrderAccounts,
+    #[msg("Order has not expired yet")]
+    OrderNotExpired,
+    #[msg("Pool spot price is too far from its TWAP")]
+    PriceDeviationTooHigh,
+    #[msg("Pool already runs the maximum number of reward programs")]
+    RewardProgramsFull,
+    #[msg("Reward program for this mint is still running")]
+    RewardProgramActive,
+    #[msg("Pool has no reward program for this mint")]
+    RewardProgramNotFound,
+    #[msg("Lock must be 1-360 days and can't end before the current lock")]
+    InvalidLockDuration,
+    #[msg("Position is locked")]
+    PositionLocked,
+    #[msg("Pool reward weights would sum to more than 10000 bps")]
+    PoolWeightsExceeded,
+    #[msg("Withdrawal would take the insurance fund below its floor")]
+    InsuranceFloorBreached,
+    #[msg("Market is paused")]
+    MarketPaused,
+    #[msg("Halving schedule can't change once a halving has passed")]
+    HalvingScheduleStarted,
+    #[msg("A candidate position is more profitable than the one being deleveraged")]
+    AdlNotMostProfitable,
 }
@@ -1,4 +1,5 @@
 use anchor_lang::prelude::*;
+use crate::state::{OrderKind, TimeInForce};
 #[event]
 pub struct LimitOrderCreated {
     pub order: Pubkey,
@@ -10,6 +11,10 @@ pub struct LimitOrderCreated {
     pub target_price: u64,
     pub minimum_receive: u64,
     pub expires_at: i64,
+    pub order_kind: OrderKind,
+    /// Keeper reward escrowed on top of `sell_amount`.
+    pub execution_reward: u64,
+    pub time_in_force: TimeInForce,
 }
 #[event]
 pub struct LimitOrderExecuted {
@@ -20,6 +25,13 @@ pub struct LimitOrderExecuted {
     pub receive_amount: u64,
     pub execution_price: u64,
     pub executed_at: i64,
+    /// Sell amount still escrowed; 0 once the order is fully filled.
+    pub remaining_sell_amount: u64,
+    pub keeper: Pubkey,
+    /// Tip paid to `keeper`; non-zero only on the fill that completes the order.
+    pub keeper_tip_lamports: u64,
+    /// Share of the escrowed execution reward paid to the keeper for this fill, in the sell token.
+    pub execution_reward: u64,
 }
 #[event]
 pub struct LimitOrderCancelled {
@@ -28,3 +40,34 @@ pub struct LimitOrderCancelled {
     pub refunded_amount: u64,
     pub cancelled_at: i64,
 }
+#[event]
+pub struct LimitOrderModified {
+    pub order: Pubkey,
+    pub owner: Pubkey,
+    pub old_target_price: u64,
+    pub new_target_price: u64,
+    pub old_minimum_receive: u64,
+    pub new_minimum_receive: u64,
+    pub old_expires_at: i64,
+    pub new_expires_at: i64,
+    pub modified_at: i64,
+}
+#[event]
+pub struct LimitOrderExpired {
+    pub order: Pubkey,
+    pub owner: Pubkey,
+    pub refunded_amount: u64,
+    /// Whoever cleaned the order up; the refund and rent still go to `owner`.
+    pub closed_by: Pubkey,
+    pub closed_at: i64,
+}
+#[event]
+pub struct BatchExecuted {
+    pub pool: Pubkey,
+    pub keeper: Pubkey,
+    /// Orders filled in full; each also emitted its own `LimitOrderExecuted`.
+    pub executed_count: u32,
+    /// Orders passed in but not executable: expired, untriggered or short of their minimum.
+    pub skipped_count: u32,
+    pub executed_at: i64,
+}
@@ -6,6 +6,8 @@ pub struct FundingUpdated {
     pub funding_rate: i64,
     pub cumulative_funding: i128,
     pub timestamp: i64,
+    pub keeper: Pubkey,
+    pub keeper_reward_u64: u64,
 }
 
 #[event]
@@ -37,4 +39,96 @@ pub struct Liquidated {
     pub bad_debt_u64: u64,
     /// Whether the market entered emergency mode.
     pub emergency: bool,
+    /// Part of `liquidator_fee_u64` paid by the insurance fund.
+    pub liquidator_fee_from_insurance_u64: u64,
+    /// Shortfall covered by the owner's free collateral (cross margin only).
+    pub shortfall_from_free_u64: u64,
+}
+
+#[event]
+pub struct InsuranceWithdrawn {
+    pub market: Pubkey,
+    pub admin: Pubkey,
+    pub amount_u64: u64,
+    pub remaining_balance_u64: u64,
+}
+
+#[event]
+pub struct TriggerExecuted {
+    pub position: Pubkey,
+    pub owner: Pubkey,
+    pub keeper: Pubkey,
+    pub market: Pubkey,
+    /// 0 = take profit, 1 = stop loss.
+    pub trigger: u8,
+    /// Oracle price the position was closed at.
+    pub price_i64: i64,
+    /// Base size closed (the whole position).
+    pub size_closed_i64: i64,
+    /// Fee paid to the keeper out of the returned collateral.
+    pub keeper_fee_u64: u64,
+}
+
+#[event]
+pub struct AutoDeleveraged {
+    pub market: Pubkey,
+    pub position: Pubkey,
+    /// Owner of the force-closed counterparty position.
+    pub counterparty: Pubkey,
+    /// Signed base size closed.
+    pub size_closed_i64: i64,
+    pub price_i64: i64,
+    /// Profit withheld from the counterparty to cover bad debt (quote atoms).
+    pub absorbed_u64: u64,
+    /// Bad debt still uncovered afterwards; 0 clears the emergency.
+    pub remaining_bad_debt_u64: u64,
+}
+
+#[event]
+pub struct PerpsTradeExecuted {
+    pub owner: Pubkey,
+    pub market: Pubkey,
+    pub position: Pubkey,
+    /// Signed base traded: +ve buys, -ve sells.
+    pub base_delta_i64: i64,
+    pub price_i64: i64,
+    /// Trading fee charged on `|base_delta_i64| × price_i64` (quote atoms).
+    pub fee_u64: u64,
+}
+
+#[event]
+pub struct PositionOpened {
+    pub owner: Pubkey,
+    pub market: Pubkey,
+    pub position: Pubkey,
+    /// Side traded: 0 = long (buy), 1 = short (sell).
+    pub side: u8,
+    /// Signed base traded: +ve buys, -ve sells.
+    pub size_delta_i64: i64,
+    pub price_i64: i64,
+    /// Position after the trade.
+    pub base_position_i64: i64,
+    pub entry_price_i64: i64,
+    /// PnL realized by the trade (non-zero when it reduced or flipped the position).
+    pub pnl_delta_i128: i128,
+    pub collateral_u64: u64,
+    pub leverage_u16: u16,
+}
+
+#[event]
+pub struct PositionClosed {
+    pub owner: Pubkey,
+    pub market: Pubkey,
+    pub position: Pubkey,
+    /// Side of the position closed: 0 = long, 1 = short.
+    pub side: u8,
+    /// Signed base traded: -ve closes a long, +ve closes a short.
+    pub size_delta_i64: i64,
+    pub price_i64: i64,
+    /// Position left open; 0 (with zero collateral and leverage) on a full close.
+    pub base_position_i64: i64,
+    pub entry_price_i64: i64,
+    pub pnl_delta_i128: i128,
+    pub collateral_u64: u64,
+    pub leverage_u16: u16,
 }
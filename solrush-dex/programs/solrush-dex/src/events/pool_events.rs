@@ -29,3 +29,48 @@ pub struct LiquidityRemoved {
     pub new_reserve_a: u64,
     pub new_reserve_b: u64,
 }
+#[event]
+pub struct MarketSpreadUpdated {
+    pub pool: Pubkey,
+    pub buy_premium_bps: u16,
+    pub sell_discount_bps: u16,
+    pub updated_by: Pubkey,
+}
+#[event]
+pub struct ProtocolFeeUpdated {
+    pub pool: Pubkey,
+    pub protocol_fee_numerator: u64,
+    pub protocol_fee_vault: Pubkey,
+    pub updated_by: Pubkey,
+}
+#[event]
+pub struct PoolFeeUpdated {
+    pub pool: Pubkey,
+    pub fee_numerator: u64,
+    pub fee_denominator: u64,
+    pub updated_by: Pubkey,
+}
+#[event]
+pub struct PoolPauseUpdated {
+    pub pool: Pubkey,
+    pub is_paused: bool,
+    pub updated_by: Pubkey,
+}
+#[event]
+pub struct ReservesSynced {
+    pub pool: Pubkey,
+    pub reserve_a: u64,
+    pub reserve_b: u64,
+    /// Vault balance minus the previously stored reserve.
+    pub delta_a: i128,
+    pub delta_b: i128,
+    pub synced_by: Pubkey,
+}
+#[event]
+pub struct FlashLoan {
+    pub pool: Pubkey,
+    pub borrower: Pubkey,
+    pub vault: Pubkey,
+    pub principal: u64,
+    pub fee: u64,
+}
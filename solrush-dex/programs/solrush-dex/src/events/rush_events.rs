@@ -23,6 +23,45 @@ pub struct RewardsClaimed {
     pub total_claimed_lifetime: u64,
 }
 #[event]
+pub struct RewardsCompounded {
+    pub user: Pubkey,
+    pub position: Pubkey,
+    pub pool: Pubkey,
+    pub rewards_amount: u64,
+    /// Pool token the rewards were deposited as, after any swap.
+    pub deposit_mint: Pubkey,
+    pub deposit_amount: u64,
+    pub lp_tokens_minted: u64,
+}
+#[event]
+pub struct LiquidityLocked {
+    pub user: Pubkey,
+    pub position: Pubkey,
+    pub pool: Pubkey,
+    pub lock_days: u64,
+    pub lock_until_ts: i64,
+    pub boost_multiplier_bps: u16,
+}
+#[event]
+pub struct RewardProgramStarted {
+    pub pool: Pubkey,
+    pub reward_mint: Pubkey,
+    pub reward_vault: Pubkey,
+    pub rewards_per_second: u64,
+    pub ends_at: i64,
+    /// Paid into `reward_vault` to cover the whole run.
+    pub funded_amount: u64,
+}
+#[event]
+pub struct ProgramRewardsClaimed {
+    pub user: Pubkey,
+    pub position: Pubkey,
+    pub pool: Pubkey,
+    pub reward_mint: Pubkey,
+    pub rewards_amount: u64,
+    pub claimed_at: i64,
+}
+#[event]
 pub struct RewardsConfigUpdated {
     pub previous_apy_numerator: u64,
     pub new_apy_numerator: u64,
@@ -35,5 +74,40 @@ pub struct RewardsPaused {
     pub is_paused: bool,
     pub paused_at: i64,
     pub paused_by: Pubkey,
-    pub reason: String,
+}
+#[event]
+pub struct ClaimIntervalUpdated {
+    pub previous_interval_secs: i64,
+    pub new_interval_secs: i64,
+    pub updated_at: i64,
+    pub updated_by: Pubkey,
+}
+#[event]
+pub struct MinRewardsLiquidityUpdated {
+    pub previous_min_lp_supply: u64,
+    pub new_min_lp_supply: u64,
+    pub updated_at: i64,
+    pub updated_by: Pubkey,
+}
+#[event]
+pub struct PoolWeightUpdated {
+    pub pool: Pubkey,
+    pub previous_weight_bps: u16,
+    pub new_weight_bps: u16,
+    pub total_weight_bps: u16,
+    pub updated_at: i64,
+    pub updated_by: Pubkey,
+}
+#[event]
+pub struct AuthorityTransferred {
+    pub previous_authority: Pubkey,
+    pub new_authority: Pubkey,
+    pub transferred_at: i64,
+}
+#[event]
+pub struct HalvingIntervalUpdated {
+    pub previous_interval_secs: i64,
+    pub new_interval_secs: i64,
+    pub updated_at: i64,
+    pub updated_by: Pubkey,
 }
@@ -5,8 +5,22 @@ pub struct SwapExecuted {
     pub pool: Pubkey,
     pub amount_in: u64,
     pub amount_out: u64,
+    /// Denominated in `fee_token`.
     pub fee_amount: u64,
+    pub protocol_fee: u64,
+    /// Mint of the input token, which both fees are charged in.
+    pub fee_token: Pubkey,
     pub is_a_to_b: bool,
     pub new_reserve_a: u64,
     pub new_reserve_b: u64,
 }
+#[event]
+pub struct RouteExecuted {
+    pub user: Pubkey,
+    pub first_pool: Pubkey,
+    pub second_pool: Pubkey,
+    pub amount_in: u64,
+    /// First hop's output, fed whole into the second hop.
+    pub intermediate_amount: u64,
+    pub amount_out: u64,
+}
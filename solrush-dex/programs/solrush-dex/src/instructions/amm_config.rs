@@ -0,0 +1,144 @@
+use anchor_lang::prelude::*;
+use crate::state::AmmConfig;
+use crate::errors::CustomError;
+use crate::constants::{MAX_WHITELISTED_MINTS, DEFAULT_MIN_FEE_BPS, DEFAULT_MAX_FEE_BPS, MAX_SLIPPAGE_BPS, DEFAULT_MIN_PARTIAL_FILL_BPS, FEE_BPS_DENOMINATOR};
+
+pub fn initialize_amm_config(ctx: Context<InitializeAmmConfig>) -> Result<()> {
+    let config = &mut ctx.accounts.amm_config;
+    config.authority = ctx.accounts.authority.key();
+    // Permissionless until governance opts in
+    config.whitelist_enabled = false;
+    config.allowed_mints = Vec::new();
+    config.bump = ctx.bumps.amm_config;
+    config.min_fee_bps = DEFAULT_MIN_FEE_BPS;
+    config.max_fee_bps = DEFAULT_MAX_FEE_BPS;
+    config.max_allowed_slippage_bps = 0;
+    config.min_partial_fill_bps = DEFAULT_MIN_PARTIAL_FILL_BPS;
+    Ok(())
+}
+
+pub fn set_fee_bounds(ctx: Context<UpdateAmmConfig>, min_fee_bps: u16, max_fee_bps: u16) -> Result<()> {
+    require!(
+        AmmConfig::is_valid_fee_band(min_fee_bps, max_fee_bps),
+        CustomError::InvalidFeeParameters
+    );
+    let config = &mut ctx.accounts.amm_config;
+    config.min_fee_bps = min_fee_bps;
+    config.max_fee_bps = max_fee_bps;
+    Ok(())
+}
+
+/// Cap how far below spot a swap's `minimum_amount_out` may be (0 disables).
+pub fn set_max_allowed_slippage(ctx: Context<UpdateAmmConfig>, max_allowed_slippage_bps: u16) -> Result<()> {
+    require!(
+        (max_allowed_slippage_bps as u64) <= MAX_SLIPPAGE_BPS,
+        CustomError::InvalidAmount
+    );
+    ctx.accounts.amm_config.max_allowed_slippage_bps = max_allowed_slippage_bps;
+    Ok(())
+}
+
+/// Set the smallest partial fill of a limit order, in bps of its size; orders
+/// already placed keep the minimum they were created with.
+pub fn set_min_partial_fill(ctx: Context<UpdateAmmConfig>, min_partial_fill_bps: u16) -> Result<()> {
+    require!(
+        (min_partial_fill_bps as u64) <= FEE_BPS_DENOMINATOR,
+        CustomError::InvalidAmount
+    );
+    ctx.accounts.amm_config.min_partial_fill_bps = min_partial_fill_bps;
+    Ok(())
+}
+
+pub fn set_mint_whitelist_enabled(ctx: Context<UpdateAmmConfig>, enabled: bool) -> Result<()> {
+    ctx.accounts.amm_config.whitelist_enabled = enabled;
+    Ok(())
+}
+
+pub fn add_whitelisted_mint(ctx: Context<UpdateAmmConfig>, mint: Pubkey) -> Result<()> {
+    let config = &mut ctx.accounts.amm_config;
+    if config.allowed_mints.contains(&mint) {
+        return Ok(());
+    }
+    require!(
+        config.allowed_mints.len() < MAX_WHITELISTED_MINTS,
+        CustomError::WhitelistFull
+    );
+    config.allowed_mints.push(mint);
+    Ok(())
+}
+
+pub fn remove_whitelisted_mint(ctx: Context<UpdateAmmConfig>, mint: Pubkey) -> Result<()> {
+    ctx.accounts.amm_config.allowed_mints.retain(|allowed| *allowed != mint);
+    Ok(())
+}
+
+/// Load the AMM config PDA, if one has been created.
+fn load_amm_config(amm_config: &AccountInfo) -> Result<Option<AmmConfig>> {
+    if amm_config.data_is_empty() {
+        return Ok(None);
+    }
+    let data = amm_config.try_borrow_data()?;
+    let mut slice: &[u8] = &data;
+    Ok(Some(AmmConfig::try_deserialize(&mut slice)?))
+}
+
+/// Check `mints` against the AMM config PDA, if one has been created.
+pub fn require_mints_allowed(amm_config: &AccountInfo, mints: &[Pubkey]) -> Result<()> {
+    let Some(config) = load_amm_config(amm_config)? else {
+        return Ok(());
+    };
+    for mint in mints {
+        require!(config.is_mint_allowed(mint), CustomError::MintNotAllowed);
+    }
+    Ok(())
+}
+
+/// Smallest partial fill of a limit order, in bps, from the AMM config PDA if
+/// one has been created.
+pub fn min_partial_fill_bps(amm_config: &AccountInfo) -> Result<u16> {
+    Ok(load_amm_config(amm_config)?.map_or(DEFAULT_MIN_PARTIAL_FILL_BPS, |config| config.min_partial_fill_bps))
+}
+
+/// Check a swap's `minimum_amount_out` against the protocol slippage cap, if
+/// the AMM config PDA exists.  `spot_out` is `None` where the cap does not apply.
+pub fn require_slippage_within_cap(
+    amm_config: &AccountInfo,
+    spot_out: Option<u64>,
+    minimum_amount_out: u64,
+) -> Result<()> {
+    let (Some(spot_out), Some(config)) = (spot_out, load_amm_config(amm_config)?) else {
+        return Ok(());
+    };
+    require!(
+        minimum_amount_out >= config.slippage_floor(spot_out),
+        CustomError::SlippageTooHigh
+    );
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct InitializeAmmConfig<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = AmmConfig::SIZE,
+        seeds = [b"amm_config"],
+        bump
+    )]
+    pub amm_config: Account<'info, AmmConfig>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateAmmConfig<'info> {
+    #[account(
+        mut,
+        seeds = [b"amm_config"],
+        bump = amm_config.bump,
+        has_one = authority @ CustomError::InvalidAuthority
+    )]
+    pub amm_config: Account<'info, AmmConfig>,
+    pub authority: Signer<'info>,
+}
@@ -0,0 +1,182 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::sysvar::instructions::{
+    load_current_index_checked,
+    load_instruction_at_checked,
+};
+use anchor_lang::Discriminator;
+use anchor_spl::token::{Token, TokenAccount, Transfer, transfer};
+use crate::state::{LiquidityPool, FlashLoanReceipt};
+use crate::errors::CustomError;
+use crate::events::FlashLoan;
+use crate::utils::{acquire_pool_lock, calculate_flash_fee, vault_received};
+
+/// Lend `amount` out of one pool vault. A `flash_loan_repay` for the same pool
+/// must appear later in the transaction; the pool stays locked until it runs.
+pub fn flash_loan_borrow(ctx: Context<FlashLoanBorrow>, amount: u64) -> Result<()> {
+    require!(amount > 0, CustomError::InvalidAmount);
+    ctx.accounts.pool.require_active()?;
+    require_repay_follows(&ctx.accounts.instructions, &ctx.accounts.pool.key())?;
+    acquire_pool_lock(&mut ctx.accounts.pool)?;
+    let pool = &ctx.accounts.pool;
+    let vault_key = ctx.accounts.vault.key();
+    let reserve = if vault_key == pool.token_a_vault {
+        pool.reserve_a
+    } else {
+        pool.reserve_b
+    };
+    require!(amount <= reserve, CustomError::InsufficientPoolReserves);
+    let fee = calculate_flash_fee(amount, pool.flash_fee_bps)?;
+    let receipt = &mut ctx.accounts.receipt;
+    receipt.pool = pool.key();
+    receipt.borrower = ctx.accounts.borrower.key();
+    receipt.vault = vault_key;
+    receipt.principal = amount;
+    receipt.fee = fee;
+    receipt.bump = ctx.bumps.receipt;
+    let signer_seeds: &[&[&[u8]]] = &[&[
+        b"pool",
+        pool.token_a_mint.as_ref(),
+        pool.token_b_mint.as_ref(),
+        &[pool.bump],
+    ]];
+    transfer(
+        CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.vault.to_account_info(),
+                to: ctx.accounts.borrower_token_account.to_account_info(),
+                authority: pool.to_account_info(),
+            },
+            signer_seeds,
+        ),
+        amount,
+    )
+}
+
+/// Return principal plus fee; the fee accrues to the vault's reserve.
+pub fn flash_loan_repay(ctx: Context<FlashLoanRepay>) -> Result<()> {
+    let receipt = &ctx.accounts.receipt;
+    let owed = receipt
+        .amount_owed()
+        .ok_or(error!(CustomError::CalculationOverflow))?;
+    let balance_before = ctx.accounts.vault.amount;
+    transfer(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.borrower_token_account.to_account_info(),
+                to: ctx.accounts.vault.to_account_info(),
+                authority: ctx.accounts.borrower.to_account_info(),
+            },
+        ),
+        owed,
+    )?;
+    // A fee-on-transfer mint would leave the vault short of principal plus fee
+    require!(
+        vault_received(&mut ctx.accounts.vault, balance_before)? >= owed,
+        CustomError::FlashLoanNotRepaid
+    );
+    let pool = &mut ctx.accounts.pool;
+    if receipt.vault == pool.token_a_vault {
+        pool.reserve_a = pool
+            .reserve_a
+            .checked_add(receipt.fee)
+            .ok_or(error!(CustomError::CalculationOverflow))?;
+    } else {
+        pool.reserve_b = pool
+            .reserve_b
+            .checked_add(receipt.fee)
+            .ok_or(error!(CustomError::CalculationOverflow))?;
+    }
+    emit!(FlashLoan {
+        pool: pool.key(),
+        borrower: receipt.borrower,
+        vault: receipt.vault,
+        principal: receipt.principal,
+        fee: receipt.fee,
+    });
+    pool.unlock();
+    Ok(())
+}
+
+/// Scan the rest of the transaction for a top-level `flash_loan_repay` on `pool`.
+fn require_repay_follows(instructions: &AccountInfo, pool: &Pubkey) -> Result<()> {
+    let current = load_current_index_checked(instructions)? as usize;
+    let mut index = current + 1;
+    while let Ok(ix) = load_instruction_at_checked(index, instructions) {
+        if ix.program_id == crate::ID
+            && ix.data.starts_with(crate::instruction::FlashLoanRepay::DISCRIMINATOR)
+            && ix.accounts.first().map(|meta| meta.pubkey) == Some(*pool)
+        {
+            return Ok(());
+        }
+        index += 1;
+    }
+    Err(error!(CustomError::FlashLoanNotRepaid))
+}
+
+#[derive(Accounts)]
+pub struct FlashLoanBorrow<'info> {
+    #[account(
+        mut,
+        seeds = [b"pool", pool.token_a_mint.as_ref(), pool.token_b_mint.as_ref()],
+        bump = pool.bump
+    )]
+    pub pool: Account<'info, LiquidityPool>,
+    #[account(
+        init,
+        payer = borrower,
+        space = FlashLoanReceipt::LEN,
+        seeds = [b"flash_loan", pool.key().as_ref()],
+        bump
+    )]
+    pub receipt: Account<'info, FlashLoanReceipt>,
+    #[account(
+        mut,
+        constraint = vault.key() == pool.token_a_vault || vault.key() == pool.token_b_vault @ CustomError::InvalidVault
+    )]
+    pub vault: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = vault.mint
+    )]
+    pub borrower_token_account: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub borrower: Signer<'info>,
+    /// CHECK: instructions sysvar, address-checked
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: AccountInfo<'info>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct FlashLoanRepay<'info> {
+    /// Must stay the first account: `flash_loan_borrow` matches on it.
+    #[account(
+        mut,
+        seeds = [b"pool", pool.token_a_mint.as_ref(), pool.token_b_mint.as_ref()],
+        bump = pool.bump
+    )]
+    pub pool: Account<'info, LiquidityPool>,
+    #[account(
+        mut,
+        close = borrower,
+        seeds = [b"flash_loan", pool.key().as_ref()],
+        bump = receipt.bump,
+        has_one = borrower @ CustomError::InvalidAuthority,
+        has_one = vault @ CustomError::InvalidVault
+    )]
+    pub receipt: Account<'info, FlashLoanReceipt>,
+    #[account(mut)]
+    pub vault: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = vault.mint,
+        token::authority = borrower
+    )]
+    pub borrower_token_account: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub borrower: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
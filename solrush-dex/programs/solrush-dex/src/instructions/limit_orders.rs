@@ -1,12 +1,17 @@
 use anchor_lang::prelude::*;
 use anchor_spl::{
     associated_token::AssociatedToken,
-    token::{Token, TokenAccount, Mint, Transfer, transfer},
+    token::{Token, TokenAccount, Mint, Transfer, transfer, CloseAccount, close_account},
 };
-use crate::state::{LiquidityPool, LimitOrder, OrderStatus};
+use anchor_lang::system_program;
+use crate::state::{LiquidityPool, LimitOrder, OrderKind, OrderStatus, TimeInForce, validate_fill_amount};
+use crate::constants::{MAX_EXECUTION_REWARD_BPS, LIMIT_ORDER_TWAP_WINDOW_SECS, MAX_LIMIT_ORDER_TWAP_DEVIATION_BPS};
 use crate::errors::CustomError;
-use crate::events::{LimitOrderCreated, LimitOrderExecuted, LimitOrderCancelled};
-use crate::utils::{calculate_output_amount, calculate_pool_price, check_price_condition};
+use crate::events::{LimitOrderCreated, LimitOrderExecuted, LimitOrderCancelled, LimitOrderExpired, LimitOrderModified, BatchExecuted};
+use crate::instructions::amm_config::min_partial_fill_bps;
+use crate::instructions::swap::{quote_pool_swap, pay_out_swap, SwapPayout, SwapQuote};
+use crate::utils::{calculate_pool_price, calculate_protocol_fee, check_price_condition, acquire_pool_lock, vault_received};
+#[allow(clippy::too_many_arguments)]
 pub fn create_limit_order(
     ctx: Context<CreateLimitOrder>,
     sell_amount: u64,
@@ -14,13 +19,24 @@ pub fn create_limit_order(
     minimum_receive: u64,
     expiry_days: i64,
     order_id: u64,
+    keeper_tip_lamports: u64,
+    order_kind: OrderKind,
+    execution_reward_bps: u16,
+    time_in_force: TimeInForce,
 ) -> Result<()> {
     require!(sell_amount > 0, CustomError::InvalidAmount);
     require!(target_price > 0, CustomError::InvalidAmount);
     require!(minimum_receive > 0, CustomError::InvalidAmount);
     require!(expiry_days > 0, CustomError::InvalidExpiryTime);
+    require!(execution_reward_bps <= MAX_EXECUTION_REWARD_BPS, CustomError::InvalidAmount);
+    ctx.accounts.pool.require_funded()?;
+    // Escrowed on top of the sell amount and paid out to keepers as the order fills
+    let execution_reward = ((sell_amount as u128) * (execution_reward_bps as u128) / 10_000) as u64;
+    let escrow_amount = sell_amount
+        .checked_add(execution_reward)
+        .ok_or(error!(CustomError::CalculationOverflow))?;
     require!(
-        ctx.accounts.user_token_in.amount >= sell_amount,
+        ctx.accounts.user_token_in.amount >= escrow_amount,
         CustomError::InsufficientBalance
     );
     let order = &mut ctx.accounts.limit_order;
@@ -37,6 +53,25 @@ pub fn create_limit_order(
     order.status = OrderStatus::Pending;
     order.bump = ctx.bumps.limit_order;
     order.order_id = order_id;
+    order.keeper_tip_lamports = keeper_tip_lamports;
+    order.order_kind = order_kind;
+    order.execution_reward_bps = execution_reward_bps;
+    order.execution_reward = execution_reward;
+    order.min_fill_amount = ((sell_amount as u128)
+        * (min_partial_fill_bps(&ctx.accounts.amm_config)? as u128)
+        / 10_000) as u64;
+    if keeper_tip_lamports > 0 {
+        system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.user.to_account_info(),
+                    to: order.to_account_info(),
+                },
+            ),
+            keeper_tip_lamports,
+        )?;
+    }
     transfer(
         CpiContext::new(
             ctx.accounts.token_program.to_account_info(),
@@ -46,7 +81,7 @@ pub fn create_limit_order(
                 authority: ctx.accounts.user.to_account_info(),
             },
         ),
-        sell_amount,
+        escrow_amount,
     )?;
     emit!(LimitOrderCreated {
         order: order.key(),
@@ -58,41 +93,169 @@ pub fn create_limit_order(
         target_price,
         minimum_receive,
         expires_at: order.expires_at,
+        order_kind,
+        execution_reward,
+        time_in_force,
     });
+    if time_in_force != TimeInForce::GoodTilCancelled {
+        fill_at_creation(ctx.accounts, time_in_force, now)?;
+    }
     Ok(())
 }
-pub fn execute_limit_order(
-    ctx: Context<ExecuteLimitOrder>,
-) -> Result<()> {
-    let order = &mut ctx.accounts.limit_order;
-    let pool = &mut ctx.accounts.pool;
-    let now = Clock::get()?.unix_timestamp;
+
+/// Fill an IOC or FOK order against the pool in the instruction that created
+/// it, then refund and close whatever is left: IOC takes the largest fill the
+/// pool allows, FOK the whole order or nothing.  The creator stands in for the
+/// keeper, so any tip and execution reward come straight back.
+fn fill_at_creation(accounts: &mut CreateLimitOrder, time_in_force: TimeInForce, now: i64) -> Result<()> {
+    let (Some(pool_token_a_vault), Some(pool_token_b_vault)) =
+        (accounts.pool_token_a_vault.as_mut(), accounts.pool_token_b_vault.as_mut())
+    else {
+        return err!(CustomError::InvalidVault);
+    };
+    accounts.pool.require_active()?;
+    acquire_pool_lock(&mut accounts.pool)?;
+    let fill = match time_in_force {
+        TimeInForce::FillOrKill => {
+            let sell_amount = accounts.limit_order.sell_amount;
+            Some((sell_amount, quote_fill(&accounts.limit_order, &accounts.pool, sell_amount, now)?))
+        }
+        _ => largest_immediate_fill(&accounts.limit_order, &accounts.pool, now),
+    };
+    if let Some((fill_amount, quote)) = fill {
+        let (pool_vault_in, pool_vault_out) = if quote.is_sell {
+            (pool_token_a_vault, pool_token_b_vault)
+        } else {
+            (pool_token_b_vault, pool_token_a_vault)
+        };
+        settle_fill(
+            FillAccounts {
+                pool: &mut accounts.pool,
+                limit_order: &mut accounts.limit_order,
+                order_vault: &mut accounts.order_vault,
+                pool_vault_in,
+                pool_vault_out,
+                protocol_fee_account: accounts.protocol_fee_account.as_ref().map(|account| account.to_account_info()),
+                user_token_out: accounts.user_token_out.to_account_info(),
+                keeper: accounts.user.to_account_info(),
+                keeper_token_account: accounts.user_token_in.to_account_info(),
+                owner: accounts.user.to_account_info(),
+                token_program: accounts.token_program.to_account_info(),
+            },
+            fill_amount,
+            quote,
+            now,
+        )?;
+    }
+    if accounts.limit_order.status == OrderStatus::Pending {
+        let refunded_amount = refund_escrow(
+            &accounts.limit_order,
+            &accounts.order_vault,
+            accounts.user_token_in.to_account_info(),
+            accounts.user.to_account_info(),
+            accounts.token_program.to_account_info(),
+        )?;
+        accounts.limit_order.status = OrderStatus::Cancelled;
+        emit!(LimitOrderCancelled {
+            order: accounts.limit_order.key(),
+            owner: accounts.user.key(),
+            refunded_amount,
+            cancelled_at: now,
+        });
+        accounts.limit_order.close(accounts.user.to_account_info())?;
+    }
+    accounts.pool.unlock();
+    Ok(())
+}
+
+/// Largest fill of the order the pool can take now, or `None` if even the
+/// minimum partial fill would not execute.  The output of a fill grows more
+/// slowly than its pro-rata minimum, so the fills that pass form a range
+/// starting at zero and can be bisected.
+fn largest_immediate_fill(order: &LimitOrder, pool: &LiquidityPool, now: i64) -> Option<(u64, FillQuote)> {
+    if let Ok(quote) = quote_fill(order, pool, order.sell_amount, now) {
+        return Some((order.sell_amount, quote));
+    }
+    let (mut low, mut high) = (order.min_fill_amount.max(1), order.sell_amount - 1);
+    let mut best = None;
+    while low <= high {
+        let mid = low + (high - low) / 2;
+        match quote_fill(order, pool, mid, now) {
+            Ok(quote) => {
+                best = Some((mid, quote));
+                low = mid + 1;
+            }
+            Err(_) => high = mid - 1,
+        }
+    }
+    best
+}
+/// A fill the pool can take now: the spot price it fills at, what the owner
+/// receives and the swap fee it pays.
+struct FillQuote {
+    current_price: u64,
+    is_sell: bool,
+    output_amount: u64,
+    fee_amount: u64,
+}
+
+/// Check that `fill_amount` of a pending, unexpired order can execute against
+/// the pool as it stands and quote its output.  The trigger is checked against
+/// the pool's TWAP, which a swap just before execution can't move, and spot
+/// must be near it; the fill itself prices against the live reserves.
+fn quote_fill(order: &LimitOrder, pool: &LiquidityPool, fill_amount: u64, now: i64) -> Result<FillQuote> {
     require!(
         order.status == OrderStatus::Pending,
         CustomError::InvalidOrderStatus
     );
-    require!(now < order.expires_at, CustomError::OrderExpired);
+    require!(!order.is_expired(now), CustomError::OrderExpired);
+    validate_fill_amount(fill_amount, order.sell_amount, order.min_fill_amount)?;
+    pool.require_funded()?;
+    let reference_price = pool.twap_price_a(LIMIT_ORDER_TWAP_WINDOW_SECS, now)?;
+    require!(
+        pool.twap_deviation_bps(LIMIT_ORDER_TWAP_WINDOW_SECS, now)? <= MAX_LIMIT_ORDER_TWAP_DEVIATION_BPS,
+        CustomError::PriceDeviationTooHigh
+    );
     let current_price = calculate_pool_price(pool.reserve_a, pool.reserve_b)?;
     let is_sell = order.sell_token == pool.token_a_mint;
     require!(
-        check_price_condition(current_price, order.target_price, is_sell),
+        check_price_condition(reference_price, order.target_price, is_sell, order.order_kind),
         CustomError::PriceConditionNotMet
     );
-    let output_amount = calculate_output_amount(
-        order.sell_amount,
-        if is_sell { pool.reserve_a } else { pool.reserve_b },
-        if is_sell { pool.reserve_b } else { pool.reserve_a },
-        pool.fee_numerator,
-        pool.fee_denominator,
-    )?;
+    // Priced and charged exactly as a `swap` of the same size
+    let SwapQuote { amount_out: output_amount, fee_amount } = quote_pool_swap(pool, fill_amount, is_sell, now)?;
     require!(
-        output_amount >= order.minimum_receive,
+        output_amount >= order.minimum_receive_for(fill_amount)?,
         CustomError::SlippageTooHigh
     );
+    Ok(FillQuote { current_price, is_sell, output_amount, fee_amount })
+}
+
+/// Accounts one order fill moves tokens and lamports between.
+struct FillAccounts<'a, 'info> {
+    pool: &'a mut Account<'info, LiquidityPool>,
+    limit_order: &'a mut Account<'info, LimitOrder>,
+    order_vault: &'a mut Account<'info, TokenAccount>,
+    pool_vault_in: &'a mut Account<'info, TokenAccount>,
+    pool_vault_out: &'a mut Account<'info, TokenAccount>,
+    /// Treasury account for the sell token; required when the pool charges a protocol fee.
+    protocol_fee_account: Option<AccountInfo<'info>>,
+    user_token_out: AccountInfo<'info>,
+    keeper: AccountInfo<'info>,
+    keeper_token_account: AccountInfo<'info>,
+    owner: AccountInfo<'info>,
+    token_program: AccountInfo<'info>,
+}
+
+/// Move a quoted fill through the pool, pay the keeper its share of the
+/// execution reward and, once nothing is left to sell, its tip, closing the
+/// order and its escrow to the owner.
+fn settle_fill(accounts: FillAccounts, fill_amount: u64, quote: FillQuote, now: i64) -> Result<()> {
+    let FillQuote { current_price, is_sell, output_amount, fee_amount } = quote;
+    let order = &accounts.limit_order;
     let order_key = order.key();
     let order_owner = order.owner;
     let order_pool = order.pool;
-    let order_sell_amount = order.sell_amount;
     let order_bump = order.bump;
     let order_order_id = order.order_id;
     let order_signer_seeds: &[&[&[u8]]] = &[&[
@@ -102,91 +265,254 @@ pub fn execute_limit_order(
         &order_order_id.to_le_bytes(),
         &[order_bump],
     ]];
+    let protocol_fee = calculate_protocol_fee(
+        fill_amount,
+        accounts.pool.protocol_fee_numerator,
+        accounts.pool.fee_denominator,
+    )?;
+    let balance_before = accounts.pool_vault_in.amount;
     transfer(
         CpiContext::new_with_signer(
-            ctx.accounts.token_program.to_account_info(),
+            accounts.token_program.clone(),
             Transfer {
-                from: ctx.accounts.order_vault.to_account_info(),
-                to: ctx.accounts.pool_vault_in.to_account_info(),
-                authority: ctx.accounts.limit_order.to_account_info(),
+                from: accounts.order_vault.to_account_info(),
+                to: accounts.pool_vault_in.to_account_info(),
+                authority: accounts.limit_order.to_account_info(),
             },
             order_signer_seeds,
         ),
-        order_sell_amount,
+        fill_amount - protocol_fee,
     )?;
-    let pool = &mut ctx.accounts.pool;
-    if is_sell {
-        pool.reserve_a = pool
-            .reserve_a
-            .checked_add(order_sell_amount)
-            .ok_or(error!(CustomError::CalculationOverflow))?;
-        pool.reserve_b = pool
-            .reserve_b
-            .checked_sub(output_amount)
-            .ok_or(error!(CustomError::InsufficientPoolReserves))?;
-    } else {
-        pool.reserve_b = pool
-            .reserve_b
-            .checked_add(order_sell_amount)
-            .ok_or(error!(CustomError::CalculationOverflow))?;
-        pool.reserve_a = pool
-            .reserve_a
-            .checked_sub(output_amount)
-            .ok_or(error!(CustomError::InsufficientPoolReserves))?;
+    if protocol_fee > 0 {
+        let treasury = accounts
+            .protocol_fee_account
+            .clone()
+            .ok_or(error!(CustomError::MissingProtocolFeeAccount))?;
+        transfer(
+            CpiContext::new_with_signer(
+                accounts.token_program.clone(),
+                Transfer {
+                    from: accounts.order_vault.to_account_info(),
+                    to: treasury,
+                    authority: accounts.limit_order.to_account_info(),
+                },
+                order_signer_seeds,
+            ),
+            protocol_fee,
+        )?;
     }
-    let pool_key = pool.key();
-    let token_a_mint = pool.token_a_mint;
-    let token_b_mint = pool.token_b_mint;
-    let bump_seed = pool.bump;
-    let pool_signer_seeds: &[&[&[u8]]] = &[&[
-        b"pool",
-        token_a_mint.as_ref(),
-        token_b_mint.as_ref(),
-        &[bump_seed],
-    ]];
-    transfer(
-        CpiContext::new_with_signer(
-            ctx.accounts.token_program.to_account_info(),
-            Transfer {
-                from: ctx.accounts.pool_vault_out.to_account_info(),
-                to: ctx.accounts.user_token_out.to_account_info(),
-                authority: pool.to_account_info(),
-            },
-            pool_signer_seeds,
-        ),
+    let pool_amount_in = vault_received(accounts.pool_vault_in, balance_before)?;
+    // The fill was priced up front, so a short delivery would underpay the pool
+    require!(
+        pool_amount_in == fill_amount - protocol_fee,
+        CustomError::TransferFeeUnsupported
+    );
+    let pool_key = accounts.pool.key();
+    pay_out_swap(
+        SwapPayout {
+            pool: &mut *accounts.pool,
+            vault_in: &mut *accounts.pool_vault_in,
+            vault_out: &mut *accounts.pool_vault_out,
+            recipient: accounts.user_token_out.clone(),
+            token_program: accounts.token_program.clone(),
+        },
+        fill_amount,
+        pool_amount_in,
         output_amount,
+        fee_amount,
+        is_sell,
     )?;
-    let order = &mut ctx.accounts.limit_order;
-    order.status = OrderStatus::Executed;
+    let execution_reward = accounts.limit_order.record_fill(fill_amount)?;
+    let remaining_sell_amount = accounts.limit_order.sell_amount;
+    if execution_reward > 0 {
+        transfer(
+            CpiContext::new_with_signer(
+                accounts.token_program.clone(),
+                Transfer {
+                    from: accounts.order_vault.to_account_info(),
+                    to: accounts.keeper_token_account.clone(),
+                    authority: accounts.limit_order.to_account_info(),
+                },
+                order_signer_seeds,
+            ),
+            execution_reward,
+        )?;
+    }
+    let mut keeper_tip = 0;
+    if remaining_sell_amount == 0 {
+        // Only an empty escrow can be closed; tokens sent to it by anyone else keep it open
+        accounts.order_vault.reload()?;
+        if accounts.order_vault.amount == 0 {
+            close_account(CpiContext::new_with_signer(
+                accounts.token_program.clone(),
+                CloseAccount {
+                    account: accounts.order_vault.to_account_info(),
+                    destination: accounts.owner.clone(),
+                    authority: accounts.limit_order.to_account_info(),
+                },
+                order_signer_seeds,
+            ))?;
+        }
+        let order_info = accounts.limit_order.to_account_info();
+        keeper_tip = accounts.limit_order.keeper_tip_payout(
+            order_info.lamports(),
+            Rent::get()?.minimum_balance(LimitOrder::SIZE),
+        );
+        if keeper_tip > 0 {
+            order_info.sub_lamports(keeper_tip)?;
+            accounts.keeper.add_lamports(keeper_tip)?;
+        }
+        accounts.limit_order.close(accounts.owner.clone())?;
+    }
     emit!(LimitOrderExecuted {
         order: order_key,
         owner: order_owner,
         pool: pool_key,
-        sell_amount: order.sell_amount,
+        sell_amount: fill_amount,
         receive_amount: output_amount,
         execution_price: current_price,
         executed_at: now,
+        remaining_sell_amount,
+        keeper: accounts.keeper.key(),
+        keeper_tip_lamports: keeper_tip,
+        execution_reward,
     });
     Ok(())
 }
-pub fn cancel_limit_order(
-    ctx: Context<CancelLimitOrder>,
+
+/// Fill `fill_amount` of the order's remaining `sell_amount` against the pool.
+/// The order stays `Pending` until a fill takes the last of it; that fill pays
+/// the keeper its tip and closes the order and its escrow, rent to the owner.
+pub fn execute_limit_order(
+    ctx: Context<ExecuteLimitOrder>,
+    fill_amount: u64,
 ) -> Result<()> {
-    let order = &mut ctx.accounts.limit_order;
-    require!(
-        ctx.accounts.user.key() == order.owner,
-        CustomError::UnauthorizedOrderOwner
-    );
+    ctx.accounts.pool.require_active()?;
+    acquire_pool_lock(&mut ctx.accounts.pool)?;
+    let now = Clock::get()?.unix_timestamp;
+    let quote = quote_fill(&ctx.accounts.limit_order, &ctx.accounts.pool, fill_amount, now)?;
+    let accounts = &mut *ctx.accounts;
+    settle_fill(
+        FillAccounts {
+            pool: &mut accounts.pool,
+            limit_order: &mut accounts.limit_order,
+            order_vault: &mut accounts.order_vault,
+            pool_vault_in: &mut accounts.pool_vault_in,
+            pool_vault_out: &mut accounts.pool_vault_out,
+            protocol_fee_account: accounts.protocol_fee_account.as_ref().map(|account| account.to_account_info()),
+            user_token_out: accounts.user_token_out.to_account_info(),
+            keeper: accounts.keeper.to_account_info(),
+            keeper_token_account: accounts.keeper_token_account.to_account_info(),
+            owner: accounts.owner.to_account_info(),
+            token_program: accounts.token_program.to_account_info(),
+        },
+        fill_amount,
+        quote,
+        now,
+    )?;
+    ctx.accounts.pool.unlock();
+    Ok(())
+}
+
+/// Fill every order passed in `remaining_accounts` as `(limit_order,
+/// order_vault, user_token_out, owner)` groups whose price condition holds,
+/// each for its whole remaining amount.  Orders that are expired, untriggered
+/// or would fill below their minimum are skipped rather than failing the
+/// batch; accounts that don't belong to the order or pool still fail it.
+pub fn execute_limit_orders_batch<'info>(
+    ctx: Context<'_, '_, 'info, 'info, ExecuteLimitOrdersBatch<'info>>,
+) -> Result<()> {
+    let remaining_accounts = ctx.remaining_accounts;
     require!(
-        order.status == OrderStatus::Pending,
-        CustomError::InvalidOrderStatus
+        !remaining_accounts.is_empty() && remaining_accounts.len().is_multiple_of(4),
+        CustomError::IncompleteOrderAccounts
     );
+    ctx.accounts.pool.require_active()?;
+    acquire_pool_lock(&mut ctx.accounts.pool)?;
     let now = Clock::get()?.unix_timestamp;
-    let order_key = order.key();
-    let bump_seed = order.bump;
-    let pool_key = order.pool;
-    let owner_key = order.owner;
-    let order_id = order.order_id;
+    let accounts = &mut *ctx.accounts;
+    let pool_key = accounts.pool.key();
+    let mut executed: u32 = 0;
+    let mut skipped: u32 = 0;
+    for group in remaining_accounts.chunks_exact(4) {
+        let (order_info, vault_info, user_out_info, owner_info) = (&group[0], &group[1], &group[2], &group[3]);
+        let mut limit_order = Account::<LimitOrder>::try_from(order_info)?;
+        require_keys_eq!(limit_order.pool, pool_key, CustomError::InvalidPool);
+        let mut order_vault = Account::<TokenAccount>::try_from(vault_info)?;
+        require!(
+            order_vault.owner == order_info.key()
+                && order_vault.amount >= limit_order.sell_amount + limit_order.execution_reward,
+            CustomError::InvalidVault
+        );
+        let user_token_out = Account::<TokenAccount>::try_from(user_out_info)?;
+        require!(
+            owner_info.key() == limit_order.owner && user_token_out.owner == limit_order.owner,
+            CustomError::UnauthorizedOrderOwner
+        );
+        require_keys_eq!(user_token_out.mint, limit_order.buy_token, CustomError::InvalidMint);
+        let fill_amount = limit_order.sell_amount;
+        let Ok(quote) = quote_fill(&limit_order, &accounts.pool, fill_amount, now) else {
+            skipped += 1;
+            continue;
+        };
+        let (pool_vault_in, pool_vault_out, keeper_token_account, protocol_fee_account) = if quote.is_sell {
+            (
+                &mut accounts.pool_token_a_vault,
+                &mut accounts.pool_token_b_vault,
+                &accounts.keeper_token_a,
+                &accounts.protocol_fee_account_a,
+            )
+        } else {
+            (
+                &mut accounts.pool_token_b_vault,
+                &mut accounts.pool_token_a_vault,
+                &accounts.keeper_token_b,
+                &accounts.protocol_fee_account_b,
+            )
+        };
+        settle_fill(
+            FillAccounts {
+                pool_vault_in,
+                pool_vault_out,
+                protocol_fee_account: protocol_fee_account.as_ref().map(|account| account.to_account_info()),
+                keeper_token_account: keeper_token_account.to_account_info(),
+                pool: &mut accounts.pool,
+                limit_order: &mut limit_order,
+                order_vault: &mut order_vault,
+                user_token_out: user_out_info.clone(),
+                keeper: accounts.keeper.to_account_info(),
+                owner: owner_info.clone(),
+                token_program: accounts.token_program.to_account_info(),
+            },
+            fill_amount,
+            quote,
+            now,
+        )?;
+        executed += 1;
+    }
+    emit!(BatchExecuted {
+        pool: pool_key,
+        keeper: accounts.keeper.key(),
+        executed_count: executed,
+        skipped_count: skipped,
+        executed_at: now,
+    });
+    ctx.accounts.pool.unlock();
+    Ok(())
+}
+/// Return an order's unfilled escrow, with its unpaid execution reward, to
+/// `refund_to` and close the emptied vault, rent to `rent_to`.
+fn refund_escrow<'info>(
+    limit_order: &Account<'info, LimitOrder>,
+    order_vault: &Account<'info, TokenAccount>,
+    refund_to: AccountInfo<'info>,
+    rent_to: AccountInfo<'info>,
+    token_program: AccountInfo<'info>,
+) -> Result<u64> {
+    let bump_seed = limit_order.bump;
+    let pool_key = limit_order.pool;
+    let owner_key = limit_order.owner;
+    let order_id = limit_order.order_id;
     let signer_seeds: &[&[&[u8]]] = &[&[
         b"limit_order",
         pool_key.as_ref(),
@@ -194,29 +520,152 @@ pub fn cancel_limit_order(
         &order_id.to_le_bytes(),
         &[bump_seed],
     ]];
+    // The unpaid execution reward goes back with the unfilled amount
+    let refunded_amount = limit_order.sell_amount + limit_order.execution_reward;
     transfer(
         CpiContext::new_with_signer(
-            ctx.accounts.token_program.to_account_info(),
+            token_program.clone(),
             Transfer {
-                from: ctx.accounts.order_vault.to_account_info(),
-                to: ctx.accounts.user_token_in.to_account_info(),
-                authority: order.to_account_info(),
+                from: order_vault.to_account_info(),
+                to: refund_to,
+                authority: limit_order.to_account_info(),
             },
             signer_seeds,
         ),
-        order.sell_amount,
+        refunded_amount,
+    )?;
+    close_account(CpiContext::new_with_signer(
+        token_program,
+        CloseAccount {
+            account: order_vault.to_account_info(),
+            destination: rent_to,
+            authority: limit_order.to_account_info(),
+        },
+        signer_seeds,
+    ))?;
+    Ok(refunded_amount)
+}
+
+pub fn cancel_limit_order(
+    ctx: Context<CancelLimitOrder>,
+) -> Result<()> {
+    let order = &ctx.accounts.limit_order;
+    require!(
+        ctx.accounts.user.key() == order.owner,
+        CustomError::UnauthorizedOrderOwner
+    );
+    require!(
+        order.status == OrderStatus::Pending,
+        CustomError::InvalidOrderStatus
+    );
+    let now = Clock::get()?.unix_timestamp;
+    let refunded_amount = refund_escrow(
+        order,
+        &ctx.accounts.order_vault,
+        ctx.accounts.user_token_in.to_account_info(),
+        ctx.accounts.user.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
     )?;
+    // The order account itself closes on exit, rent and any unpaid tip to the owner
+    let order = &mut ctx.accounts.limit_order;
     order.status = OrderStatus::Cancelled;
     emit!(LimitOrderCancelled {
-        order: order_key,
+        order: order.key(),
         owner: order.owner,
-        refunded_amount: order.sell_amount,
+        refunded_amount,
         cancelled_at: now,
     });
     Ok(())
 }
+
+/// Change a pending order's target price, minimum receive and expiry without
+/// cancelling it, keeping its escrow, rent and queue position.
+pub fn modify_limit_order(
+    ctx: Context<ModifyLimitOrder>,
+    new_target_price: u64,
+    new_minimum_receive: u64,
+    new_expiry_days: i64,
+) -> Result<()> {
+    let order = &mut ctx.accounts.limit_order;
+    let now = Clock::get()?.unix_timestamp;
+    let (old_target_price, old_minimum_receive, old_expires_at) =
+        (order.target_price, order.minimum_receive, order.expires_at);
+    order.modify(new_target_price, new_minimum_receive, new_expiry_days, now)?;
+    emit!(LimitOrderModified {
+        order: order.key(),
+        owner: order.owner,
+        old_target_price,
+        new_target_price,
+        old_minimum_receive,
+        new_minimum_receive,
+        old_expires_at,
+        new_expires_at: order.expires_at,
+        modified_at: now,
+    });
+    Ok(())
+}
+
+/// Refund an order past its expiry (and grace period) to its owner and close
+/// it and its escrow, rent and any unpaid keeper tip to the owner.  Anyone may
+/// call this, so expired orders don't keep their funds locked.
+pub fn close_expired_order(ctx: Context<CloseExpiredOrder>) -> Result<()> {
+    let order = &ctx.accounts.limit_order;
+    require!(
+        order.status == OrderStatus::Pending,
+        CustomError::InvalidOrderStatus
+    );
+    let now = Clock::get()?.unix_timestamp;
+    require!(order.is_expired(now), CustomError::OrderNotExpired);
+    let refunded_amount = refund_escrow(
+        order,
+        &ctx.accounts.order_vault,
+        ctx.accounts.owner_token_in.to_account_info(),
+        ctx.accounts.owner.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+    )?;
+    let order = &mut ctx.accounts.limit_order;
+    order.status = OrderStatus::Expired;
+    emit!(LimitOrderExpired {
+        order: order.key(),
+        owner: order.owner,
+        refunded_amount,
+        closed_by: ctx.accounts.caller.key(),
+        closed_at: now,
+    });
+    Ok(())
+}
+/// How far an order's trigger is from the current pool price, for keeper ordering.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct OrderPriceGap {
+    /// Pool price of token A in token B, scaled by 1e6.
+    pub current_price: u64,
+    pub target_price: u64,
+    /// `(current_price - target_price) / target_price` in bps; positive when the pool is above target.
+    pub gap_bps: i64,
+    /// Whether a full fill would execute now: triggered on the TWAP, spot near it, minimum met.
+    pub is_executable: bool,
+}
+
+pub fn order_price_gap(order: &LimitOrder, pool: &LiquidityPool, now: i64) -> Result<OrderPriceGap> {
+    require!(order.target_price > 0, CustomError::InvalidAmount);
+    pool.require_funded()?;
+    let current_price = calculate_pool_price(pool.reserve_a, pool.reserve_b)?;
+    let gap_bps = ((current_price as i128 - order.target_price as i128) * 10_000)
+        / order.target_price as i128;
+    Ok(OrderPriceGap {
+        current_price,
+        target_price: order.target_price,
+        gap_bps: i64::try_from(gap_bps).map_err(|_| error!(CustomError::CalculationOverflow))?,
+        is_executable: quote_fill(order, pool, order.sell_amount, now).is_ok(),
+    })
+}
+
+pub fn get_order_price_gap(ctx: Context<GetOrderPriceGap>) -> Result<OrderPriceGap> {
+    let now = Clock::get()?.unix_timestamp;
+    order_price_gap(&ctx.accounts.limit_order, &ctx.accounts.pool, now)
+}
 #[derive(Accounts)]
-#[instruction(sell_amount: u64, target_price: u64, minimum_receive: u64, expiry_days: i64, order_id: u64)]
+#[instruction(sell_amount: u64, target_price: u64, minimum_receive: u64, expiry_days: i64, order_id: u64, keeper_tip_lamports: u64, order_kind: OrderKind, execution_reward_bps: u16, time_in_force: TimeInForce)]
 pub struct CreateLimitOrder<'info> {
     #[account(mut)]
     pub pool: Account<'info, LiquidityPool>,
@@ -229,6 +678,9 @@ pub struct CreateLimitOrder<'info> {
     )]
     pub limit_order: Account<'info, LimitOrder>,
     pub sell_token_mint: Account<'info, Mint>,
+    /// CHECK: AMM config PDA; may be uninitialized, in which case the default minimum fill applies
+    #[account(seeds = [b"amm_config"], bump)]
+    pub amm_config: UncheckedAccount<'info>,
     #[account(mut, token::mint = sell_token_mint, token::authority = user)]
     pub user_token_in: Account<'info, TokenAccount>,
     #[account(mut)]
@@ -240,6 +692,18 @@ pub struct CreateLimitOrder<'info> {
         token::authority = limit_order
     )]
     pub order_vault: Account<'info, TokenAccount>,
+    /// The pool's vaults, needed only by IOC and FOK orders, which fill at creation.
+    #[account(mut, address = pool.token_a_vault @ CustomError::InvalidVault)]
+    pub pool_token_a_vault: Option<Account<'info, TokenAccount>>,
+    #[account(mut, address = pool.token_b_vault @ CustomError::InvalidVault)]
+    pub pool_token_b_vault: Option<Account<'info, TokenAccount>>,
+    /// Treasury account for the sell token; required when an IOC or FOK fill pays a protocol fee.
+    #[account(
+        mut,
+        token::mint = sell_token_mint,
+        token::authority = pool.protocol_fee_vault
+    )]
+    pub protocol_fee_account: Option<Account<'info, TokenAccount>>,
     #[account(mut)]
     pub user: Signer<'info>,
     pub token_program: Program<'info, Token>,
@@ -256,21 +720,71 @@ pub struct ExecuteLimitOrder<'info> {
         constraint = limit_order.pool == pool.key() @ CustomError::InvalidPool
     )]
     pub limit_order: Account<'info, LimitOrder>,
-    #[account(mut)]
+    /// Escrow holding the order's unfilled `sell_amount`.
+    #[account(
+        mut,
+        token::authority = limit_order,
+        constraint = order_vault.amount >= limit_order.sell_amount + limit_order.execution_reward @ CustomError::InvalidVault
+    )]
     pub order_vault: Account<'info, TokenAccount>,
-    #[account(mut)]
+    #[account(mut, address = pool.vault_for(&limit_order.sell_token) @ CustomError::InvalidVault)]
     pub pool_vault_in: Account<'info, TokenAccount>,
-    #[account(mut)]
+    /// The owner's account for the buy token.
+    #[account(
+        mut,
+        token::mint = limit_order.buy_token,
+        token::authority = limit_order.owner
+    )]
     pub user_token_out: Account<'info, TokenAccount>,
-    #[account(mut)]
+    #[account(mut, address = pool.vault_for(&limit_order.buy_token) @ CustomError::InvalidVault)]
     pub pool_vault_out: Account<'info, TokenAccount>,
+    /// Treasury account for the sell token; required when the pool charges a protocol fee.
+    #[account(
+        mut,
+        token::mint = limit_order.sell_token,
+        token::authority = pool.protocol_fee_vault
+    )]
+    pub protocol_fee_account: Option<Account<'info, TokenAccount>>,
+    /// Earns the order's keeper tip on the fill that completes it.
+    #[account(mut)]
+    pub keeper: Signer<'info>,
+    /// Receives the fill's share of the escrowed execution reward, in the sell token.
+    #[account(mut, token::mint = limit_order.sell_token)]
+    pub keeper_token_account: Account<'info, TokenAccount>,
+    /// CHECK: receives the order's rent when it closes — must be the order owner.
+    #[account(mut, address = limit_order.owner @ CustomError::UnauthorizedOrderOwner)]
+    pub owner: AccountInfo<'info>,
     pub token_program: Program<'info, Token>,
 }
 #[derive(Accounts)]
-pub struct CancelLimitOrder<'info> {
+pub struct ExecuteLimitOrdersBatch<'info> {
     #[account(mut)]
-    pub limit_order: Account<'info, LimitOrder>,
+    pub pool: Account<'info, LiquidityPool>,
+    #[account(mut, address = pool.token_a_vault @ CustomError::InvalidVault)]
+    pub pool_token_a_vault: Account<'info, TokenAccount>,
+    #[account(mut, address = pool.token_b_vault @ CustomError::InvalidVault)]
+    pub pool_token_b_vault: Account<'info, TokenAccount>,
+    /// Earns the tip of every order the batch completes.
     #[account(mut)]
+    pub keeper: Signer<'info>,
+    /// Receive the execution rewards of orders selling token A and token B respectively.
+    #[account(mut, token::mint = pool.token_a_mint)]
+    pub keeper_token_a: Account<'info, TokenAccount>,
+    #[account(mut, token::mint = pool.token_b_mint)]
+    pub keeper_token_b: Account<'info, TokenAccount>,
+    /// Treasury accounts for token A and token B; each required when orders
+    /// selling that token fill in a pool that charges a protocol fee.
+    #[account(mut, token::mint = pool.token_a_mint, token::authority = pool.protocol_fee_vault)]
+    pub protocol_fee_account_a: Option<Account<'info, TokenAccount>>,
+    #[account(mut, token::mint = pool.token_b_mint, token::authority = pool.protocol_fee_vault)]
+    pub protocol_fee_account_b: Option<Account<'info, TokenAccount>>,
+    pub token_program: Program<'info, Token>,
+}
+#[derive(Accounts)]
+pub struct CancelLimitOrder<'info> {
+    #[account(mut, close = user)]
+    pub limit_order: Account<'info, LimitOrder>,
+    #[account(mut, token::authority = limit_order)]
     pub order_vault: Account<'info, TokenAccount>,
     #[account(mut)]
     pub user_token_in: Account<'info, TokenAccount>,
@@ -278,3 +792,245 @@ pub struct CancelLimitOrder<'info> {
     pub user: Signer<'info>,
     pub token_program: Program<'info, Token>,
 }
+#[derive(Accounts)]
+pub struct ModifyLimitOrder<'info> {
+    #[account(
+        mut,
+        constraint = limit_order.owner == owner.key() @ CustomError::UnauthorizedOrderOwner
+    )]
+    pub limit_order: Account<'info, LimitOrder>,
+    pub owner: Signer<'info>,
+}
+#[derive(Accounts)]
+pub struct CloseExpiredOrder<'info> {
+    #[account(mut, close = owner)]
+    pub limit_order: Account<'info, LimitOrder>,
+    #[account(mut, token::authority = limit_order)]
+    pub order_vault: Account<'info, TokenAccount>,
+    /// Receives the refund: the owner's account for the sell token.
+    #[account(
+        mut,
+        token::mint = limit_order.sell_token,
+        token::authority = limit_order.owner
+    )]
+    pub owner_token_in: Account<'info, TokenAccount>,
+    /// CHECK: receives the rent of both accounts — must be the order owner.
+    #[account(mut, address = limit_order.owner @ CustomError::UnauthorizedOrderOwner)]
+    pub owner: AccountInfo<'info>,
+    pub caller: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+#[derive(Accounts)]
+pub struct GetOrderPriceGap<'info> {
+    pub pool: Account<'info, LiquidityPool>,
+    #[account(constraint = limit_order.pool == pool.key() @ CustomError::InvalidPool)]
+    pub limit_order: Account<'info, LimitOrder>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::pool::tests::make_pool;
+    use crate::constants::LIMIT_ORDER_EXPIRY_GRACE_SECS;
+
+    const NOW: i64 = 500;
+
+    /// A pool with price history back past the limit-order TWAP window, so the
+    /// TWAP tracks the reserves until they are moved through a trade.
+    fn make_twap_pool(reserve_a: u64, reserve_b: u64) -> LiquidityPool {
+        let mut pool = make_pool(reserve_a, reserve_b);
+        pool.token_a_mint = Pubkey::new_unique();
+        pool.init_price_observations(NOW - LIMIT_ORDER_TWAP_WINDOW_SECS);
+        pool
+    }
+
+    fn make_order(sell_token: Pubkey, target_price: u64) -> LimitOrder {
+        LimitOrder {
+            owner: Pubkey::default(),
+            pool: Pubkey::default(),
+            sell_token,
+            buy_token: Pubkey::default(),
+            sell_amount: 1_000,
+            target_price,
+            minimum_receive: 1,
+            created_at: 0,
+            expires_at: 1_000,
+            status: OrderStatus::Pending,
+            bump: 0,
+            order_id: 0,
+            keeper_tip_lamports: 0,
+            order_kind: OrderKind::TakeProfit,
+            execution_reward_bps: 0,
+            execution_reward: 0,
+            min_fill_amount: 100,
+        }
+    }
+
+    #[test]
+    fn test_sell_order_gap() {
+        let pool = make_twap_pool(1_000_000, 2_000_000);
+        // Pool at 2.0, selling A at 2.5: 20% below target, not yet executable
+        let gap = order_price_gap(&make_order(pool.token_a_mint, 2_500_000), &pool, NOW).unwrap();
+        assert_eq!(gap.current_price, 2_000_000);
+        assert_eq!(gap.gap_bps, -2_000);
+        assert!(!gap.is_executable);
+        // Selling A at 1.6: pool is 25% above target
+        let gap = order_price_gap(&make_order(pool.token_a_mint, 1_600_000), &pool, NOW).unwrap();
+        assert_eq!(gap.gap_bps, 2_500);
+        assert!(gap.is_executable);
+    }
+
+    #[test]
+    fn test_buy_order_gap() {
+        let pool = make_twap_pool(1_000_000, 2_000_000);
+        let sell_b = Pubkey::new_unique();
+        // Buying A at 1.6 while the pool is at 2.0: waits for the price to fall
+        let gap = order_price_gap(&make_order(sell_b, 1_600_000), &pool, NOW).unwrap();
+        assert_eq!(gap.gap_bps, 2_500);
+        assert!(!gap.is_executable);
+        let gap = order_price_gap(&make_order(sell_b, 2_500_000), &pool, NOW).unwrap();
+        assert_eq!(gap.gap_bps, -2_000);
+        assert!(gap.is_executable);
+        // At the trigger exactly, both sides execute
+        let gap = order_price_gap(&make_order(sell_b, 2_000_000), &pool, NOW).unwrap();
+        assert_eq!(gap.gap_bps, 0);
+        assert!(gap.is_executable);
+    }
+
+    #[test]
+    fn test_stop_loss_executes_only_once_price_falls_to_trigger() {
+        let mut pool = make_twap_pool(1_000_000, 2_000_000);
+        let mut order = make_order(pool.token_a_mint, 1_800_000);
+        order.order_kind = OrderKind::StopLoss;
+        // Pool at 2.0, above the 1.8 stop: a take-profit would fire, the stop-loss waits
+        assert!(!order_price_gap(&order, &pool, NOW).unwrap().is_executable);
+        // Price falls to 1.8 and below
+        pool.reserve_b = 1_800_000;
+        assert!(order_price_gap(&order, &pool, NOW).unwrap().is_executable);
+        pool.reserve_b = 1_500_000;
+        assert!(order_price_gap(&order, &pool, NOW).unwrap().is_executable);
+    }
+
+    #[test]
+    fn test_expired_order_not_executable() {
+        let pool = make_twap_pool(1_000_000, 2_000_000);
+        let order = make_order(pool.token_a_mint, 1_000_000);
+        // Still executable at the expiry boundary and through the grace period
+        assert!(order_price_gap(&order, &pool, 1_000).unwrap().is_executable);
+        assert!(order_price_gap(&order, &pool, 1_000 + LIMIT_ORDER_EXPIRY_GRACE_SECS).unwrap().is_executable);
+        let gap = order_price_gap(&order, &pool, 1_001 + LIMIT_ORDER_EXPIRY_GRACE_SECS).unwrap();
+        assert_eq!(gap.gap_bps, 10_000);
+        assert!(!gap.is_executable);
+    }
+
+    #[test]
+    fn test_batch_quotes_skip_ineligible_orders() {
+        let pool = make_twap_pool(1_000_000, 2_000_000);
+        let sell_b = Pubkey::new_unique();
+        // Pool at 2.0: a sell of A at 1.6 and a buy of A at 2.5 are triggered,
+        // a sell of A at 2.5 is not
+        let orders = [
+            make_order(pool.token_a_mint, 1_600_000),
+            make_order(sell_b, 2_500_000),
+            make_order(pool.token_a_mint, 2_500_000),
+        ];
+        let quotes: Vec<_> = orders
+            .iter()
+            .map(|order| quote_fill(order, &pool, order.sell_amount, NOW))
+            .collect();
+        assert_eq!(quotes.iter().filter(|quote| quote.is_ok()).count(), 2);
+        assert_eq!(
+            quotes[2].as_ref().err().unwrap(),
+            &error!(CustomError::PriceConditionNotMet)
+        );
+        let buy = quotes[1].as_ref().unwrap();
+        assert!(!buy.is_sell);
+        assert!(buy.output_amount > 0);
+        // A fill pays the same fee as a swap of its size
+        let swap = quote_pool_swap(&pool, 1_000, false, NOW).unwrap();
+        assert_eq!((buy.output_amount, buy.fee_amount), (swap.amount_out, 3));
+        // Past the grace period the triggered sell is skipped too
+        assert_eq!(
+            quote_fill(&orders[0], &pool, 1_000, 1_001 + LIMIT_ORDER_EXPIRY_GRACE_SECS).err().unwrap(),
+            error!(CustomError::OrderExpired)
+        );
+    }
+
+    #[test]
+    fn test_swap_before_execution_cannot_trigger_order() {
+        let mut pool = make_twap_pool(1_000_000, 2_000_000);
+        let order = make_order(pool.token_a_mint, 2_020_000);
+        assert_eq!(
+            quote_fill(&order, &pool, 1_000, NOW).err().unwrap(),
+            error!(CustomError::PriceConditionNotMet)
+        );
+        // A keeper pushes spot 1.5% up to 2.03, past the 2.02 target, right before executing
+        pool.update_price_cumulatives(NOW);
+        pool.reserve_b = 2_030_000;
+        assert_eq!(
+            quote_fill(&order, &pool, 1_000, NOW).err().unwrap(),
+            error!(CustomError::PriceConditionNotMet)
+        );
+        // A larger push is refused outright, even for an order the TWAP has triggered
+        pool.reserve_b = 2_500_000;
+        let triggered = make_order(pool.token_a_mint, 1_600_000);
+        assert_eq!(
+            quote_fill(&triggered, &pool, 1_000, NOW).err().unwrap(),
+            error!(CustomError::PriceDeviationTooHigh)
+        );
+        // Back near the TWAP the triggered order fills, priced at the live reserves
+        pool.reserve_b = 2_030_000;
+        let quote = quote_fill(&triggered, &pool, 1_000, NOW).unwrap();
+        assert_eq!(quote.current_price, 2_030_000);
+    }
+
+    #[test]
+    fn test_order_needs_twap_history() {
+        let mut pool = make_pool(1_000_000, 2_000_000);
+        pool.token_a_mint = Pubkey::new_unique();
+        pool.init_price_observations(NOW - LIMIT_ORDER_TWAP_WINDOW_SECS + 1);
+        let order = make_order(pool.token_a_mint, 1_600_000);
+        assert_eq!(
+            quote_fill(&order, &pool, 1_000, NOW).err().unwrap(),
+            error!(CustomError::TwapWindowUnavailable)
+        );
+        assert!(quote_fill(&order, &pool, 1_000, NOW + 1).is_ok());
+    }
+
+    #[test]
+    fn test_min_fill_fixed_at_original_size() {
+        let pool = make_twap_pool(1_000_000, 2_000_000);
+        let mut order = make_order(pool.token_a_mint, 1_600_000);
+        // Half filled: the minimum stays 10% of the original 1_000, not of what is left
+        order.record_fill(500).unwrap();
+        assert_eq!(
+            quote_fill(&order, &pool, 99, NOW).err().unwrap(),
+            error!(CustomError::InvalidAmount)
+        );
+        assert!(quote_fill(&order, &pool, 100, NOW).is_ok());
+    }
+
+    #[test]
+    fn test_immediate_or_cancel_fills_what_the_pool_allows() {
+        let pool = make_twap_pool(1_000_000, 2_000_000);
+        // Sell 100k A for at least 1.9 B each: the pool only gives that much for about half
+        let mut order = make_order(pool.token_a_mint, 1_900_000);
+        order.sell_amount = 100_000;
+        order.minimum_receive = 190_000;
+        let (fill, quote) = largest_immediate_fill(&order, &pool, NOW).unwrap();
+        assert!(fill > 45_000 && fill < 55_000, "fill {fill}");
+        assert!(quote.output_amount >= order.minimum_receive_for(fill).unwrap());
+        assert!(quote_fill(&order, &pool, fill + 1, NOW).is_err());
+        // Fill-or-kill refuses the same order outright
+        assert_eq!(
+            quote_fill(&order, &pool, order.sell_amount, NOW).err().unwrap(),
+            error!(CustomError::SlippageTooHigh)
+        );
+        // With a looser minimum the whole order goes through
+        order.minimum_receive = 150_000;
+        assert_eq!(largest_immediate_fill(&order, &pool, NOW).unwrap().0, 100_000);
+        // Nothing fills when the price condition doesn't hold
+        order.target_price = 2_500_000;
+        assert!(largest_immediate_fill(&order, &pool, NOW).is_none());
+    }
+}
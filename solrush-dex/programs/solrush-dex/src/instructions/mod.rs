@@ -3,8 +3,12 @@ pub mod swap;
 pub mod limit_orders;
 pub mod rewards;
 pub mod perps;
+pub mod flash_loan;
+pub mod amm_config;
 pub use pool::*;
 pub use swap::*;
 pub use limit_orders::*;
 pub use rewards::*;
 pub use perps::*;
+pub use flash_loan::*;
+pub use amm_config::*;
@@ -1,10 +1,14 @@
 use anchor_lang::prelude::*;
 use anchor_spl::token::{self, Mint, Token, TokenAccount, Transfer};
-use pyth_sdk_solana::load_price_feed_from_account_info;
+use pyth_sdk_solana::{load_price_feed_from_account_info, PriceFeed};
 use crate::errors::CustomError;
-use crate::perps_math::{self, PositionState, notional_value, unrealized_pnl, PRICE_SCALE, initial_margin, compute_equity, can_increase_position};
-use crate::state::{PerpsGlobalState, PerpsMarket, PerpsOraclePrice, PerpsPosition, PerpsUserAccount, InsuranceVault};
-use crate::events::{FundingUpdated, FundingSettled, Liquidated};
+use crate::perps_math::{self, notional_value, unrealized_pnl, initial_margin, compute_equity, can_increase_position};
+use crate::state::{MarginMode, PerpsGlobalState, PerpsLimitOrder, PerpsMarket, PerpsOraclePrice, PerpsPosition, PerpsUserAccount, InsuranceVault};
+use crate::events::{
+    AutoDeleveraged, FundingUpdated, FundingSettled, InsuranceWithdrawn, Liquidated, PerpsTradeExecuted, PositionClosed,
+    PositionOpened, TriggerExecuted,
+};
+use crate::constants::{DEFAULT_MAX_ORACLE_STALENESS_SECS, DEFAULT_MIN_INSURANCE_SEED, MAX_PERPS_MARKETS, TRIGGER_KEEPER_FEE_BPS};
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
 pub enum OrderType {
@@ -18,63 +22,262 @@ pub enum PositionSide {
     Short = 1,
 }
 
+impl PositionSide {
+    /// Signed base delta for trading `size` on this side.
+    pub fn base_delta(self, size: i64) -> Result<i64> {
+        match self {
+            PositionSide::Long => Ok(size),
+            PositionSide::Short => size.checked_neg().ok_or(error!(CustomError::CalculationOverflow)),
+        }
+    }
+}
+
+/// Read-only snapshot of a perps position returned by `get_position`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PositionView {
+    pub base_position_i64: i64,
+    pub entry_price_i64: i64,
+    /// 0 = long (or empty), 1 = short — always derived from the base sign.
+    pub side: u8,
+    pub collateral_u64: u64,
+    pub leverage_u16: u16,
+    /// Liquidation mark (see `PerpsMarket::liquidation_mark_price`); `unrealized_pnl_i128` is taken at it.
+    pub mark_price_i64: i64,
+    pub unrealized_pnl_i128: i128,
+    /// Mark at which the position becomes liquidatable (see `position_liquidation_price`).
+    pub liquidation_price_i64: i64,
+}
+
+/// What `liquidate_position` would do right now, returned by `preview_liquidation`.
+/// Amounts are atomic quote; all zero when `liquidatable` is false.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct LiquidationPreview {
+    pub liquidatable: bool,
+    /// Funding settled first (positive = the position pays).
+    pub funding_delta_i128: i128,
+    pub size_closed_i64: i64,
+    pub full_close: bool,
+    pub liquidator_fee_bps: u16,
+    /// Total liquidator fee, including the part drawn from insurance.
+    pub liquidator_fee_u64: u64,
+    pub liquidator_fee_from_insurance_u64: u64,
+    pub insurance_penalty_u64: u64,
+    pub shortfall_from_free_u64: u64,
+    pub bad_debt_u64: u64,
+    /// Left for auto-deleveraging once insurance is exhausted.
+    pub uncovered_bad_debt_u64: u64,
+    pub emergency: bool,
+    /// Collateral left on the position, or returned to the owner on a full close.
+    pub remaining_collateral_u64: u64,
+}
+
+/// Cross-margin health returned by `get_cross_margin_health`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct CrossMarginHealthView {
+    pub quote_mint: Pubkey,
+    /// Cross-margin positions netted (isolated ones are skipped).
+    pub positions_u8: u8,
+    pub equity_i128: i128,
+    pub unrealized_pnl_i128: i128,
+    pub maintenance_margin_i128: i128,
+    /// `equity / maintenance_margin` in bps; below 10_000 is unhealthy.
+    pub health_factor_bps: u64,
+}
+
+/// Single-position margin snapshot returned by `get_position_health`, valued
+/// exactly as `liquidate_position` checks eligibility: funding settled,
+/// collateral haircut, TWAP mark, confidence-widened maintenance margin.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PositionHealth {
+    pub equity_i128: i128,
+    pub maintenance_margin_i128: i128,
+    pub initial_margin_i128: i128,
+    pub unrealized_pnl_i128: i128,
+    /// Funding not yet settled (positive = the position pays); already in equity.
+    pub pending_funding_i128: i128,
+    pub notional_i128: i128,
+    /// `equity / maintenance_margin` in bps; below 10_000 is liquidatable.
+    pub health_factor_bps: u64,
+}
+
 // ─────────────────────────────────────────────
 // Funding settlement helper (pure, no side effects)
 // ─────────────────────────────────────────────
 
 /// Settle accumulated funding for a position.
 ///
-/// funding_delta = base_position × (market_cum_funding − last_cum_funding)
+/// funding_delta = base_position × (market_cum_funding − last_cum_funding) / BASE_SCALE
 ///
-/// Positive delta → longs pay (collateral decreases).
-/// Negative delta → shorts pay (collateral increases for the position).
+/// `base_position` is in base atoms and the funding index is atomic quote per
+/// whole base unit, so the delta is in atomic quote (see `perps_math::funding_payment`).
 ///
-/// Returns `(new_collateral, new_checkpoint, funding_delta)`.
+/// Positive delta → the position pays into the market funding pool.
+/// Negative delta → the position is paid out of that pool, never more than
+/// counterparties have actually paid in (see `perps_math::apply_funding_transfer`).
 fn settle_funding_inner(
     base_position: i64,
     collateral: u64,
+    unpaid_funding: u64,
     last_cum_funding: i128,
     market_cum_funding: i128,
-) -> Result<(u64, i128, i128)> {
-    if base_position == 0 {
-        // No position to settle — just advance checkpoint
-        return Ok((collateral, market_cum_funding, 0));
-    }
+    funding_pool: u64,
+) -> Result<SettledFunding> {
     let cum_diff = market_cum_funding
         .checked_sub(last_cum_funding)
         .ok_or(error!(CustomError::CalculationOverflow))?;
-    if cum_diff == 0 {
-        return Ok((collateral, market_cum_funding, 0));
-    }
-    let funding_delta = (base_position as i128)
-        .checked_mul(cum_diff)
-        .ok_or(error!(CustomError::CalculationOverflow))?;
-    let new_collateral_i128 = (collateral as i128)
-        .checked_sub(funding_delta)
-        .ok_or(error!(CustomError::CalculationOverflow))?;
-    let new_collateral = if new_collateral_i128 <= 0 {
-        0u64
+    let funding_delta = if base_position == 0 || cum_diff == 0 {
+        0
     } else {
-        u64::try_from(new_collateral_i128)
-            .map_err(|_| error!(CustomError::CalculationOverflow))?
+        perps_math::funding_payment(base_position, cum_diff)?
     };
-    Ok((new_collateral, market_cum_funding, funding_delta))
+    let transfer = perps_math::apply_funding_transfer(
+        collateral,
+        unpaid_funding,
+        funding_delta,
+        funding_pool,
+    )?;
+    Ok(SettledFunding {
+        collateral: transfer.collateral,
+        checkpoint: market_cum_funding,
+        funding_delta,
+        unpaid_funding: transfer.unpaid_funding,
+        funding_pool: transfer.funding_pool,
+    })
+}
+
+struct SettledFunding {
+    collateral: u64,
+    checkpoint: i128,
+    funding_delta: i128,
+    unpaid_funding: u64,
+    funding_pool: u64,
+}
+
+/// Settle `position`'s accumulated funding against `market` in place.
+fn settle_position_funding<'info>(
+    market: &mut Account<'info, PerpsMarket>,
+    position: &mut Account<'info, PerpsPosition>,
+) -> Result<()> {
+    let settled = settle_funding_inner(
+        position.base_position_i64,
+        position.collateral_u64,
+        position.unpaid_funding_u64,
+        position.last_funding_i128,
+        market.cumulative_funding_i128,
+        market.funding_pool_u64,
+    )?;
+    position.collateral_u64 = settled.collateral;
+    position.last_funding_i128 = settled.checkpoint;
+    position.unpaid_funding_u64 = settled.unpaid_funding;
+    market.funding_pool_u64 = settled.funding_pool;
+    if settled.funding_delta != 0 {
+        emit!(FundingSettled {
+            position: position.key(),
+            funding_delta: settled.funding_delta,
+            new_collateral: settled.collateral,
+        });
+    }
+    Ok(())
 }
 
-fn read_oracle_price<'info>(oracle_price_account: &AccountInfo<'info>) -> Result<i64> {
+/// Value the user's whole account: `local` (this market's position, if open,
+/// at `price`) plus every open position in other markets, read from
+/// `remaining_accounts` as `(market, oracle, position)` triples.  Each
+/// position is valued at its market's oracle price and collateral haircut;
+/// funding not yet settled on the other positions is ignored.
+fn account_margin(
+    remaining_accounts: &[AccountInfo],
+    user: &PerpsUserAccount,
+    market: &PerpsMarket,
+    market_key: Pubkey,
+    local: Option<(&PerpsPosition, i64)>,
+) -> Result<perps_math::AccountMargin> {
+    require!(remaining_accounts.len().is_multiple_of(3), CustomError::IncompleteMarginAccounts);
+    let mut positions = Vec::with_capacity(remaining_accounts.len() / 3 + 1);
+    let mut prices = Vec::with_capacity(positions.capacity());
+    let mut seen_markets = vec![market_key];
+    for triple in remaining_accounts.chunks_exact(3) {
+        let (_, position, price) = read_margin_triple(triple, user.owner, &mut seen_markets)?;
+        prices.push(price);
+        positions.push(position);
+    }
+    if let Some((position, price)) = local {
+        let mut position = position.clone();
+        position.collateral_u64 =
+            perps_math::haircut_collateral(position.collateral_u64, market.collateral_haircut_bps)?;
+        positions.push(position);
+        prices.push(price);
+    }
+    // Leaving a position out would hide its margin requirement
+    require!(
+        positions.len() == user.positions_count_u8 as usize,
+        CustomError::IncompleteMarginAccounts
+    );
+    perps_math::aggregate_account_margin(&positions, &prices)
+}
+
+/// Load one `(market, oracle, position)` triple of `owner`'s open position,
+/// rejecting markets already in `seen_markets`.  Returns the market, the
+/// position with its collateral haircut applied, and the oracle price.
+fn read_margin_triple(
+    triple: &[AccountInfo],
+    owner: Pubkey,
+    seen_markets: &mut Vec<Pubkey>,
+) -> Result<(PerpsMarket, PerpsPosition, i64)> {
+    let (market_info, oracle_info, position_info) = (&triple[0], &triple[1], &triple[2]);
+    require!(
+        market_info.owner == &crate::ID && position_info.owner == &crate::ID,
+        CustomError::IncompleteMarginAccounts
+    );
+    require!(!seen_markets.contains(market_info.key), CustomError::IncompleteMarginAccounts);
+    seen_markets.push(market_info.key());
+    let market = PerpsMarket::try_deserialize(&mut &market_info.try_borrow_data()?[..])?;
+    let mut position = PerpsPosition::try_deserialize(&mut &position_info.try_borrow_data()?[..])?;
+    require!(
+        position.owner == owner && position.market == market_info.key() && !position.is_empty(),
+        CustomError::IncompleteMarginAccounts
+    );
+    require!(
+        oracle_info.key() == market.oracle_price_account,
+        CustomError::OraclePriceUnavailable
+    );
+    let price = read_oracle_price(oracle_info, market.max_oracle_staleness_secs)?;
+    position.collateral_u64 =
+        perps_math::haircut_collateral(position.collateral_u64, market.collateral_haircut_bps)?;
+    Ok((market, position, price))
+}
+
+pub(crate) fn read_oracle_price<'info>(oracle_price_account: &AccountInfo<'info>, max_staleness_secs: u32) -> Result<i64> {
+    let (price, _) = read_oracle_price_and_conf(oracle_price_account, max_staleness_secs)?;
+    Ok(price)
+}
+
+/// Read `(price, confidence)` from the oracle.
+///
+/// The admin-set `PerpsOraclePrice` adapter has no confidence interval and
+/// reports 0; Pyth feeds report their published `conf` and are rejected once
+/// older than `max_staleness_secs` (the market's `max_oracle_staleness_secs`).
+fn read_oracle_price_and_conf<'info>(
+    oracle_price_account: &AccountInfo<'info>,
+    max_staleness_secs: u32,
+) -> Result<(i64, u64)> {
     if oracle_price_account.owner == &crate::ID {
         let data = oracle_price_account.try_borrow_data()?;
         let mut slice: &[u8] = &data;
         let oracle = PerpsOraclePrice::try_deserialize(&mut slice)?;
-        return Ok(oracle.price_i64);
+        return Ok((oracle.price_i64, 0));
     }
     let price_feed = load_price_feed_from_account_info(oracle_price_account)
         .map_err(|_| error!(CustomError::OraclePriceUnavailable))?;
-    let clock = Clock::get()?;
+    fresh_pyth_price(&price_feed, Clock::get()?.unix_timestamp, max_staleness_secs)
+}
+
+/// `(price, conf)` of a Pyth feed published no more than `max_staleness_secs` before `now`.
+fn fresh_pyth_price(price_feed: &PriceFeed, now: i64, max_staleness_secs: u32) -> Result<(i64, u64)> {
     let price = price_feed
-        .get_price_no_older_than(clock.unix_timestamp, 60)
+        .get_price_no_older_than(now, max_staleness_secs as u64)
         .ok_or(error!(CustomError::OraclePriceUnavailable))?;
-    Ok(price.price)
+    Ok((price.price, price.conf))
 }
 
 #[derive(Accounts)]
@@ -95,12 +298,60 @@ pub struct InitializePerpsGlobal<'info> {
 pub fn initialize_global(ctx: Context<InitializePerpsGlobal>, fee_bps: u16) -> Result<()> {
     let global = &mut ctx.accounts.global;
     global.authority = ctx.accounts.admin.key();
-    global.paused = false;
+    global.trading_paused = false;
     global.fee_bps = fee_bps;
     global.bump = ctx.bumps.global;
+    global.max_markets = MAX_PERPS_MARKETS as u16;
+    global.markets = Vec::new();
+    global.liquidations_enabled = true;
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct UpdatePerpsGlobal<'info> {
+    pub admin: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"perps_global"],
+        bump = global.bump,
+        constraint = global.authority == admin.key() @ CustomError::UnauthorizedAdmin
+    )]
+    pub global: Account<'info, PerpsGlobalState>,
+}
+
+/// Cap the number of markets; cannot go below the markets already created.
+pub fn set_max_markets(ctx: Context<UpdatePerpsGlobal>, max_markets: u16) -> Result<()> {
+    let global = &mut ctx.accounts.global;
+    require!(
+        (max_markets as usize) <= MAX_PERPS_MARKETS && (max_markets as usize) >= global.markets.len(),
+        CustomError::InvalidRiskParams
+    );
+    global.max_markets = max_markets;
+    Ok(())
+}
+
+/// Pause new risk while optionally keeping liquidations running to delever.
+pub fn set_perps_pause(
+    ctx: Context<UpdatePerpsGlobal>,
+    trading_paused: bool,
+    liquidations_enabled: bool,
+) -> Result<()> {
+    let global = &mut ctx.accounts.global;
+    global.trading_paused = trading_paused;
+    global.liquidations_enabled = liquidations_enabled;
     Ok(())
 }
 
+#[derive(Accounts)]
+pub struct ListMarkets<'info> {
+    #[account(seeds = [b"perps_global"], bump = global.bump)]
+    pub global: Account<'info, PerpsGlobalState>,
+}
+
+pub fn list_markets(ctx: Context<ListMarkets>) -> Result<Vec<Pubkey>> {
+    Ok(ctx.accounts.global.markets.clone())
+}
+
 #[derive(Accounts)]
 pub struct CreatePerpsMarket<'info> {
     #[account(mut)]
@@ -197,12 +448,16 @@ pub fn create_market(
     maintenance_margin_bps: u16,
     max_funding_rate: i64,
     funding_interval_secs: i64,
+    max_open_interest_i128: i128,
 ) -> Result<()> {
-    require!(!ctx.accounts.global.paused, CustomError::PerpsPaused);
+    ctx.accounts.global.require_trading_active()?;
     require!(max_leverage > 0, CustomError::InvalidLeverage);
+    require!(max_open_interest_i128 >= 0, CustomError::InvalidRiskParams);
     require!(max_funding_rate >= 0, CustomError::InvalidFundingParams);
     require!(funding_interval_secs > 0, CustomError::InvalidFundingParams);
-    let _ = read_oracle_price(&ctx.accounts.oracle_price_account)?;
+    let _ = read_oracle_price(&ctx.accounts.oracle_price_account, DEFAULT_MAX_ORACLE_STALENESS_SECS)?;
+    let market_key = ctx.accounts.market.key();
+    ctx.accounts.global.register_market(market_key)?;
     let market = &mut ctx.accounts.market;
     market.base_mint = ctx.accounts.base_mint.key();
     market.quote_mint = ctx.accounts.quote_mint.key();
@@ -222,6 +477,179 @@ pub fn create_market(
     market.liquidation_fee_bps = 250;
     market.liquidation_penalty_bps = 250;
     market.emergency = false;
+    // Confidence-linked MM disabled by default
+    market.conf_mm_multiplier_bps = 0;
+    // Inactive until the insurance vault is seeded and `activate_market` runs
+    market.active = false;
+    market.min_insurance_seed_u64 = DEFAULT_MIN_INSURANCE_SEED;
+    market.funding_pool_u64 = 0;
+    market.collateral_haircut_bps = 0;
+    market.liquidation_fee_insurance_first = false;
+    market.long_open_interest_u64 = 0;
+    market.short_open_interest_u64 = 0;
+    market.max_oi_imbalance_bps = 0;
+    market.oi_imbalance_free_base_u64 = 0;
+    market.trading_fees_u64 = 0;
+    market.mark_price_cumulative_i128 = 0;
+    market.last_mark_ts = 0;
+    market.twap_window_secs = 0;
+    market.uncovered_bad_debt_u64 = 0;
+    market.bad_debt_side_u8 = 0;
+    market.max_oracle_staleness_secs = DEFAULT_MAX_ORACLE_STALENESS_SECS;
+    // Flat liquidator fee until `set_liquidation_incentive` raises the cap
+    market.max_liquidation_fee_bps = 250;
+    market.max_open_interest_i128 = max_open_interest_i128;
+    market.funding_keeper_reward_u64 = 0;
+    market.min_liquidation_fee_u64 = 0;
+    market.insurance_floor_u64 = 0;
+    market.paused = false;
+    market.funding_skew_bps = 0;
+    Ok(())
+}
+
+// ─────────────────────────────────────────────────────
+// Market risk configuration (admin)
+// ─────────────────────────────────────────────────────
+
+#[derive(Accounts)]
+pub struct UpdatePerpsMarket<'info> {
+    pub admin: Signer<'info>,
+    #[account(
+        seeds = [b"perps_global"],
+        bump = global.bump,
+        constraint = global.authority == admin.key() @ CustomError::UnauthorizedAdmin
+    )]
+    pub global: Account<'info, PerpsGlobalState>,
+    #[account(
+        mut,
+        seeds = [b"perps_market", market.base_mint.as_ref(), market.quote_mint.as_ref()],
+        bump = market.bump
+    )]
+    pub market: Account<'info, PerpsMarket>,
+}
+
+/// Cap the long/short open interest skew for opens that widen it.
+///
+/// `max_oi_imbalance_bps` = 0 disables the cap; skews up to `free_base_u64`
+/// base atoms are always allowed so an empty market can take its first trades.
+pub fn set_oi_imbalance_limit(
+    ctx: Context<UpdatePerpsMarket>,
+    max_oi_imbalance_bps: u16,
+    free_base_u64: u64,
+) -> Result<()> {
+    require!(max_oi_imbalance_bps <= 10_000, CustomError::InvalidRiskParams);
+    let market = &mut ctx.accounts.market;
+    market.max_oi_imbalance_bps = max_oi_imbalance_bps;
+    market.oi_imbalance_free_base_u64 = free_base_u64;
+    Ok(())
+}
+
+/// Set the window of the time-weighted mark used for liquidation checks.
+///
+/// 0 disables the TWAP and liquidates on the spot oracle price.
+pub fn set_mark_twap_window(ctx: Context<UpdatePerpsMarket>, twap_window_secs: i64) -> Result<()> {
+    require!(twap_window_secs >= 0, CustomError::InvalidRiskParams);
+    ctx.accounts.market.twap_window_secs = twap_window_secs;
+    Ok(())
+}
+
+/// Set the liquidator fee: `liquidation_fee_bps` for a position just under
+/// maintenance, scaling up to `max_liquidation_fee_bps` as its equity falls to 0.
+pub fn set_liquidation_incentive(
+    ctx: Context<UpdatePerpsMarket>,
+    liquidation_fee_bps: u16,
+    max_liquidation_fee_bps: u16,
+) -> Result<()> {
+    require!(
+        liquidation_fee_bps <= max_liquidation_fee_bps && max_liquidation_fee_bps <= 10_000,
+        CustomError::InvalidRiskParams
+    );
+    let market = &mut ctx.accounts.market;
+    market.liquidation_fee_bps = liquidation_fee_bps;
+    market.max_liquidation_fee_bps = max_liquidation_fee_bps;
+    Ok(())
+}
+
+/// Set the minimum liquidator fee (atomic quote) paid per liquidation, 0 = none.
+pub fn set_min_liquidation_fee(ctx: Context<UpdatePerpsMarket>, min_liquidation_fee_u64: u64) -> Result<()> {
+    ctx.accounts.market.min_liquidation_fee_u64 = min_liquidation_fee_u64;
+    Ok(())
+}
+
+/// Set the funding rate a one-sided book adds on top of the premium, in bps;
+/// scaled by the actual long/short imbalance (0 = premium only).
+pub fn set_funding_skew(ctx: Context<UpdatePerpsMarket>, funding_skew_bps: u16) -> Result<()> {
+    require!(funding_skew_bps <= 10_000, CustomError::InvalidRiskParams);
+    ctx.accounts.market.funding_skew_bps = funding_skew_bps;
+    Ok(())
+}
+
+/// Set the reward paid per funding update to its keeper (0 = none).
+/// Paid out of collected trading fees, never beyond them.
+pub fn set_funding_keeper_reward(ctx: Context<UpdatePerpsMarket>, funding_keeper_reward_u64: u64) -> Result<()> {
+    ctx.accounts.market.funding_keeper_reward_u64 = funding_keeper_reward_u64;
+    Ok(())
+}
+
+/// Set how old a Pyth price may be before the market rejects it.
+/// The admin-set `PerpsOraclePrice` adapter is never treated as stale.
+pub fn set_oracle_staleness(ctx: Context<UpdatePerpsMarket>, max_oracle_staleness_secs: u32) -> Result<()> {
+    require!(max_oracle_staleness_secs > 0, CustomError::InvalidRiskParams);
+    ctx.accounts.market.max_oracle_staleness_secs = max_oracle_staleness_secs;
+    Ok(())
+}
+
+/// Set how strongly oracle uncertainty widens the maintenance margin.
+///
+/// `conf_mm_multiplier_bps` = 0 disables the linkage; 10_000 adds the full
+/// confidence-to-price ratio on top of `maintenance_margin_bps`.
+pub fn set_confidence_margin_multiplier(
+    ctx: Context<UpdatePerpsMarket>,
+    conf_mm_multiplier_bps: u16,
+) -> Result<()> {
+    require!(conf_mm_multiplier_bps <= 50_000, CustomError::InvalidRiskParams);
+    ctx.accounts.market.conf_mm_multiplier_bps = conf_mm_multiplier_bps;
+    Ok(())
+}
+
+/// Set the insurance balance `withdraw_insurance` must leave in the fund.
+pub fn set_insurance_floor(ctx: Context<UpdatePerpsMarket>, insurance_floor_u64: u64) -> Result<()> {
+    ctx.accounts.market.insurance_floor_u64 = insurance_floor_u64;
+    Ok(())
+}
+
+/// Set the insurance balance required before the market can be activated.
+pub fn set_min_insurance_seed(ctx: Context<UpdatePerpsMarket>, min_insurance_seed_u64: u64) -> Result<()> {
+    ctx.accounts.market.min_insurance_seed_u64 = min_insurance_seed_u64;
+    Ok(())
+}
+
+/// Value collateral at `(10_000 - collateral_haircut_bps) / 10_000` in equity checks.
+pub fn set_collateral_haircut(ctx: Context<UpdatePerpsMarket>, collateral_haircut_bps: u16) -> Result<()> {
+    require!(collateral_haircut_bps < 10_000, CustomError::InvalidRiskParams);
+    ctx.accounts.market.collateral_haircut_bps = collateral_haircut_bps;
+    Ok(())
+}
+
+/// Cap the market's total open notional (0 = uncapped).  Lowering it below
+/// the current open interest only blocks increases; positions can still close.
+pub fn set_max_open_interest(ctx: Context<UpdatePerpsMarket>, max_open_interest_i128: i128) -> Result<()> {
+    require!(max_open_interest_i128 >= 0, CustomError::InvalidRiskParams);
+    ctx.accounts.market.max_open_interest_i128 = max_open_interest_i128;
+    Ok(())
+}
+
+/// Halt one market's opens, funding updates and liquidations without the
+/// global pause; positions can still be closed to de-risk.
+pub fn set_market_paused(ctx: Context<UpdatePerpsMarket>, paused: bool) -> Result<()> {
+    ctx.accounts.market.paused = paused;
+    Ok(())
+}
+
+/// Choose whether the liquidator fee is drawn from the insurance fund or the
+/// liquidated position's collateral first. Neither touches other users' collateral.
+pub fn set_liquidation_fee_source(ctx: Context<UpdatePerpsMarket>, insurance_first: bool) -> Result<()> {
+    ctx.accounts.market.liquidation_fee_insurance_first = insurance_first;
     Ok(())
 }
 
@@ -279,7 +707,7 @@ pub struct DepositCollateral<'info> {
 }
 
 pub fn deposit_collateral(ctx: Context<DepositCollateral>, amount: u64) -> Result<()> {
-    require!(!ctx.accounts.global.paused, CustomError::PerpsPaused);
+    ctx.accounts.global.require_trading_active()?;
     require!(amount > 0, CustomError::InvalidAmount);
     token::transfer(
         CpiContext::new(
@@ -344,8 +772,13 @@ pub fn open_position(
     size_i64: i64,
     leverage_u16: u16,
     order_type: OrderType,
+    margin_mode: MarginMode,
+    reduce_only: bool,
 ) -> Result<()> {
-    require!(!ctx.accounts.global.paused, CustomError::PerpsPaused);
+    ctx.accounts.global.require_trading_active()?;
+    require!(ctx.accounts.market.active, CustomError::MarketInactive);
+    ctx.accounts.market.require_not_paused()?;
+    // Limit orders rest in their own account: see `place_perps_limit_order`.
     require!(order_type == OrderType::Market, CustomError::OrderTypeNotSupported);
     require!(size_i64 > 0, CustomError::InvalidAmount);
     require!(leverage_u16 > 0, CustomError::InvalidLeverage);
@@ -358,39 +791,91 @@ pub fn open_position(
         ctx.accounts.oracle_price_account.key() == ctx.accounts.market.oracle_price_account,
         CustomError::OraclePriceUnavailable
     );
-    let price = read_oracle_price(&ctx.accounts.oracle_price_account)?;
-
-    // ── Settle accumulated funding before trade ──
-    let position = &mut ctx.accounts.position;
-    let (settled_coll, settled_checkpoint, funding_delta) = settle_funding_inner(
-        position.base_position_i64,
-        position.collateral_u64,
-        position.last_funding_i128,
-        ctx.accounts.market.cumulative_funding_i128,
+    let price = read_oracle_price(&ctx.accounts.oracle_price_account, ctx.accounts.market.max_oracle_staleness_secs)?;
+
+    let accounts = &mut *ctx.accounts;
+    accounts.position.owner = accounts.owner.key();
+    accounts.position.market = accounts.market.key();
+    accounts.position.bump = ctx.bumps.position;
+    // The mode is fixed for the life of a position
+    if accounts.position.is_empty() {
+        accounts.position.margin_mode_u8 = margin_mode as u8;
+    } else {
+        require!(
+            accounts.position.margin_mode_u8 == margin_mode as u8,
+            CustomError::MarginModeMismatch
+        );
+    }
+    let old_base = accounts.position.base_position_i64;
+    let trade_base_delta = side.base_delta(size_i64)?;
+    if reduce_only {
+        accounts.position.validate_reduce_only(trade_base_delta)?;
+    }
+    apply_open_trade(
+        &mut accounts.market,
+        &mut accounts.user,
+        &mut accounts.position,
+        trade_base_delta,
+        leverage_u16,
+        price,
+        accounts.global.fee_bps,
     )?;
-    position.collateral_u64 = settled_coll;
-    position.last_funding_i128 = settled_checkpoint;
-    if funding_delta != 0 {
-        emit!(FundingSettled {
-            position: position.key(),
-            funding_delta,
-            new_collateral: settled_coll,
-        });
+
+    require_account_initial_margin(
+        ctx.remaining_accounts,
+        &accounts.user,
+        &accounts.market,
+        &accounts.position,
+        old_base,
+        price,
+    )
+}
+
+/// Growing cross exposure must be backed across every market the user
+/// trades, not just this one: once a trade has grown a cross position from
+/// `old_base`, the whole account (see `account_margin`) must still cover
+/// initial margin.
+fn require_account_initial_margin(
+    remaining_accounts: &[AccountInfo],
+    user: &PerpsUserAccount,
+    market: &Account<PerpsMarket>,
+    position: &PerpsPosition,
+    old_base: i64,
+    price: i64,
+) -> Result<()> {
+    if position.base_position_i64.unsigned_abs() > old_base.unsigned_abs()
+        && !position.is_isolated()
+        && user.positions_count_u8 > 1
+    {
+        let margin = account_margin(remaining_accounts, user, market, market.key(), Some((position, price)))?;
+        require!(
+            margin.covers_initial_margin(user.collateral_quote_u64),
+            CustomError::InsufficientMargin
+        );
     }
+    Ok(())
+}
 
-    // Compute signed base delta from side + size
-    let trade_base_delta: i64 = match side {
-        PositionSide::Long => size_i64,
-        PositionSide::Short => size_i64.checked_neg().ok_or(error!(CustomError::CalculationOverflow))?,
-    };
+/// Settle funding, push `trade_base_delta` through the position engine at
+/// `price`, charge the `fee_bps` trading fee on the traded notional, and move
+/// the resulting margin between the user's free collateral and the position.
+/// Callers validate the market, oracle and leverage and set the position's
+/// owner, market and bump first.
+fn apply_open_trade<'info>(
+    market: &mut Account<'info, PerpsMarket>,
+    user: &mut Account<'info, PerpsUserAccount>,
+    position: &mut Account<'info, PerpsPosition>,
+    trade_base_delta: i64,
+    leverage_u16: u16,
+    price: i64,
+    fee_bps: u16,
+) -> Result<()> {
+    market.sample_mark_price(price, Clock::get()?.unix_timestamp)?;
+    // ── Settle accumulated funding before trade ──
+    settle_position_funding(market, position)?;
 
     // Build current position state snapshot
-    let current_state = PositionState {
-        base_position: position.base_position_i64,
-        entry_price: position.entry_price_i64,
-        realized_pnl: position.realized_pnl_i128,
-        last_cum_funding: position.last_funding_i128,
-    };
+    let current_state = position.engine_state();
 
     // Apply the trade via the position engine (pure function)
     let result = perps_math::apply_trade_to_position(&current_state, trade_base_delta, price)?;
@@ -414,19 +899,25 @@ pub fn open_position(
         im_u64
     };
 
+    market.check_oi_imbalance(current_state.base_position, result.new_base_position)?;
+    let oi_delta = new_notional
+        .checked_sub(old_notional_val)
+        .ok_or(error!(CustomError::CalculationOverflow))?;
+    market.check_open_interest_cap(oi_delta)?;
+
     let old_collateral = position.collateral_u64;
     let additional_collateral = target_collateral.saturating_sub(old_collateral);
+    let fee = perps_math::trading_fee(notional_value(trade_base_delta, price)?, fee_bps)?;
 
-    let user = &mut ctx.accounts.user;
     require!(
-        user.collateral_quote_u64 >= additional_collateral,
+        user.collateral_quote_u64 >= additional_collateral.saturating_add(fee),
         CustomError::InsufficientCollateral
     );
 
     // ── Risk guard: IM check when increasing position ──
     if new_notional > old_notional_val && result.new_base_position != 0 {
         let equity_after = compute_equity(
-            target_collateral, 0,
+            perps_math::haircut_collateral(target_collateral, market.collateral_haircut_bps)?, 0,
             result.new_base_position, result.new_entry_price,
             price, 0,
         )?;
@@ -440,6 +931,7 @@ pub fn open_position(
         .collateral_quote_u64
         .checked_sub(additional_collateral)
         .ok_or(error!(CustomError::CalculationOverflow))?;
+    collect_trading_fee(market, user, fee);
 
     // If a partial close occurred (pnl_delta != 0), credit realized PnL to user
     if result.pnl_delta > 0 {
@@ -454,26 +946,23 @@ pub fn open_position(
 
     // Determine if this is a brand new position (prevously empty)
     let was_empty = current_state.base_position == 0;
+    if was_empty {
+        // Exit triggers belong to the previous position
+        position.clear_triggers();
+    }
 
     // Update on-chain position fields
-    position.owner = ctx.accounts.owner.key();
-    position.market = ctx.accounts.market.key();
     position.base_position_i64 = result.new_base_position;
     position.entry_price_i64 = result.new_entry_price;
     position.realized_pnl_i128 = result.new_realized_pnl;
     position.side = position.derived_side();
     position.collateral_u64 = target_collateral;
     position.leverage_u16 = leverage_u16;
-    position.last_funding_i128 = ctx.accounts.market.cumulative_funding_i128;
-    position.bump = ctx.bumps.position;
+    position.last_funding_i128 = market.cumulative_funding_i128;
 
     // Update open interest — add new notional, subtract old
-    let oi_delta = new_notional
-        .checked_sub(old_notional_val)
-        .ok_or(error!(CustomError::CalculationOverflow))?;
-    ctx.accounts.market.open_interest_i128 = ctx
-        .accounts
-        .market
+    market.apply_position_oi(current_state.base_position, result.new_base_position);
+    market.open_interest_i128 = market
         .open_interest_i128
         .checked_add(oi_delta)
         .ok_or(error!(CustomError::CalculationOverflow))?;
@@ -490,15 +979,84 @@ pub fn open_position(
         user.positions_count_u8 = user.positions_count_u8.saturating_sub(1);
     }
 
+    emit!(PerpsTradeExecuted {
+        owner: position.owner,
+        market: market.key(),
+        position: position.key(),
+        base_delta_i64: trade_base_delta,
+        price_i64: price,
+        fee_u64: fee,
+    });
+    emit!(position_opened_event(position.key(), position, market.key(), trade_base_delta, price, &result));
     Ok(())
 }
 
+/// `PositionOpened` for a trade that produced `result`, read once the result
+/// has been written back to `position`.
+fn position_opened_event(
+    position_key: Pubkey,
+    position: &PerpsPosition,
+    market: Pubkey,
+    trade_base_delta: i64,
+    price: i64,
+    result: &perps_math::TradeResult,
+) -> PositionOpened {
+    PositionOpened {
+        owner: position.owner,
+        market,
+        position: position_key,
+        side: if trade_base_delta >= 0 { PositionSide::Long as u8 } else { PositionSide::Short as u8 },
+        size_delta_i64: trade_base_delta,
+        price_i64: price,
+        base_position_i64: result.new_base_position,
+        entry_price_i64: result.new_entry_price,
+        pnl_delta_i128: result.pnl_delta,
+        collateral_u64: position.collateral_u64,
+        leverage_u16: position.leverage_u16,
+    }
+}
+
+/// `PositionClosed` for a close that produced `result`, read once the result
+/// has been written back to `position` (or the position reset).
+fn position_closed_event(
+    position_key: Pubkey,
+    position: &PerpsPosition,
+    market: Pubkey,
+    close_delta: i64,
+    price: i64,
+    result: &perps_math::TradeResult,
+) -> PositionClosed {
+    PositionClosed {
+        owner: position.owner,
+        market,
+        position: position_key,
+        side: if close_delta < 0 { PositionSide::Long as u8 } else { PositionSide::Short as u8 },
+        size_delta_i64: close_delta,
+        price_i64: price,
+        base_position_i64: result.new_base_position,
+        entry_price_i64: result.new_entry_price,
+        pnl_delta_i128: result.pnl_delta,
+        collateral_u64: position.collateral_u64,
+        leverage_u16: position.leverage_u16,
+    }
+}
+
+/// Move a trading fee out of the user's free collateral; the tokens stay in
+/// the collateral vault and are tallied on `market.trading_fees_u64`.
+/// Takes at most what the user has.  Returns the amount charged.
+fn collect_trading_fee(market: &mut PerpsMarket, user: &mut PerpsUserAccount, fee: u64) -> u64 {
+    let charged = fee.min(user.collateral_quote_u64);
+    user.collateral_quote_u64 -= charged;
+    market.trading_fees_u64 = market.trading_fees_u64.saturating_add(charged);
+    charged
+}
+
 #[derive(Accounts)]
-pub struct ClosePosition<'info> {
+#[instruction(side: PositionSide, size_i64: i64, limit_price_i64: i64, leverage_u16: u16, expiry_ts: i64, order_id: u64)]
+pub struct PlacePerpsLimitOrder<'info> {
     #[account(mut)]
     pub owner: Signer<'info>,
     #[account(
-        mut,
         seeds = [b"perps_global"],
         bump = global.bump
     )]
@@ -511,63 +1069,319 @@ pub struct ClosePosition<'info> {
     )]
     pub user: Account<'info, PerpsUserAccount>,
     #[account(
-        mut,
         seeds = [b"perps_market", market.base_mint.as_ref(), market.quote_mint.as_ref()],
         bump = market.bump
     )]
     pub market: Account<'info, PerpsMarket>,
-    /// CHECK: validated in handler
-    pub oracle_price_account: AccountInfo<'info>,
+    /// Created up front so the keeper never has to pay for it at fill time.
     #[account(
-        mut,
+        init_if_needed,
+        payer = owner,
+        space = PerpsPosition::LEN,
         seeds = [b"perps_position", owner.key().as_ref(), market.key().as_ref()],
-        bump = position.bump,
-        constraint = position.owner == owner.key(),
-        constraint = position.market == market.key()
+        bump,
+        constraint = position.owner == Pubkey::default() || position.owner == owner.key(),
+        constraint = position.market == Pubkey::default() || position.market == market.key()
     )]
     pub position: Account<'info, PerpsPosition>,
+    #[account(
+        init,
+        payer = owner,
+        space = PerpsLimitOrder::LEN,
+        seeds = [b"perps_limit_order", owner.key().as_ref(), market.key().as_ref(), &order_id.to_le_bytes()],
+        bump
+    )]
+    pub order: Account<'info, PerpsLimitOrder>,
+    pub system_program: Program<'info, System>,
 }
 
-pub fn close_position(ctx: Context<ClosePosition>, amount_base: u64) -> Result<()> {
-    require!(!ctx.accounts.global.paused, CustomError::PerpsPaused);
-    let position = &mut ctx.accounts.position;
-    require!(position.base_position_i64 != 0, CustomError::NoOpenPosition);
+/// Rest a limit order on a perps market.
+///
+/// Initial margin for `size_i64` at `limit_price_i64` is reserved out of the
+/// user's free collateral until the order is filled or cancelled.  A
+/// `reduce_only` order is checked against the position when it fills.
+#[allow(clippy::too_many_arguments)]
+pub fn place_perps_limit_order(
+    ctx: Context<PlacePerpsLimitOrder>,
+    side: PositionSide,
+    size_i64: i64,
+    limit_price_i64: i64,
+    leverage_u16: u16,
+    expiry_ts: i64,
+    order_id: u64,
+    reduce_only: bool,
+) -> Result<()> {
+    ctx.accounts.global.require_trading_active()?;
+    require!(ctx.accounts.market.active, CustomError::MarketInactive);
+    ctx.accounts.market.require_not_paused()?;
+    require!(size_i64 > 0, CustomError::InvalidAmount);
+    require!(limit_price_i64 > 0, CustomError::InvalidAmount);
+    require!(leverage_u16 > 0, CustomError::InvalidLeverage);
+    require!(
+        leverage_u16 <= ctx.accounts.market.max_leverage,
+        CustomError::InvalidLeverage
+    );
+    require!(
+        expiry_ts > Clock::get()?.unix_timestamp,
+        CustomError::InvalidExpiryTime
+    );
 
-    // Validate amount_base does not exceed abs(base_position)
-    let abs_position = position.base_position_i64.unsigned_abs();
-    require!(amount_base > 0, CustomError::InvalidAmount);
-    require!(amount_base <= abs_position, CustomError::CloseAmountExceedsPosition);
+    let notional = notional_value(size_i64, limit_price_i64)?;
+    let reserved = u64::try_from(initial_margin(notional, leverage_u16)?)
+        .map_err(|_| error!(CustomError::CalculationOverflow))?;
 
+    let user = &mut ctx.accounts.user;
     require!(
-        ctx.accounts.oracle_price_account.key() == ctx.accounts.market.oracle_price_account,
-        CustomError::OraclePriceUnavailable
+        user.collateral_quote_u64 >= reserved,
+        CustomError::InsufficientCollateral
     );
-    let price = read_oracle_price(&ctx.accounts.oracle_price_account)?;
+    user.collateral_quote_u64 -= reserved;
 
-    // ── Settle accumulated funding before close ──
-    let (settled_coll, settled_checkpoint, funding_delta) = settle_funding_inner(
-        position.base_position_i64,
-        position.collateral_u64,
-        position.last_funding_i128,
-        ctx.accounts.market.cumulative_funding_i128,
-    )?;
-    position.collateral_u64 = settled_coll;
-    position.last_funding_i128 = settled_checkpoint;
-    if funding_delta != 0 {
-        emit!(FundingSettled {
-            position: position.key(),
-            funding_delta,
-            new_collateral: settled_coll,
-        });
+    let position = &mut ctx.accounts.position;
+    position.owner = ctx.accounts.owner.key();
+    position.market = ctx.accounts.market.key();
+    position.bump = ctx.bumps.position;
+
+    let order = &mut ctx.accounts.order;
+    order.owner = ctx.accounts.owner.key();
+    order.market = ctx.accounts.market.key();
+    order.side = side as u8;
+    order.size_i64 = size_i64;
+    order.limit_price_i64 = limit_price_i64;
+    order.leverage = leverage_u16;
+    order.expiry_ts = expiry_ts;
+    order.order_id = order_id;
+    order.reserved_collateral_u64 = reserved;
+    order.bump = ctx.bumps.order;
+    order.reduce_only = reduce_only;
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct CancelPerpsLimitOrder<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"perps_user", owner.key().as_ref()],
+        bump = user.bump,
+        constraint = user.owner == owner.key()
+    )]
+    pub user: Account<'info, PerpsUserAccount>,
+    #[account(
+        mut,
+        close = owner,
+        seeds = [b"perps_limit_order", owner.key().as_ref(), order.market.as_ref(), &order.order_id.to_le_bytes()],
+        bump = order.bump,
+        has_one = owner @ CustomError::UnauthorizedOrderOwner
+    )]
+    pub order: Account<'info, PerpsLimitOrder>,
+}
+
+/// Cancel a resting perps limit order and release its reserved collateral.
+/// Allowed while trading is paused.
+pub fn cancel_perps_limit_order(ctx: Context<CancelPerpsLimitOrder>) -> Result<()> {
+    let reserved = ctx.accounts.order.reserved_collateral_u64;
+    let user = &mut ctx.accounts.user;
+    user.collateral_quote_u64 = user
+        .collateral_quote_u64
+        .checked_add(reserved)
+        .ok_or(error!(CustomError::CalculationOverflow))?;
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct ExecutePerpsLimitOrder<'info> {
+    pub keeper: Signer<'info>,
+    /// CHECK: order owner, receives the order account's rent; checked via `has_one`.
+    #[account(mut)]
+    pub owner: AccountInfo<'info>,
+    #[account(
+        seeds = [b"perps_global"],
+        bump = global.bump
+    )]
+    pub global: Account<'info, PerpsGlobalState>,
+    #[account(
+        mut,
+        seeds = [b"perps_user", owner.key().as_ref()],
+        bump = user.bump,
+        constraint = user.owner == owner.key()
+    )]
+    pub user: Account<'info, PerpsUserAccount>,
+    #[account(
+        mut,
+        seeds = [b"perps_market", market.base_mint.as_ref(), market.quote_mint.as_ref()],
+        bump = market.bump
+    )]
+    pub market: Account<'info, PerpsMarket>,
+    /// CHECK: validated in handler
+    pub oracle_price_account: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [b"perps_position", owner.key().as_ref(), market.key().as_ref()],
+        bump = position.bump,
+        constraint = position.owner == owner.key(),
+        constraint = position.market == market.key()
+    )]
+    pub position: Account<'info, PerpsPosition>,
+    #[account(
+        mut,
+        close = owner,
+        seeds = [b"perps_limit_order", owner.key().as_ref(), market.key().as_ref(), &order.order_id.to_le_bytes()],
+        bump = order.bump,
+        has_one = owner @ CustomError::UnauthorizedOrderOwner
+    )]
+    pub order: Account<'info, PerpsLimitOrder>,
+}
+
+/// Fill a resting perps limit order at the oracle price once it crosses the
+/// limit.  Permissionless; the reservation is returned to the user and the
+/// trade is margined exactly as a market `open_position` would be, the
+/// owner's other open positions passed in `remaining_accounts` likewise.
+pub fn execute_perps_limit_order(ctx: Context<ExecutePerpsLimitOrder>) -> Result<()> {
+    ctx.accounts.global.require_trading_active()?;
+    require!(ctx.accounts.market.active, CustomError::MarketInactive);
+    ctx.accounts.market.require_not_paused()?;
+    require!(
+        !ctx.accounts.order.is_expired(Clock::get()?.unix_timestamp),
+        CustomError::OrderExpired
+    );
+    require!(
+        ctx.accounts.oracle_price_account.key() == ctx.accounts.market.oracle_price_account,
+        CustomError::OraclePriceUnavailable
+    );
+    let price = read_oracle_price(&ctx.accounts.oracle_price_account, ctx.accounts.market.max_oracle_staleness_secs)?;
+    require!(
+        ctx.accounts.order.price_condition_met(price),
+        CustomError::PriceConditionNotMet
+    );
+
+    let accounts = &mut *ctx.accounts;
+    let order = &accounts.order;
+    accounts.user.collateral_quote_u64 = accounts
+        .user
+        .collateral_quote_u64
+        .checked_add(order.reserved_collateral_u64)
+        .ok_or(error!(CustomError::CalculationOverflow))?;
+    let side = if order.is_buy() { PositionSide::Long } else { PositionSide::Short };
+    let trade_base_delta = side.base_delta(order.size_i64)?;
+    // The position may have changed since the order was placed
+    if order.reduce_only {
+        accounts.position.validate_reduce_only(trade_base_delta)?;
     }
+    let old_base = accounts.position.base_position_i64;
+    apply_open_trade(
+        &mut accounts.market,
+        &mut accounts.user,
+        &mut accounts.position,
+        trade_base_delta,
+        order.leverage,
+        price,
+        accounts.global.fee_bps,
+    )?;
+    require_account_initial_margin(
+        ctx.remaining_accounts,
+        &accounts.user,
+        &accounts.market,
+        &accounts.position,
+        old_base,
+        price,
+    )
+}
+
+#[derive(Accounts)]
+pub struct ClosePosition<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"perps_global"],
+        bump = global.bump
+    )]
+    pub global: Account<'info, PerpsGlobalState>,
+    #[account(
+        mut,
+        seeds = [b"perps_user", owner.key().as_ref()],
+        bump = user.bump,
+        constraint = user.owner == owner.key()
+    )]
+    pub user: Account<'info, PerpsUserAccount>,
+    #[account(
+        mut,
+        seeds = [b"perps_market", market.base_mint.as_ref(), market.quote_mint.as_ref()],
+        bump = market.bump
+    )]
+    pub market: Account<'info, PerpsMarket>,
+    /// CHECK: validated in handler
+    pub oracle_price_account: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [b"perps_position", owner.key().as_ref(), market.key().as_ref()],
+        bump = position.bump,
+        constraint = position.owner == owner.key(),
+        constraint = position.market == market.key()
+    )]
+    pub position: Account<'info, PerpsPosition>,
+}
+
+pub fn close_position(ctx: Context<ClosePosition>, amount_base: u64) -> Result<()> {
+    // Reducing risk stays open through a trading pause
+    let position = &ctx.accounts.position;
+    require!(position.base_position_i64 != 0, CustomError::NoOpenPosition);
+
+    // Validate amount_base does not exceed abs(base_position)
+    require!(amount_base > 0, CustomError::InvalidAmount);
+    require!(
+        amount_base <= position.base_position_i64.unsigned_abs(),
+        CustomError::CloseAmountExceedsPosition
+    );
+
+    require!(
+        ctx.accounts.oracle_price_account.key() == ctx.accounts.market.oracle_price_account,
+        CustomError::OraclePriceUnavailable
+    );
+    let price = read_oracle_price(&ctx.accounts.oracle_price_account, ctx.accounts.market.max_oracle_staleness_secs)?;
+
+    let accounts = &mut *ctx.accounts;
+    apply_close_trade(
+        &mut accounts.market,
+        &mut accounts.user,
+        &mut accounts.position,
+        amount_base,
+        price,
+        accounts.global.fee_bps,
+    )?;
+    Ok(())
+}
+
+/// `close_position` taking the base amount to reduce as a positive `i64`; the
+/// direction always comes from the position.  Anything short of the full size
+/// leaves the entry price and `positions_count_u8` untouched.
+pub fn close_position_partial(ctx: Context<ClosePosition>, close_size_i64: i64) -> Result<()> {
+    require!(close_size_i64 > 0, CustomError::InvalidAmount);
+    close_position(ctx, close_size_i64 as u64)
+}
+
+/// Settle funding and close `amount_base` of `position` at `price`, crediting
+/// released collateral plus realized PnL, less the `fee_bps` trading fee on the
+/// closed notional, to the user's free collateral.  Returns the net amount
+/// credited.  Callers validate the oracle and the size.
+fn apply_close_trade<'info>(
+    market: &mut Account<'info, PerpsMarket>,
+    user: &mut Account<'info, PerpsUserAccount>,
+    position: &mut Account<'info, PerpsPosition>,
+    amount_base: u64,
+    price: i64,
+    fee_bps: u16,
+) -> Result<u64> {
+    let abs_position = position.base_position_i64.unsigned_abs();
+
+    market.sample_mark_price(price, Clock::get()?.unix_timestamp)?;
+    // ── Settle accumulated funding before close ──
+    settle_position_funding(market, position)?;
 
     // Build position state snapshot
-    let current_state = PositionState {
-        base_position: position.base_position_i64,
-        entry_price: position.entry_price_i64,
-        realized_pnl: position.realized_pnl_i128,
-        last_cum_funding: position.last_funding_i128,
-    };
+    let current_state = position.engine_state();
 
     // Compute close delta: opposite direction of the position
     // For a long (positive base), close_delta is negative; for a short, positive.
@@ -581,55 +1395,31 @@ pub fn close_position(ctx: Context<ClosePosition>, amount_base: u64) -> Result<(
 
     let is_full_close = result.new_base_position == 0;
 
-    // Determine collateral to release proportionally
-    // For a full close: release all collateral + pnl
-    // For partial: release (amount_base / abs_position) * collateral + pnl
-    let collateral_release: u64 = if is_full_close {
-        position.collateral_u64
-    } else {
-        // Proportional: collateral * amount_base / abs_position
-        (position.collateral_u64 as u128)
-            .checked_mul(amount_base as u128)
-            .ok_or(error!(CustomError::CalculationOverflow))?
-            .checked_div(abs_position as u128)
-            .ok_or(error!(CustomError::CalculationOverflow))? as u64
-    };
-
-    // Total return = released collateral + pnl_delta (clamped to 0 min)
-    let mut return_i128 = i128::from(collateral_release);
-    return_i128 = return_i128
-        .checked_add(result.pnl_delta)
-        .ok_or(error!(CustomError::CalculationOverflow))?;
-    if return_i128 < 0 {
-        return_i128 = 0;
-    }
-    let collateral_return = u64::try_from(return_i128)
-        .map_err(|_| error!(CustomError::CalculationOverflow))?;
+    // Release collateral proportionally; return it plus realized PnL (floored at 0)
+    let (collateral_release, collateral_return) = perps_math::close_collateral_return(
+        position.collateral_u64,
+        amount_base,
+        abs_position,
+        result.pnl_delta,
+    )?;
 
-    let user = &mut ctx.accounts.user;
     user.collateral_quote_u64 = user
         .collateral_quote_u64
         .checked_add(collateral_return)
         .ok_or(error!(CustomError::CalculationOverflow))?;
 
     // Update open interest: subtract closed notional
+    market.apply_position_oi(current_state.base_position, result.new_base_position);
     let closed_notional = notional_value(close_delta, price)?;
-    ctx.accounts.market.open_interest_i128 = ctx
-        .accounts
-        .market
+    let fee = collect_trading_fee(market, user, perps_math::trading_fee(closed_notional, fee_bps)?);
+    market.open_interest_i128 = market
         .open_interest_i128
         .checked_sub(closed_notional)
         .ok_or(error!(CustomError::CalculationOverflow))?;
 
     if is_full_close {
-        // Reset position fields (full close)
-        position.base_position_i64 = 0;
-        position.entry_price_i64 = 0;
-        position.collateral_u64 = 0;
-        position.leverage_u16 = 0;
-        position.last_funding_i128 = 0;
-        position.realized_pnl_i128 = result.new_realized_pnl;
-        position.side = 0;
+        // Realized PnL was just paid into collateral; the next open starts fresh
+        position.reset();
 
         user.positions_count_u8 = user
             .positions_count_u8
@@ -645,6 +1435,153 @@ pub fn close_position(ctx: Context<ClosePosition>, amount_base: u64) -> Result<(
         position.side = position.derived_side();
     }
 
+    emit!(PerpsTradeExecuted {
+        owner: position.owner,
+        market: market.key(),
+        position: position.key(),
+        base_delta_i64: close_delta,
+        price_i64: price,
+        fee_u64: fee,
+    });
+    emit!(position_closed_event(position.key(), position, market.key(), close_delta, price, &result));
+    Ok(collateral_return.saturating_sub(fee))
+}
+
+#[derive(Accounts)]
+pub struct SetPositionTriggers<'info> {
+    pub owner: Signer<'info>,
+    #[account(
+        seeds = [b"perps_market", market.base_mint.as_ref(), market.quote_mint.as_ref()],
+        bump = market.bump
+    )]
+    pub market: Account<'info, PerpsMarket>,
+    #[account(
+        mut,
+        seeds = [b"perps_position", owner.key().as_ref(), market.key().as_ref()],
+        bump = position.bump,
+        constraint = position.owner == owner.key(),
+        constraint = position.market == market.key()
+    )]
+    pub position: Account<'info, PerpsPosition>,
+}
+
+/// Register take-profit / stop-loss prices on an open position (0 clears one).
+/// Triggers are dropped when the position is fully closed.
+pub fn set_position_triggers(
+    ctx: Context<SetPositionTriggers>,
+    take_profit_price_i64: i64,
+    stop_loss_price_i64: i64,
+) -> Result<()> {
+    let position = &mut ctx.accounts.position;
+    position.validate_triggers(take_profit_price_i64, stop_loss_price_i64)?;
+    position.take_profit_price_i64 = take_profit_price_i64;
+    position.stop_loss_price_i64 = stop_loss_price_i64;
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct ExecutePositionTrigger<'info> {
+    /// Anyone can execute a trigger that has been met (permissionless).
+    pub keeper: Signer<'info>,
+    #[account(
+        seeds = [b"perps_global"],
+        bump = global.bump
+    )]
+    pub global: Account<'info, PerpsGlobalState>,
+    #[account(
+        mut,
+        seeds = [b"perps_user", position_owner.key().as_ref()],
+        bump = user.bump,
+        constraint = user.owner == position_owner.key()
+    )]
+    pub user: Account<'info, PerpsUserAccount>,
+    /// CHECK: we only read the key — validated via seeds on `user` and `position`.
+    pub position_owner: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [b"perps_market", market.base_mint.as_ref(), market.quote_mint.as_ref()],
+        bump = market.bump
+    )]
+    pub market: Account<'info, PerpsMarket>,
+    /// CHECK: validated against market.oracle_price_account
+    pub oracle_price_account: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [b"perps_position", position_owner.key().as_ref(), market.key().as_ref()],
+        bump = position.bump,
+        constraint = position.owner == position_owner.key(),
+        constraint = position.market == market.key()
+    )]
+    pub position: Account<'info, PerpsPosition>,
+    /// Collateral vault of the market (to pay the keeper fee).
+    #[account(mut, address = market.collateral_vault)]
+    pub collateral_vault: Account<'info, TokenAccount>,
+    /// Keeper's quote-token ATA to receive the keeper fee.
+    #[account(mut, constraint = keeper_ata.mint == market.quote_mint)]
+    pub keeper_ata: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+/// Close a whole position whose take-profit or stop-loss has been reached,
+/// through the same path as `close_position`.  The keeper is paid
+/// `TRIGGER_KEEPER_FEE_BPS` of the closed notional out of the returned collateral.
+pub fn execute_position_trigger(ctx: Context<ExecutePositionTrigger>) -> Result<()> {
+    require!(
+        ctx.accounts.oracle_price_account.key() == ctx.accounts.market.oracle_price_account,
+        CustomError::OraclePriceUnavailable
+    );
+    let price = read_oracle_price(&ctx.accounts.oracle_price_account, ctx.accounts.market.max_oracle_staleness_secs)?;
+    let trigger = ctx
+        .accounts
+        .position
+        .triggered_exit(price)
+        .ok_or(error!(CustomError::TriggerNotMet))?;
+
+    let size_closed = ctx.accounts.position.base_position_i64;
+    let accounts = &mut *ctx.accounts;
+    let collateral_return = apply_close_trade(
+        &mut accounts.market,
+        &mut accounts.user,
+        &mut accounts.position,
+        size_closed.unsigned_abs(),
+        price,
+        accounts.global.fee_bps,
+    )?;
+
+    let keeper_fee = perps_math::trading_fee(notional_value(size_closed, price)?, TRIGGER_KEEPER_FEE_BPS)?
+        .min(collateral_return);
+    if keeper_fee > 0 {
+        accounts.user.collateral_quote_u64 -= keeper_fee;
+        let market_seeds: &[&[&[u8]]] = &[&[
+            b"perps_market",
+            accounts.market.base_mint.as_ref(),
+            accounts.market.quote_mint.as_ref(),
+            &[accounts.market.bump],
+        ]];
+        token::transfer(
+            CpiContext::new_with_signer(
+                accounts.token_program.to_account_info(),
+                Transfer {
+                    from: accounts.collateral_vault.to_account_info(),
+                    to: accounts.keeper_ata.to_account_info(),
+                    authority: accounts.market.to_account_info(),
+                },
+                market_seeds,
+            ),
+            keeper_fee,
+        )?;
+    }
+
+    emit!(TriggerExecuted {
+        position: accounts.position.key(),
+        owner: accounts.position_owner.key(),
+        keeper: accounts.keeper.key(),
+        market: accounts.market.key(),
+        trigger: trigger as u8,
+        price_i64: price,
+        size_closed_i64: size_closed,
+        keeper_fee_u64: keeper_fee,
+    });
     Ok(())
 }
 
@@ -676,21 +1613,85 @@ pub struct WithdrawCollateral<'info> {
     #[account(mut, address = market.collateral_vault, constraint = collateral_vault.mint == market.quote_mint)]
     pub collateral_vault: Account<'info, TokenAccount>,
     pub token_program: Program<'info, Token>,
+    /// CHECK: validated against market.oracle_price_account in handler.
+    /// Required, with `position`, while the user has open positions.
+    pub oracle_price_account: Option<AccountInfo<'info>>,
+    #[account(
+        mut,
+        seeds = [b"perps_position", owner.key().as_ref(), market.key().as_ref()],
+        bump = position.bump,
+        constraint = position.owner == owner.key(),
+        constraint = position.market == market.key()
+    )]
+    pub position: Option<Account<'info, PerpsPosition>>,
 }
 
+/// Withdraw quote collateral.  With no open positions only free collateral
+/// can leave.  Otherwise withdrawals are limited to `free + equity −
+/// initial_margin` summed over every cross-margin position, at oracle
+/// prices: open positions in other markets are passed as `remaining_accounts`
+/// (see `account_margin`).  Anything above the free balance is drawn from
+/// this market's position collateral; an isolated position gives up only its
+/// own margin above IM.
 pub fn withdraw_collateral(ctx: Context<WithdrawCollateral>, amount: u64) -> Result<()> {
-    require!(!ctx.accounts.global.paused, CustomError::PerpsPaused);
-    let user = &mut ctx.accounts.user;
-    require!(user.collateral_quote_u64 >= amount, CustomError::InsufficientCollateral);
-    require!(
-        user.positions_count_u8 == 0,
-        CustomError::MaintenanceMarginViolation
-    );
+    ctx.accounts.global.require_trading_active()?;
+    let accounts = &mut *ctx.accounts;
+    let user = &mut accounts.user;
 
-    user.collateral_quote_u64 = user
-        .collateral_quote_u64
-        .checked_sub(amount)
-        .ok_or(error!(CustomError::CalculationOverflow))?;
+    if user.positions_count_u8 == 0 {
+        require!(user.collateral_quote_u64 >= amount, CustomError::InsufficientCollateral);
+        user.collateral_quote_u64 -= amount;
+    } else {
+        let mut local_price = None;
+        if let Some(position) = accounts.position.as_mut().filter(|p| !p.is_empty()) {
+            let Some(oracle) = accounts.oracle_price_account.as_ref() else {
+                return err!(CustomError::InsufficientMargin);
+            };
+            require!(
+                oracle.key() == accounts.market.oracle_price_account,
+                CustomError::OraclePriceUnavailable
+            );
+            let price = read_oracle_price(oracle, accounts.market.max_oracle_staleness_secs)?;
+            settle_position_funding(&mut accounts.market, position)?;
+            local_price = Some(price);
+        }
+        let margin = account_margin(
+            ctx.remaining_accounts,
+            user,
+            &accounts.market,
+            accounts.market.key(),
+            local_price.and_then(|price| accounts.position.as_deref().map(|p| (p, price))),
+        )?;
+        let isolated = match (local_price, accounts.position.as_ref()) {
+            (Some(price), Some(position)) if position.is_isolated() => Some((position, price)),
+            _ => None,
+        };
+        // Total cap, and the part of it that may come out of free collateral
+        let (limit, free_share) = match isolated {
+            Some((position, price)) => {
+                let mut valued = (**position).clone();
+                valued.collateral_u64 = perps_math::haircut_collateral(
+                    valued.collateral_u64,
+                    accounts.market.collateral_haircut_bps,
+                )?;
+                let free_share = margin.withdrawable(user.collateral_quote_u64)?.min(user.collateral_quote_u64);
+                let own_excess = perps_math::position_margin(&valued, price)?.withdrawable(0)?;
+                (free_share.saturating_add(own_excess), free_share)
+            }
+            None => (margin.withdrawable(user.collateral_quote_u64)?, user.collateral_quote_u64),
+        };
+        require!(amount <= limit, CustomError::InsufficientMargin);
+
+        let from_position = amount.saturating_sub(free_share);
+        if from_position > 0 {
+            let position = accounts.position.as_mut().filter(|_| local_price.is_some());
+            let Some(position) = position.filter(|p| from_position <= p.collateral_u64) else {
+                return err!(CustomError::InsufficientMargin);
+            };
+            position.collateral_u64 -= from_position;
+        }
+        user.collateral_quote_u64 -= amount - from_position;
+    }
 
     let seeds: &[&[&[u8]]] = &[&[
         b"perps_market",
@@ -719,6 +1720,8 @@ pub fn withdraw_collateral(ctx: Context<WithdrawCollateral>, amount: u64) -> Res
 
 #[derive(Accounts)]
 pub struct UpdateFunding<'info> {
+    /// Anyone can crank funding (permissionless); paid `funding_keeper_reward_u64`.
+    pub keeper: Signer<'info>,
     #[account(
         seeds = [b"perps_global"],
         bump = global.bump
@@ -732,17 +1735,28 @@ pub struct UpdateFunding<'info> {
     pub market: Account<'info, PerpsMarket>,
     /// CHECK: validated against market.oracle_price_account in handler
     pub oracle_price_account: AccountInfo<'info>,
-}
-
+    /// Collateral vault of the market (holds the trading fees the reward is paid from).
+    #[account(mut, address = market.collateral_vault)]
+    pub collateral_vault: Account<'info, TokenAccount>,
+    /// Keeper's quote-token ATA to receive the reward.
+    #[account(mut, constraint = keeper_ata.mint == market.quote_mint)]
+    pub keeper_ata: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
 /// Update the cumulative funding index for a market.
 ///
-/// `mark_price_i64` — the current perpetual mark price (PRICE_SCALE).
+/// The mark is the market's TWAP of the oracle (see
+/// `PerpsMarket::liquidation_mark_price`), falling back to the index while
+/// the TWAP is off or too short, where only the open-interest skew is paid.
 ///
 /// premium = (mark − index) / index   (scaled by PRICE_SCALE)
-/// funding_rate = clamp(premium, ±max_funding_rate)
-/// cum_funding += index_price × funding_rate / PRICE_SCALE
-pub fn update_funding(ctx: Context<UpdateFunding>, mark_price_i64: i64) -> Result<()> {
-    require!(!ctx.accounts.global.paused, CustomError::PerpsPaused);
+/// skew = funding_skew_bps / 10_000 × (long_oi − short_oi) / (long_oi + short_oi)
+/// funding_rate = clamp(premium + skew, ±max_funding_rate)
+/// cum_funding += index_price × funding_rate / PRICE_SCALE   (atomic quote per whole base unit)
+pub fn update_funding(ctx: Context<UpdateFunding>) -> Result<()> {
+    ctx.accounts.global.require_trading_active()?;
+    ctx.accounts.market.require_not_paused()?;
 
     let market = &mut ctx.accounts.market;
 
@@ -758,33 +1772,31 @@ pub fn update_funding(ctx: Context<UpdateFunding>, mark_price_i64: i64) -> Resul
     require!(elapsed >= market.funding_interval_secs, CustomError::FundingTooSoon);
 
     // Index price from oracle
-    let index_price = read_oracle_price(&ctx.accounts.oracle_price_account)?;
+    let index_price = read_oracle_price(&ctx.accounts.oracle_price_account, market.max_oracle_staleness_secs)?;
     require!(index_price > 0, CustomError::OraclePriceUnavailable);
 
-    let mark = mark_price_i64 as i128;
-    let index = index_price as i128;
-    let price_scale = PRICE_SCALE;
-
-    // premium = (mark - index) * PRICE_SCALE / index
-    let premium = (mark - index)
-        .checked_mul(price_scale)
-        .ok_or(error!(CustomError::CalculationOverflow))?
-        / index;
-
-    // Clamp by ±max_funding_rate
-    let max_rate = market.max_funding_rate_i64 as i128;
-    let clamped_rate = premium.max(-max_rate).min(max_rate);
+    // Mark from the samples so far, then add the index to them
+    let mark_price = market.liquidation_mark_price(index_price, now);
+    market.sample_mark_price(index_price, now)?;
+
+    // Premium plus open-interest skew, clamped by ±max_funding_rate
+    let clamped_rate = perps_math::funding_rate(
+        mark_price,
+        index_price,
+        market.long_open_interest_u64,
+        market.short_open_interest_u64,
+        market.funding_skew_bps,
+        market.max_funding_rate_i64,
+    )?;
 
     // Store the current period funding rate
     market.funding_rate_i64 = i64::try_from(clamped_rate)
         .map_err(|_| error!(CustomError::CalculationOverflow))?;
 
     // cum_funding += index_price * clamped_rate / PRICE_SCALE
-    // This gives atomic-quote-per-base-unit increment.
-    let funding_increment = index
-        .checked_mul(clamped_rate)
-        .ok_or(error!(CustomError::CalculationOverflow))?
-        / price_scale;
+    // This gives an atomic-quote-per-whole-base-unit increment; settlement
+    // divides by BASE_SCALE to convert a base-atom position into quote.
+    let funding_increment = perps_math::funding_increment(index_price, clamped_rate)?;
 
     market.cumulative_funding_i128 = market
         .cumulative_funding_i128
@@ -793,11 +1805,36 @@ pub fn update_funding(ctx: Context<UpdateFunding>, mark_price_i64: i64) -> Resul
 
     market.last_funding_ts = now;
 
+    // Only reached once the interval has elapsed, so each period pays at most once
+    let keeper_reward = market.take_funding_keeper_reward();
+    if keeper_reward > 0 {
+        let market_seeds: &[&[&[u8]]] = &[&[
+            b"perps_market",
+            market.base_mint.as_ref(),
+            market.quote_mint.as_ref(),
+            &[market.bump],
+        ]];
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.collateral_vault.to_account_info(),
+                    to: ctx.accounts.keeper_ata.to_account_info(),
+                    authority: market.to_account_info(),
+                },
+                market_seeds,
+            ),
+            keeper_reward,
+        )?;
+    }
+
     emit!(FundingUpdated {
         market: market.key(),
         funding_rate: market.funding_rate_i64,
         cumulative_funding: market.cumulative_funding_i128,
         timestamp: now,
+        keeper: ctx.accounts.keeper.key(),
+        keeper_reward_u64: keeper_reward,
     });
 
     Ok(())
@@ -853,6 +1890,42 @@ pub fn initialize_insurance_vault(ctx: Context<InitializeInsuranceVault>) -> Res
     Ok(())
 }
 
+// ─────────────────────────────────────────────────────
+// Phase 4 — Market activation (requires seeded insurance)
+// ─────────────────────────────────────────────────────
+
+#[derive(Accounts)]
+pub struct ActivateMarket<'info> {
+    pub admin: Signer<'info>,
+    #[account(
+        seeds = [b"perps_global"],
+        bump = global.bump,
+        constraint = global.authority == admin.key() @ CustomError::UnauthorizedAdmin
+    )]
+    pub global: Account<'info, PerpsGlobalState>,
+    #[account(
+        mut,
+        seeds = [b"perps_market", market.base_mint.as_ref(), market.quote_mint.as_ref()],
+        bump = market.bump
+    )]
+    pub market: Account<'info, PerpsMarket>,
+    #[account(
+        seeds = [b"insurance_vault", market.key().as_ref()],
+        bump = insurance_vault.bump,
+        constraint = insurance_vault.market == market.key()
+    )]
+    pub insurance_vault: Account<'info, InsuranceVault>,
+}
+
+pub fn activate_market(ctx: Context<ActivateMarket>) -> Result<()> {
+    require!(
+        ctx.accounts.market.insurance_seed_met(ctx.accounts.insurance_vault.balance_u64),
+        CustomError::InsuranceSeedNotMet
+    );
+    ctx.accounts.market.active = true;
+    Ok(())
+}
+
 // ─────────────────────────────────────────────────────
 // Phase 4 — Deposit to insurance fund
 // ─────────────────────────────────────────────────────
@@ -906,6 +1979,72 @@ pub fn deposit_insurance(ctx: Context<DepositInsurance>, amount: u64) -> Result<
 // Phase 4 — Liquidation
 // ─────────────────────────────────────────────────────
 
+#[derive(Accounts)]
+pub struct WithdrawInsurance<'info> {
+    pub admin: Signer<'info>,
+    #[account(
+        seeds = [b"perps_global"],
+        bump = global.bump,
+        constraint = global.authority == admin.key() @ CustomError::UnauthorizedAdmin
+    )]
+    pub global: Account<'info, PerpsGlobalState>,
+    #[account(
+        seeds = [b"perps_market", market.base_mint.as_ref(), market.quote_mint.as_ref()],
+        bump = market.bump
+    )]
+    pub market: Account<'info, PerpsMarket>,
+    #[account(
+        mut,
+        seeds = [b"insurance_vault", market.key().as_ref()],
+        bump = insurance_vault.bump,
+        constraint = insurance_vault.market == market.key()
+    )]
+    pub insurance_vault: Account<'info, InsuranceVault>,
+    #[account(mut, address = insurance_vault.vault_ata)]
+    pub insurance_vault_ata: Account<'info, TokenAccount>,
+    #[account(mut, constraint = admin_ata.mint == market.quote_mint)]
+    pub admin_ata: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+/// Move accrued insurance back out, never below the market's insurance floor
+/// (at least its activation seed while it is live) and never during an emergency.
+pub fn withdraw_insurance(ctx: Context<WithdrawInsurance>, amount: u64) -> Result<()> {
+    require!(amount > 0, CustomError::InvalidAmount);
+    require!(!ctx.accounts.market.emergency, CustomError::MarketEmergency);
+    require!(
+        amount <= ctx.accounts.market.withdrawable_insurance(ctx.accounts.insurance_vault.balance_u64),
+        CustomError::InsuranceFloorBreached
+    );
+    let market_key = ctx.accounts.market.key();
+    let insurance_seeds: &[&[&[u8]]] = &[&[
+        b"insurance_vault",
+        market_key.as_ref(),
+        &[ctx.accounts.insurance_vault.bump],
+    ]];
+    token::transfer(
+        CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.insurance_vault_ata.to_account_info(),
+                to: ctx.accounts.admin_ata.to_account_info(),
+                authority: ctx.accounts.insurance_vault.to_account_info(),
+            },
+            insurance_seeds,
+        ),
+        amount,
+    )?;
+    let insurance_vault = &mut ctx.accounts.insurance_vault;
+    insurance_vault.balance_u64 -= amount;
+    emit!(InsuranceWithdrawn {
+        market: market_key,
+        admin: ctx.accounts.admin.key(),
+        amount_u64: amount,
+        remaining_balance_u64: insurance_vault.balance_u64,
+    });
+    Ok(())
+}
+
 #[derive(Accounts)]
 pub struct LiquidatePosition<'info> {
     /// The liquidator — anyone can call this (permissionless).
@@ -960,58 +2099,101 @@ pub struct LiquidatePosition<'info> {
     pub token_program: Program<'info, Token>,
 }
 
-pub fn liquidate_position(ctx: Context<LiquidatePosition>) -> Result<()> {
-    require!(!ctx.accounts.global.paused, CustomError::PerpsPaused);
-
-    // Prevent self-liquidation
-    require!(
-        ctx.accounts.liquidator.key() != ctx.accounts.position_owner.key(),
-        CustomError::SelfLiquidation
-    );
-
-    let position = &mut ctx.accounts.position;
-    require!(position.base_position_i64 != 0, CustomError::NoOpenPosition);
+/// Everything `liquidate_position` decides before it moves any tokens.
+struct LiquidationPlan {
+    settled: SettledFunding,
+    liq_fee_bps: u16,
+    actual_close: i64,
+    is_full_close: bool,
+    trade: perps_math::TradeResult,
+    shortfall_from_free: u64,
+    funding: perps_math::LiquidationFunding,
+    /// Negative equity neither free collateral nor insurance covers.
+    uncovered_bad_debt: u64,
+}
 
-    require!(
-        ctx.accounts.oracle_price_account.key() == ctx.accounts.market.oracle_price_account,
-        CustomError::OraclePriceUnavailable
-    );
-    let price = read_oracle_price(&ctx.accounts.oracle_price_account)?;
+impl LiquidationPlan {
+    fn preview(&self) -> LiquidationPreview {
+        LiquidationPreview {
+            liquidatable: true,
+            funding_delta_i128: self.settled.funding_delta,
+            size_closed_i64: self.actual_close,
+            full_close: self.is_full_close,
+            liquidator_fee_bps: self.liq_fee_bps,
+            liquidator_fee_u64: self.funding.fee_from_collateral + self.funding.fee_from_insurance,
+            liquidator_fee_from_insurance_u64: self.funding.fee_from_insurance,
+            insurance_penalty_u64: self.funding.penalty_from_collateral,
+            shortfall_from_free_u64: self.shortfall_from_free,
+            bad_debt_u64: self.funding.bad_debt,
+            uncovered_bad_debt_u64: self.uncovered_bad_debt,
+            emergency: self.funding.insurance_exhausted,
+            remaining_collateral_u64: self.funding.remaining_collateral,
+        }
+    }
+}
 
-    // ── Settle accumulated funding before liquidation check ──
-    let (settled_coll, settled_checkpoint, funding_delta) = settle_funding_inner(
+/// Plan liquidating `position` at the oracle's `price` and `conf`, without
+/// side effects: settle its funding, judge eligibility at the liquidation
+/// mark, size the close and source the fee, penalty and any bad debt.
+/// `None` when the position is not liquidatable.
+fn plan_liquidation(
+    market: &PerpsMarket,
+    position: &PerpsPosition,
+    free_collateral: u64,
+    insurance_balance: u64,
+    price: i64,
+    conf: u64,
+    now: i64,
+) -> Result<Option<LiquidationPlan>> {
+    // Widen MM while the oracle is uncertain (no-op when the linkage is disabled)
+    let effective_mm_bps = perps_math::confidence_adjusted_mm_bps(
+        market.maintenance_margin_bps,
+        price,
+        conf,
+        market.conf_mm_multiplier_bps,
+    )?;
+    let settled = settle_funding_inner(
         position.base_position_i64,
         position.collateral_u64,
+        position.unpaid_funding_u64,
         position.last_funding_i128,
-        ctx.accounts.market.cumulative_funding_i128,
+        market.cumulative_funding_i128,
+        market.funding_pool_u64,
     )?;
-    position.collateral_u64 = settled_coll;
-    position.last_funding_i128 = settled_checkpoint;
-    if funding_delta != 0 {
-        emit!(FundingSettled {
-            position: position.key(),
-            funding_delta,
-            new_collateral: settled_coll,
-        });
-    }
 
-    // ── STEP 1: Liquidation eligibility ──
+    // ── STEP 1: Liquidation eligibility (at the TWAP mark; PnL realizes at spot) ──
+    let mark_price = market.liquidation_mark_price(price, now);
+    let valued_collateral = perps_math::haircut_collateral(settled.collateral, market.collateral_haircut_bps)?;
     let liquidatable = perps_math::is_liquidatable(
-        position.collateral_u64,
+        valued_collateral,
         position.base_position_i64,
         position.entry_price_i64,
-        price,
-        ctx.accounts.market.maintenance_margin_bps,
+        mark_price,
+        effective_mm_bps,
+    )?;
+    if !liquidatable {
+        return Ok(None);
+    }
+    // Deeper positions are riskier to close, so they pay the liquidator more
+    let liq_fee_bps = perps_math::scaled_liquidation_fee_bps(
+        market.liquidation_fee_bps,
+        market.max_liquidation_fee_bps,
+        perps_math::position_equity(
+            valued_collateral,
+            position.base_position_i64,
+            position.entry_price_i64,
+            mark_price,
+        )?,
+        perps_math::maintenance_margin(notional_value(position.base_position_i64, mark_price)?, effective_mm_bps)?,
     )?;
-    require!(liquidatable, CustomError::NotLiquidatable);
 
     // ── STEP 2: Compute close size (partial or full) ──
     let close_size_abs = perps_math::compute_liquidation_close_size(
-        position.collateral_u64,
+        valued_collateral,
         position.base_position_i64,
         position.entry_price_i64,
-        price,
-        ctx.accounts.market.maintenance_margin_bps,
+        mark_price,
+        effective_mm_bps,
     )?;
     let abs_base = position.base_position_i64.unsigned_abs() as i64;
     let actual_close = close_size_abs.min(abs_base);
@@ -1023,84 +2205,147 @@ pub fn liquidate_position(ctx: Context<LiquidatePosition>) -> Result<()> {
     } else {
         actual_close
     };
-
-    // Apply the trade
-    let current_state = perps_math::PositionState {
-        base_position: position.base_position_i64,
-        entry_price: position.entry_price_i64,
-        realized_pnl: position.realized_pnl_i128,
-        last_cum_funding: position.last_funding_i128,
-    };
-    let result = perps_math::apply_trade_to_position(&current_state, close_delta, price)?;
+    let mut current_state = position.engine_state();
+    current_state.last_cum_funding = settled.checkpoint;
+    let trade = perps_math::apply_trade_to_position(&current_state, close_delta, price)?;
 
     // ── Compute fees ──
     let closed_notional = perps_math::notional_value(actual_close, price)?;
-    // liquidation_fee = closed_notional * fee_bps / 10_000 / PRICE_SCALE
-    // (notional is in PRICE_SCALE-squared units: base_units * price_units)
-    let liq_fee_scaled = closed_notional
-        .checked_mul(ctx.accounts.market.liquidation_fee_bps as i128)
-        .ok_or(error!(CustomError::CalculationOverflow))?
-        / 10_000i128;
-    let liq_fee = u64::try_from(
-        liq_fee_scaled / perps_math::PRICE_SCALE
-    ).unwrap_or(0);
-
-    let penalty_scaled = closed_notional
-        .checked_mul(ctx.accounts.market.liquidation_penalty_bps as i128)
-        .ok_or(error!(CustomError::CalculationOverflow))?
-        / 10_000i128;
-    let insurance_penalty = u64::try_from(
-        penalty_scaled / perps_math::PRICE_SCALE
-    ).unwrap_or(0);
-
-    // ── Compute equity after trade to determine bad debt ──
-    // Effective collateral after the partial close
-    let mut remaining_collateral_i128 = i128::from(position.collateral_u64);
-    remaining_collateral_i128 = remaining_collateral_i128
-        .checked_add(result.pnl_delta)
-        .ok_or(error!(CustomError::CalculationOverflow))?;
+    // Same rounding as a trading fee; on dust that can be 0, hence the floor
+    let liq_fee = perps_math::trading_fee(closed_notional, liq_fee_bps)?.max(market.min_liquidation_fee_u64);
+    let insurance_penalty = perps_math::trading_fee(closed_notional, market.liquidation_penalty_bps)?;
 
-    // Deduct fees from remaining collateral
-    remaining_collateral_i128 = remaining_collateral_i128
-        .checked_sub(liq_fee as i128)
-        .ok_or(error!(CustomError::CalculationOverflow))?;
-    remaining_collateral_i128 = remaining_collateral_i128
-        .checked_sub(insurance_penalty as i128)
+    // ── Cross margin: the owner's free collateral covers a shortfall before insurance ──
+    let collateral_after_pnl = i128::from(settled.collateral)
+        .checked_add(trade.pnl_delta)
         .ok_or(error!(CustomError::CalculationOverflow))?;
+    let shortfall_from_free = perps_math::shortfall_from_free_collateral(
+        collateral_after_pnl,
+        free_collateral,
+        position.is_isolated(),
+    );
+    let collateral_after_pnl = collateral_after_pnl + i128::from(shortfall_from_free);
+
+    // ── Source fee and penalty: own collateral first, insurance for overflow ──
+    let funding = perps_math::source_liquidation_fees(
+        collateral_after_pnl,
+        liq_fee,
+        insurance_penalty,
+        insurance_balance,
+        market.liquidation_fee_insurance_first,
+    );
+    let uncovered_bad_debt = u64::try_from(collateral_after_pnl.min(0).unsigned_abs())
+        .unwrap_or(u64::MAX)
+        .saturating_sub(funding.bad_debt);
+    Ok(Some(LiquidationPlan {
+        settled,
+        liq_fee_bps,
+        actual_close,
+        is_full_close,
+        trade,
+        shortfall_from_free,
+        funding,
+        uncovered_bad_debt,
+    }))
+}
 
-    let mut bad_debt: u64 = 0;
-    let mut market_emergency = false;
-
-    if remaining_collateral_i128 < 0 {
-        // ── STEP 3: Bad debt — draw from insurance fund ──
-        let deficit = remaining_collateral_i128.unsigned_abs();
-        bad_debt = u64::try_from(deficit).unwrap_or(u64::MAX);
-
-        let iv = &mut ctx.accounts.insurance_vault;
-        if iv.balance_u64 >= bad_debt {
-            iv.balance_u64 = iv.balance_u64.checked_sub(bad_debt).unwrap();
-        } else {
-            // Insurance fund insufficient — set emergency flag
-            bad_debt = iv.balance_u64; // absorb what we can
-            iv.balance_u64 = 0;
-            ctx.accounts.market.emergency = true;
-            market_emergency = true;
-        }
-        remaining_collateral_i128 = 0;
+pub fn liquidate_position(ctx: Context<LiquidatePosition>) -> Result<()> {
+    ctx.accounts.global.require_liquidations_enabled()?;
+    ctx.accounts.market.require_not_paused()?;
+
+    // Prevent self-liquidation
+    require!(
+        ctx.accounts.liquidator.key() != ctx.accounts.position_owner.key(),
+        CustomError::SelfLiquidation
+    );
+
+    require!(ctx.accounts.position.base_position_i64 != 0, CustomError::NoOpenPosition);
+
+    require!(
+        ctx.accounts.oracle_price_account.key() == ctx.accounts.market.oracle_price_account,
+        CustomError::OraclePriceUnavailable
+    );
+    let (price, conf) = read_oracle_price_and_conf(
+        &ctx.accounts.oracle_price_account,
+        ctx.accounts.market.max_oracle_staleness_secs,
+    )?;
+    let now = Clock::get()?.unix_timestamp;
+    let plan = plan_liquidation(
+        &ctx.accounts.market,
+        &ctx.accounts.position,
+        ctx.accounts.user.collateral_quote_u64,
+        ctx.accounts.insurance_vault.balance_u64,
+        price,
+        conf,
+        now,
+    )?
+    .ok_or(error!(CustomError::NotLiquidatable))?;
+    ctx.accounts.market.sample_mark_price(price, now)?;
+
+    // ── Settle accumulated funding before liquidation ──
+    let position = &mut ctx.accounts.position;
+    let settled = &plan.settled;
+    position.collateral_u64 = settled.collateral;
+    position.last_funding_i128 = settled.checkpoint;
+    position.unpaid_funding_u64 = settled.unpaid_funding;
+    ctx.accounts.market.funding_pool_u64 = settled.funding_pool;
+    if settled.funding_delta != 0 {
+        emit!(FundingSettled {
+            position: position.key(),
+            funding_delta: settled.funding_delta,
+            new_collateral: settled.collateral,
+        });
     }
 
-    let remaining_collateral = u64::try_from(remaining_collateral_i128)
-        .map_err(|_| error!(CustomError::CalculationOverflow))?;
+    let result = plan.trade;
+    let actual_close = plan.actual_close;
+    let is_full_close = plan.is_full_close;
+    let shortfall_from_free = plan.shortfall_from_free;
+    ctx.accounts.user.collateral_quote_u64 -= shortfall_from_free;
+    let funding = plan.funding;
+    let bad_debt = funding.bad_debt;
+    let market_emergency = funding.insurance_exhausted;
+    let remaining_collateral = funding.remaining_collateral;
+    // Whatever insurance could not cover waits for auto-deleveraging
+    ctx.accounts.market.record_uncovered_bad_debt(plan.uncovered_bad_debt, position.derived_side());
+
+    let market_seeds: &[&[&[u8]]] = &[&[
+        b"perps_market",
+        ctx.accounts.market.base_mint.as_ref(),
+        ctx.accounts.market.quote_mint.as_ref(),
+        &[ctx.accounts.market.bump],
+    ]];
+    let market_key = ctx.accounts.market.key();
+    let insurance_seeds: &[&[&[u8]]] = &[&[
+        b"insurance_vault",
+        market_key.as_ref(),
+        &[ctx.accounts.insurance_vault.bump],
+    ]];
 
-    // ── Transfer liquidation fee to liquidator from collateral vault ──
-    let actual_liq_fee = liq_fee.min(ctx.accounts.collateral_vault.amount);
-    if actual_liq_fee > 0 {
-        let seeds: &[&[&[u8]]] = &[&[
-            b"perps_market",
-            ctx.accounts.market.base_mint.as_ref(),
-            ctx.accounts.market.quote_mint.as_ref(),
-            &[ctx.accounts.market.bump],
-        ]];
+    // ── Bad debt: insurance refills the collateral vault, not other traders ──
+    if bad_debt > 0 {
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.insurance_vault_ata.to_account_info(),
+                    to: ctx.accounts.collateral_vault.to_account_info(),
+                    authority: ctx.accounts.insurance_vault.to_account_info(),
+                },
+                insurance_seeds,
+            ),
+            bad_debt,
+        )?;
+        ctx.accounts.insurance_vault.balance_u64 = ctx
+            .accounts
+            .insurance_vault
+            .balance_u64
+            .checked_sub(bad_debt)
+            .ok_or(error!(CustomError::InsuranceFundDepleted))?;
+    }
+
+    // ── Liquidator fee: the position's share of the collateral vault ──
+    if funding.fee_from_collateral > 0 {
         token::transfer(
             CpiContext::new_with_signer(
                 ctx.accounts.token_program.to_account_info(),
@@ -1109,23 +2354,36 @@ pub fn liquidate_position(ctx: Context<LiquidatePosition>) -> Result<()> {
                     to: ctx.accounts.liquidator_ata.to_account_info(),
                     authority: ctx.accounts.market.to_account_info(),
                 },
-                seeds,
+                market_seeds,
             ),
-            actual_liq_fee,
+            funding.fee_from_collateral,
         )?;
     }
 
-    // ── Transfer penalty to insurance vault from collateral vault ──
-    let actual_penalty = insurance_penalty.min(
-        ctx.accounts.collateral_vault.amount.saturating_sub(actual_liq_fee)
-    );
+    // ── Liquidator fee: overflow from the insurance fund ──
+    if funding.fee_from_insurance > 0 {
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.insurance_vault_ata.to_account_info(),
+                    to: ctx.accounts.liquidator_ata.to_account_info(),
+                    authority: ctx.accounts.insurance_vault.to_account_info(),
+                },
+                insurance_seeds,
+            ),
+            funding.fee_from_insurance,
+        )?;
+        ctx.accounts.insurance_vault.balance_u64 = ctx
+            .accounts
+            .insurance_vault
+            .balance_u64
+            .saturating_sub(funding.fee_from_insurance);
+    }
+
+    // ── Penalty to insurance vault, from the position's collateral only ──
+    let actual_penalty = funding.penalty_from_collateral;
     if actual_penalty > 0 {
-        let seeds: &[&[&[u8]]] = &[&[
-            b"perps_market",
-            ctx.accounts.market.base_mint.as_ref(),
-            ctx.accounts.market.quote_mint.as_ref(),
-            &[ctx.accounts.market.bump],
-        ]];
         token::transfer(
             CpiContext::new_with_signer(
                 ctx.accounts.token_program.to_account_info(),
@@ -1134,7 +2392,7 @@ pub fn liquidate_position(ctx: Context<LiquidatePosition>) -> Result<()> {
                     to: ctx.accounts.insurance_vault_ata.to_account_info(),
                     authority: ctx.accounts.market.to_account_info(),
                 },
-                seeds,
+                market_seeds,
             ),
             actual_penalty,
         )?;
@@ -1145,8 +2403,10 @@ pub fn liquidate_position(ctx: Context<LiquidatePosition>) -> Result<()> {
             .checked_add(actual_penalty)
             .ok_or(error!(CustomError::CalculationOverflow))?;
     }
+    let actual_liq_fee = funding.fee_from_collateral + funding.fee_from_insurance;
 
     // ── Update open interest ──
+    ctx.accounts.market.apply_position_oi(position.base_position_i64, result.new_base_position);
     let closed_oi = perps_math::notional_value(actual_close, price)?;
     ctx.accounts.market.open_interest_i128 = ctx
         .accounts
@@ -1157,13 +2417,7 @@ pub fn liquidate_position(ctx: Context<LiquidatePosition>) -> Result<()> {
 
     // ── Update position ──
     if is_full_close {
-        position.base_position_i64 = 0;
-        position.entry_price_i64 = 0;
-        position.collateral_u64 = 0;
-        position.leverage_u16 = 0;
-        position.last_funding_i128 = 0;
-        position.realized_pnl_i128 = result.new_realized_pnl;
-        position.side = 0;
+        position.reset();
 
         let user = &mut ctx.accounts.user;
         user.positions_count_u8 = user.positions_count_u8.saturating_sub(1);
@@ -1196,7 +2450,624 @@ pub fn liquidate_position(ctx: Context<LiquidatePosition>) -> Result<()> {
         insurance_penalty_u64: actual_penalty,
         bad_debt_u64: bad_debt,
         emergency: market_emergency,
+        liquidator_fee_from_insurance_u64: funding.fee_from_insurance,
+        shortfall_from_free_u64: shortfall_from_free,
     });
 
     Ok(())
 }
+
+#[derive(Accounts)]
+pub struct PreviewLiquidation<'info> {
+    #[account(
+        seeds = [b"perps_global"],
+        bump = global.bump
+    )]
+    pub global: Account<'info, PerpsGlobalState>,
+    #[account(
+        seeds = [b"perps_user", position_owner.key().as_ref()],
+        bump = user.bump,
+        constraint = user.owner == position_owner.key()
+    )]
+    pub user: Account<'info, PerpsUserAccount>,
+    /// CHECK: we only read the key — validated via seeds on `user` and `position`.
+    pub position_owner: AccountInfo<'info>,
+    #[account(
+        seeds = [b"perps_market", market.base_mint.as_ref(), market.quote_mint.as_ref()],
+        bump = market.bump
+    )]
+    pub market: Account<'info, PerpsMarket>,
+    /// CHECK: validated against market.oracle_price_account
+    pub oracle_price_account: AccountInfo<'info>,
+    #[account(
+        seeds = [b"perps_position", position_owner.key().as_ref(), market.key().as_ref()],
+        bump = position.bump,
+        constraint = position.owner == position_owner.key(),
+        constraint = position.market == market.key()
+    )]
+    pub position: Account<'info, PerpsPosition>,
+    #[account(
+        seeds = [b"insurance_vault", market.key().as_ref()],
+        bump = insurance_vault.bump,
+        constraint = insurance_vault.market == market.key()
+    )]
+    pub insurance_vault: Account<'info, InsuranceVault>,
+}
+
+/// Run `liquidate_position`'s computation, funding settlement included,
+/// without executing it.  Not liquidatable while liquidations are disabled
+/// or the market is paused.
+pub fn preview_liquidation(ctx: Context<PreviewLiquidation>) -> Result<LiquidationPreview> {
+    require!(ctx.accounts.position.base_position_i64 != 0, CustomError::NoOpenPosition);
+    require!(
+        ctx.accounts.oracle_price_account.key() == ctx.accounts.market.oracle_price_account,
+        CustomError::OraclePriceUnavailable
+    );
+    let (price, conf) = read_oracle_price_and_conf(
+        &ctx.accounts.oracle_price_account,
+        ctx.accounts.market.max_oracle_staleness_secs,
+    )?;
+    let plan = plan_liquidation(
+        &ctx.accounts.market,
+        &ctx.accounts.position,
+        ctx.accounts.user.collateral_quote_u64,
+        ctx.accounts.insurance_vault.balance_u64,
+        price,
+        conf,
+        Clock::get()?.unix_timestamp,
+    )?;
+    Ok(match plan {
+        Some(plan) if ctx.accounts.global.liquidations_enabled && !ctx.accounts.market.paused => plan.preview(),
+        _ => LiquidationPreview::default(),
+    })
+}
+
+/// Value `position` at `price` the way `plan_liquidation` decides eligibility.
+fn position_health(market: &PerpsMarket, position: &PerpsPosition, price: i64, conf: u64, now: i64) -> Result<PositionHealth> {
+    let effective_mm_bps = perps_math::confidence_adjusted_mm_bps(
+        market.maintenance_margin_bps,
+        price,
+        conf,
+        market.conf_mm_multiplier_bps,
+    )?;
+    let settled = settle_funding_inner(
+        position.base_position_i64,
+        position.collateral_u64,
+        position.unpaid_funding_u64,
+        position.last_funding_i128,
+        market.cumulative_funding_i128,
+        market.funding_pool_u64,
+    )?;
+    let mark_price = market.liquidation_mark_price(price, now);
+    let valued_collateral = perps_math::haircut_collateral(settled.collateral, market.collateral_haircut_bps)?;
+    let unrealized_pnl = unrealized_pnl(position.base_position_i64, position.entry_price_i64, mark_price)?;
+    let notional = notional_value(position.base_position_i64, mark_price)?;
+    let health = perps_math::CrossMarginHealth {
+        equity: perps_math::checked_add_signed(valued_collateral as i128, unrealized_pnl)?,
+        unrealized_pnl,
+        maintenance_margin: perps_math::maintenance_margin(notional, effective_mm_bps)?,
+    };
+    Ok(PositionHealth {
+        equity_i128: health.equity,
+        maintenance_margin_i128: health.maintenance_margin,
+        initial_margin_i128: perps_math::initial_margin(notional, position.leverage_u16)?,
+        unrealized_pnl_i128: unrealized_pnl,
+        pending_funding_i128: settled.funding_delta,
+        notional_i128: notional,
+        health_factor_bps: health.health_factor_bps(),
+    })
+}
+
+#[derive(Accounts)]
+pub struct GetPositionHealth<'info> {
+    /// CHECK: we only read the key — validated via seeds on `position`.
+    pub position_owner: AccountInfo<'info>,
+    #[account(
+        seeds = [b"perps_market", market.base_mint.as_ref(), market.quote_mint.as_ref()],
+        bump = market.bump
+    )]
+    pub market: Account<'info, PerpsMarket>,
+    /// CHECK: validated against market.oracle_price_account in handler
+    pub oracle_price_account: AccountInfo<'info>,
+    #[account(
+        seeds = [b"perps_position", position_owner.key().as_ref(), market.key().as_ref()],
+        bump = position.bump,
+        constraint = position.owner == position_owner.key(),
+        constraint = position.market == market.key()
+    )]
+    pub position: Account<'info, PerpsPosition>,
+}
+
+/// Everything needed to judge a position's margin in one call; no state changes.
+pub fn get_position_health(ctx: Context<GetPositionHealth>) -> Result<PositionHealth> {
+    require!(
+        ctx.accounts.oracle_price_account.key() == ctx.accounts.market.oracle_price_account,
+        CustomError::OraclePriceUnavailable
+    );
+    let (price, conf) = read_oracle_price_and_conf(
+        &ctx.accounts.oracle_price_account,
+        ctx.accounts.market.max_oracle_staleness_secs,
+    )?;
+    let now = Clock::get()?.unix_timestamp;
+    position_health(&ctx.accounts.market, &ctx.accounts.position, price, conf, now)
+}
+
+#[derive(Accounts)]
+pub struct CloseLiquidatedPosition<'info> {
+    /// Anyone may clean up; rent goes back to the owner.
+    pub caller: Signer<'info>,
+    /// CHECK: rent destination — validated via seeds and `position.owner`.
+    #[account(mut)]
+    pub position_owner: AccountInfo<'info>,
+    pub market: Account<'info, PerpsMarket>,
+    #[account(
+        mut,
+        close = position_owner,
+        seeds = [b"perps_position", position_owner.key().as_ref(), market.key().as_ref()],
+        bump = position.bump,
+        constraint = position.owner == position_owner.key(),
+        constraint = position.market == market.key()
+    )]
+    pub position: Account<'info, PerpsPosition>,
+}
+
+pub fn close_liquidated_position(ctx: Context<CloseLiquidatedPosition>) -> Result<()> {
+    require!(ctx.accounts.position.is_closable(), CustomError::PositionNotEmpty);
+    Ok(())
+}
+
+// ─────────────────────────────────────────────────────
+// Auto-deleveraging
+// ─────────────────────────────────────────────────────
+
+#[derive(Accounts)]
+pub struct AdlPosition<'info> {
+    /// Picks the counterparty and the candidates it is ranked against.
+    pub admin: Signer<'info>,
+    #[account(
+        seeds = [b"perps_global"],
+        bump = global.bump,
+        constraint = global.authority == admin.key() @ CustomError::UnauthorizedAdmin
+    )]
+    pub global: Account<'info, PerpsGlobalState>,
+    #[account(
+        mut,
+        seeds = [b"perps_user", position_owner.key().as_ref()],
+        bump = user.bump,
+        constraint = user.owner == position_owner.key()
+    )]
+    pub user: Account<'info, PerpsUserAccount>,
+    /// CHECK: we only read the key — validated via seeds on `user` and `position`.
+    pub position_owner: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [b"perps_market", market.base_mint.as_ref(), market.quote_mint.as_ref()],
+        bump = market.bump
+    )]
+    pub market: Account<'info, PerpsMarket>,
+    /// CHECK: validated against market.oracle_price_account
+    pub oracle_price_account: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [b"perps_position", position_owner.key().as_ref(), market.key().as_ref()],
+        bump = position.bump,
+        constraint = position.owner == position_owner.key(),
+        constraint = position.market == market.key()
+    )]
+    pub position: Account<'info, PerpsPosition>,
+}
+
+/// Socialize uncovered bad debt while the market is in emergency: fully close
+/// a profitable position on the side opposite the bankrupt one at the oracle
+/// price, fee-free, and withhold up to its profit from the credited collateral.
+/// The withheld quote stays in the collateral vault, filling the hole the bad
+/// debt left.  Clears `emergency` once no bad debt remains.
+///
+/// The position must be at least as profitable as every other position of
+/// the market passed in `remaining_accounts`.  The program can't enumerate a
+/// market's positions, so it is up to the admin to pass every open
+/// position on that side; ranking against an incomplete set is only as fair
+/// as the admin.
+pub fn adl_position(ctx: Context<AdlPosition>) -> Result<()> {
+    let market = &ctx.accounts.market;
+    require!(
+        market.emergency && market.uncovered_bad_debt_u64 > 0,
+        CustomError::AdlNotRequired
+    );
+    require!(
+        ctx.accounts.oracle_price_account.key() == market.oracle_price_account,
+        CustomError::OraclePriceUnavailable
+    );
+    let price = read_oracle_price(&ctx.accounts.oracle_price_account, market.max_oracle_staleness_secs)?;
+
+    let position = &ctx.accounts.position;
+    let profit = unrealized_pnl(position.base_position_i64, position.entry_price_i64, price)?;
+    require!(
+        !position.is_empty()
+            && position.derived_side() != market.bad_debt_side_u8
+            && profit > 0,
+        CustomError::AdlIneligiblePosition
+    );
+    let market_key = market.key();
+    for candidate_info in ctx.remaining_accounts {
+        require_keys_neq!(candidate_info.key(), position.key(), CustomError::AdlIneligiblePosition);
+        require!(candidate_info.owner == &crate::ID, CustomError::AdlIneligiblePosition);
+        let candidate = PerpsPosition::try_deserialize(&mut &candidate_info.try_borrow_data()?[..])?;
+        require_keys_eq!(candidate.market, market_key, CustomError::AdlIneligiblePosition);
+        if candidate.derived_side() == market.bad_debt_side_u8 || candidate.is_empty() {
+            continue;
+        }
+        require!(
+            unrealized_pnl(candidate.base_position_i64, candidate.entry_price_i64, price)? <= profit,
+            CustomError::AdlNotMostProfitable
+        );
+    }
+
+    let size_closed = position.base_position_i64;
+    let accounts = &mut *ctx.accounts;
+    let credited = apply_close_trade(
+        &mut accounts.market,
+        &mut accounts.user,
+        &mut accounts.position,
+        size_closed.unsigned_abs(),
+        price,
+        0,
+    )?;
+    let withheld = u64::try_from(profit).unwrap_or(u64::MAX).min(credited);
+    let absorbed = accounts.market.absorb_bad_debt(withheld);
+    accounts.user.collateral_quote_u64 -= absorbed;
+
+    emit!(AutoDeleveraged {
+        market: accounts.market.key(),
+        position: accounts.position.key(),
+        counterparty: accounts.position_owner.key(),
+        size_closed_i64: size_closed,
+        price_i64: price,
+        absorbed_u64: absorbed,
+        remaining_bad_debt_u64: accounts.market.uncovered_bad_debt_u64,
+    });
+    Ok(())
+}
+
+// ─────────────────────────────────────────────────────
+// Read-only position view
+// ─────────────────────────────────────────────────────
+
+#[derive(Accounts)]
+pub struct GetPosition<'info> {
+    /// CHECK: we only read the key — validated via seeds on `position`.
+    pub position_owner: AccountInfo<'info>,
+    #[account(
+        seeds = [b"perps_market", market.base_mint.as_ref(), market.quote_mint.as_ref()],
+        bump = market.bump
+    )]
+    pub market: Account<'info, PerpsMarket>,
+    /// CHECK: validated against market.oracle_price_account in handler
+    pub oracle_price_account: AccountInfo<'info>,
+    #[account(
+        seeds = [b"perps_position", position_owner.key().as_ref(), market.key().as_ref()],
+        bump = position.bump,
+        constraint = position.owner == position_owner.key(),
+        constraint = position.market == market.key()
+    )]
+    pub position: Account<'info, PerpsPosition>,
+}
+
+pub fn get_position(ctx: Context<GetPosition>) -> Result<PositionView> {
+    require!(
+        ctx.accounts.oracle_price_account.key() == ctx.accounts.market.oracle_price_account,
+        CustomError::OraclePriceUnavailable
+    );
+    let (price, conf) = read_oracle_price_and_conf(
+        &ctx.accounts.oracle_price_account,
+        ctx.accounts.market.max_oracle_staleness_secs,
+    )?;
+    let market = &ctx.accounts.market;
+    let position = &ctx.accounts.position;
+    let mark_price = market.liquidation_mark_price(price, Clock::get()?.unix_timestamp);
+    let upnl = unrealized_pnl(position.base_position_i64, position.entry_price_i64, mark_price)?;
+    let effective_mm_bps = perps_math::confidence_adjusted_mm_bps(
+        market.maintenance_margin_bps,
+        price,
+        conf,
+        market.conf_mm_multiplier_bps,
+    )?;
+    let liq_price = position_liquidation_price(market, position, effective_mm_bps)?;
+    Ok(PositionView {
+        base_position_i64: position.base_position_i64,
+        entry_price_i64: position.entry_price_i64,
+        side: position.derived_side(),
+        collateral_u64: position.collateral_u64,
+        leverage_u16: position.leverage_u16,
+        mark_price_i64: mark_price,
+        unrealized_pnl_i128: upnl,
+        liquidation_price_i64: liq_price,
+    })
+}
+
+// ─────────────────────────────────────────────────────
+// Standalone funding settlement (permissionless crank) and view
+// ─────────────────────────────────────────────────────
+
+#[derive(Accounts)]
+pub struct SettleFunding<'info> {
+    pub caller: Signer<'info>,
+    /// CHECK: we only read the key — validated via seeds on `position`.
+    pub position_owner: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [b"perps_market", market.base_mint.as_ref(), market.quote_mint.as_ref()],
+        bump = market.bump
+    )]
+    pub market: Account<'info, PerpsMarket>,
+    #[account(
+        mut,
+        seeds = [b"perps_position", position_owner.key().as_ref(), market.key().as_ref()],
+        bump = position.bump,
+        constraint = position.owner == position_owner.key(),
+        constraint = position.market == market.key()
+    )]
+    pub position: Account<'info, PerpsPosition>,
+}
+
+/// Settle a position's funding against the market's current cumulative
+/// index without trading, so a dormant position's collateral stays current.
+pub fn settle_funding(ctx: Context<SettleFunding>) -> Result<()> {
+    let accounts = &mut *ctx.accounts;
+    settle_position_funding(&mut accounts.market, &mut accounts.position)
+}
+
+#[derive(Accounts)]
+pub struct GetPendingFunding<'info> {
+    /// CHECK: we only read the key — validated via seeds on `position`.
+    pub position_owner: AccountInfo<'info>,
+    #[account(
+        seeds = [b"perps_market", market.base_mint.as_ref(), market.quote_mint.as_ref()],
+        bump = market.bump
+    )]
+    pub market: Account<'info, PerpsMarket>,
+    #[account(
+        seeds = [b"perps_position", position_owner.key().as_ref(), market.key().as_ref()],
+        bump = position.bump,
+        constraint = position.owner == position_owner.key(),
+        constraint = position.market == market.key()
+    )]
+    pub position: Account<'info, PerpsPosition>,
+}
+
+/// Funding the position would settle now (atomic quote; positive = it pays).
+/// Excludes `unpaid_funding_u64` already owed to it from earlier settlements.
+pub fn get_pending_funding(ctx: Context<GetPendingFunding>) -> Result<i128> {
+    ctx.accounts
+        .position
+        .pending_funding(ctx.accounts.market.cumulative_funding_i128)
+}
+
+#[derive(Accounts)]
+pub struct GetLiquidationPrice<'info> {
+    /// CHECK: we only read the key — validated via seeds on `position`.
+    pub position_owner: AccountInfo<'info>,
+    #[account(
+        seeds = [b"perps_market", market.base_mint.as_ref(), market.quote_mint.as_ref()],
+        bump = market.bump
+    )]
+    pub market: Account<'info, PerpsMarket>,
+    #[account(
+        seeds = [b"perps_position", position_owner.key().as_ref(), market.key().as_ref()],
+        bump = position.bump,
+        constraint = position.owner == position_owner.key(),
+        constraint = position.market == market.key()
+    )]
+    pub position: Account<'info, PerpsPosition>,
+}
+
+/// Oracle-free liquidation price at the market's base maintenance margin.
+/// `get_position` reports the confidence-adjusted figure instead.
+pub fn get_liquidation_price(ctx: Context<GetLiquidationPrice>) -> Result<i64> {
+    let market = &ctx.accounts.market;
+    position_liquidation_price(market, &ctx.accounts.position, market.maintenance_margin_bps)
+}
+
+/// Mark at which `position` becomes liquidatable under `mm_bps`, on the
+/// collateral `plan_liquidation` judges it by: funding settled, then haircut.
+fn position_liquidation_price(market: &PerpsMarket, position: &PerpsPosition, mm_bps: u16) -> Result<i64> {
+    let settled = settle_funding_inner(
+        position.base_position_i64,
+        position.collateral_u64,
+        position.unpaid_funding_u64,
+        position.last_funding_i128,
+        market.cumulative_funding_i128,
+        market.funding_pool_u64,
+    )?;
+    perps_math::liquidation_price(
+        perps_math::haircut_collateral(settled.collateral, market.collateral_haircut_bps)?,
+        position.base_position_i64,
+        position.entry_price_i64,
+        mm_bps,
+    )
+}
+
+#[derive(Accounts)]
+pub struct GetCrossMarginHealth<'info> {
+    /// CHECK: we only read the key — positions are checked against it.
+    pub position_owner: AccountInfo<'info>,
+    /// CHECK: only the key is read; positions in markets quoted in another mint are skipped.
+    pub quote_mint: AccountInfo<'info>,
+}
+
+/// Net collateral and uPnL across `position_owner`'s cross-margin positions
+/// quoted in `quote_mint`, passed in `remaining_accounts` as
+/// `(market, oracle, position)` triples.  Collateral is haircut per market;
+/// free collateral and unsettled funding are not included.
+pub fn get_cross_margin_health(ctx: Context<GetCrossMarginHealth>) -> Result<CrossMarginHealthView> {
+    let remaining_accounts = ctx.remaining_accounts;
+    require!(remaining_accounts.len().is_multiple_of(3), CustomError::IncompleteMarginAccounts);
+    let quote_mint = ctx.accounts.quote_mint.key();
+    let mut positions = Vec::with_capacity(remaining_accounts.len() / 3);
+    let mut prices = Vec::with_capacity(positions.capacity());
+    let mut mm_bps = Vec::with_capacity(positions.capacity());
+    let mut seen_markets = Vec::with_capacity(positions.capacity());
+    for triple in remaining_accounts.chunks_exact(3) {
+        let (market, position, price) =
+            read_margin_triple(triple, ctx.accounts.position_owner.key(), &mut seen_markets)?;
+        if market.quote_mint != quote_mint || position.is_isolated() {
+            continue;
+        }
+        mm_bps.push(market.maintenance_margin_bps);
+        prices.push(price);
+        positions.push(position);
+    }
+    let health = perps_math::cross_margin_health(&positions, &prices, &mm_bps)?;
+    Ok(CrossMarginHealthView {
+        quote_mint,
+        positions_u8: positions.len() as u8,
+        equity_i128: health.equity,
+        unrealized_pnl_i128: health.unrealized_pnl,
+        maintenance_margin_i128: health.maintenance_margin,
+        health_factor_bps: health.health_factor_bps(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pyth_sdk_solana::{Price, PriceIdentifier};
+
+    fn make_feed(publish_time: i64) -> PriceFeed {
+        let price = Price { price: 100_000_000, conf: 50_000, expo: -6, publish_time };
+        PriceFeed::new(PriceIdentifier::new([0; 32]), price, price)
+    }
+
+    #[test]
+    fn test_stale_pyth_price_rejected_per_market_window() {
+        let feed = make_feed(1_000);
+        assert_eq!(fresh_pyth_price(&feed, 1_060, 60).unwrap(), (100_000_000, 50_000));
+        assert_eq!(
+            fresh_pyth_price(&feed, 1_061, 60).unwrap_err(),
+            error!(CustomError::OraclePriceUnavailable)
+        );
+        // A slower-moving asset tolerates the same price for longer
+        assert!(fresh_pyth_price(&feed, 1_061, 300).is_ok());
+        assert!(fresh_pyth_price(&feed, 1_301, 300).is_err());
+    }
+
+    #[test]
+    fn test_health_factor_below_par_exactly_when_liquidatable() {
+        let mut market = crate::state::perps_market::tests::make_market(0);
+        market.cumulative_funding_i128 = perps_math::BASE_SCALE;
+        let position = crate::state::perps_position::tests::make_position(10);
+        let (mut healthy, mut unhealthy) = (0, 0);
+        for price in 80..=120 {
+            let health = position_health(&market, &position, price, 0, 0).unwrap();
+            let liquidatable = plan_liquidation(&market, &position, 0, 0, price, 0, 0).unwrap().is_some();
+            assert_eq!(health.health_factor_bps < 10_000, liquidatable, "price {price}");
+            assert_eq!(health.pending_funding_i128, 10);
+            if liquidatable { unhealthy += 1 } else { healthy += 1 }
+        }
+        assert!(healthy > 0 && unhealthy > 0);
+    }
+
+    #[test]
+    fn test_liquidation_price_matches_liquidation_eligibility() {
+        let mut market = crate::state::perps_market::tests::make_market(0);
+        let position = crate::state::perps_position::tests::make_position(10);
+        let unadjusted = position_liquidation_price(&market, &position, market.maintenance_margin_bps).unwrap();
+        // Funding owed and a collateral haircut both bring liquidation closer
+        market.cumulative_funding_i128 = perps_math::BASE_SCALE;
+        market.collateral_haircut_bps = 1_000;
+        let liq_price = position_liquidation_price(&market, &position, market.maintenance_margin_bps).unwrap();
+        assert!(liq_price > unadjusted, "{liq_price} vs {unadjusted}");
+        assert!(plan_liquidation(&market, &position, 0, 0, liq_price - 1, 0, 0).unwrap().is_some());
+        assert!(plan_liquidation(&market, &position, 0, 0, liq_price + 1, 0, 0).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_dust_liquidation_pays_minimum_fee() {
+        let mut market = crate::state::perps_market::tests::make_market(0);
+        let position = crate::state::perps_position::tests::make_position(10);
+        // 250 bps of a notional far below PRICE_SCALE rounds to nothing
+        let plan = plan_liquidation(&market, &position, 0, 0, 91, 0, 0).unwrap().unwrap();
+        assert_eq!(plan.preview().liquidator_fee_u64, 0);
+
+        market.min_liquidation_fee_u64 = 3;
+        let plan = plan_liquidation(&market, &position, 0, 0, 91, 0, 0).unwrap().unwrap();
+        assert_eq!(plan.preview().liquidator_fee_u64, 3);
+        assert_eq!(plan.funding.fee_from_collateral, 3);
+
+        // Never more than the position and insurance can fund
+        market.min_liquidation_fee_u64 = 1_000;
+        let plan = plan_liquidation(&market, &position, 0, 7, 91, 0, 0).unwrap().unwrap();
+        assert_eq!(plan.funding.remaining_collateral, 0);
+        assert_eq!(plan.funding.fee_from_insurance, 7);
+    }
+
+    #[test]
+    fn test_plan_liquidation_settles_funding_and_conserves_collateral() {
+        let mut market = crate::state::perps_market::tests::make_market(0);
+        // Long 10 @ 100 with 100 collateral (the small units of the perps_math tests)
+        let position = crate::state::perps_position::tests::make_position(10);
+        assert!(plan_liquidation(&market, &position, 0, 0, 100, 0, 0).unwrap().is_none());
+
+        // One quote per base unit of funding accrued since the checkpoint
+        market.cumulative_funding_i128 = perps_math::BASE_SCALE;
+        let plan = plan_liquidation(&market, &position, 0, 50, 91, 0, 0)
+            .unwrap()
+            .expect("below maintenance at 91");
+        assert_eq!(plan.settled.funding_delta, 10);
+        assert_eq!(plan.settled.collateral, 90);
+        assert_eq!(plan.settled.checkpoint, perps_math::BASE_SCALE);
+
+        // Whatever the close size, fee, penalty and remainder add up to the settled equity
+        let collateral_after_pnl = i128::from(plan.settled.collateral) + plan.trade.pnl_delta;
+        let funding = plan.funding;
+        assert_eq!(
+            i128::from(funding.fee_from_collateral + funding.penalty_from_collateral + funding.remaining_collateral),
+            collateral_after_pnl
+        );
+        let preview = plan.preview();
+        assert!(preview.liquidatable);
+        assert_eq!(preview.size_closed_i64, plan.actual_close);
+        assert_eq!(preview.liquidator_fee_u64, funding.fee_from_collateral + funding.fee_from_insurance);
+        assert_eq!(preview.bad_debt_u64, 0);
+    }
+
+    #[test]
+    fn test_trade_events_carry_trade_result() {
+        let market = Pubkey::new_unique();
+        let key = Pubkey::new_unique();
+        let mut position = crate::state::perps_position::tests::make_position(10);
+        position.owner = Pubkey::new_unique();
+
+        // Add 10 @ 120 to a long 10 @ 100
+        let result = perps_math::apply_trade_to_position(&position.engine_state(), 10, 120).unwrap();
+        position.base_position_i64 = result.new_base_position;
+        position.entry_price_i64 = result.new_entry_price;
+        position.collateral_u64 = 220;
+        let opened = position_opened_event(key, &position, market, 10, 120, &result);
+        assert_eq!((opened.owner, opened.market, opened.position), (position.owner, market, key));
+        assert_eq!((opened.side, opened.size_delta_i64, opened.price_i64), (0, 10, 120));
+        assert_eq!(opened.base_position_i64, result.new_base_position);
+        assert_eq!(opened.entry_price_i64, result.new_entry_price);
+        assert_eq!(opened.pnl_delta_i128, result.pnl_delta);
+        assert_eq!((opened.collateral_u64, opened.leverage_u16), (220, 5));
+        assert_eq!((opened.base_position_i64, opened.entry_price_i64, opened.pnl_delta_i128), (20, 110, 0));
+
+        // Partial close of 5 @ 130 realizes (130 − 110) × 5
+        let result = perps_math::apply_trade_to_position(&position.engine_state(), -5, 130).unwrap();
+        position.base_position_i64 = result.new_base_position;
+        position.collateral_u64 = 165;
+        let closed = position_closed_event(key, &position, market, -5, 130, &result);
+        assert_eq!((closed.side, closed.size_delta_i64, closed.price_i64), (0, -5, 130));
+        assert_eq!(closed.base_position_i64, result.new_base_position);
+        assert_eq!(closed.entry_price_i64, result.new_entry_price);
+        assert_eq!(closed.pnl_delta_i128, result.pnl_delta);
+        assert_eq!((closed.base_position_i64, closed.pnl_delta_i128), (15, 100));
+
+        // A full close reports the emptied position
+        let result = perps_math::apply_trade_to_position(&position.engine_state(), -15, 100).unwrap();
+        position.reset();
+        let closed = position_closed_event(key, &position, market, -15, 100, &result);
+        assert_eq!(closed.pnl_delta_i128, result.pnl_delta);
+        assert_eq!((closed.base_position_i64, closed.collateral_u64, closed.leverage_u16), (0, 0, 0));
+    }
+}
@@ -3,24 +3,46 @@ use anchor_spl::{
     associated_token::AssociatedToken,
     token::{Token, TokenAccount, Mint, MintTo, mint_to, Transfer, transfer, Burn, burn},
 };
-use crate::state::{LiquidityPool, UserLiquidityPosition};
+use crate::state::{LiquidityPool, SingleSidedDeposit, UserLiquidityPosition, RushConfig, AmmConfig, PerpsOraclePrice};
 use crate::errors::CustomError;
-use crate::events::{PoolCreated, LiquidityAdded, LiquidityRemoved};
+use crate::events::{PoolCreated, LiquidityAdded, SwapExecuted, LiquidityRemoved, MarketSpreadUpdated, ProtocolFeeUpdated, PoolFeeUpdated, PoolPauseUpdated, ReservesSynced};
+use crate::constants::{MAX_MARKET_SPREAD_BPS, DEFAULT_FLASH_FEE_BPS, MAX_FLASH_FEE_BPS, MAX_ALIGNMENT_BOOST_BPS, MAX_DYNAMIC_FEE_BPS, MAX_AMPLIFICATION_COEFFICIENT, MIN_INITIAL_LIQUIDITY, MINIMUM_LIQUIDITY, FEE_BPS_DENOMINATOR, is_pool_lp_mint};
+use crate::instructions::amm_config::require_mints_allowed;
 use crate::utils::{
     calculate_lp_tokens_for_add_liquidity,
     calculate_remove_liquidity_amounts,
+    calculate_lp_tokens_for_percent,
     validate_ratio_imbalance,
+    validate_initial_liquidity,
+    acquire_pool_lock,
+    vault_received,
 };
 
-pub const MINIMUM_LIQUIDITY: u64 = 1000;
-
 pub fn initialize_pool(
     ctx: Context<InitializePool>,
+    amplification_coefficient: u64,
+    initial_deposit_a: u64,
+    initial_deposit_b: u64,
 ) -> Result<()> {
+    require!(
+        amplification_coefficient <= MAX_AMPLIFICATION_COEFFICIENT,
+        CustomError::InvalidAmplification
+    );
     require!(
         ctx.accounts.token_a_mint.key() != ctx.accounts.token_b_mint.key(),
         CustomError::InvalidAmount
     );
+    require_mints_allowed(
+        &ctx.accounts.amm_config,
+        &[ctx.accounts.token_a_mint.key(), ctx.accounts.token_b_mint.key()],
+    )?;
+    // Reject circular pools built on another pool's LP token
+    for mint in [&ctx.accounts.token_a_mint, &ctx.accounts.token_b_mint] {
+        require!(
+            !is_pool_lp_mint(&mint.key(), mint.mint_authority.into()),
+            CustomError::LpMintAsPoolToken
+        );
+    }
     
     let pool = &mut ctx.accounts.pool;
     let clock = Clock::get()?;
@@ -38,29 +60,140 @@ pub fn initialize_pool(
     
     pool.token_a_decimals = ctx.accounts.token_a_mint.decimals;
     pool.token_b_decimals = ctx.accounts.token_b_mint.decimals;
-    pool.is_stablecoin_pool = false;
+    // A non-zero amplification opts the pool into the StableSwap curve
+    pool.is_stablecoin_pool = amplification_coefficient > 0;
+    pool.amplification_coefficient = amplification_coefficient;
+    pool.protocol_fee_numerator = 0;
+    pool.protocol_fee_vault = Pubkey::default();
+    pool.is_paused = false;
+    pool.flash_fee_bps = DEFAULT_FLASH_FEE_BPS;
     pool.fee_numerator = 3;
     pool.fee_denominator = 1000;
     pool.bump = ctx.bumps.pool;
     pool.created_at = clock.unix_timestamp;
     pool.total_volume_a = 0;
     pool.total_volume_b = 0;
+    pool.cumulative_fees_a = 0;
+    pool.cumulative_fees_b = 0;
+    pool.alignment_boost_bps = 0;
+    pool.alignment_band_bps = 0;
+    pool.dynamic_fee_bps = 0;
+    pool.dynamic_fee_min_bps = 0;
+    pool.dynamic_fee_max_bps = 0;
     pool.total_lp_supply = 0;
     pool.locked_liquidity = 0;
+    pool.locked = false;
+    pool.buy_premium_bps = 0;
+    pool.sell_discount_bps = 0;
+    pool.acc_reward_per_share = 0;
+    pool.boost_shares = 0;
+    pool.last_reward_timestamp = clock.unix_timestamp;
+    pool.rush_emission_index = rush_emission_index_at(&ctx.accounts.rush_config, clock.unix_timestamp)?;
+    pool.init_price_observations(clock.unix_timestamp);
+
+    let position = &mut ctx.accounts.authority_position;
+    position.owner = ctx.accounts.authority.key();
+    position.pool = pool.key();
+    position.lp_tokens = 0;
+    position.deposit_timestamp = clock.unix_timestamp;
+    position.last_claim_timestamp = clock.unix_timestamp;
+    position.total_rush_claimed = 0;
+    position.bump = ctx.bumps.authority_position;
+    position.reward_debt = 0;
+    position.pending_rewards = 0;
+
+    // Seed reserves atomically so no one else can set the opening ratio.
+    // The creator picks the ratio, so the imbalance check does not apply.
+    if initial_deposit_a > 0 || initial_deposit_b > 0 {
+        require!(initial_deposit_a > 0 && initial_deposit_b > 0, CustomError::InvalidAmount);
+        validate_initial_liquidity(initial_deposit_a, initial_deposit_b, MIN_INITIAL_LIQUIDITY)?;
+        let (Some(authority_token_a), Some(authority_token_b)) =
+            (&ctx.accounts.authority_token_a, &ctx.accounts.authority_token_b)
+        else {
+            return err!(CustomError::MissingSeedTokenAccount);
+        };
+        for (from, to, amount) in [
+            (authority_token_a, &ctx.accounts.token_a_vault, initial_deposit_a),
+            (authority_token_b, &ctx.accounts.token_b_vault, initial_deposit_b),
+        ] {
+            transfer(
+                CpiContext::new(
+                    ctx.accounts.token_program.to_account_info(),
+                    Transfer {
+                        from: from.to_account_info(),
+                        to: to.to_account_info(),
+                        authority: ctx.accounts.authority.to_account_info(),
+                    },
+                ),
+                amount,
+            )?;
+        }
+        // Seed with what actually landed; the vaults were created empty above
+        let seeded_a = vault_received(&mut ctx.accounts.token_a_vault, 0)?;
+        let seeded_b = vault_received(&mut ctx.accounts.token_b_vault, 0)?;
+        validate_initial_liquidity(seeded_a, seeded_b, MIN_INITIAL_LIQUIDITY)?;
+        let lp_tokens_to_mint = calculate_lp_tokens_for_add_liquidity(seeded_a, seeded_b, 0, 0, 0)?;
+        pool.reserve_a = seeded_a;
+        pool.reserve_b = seeded_b;
+        pool.locked_liquidity = MINIMUM_LIQUIDITY;
+        pool.total_lp_supply = lp_tokens_to_mint
+            .checked_add(MINIMUM_LIQUIDITY)
+            .ok_or(error!(CustomError::CalculationOverflow))?;
+        let token_a_mint_key = pool.token_a_mint;
+        let token_b_mint_key = pool.token_b_mint;
+        let signer_seeds: &[&[&[u8]]] = &[&[
+            b"pool",
+            token_a_mint_key.as_ref(),
+            token_b_mint_key.as_ref(),
+            &[pool.bump],
+        ]];
+        mint_to(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                MintTo {
+                    mint: ctx.accounts.lp_token_mint.to_account_info(),
+                    to: ctx.accounts.lp_token_account.to_account_info(),
+                    authority: pool.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            lp_tokens_to_mint,
+        )?;
+        position.lp_tokens = lp_tokens_to_mint;
+        emit!(LiquidityAdded {
+            user: ctx.accounts.authority.key(),
+            pool: pool.key(),
+            amount_a: seeded_a,
+            amount_b: seeded_b,
+            lp_tokens_minted: lp_tokens_to_mint,
+            new_reserve_a: pool.reserve_a,
+            new_reserve_b: pool.reserve_b,
+        });
+    }
     
     emit!(PoolCreated {
         pool: pool.key(),
         token_a_mint: ctx.accounts.token_a_mint.key(),
         token_b_mint: ctx.accounts.token_b_mint.key(),
-        reserve_a: 0,
-        reserve_b: 0,
-        lp_token_supply: 0,
+        reserve_a: pool.reserve_a,
+        reserve_b: pool.reserve_b,
+        lp_token_supply: pool.total_lp_supply,
         authority: ctx.accounts.authority.key(),
     });
     
     Ok(())
 }
 
+/// RUSH emission index at `now`, or 0 while the RUSH config PDA has not been created.
+fn rush_emission_index_at(rush_config: &AccountInfo, now: i64) -> Result<u128> {
+    if rush_config.data_is_empty() {
+        return Ok(0);
+    }
+    let data = rush_config.try_borrow_data()?;
+    let mut slice: &[u8] = &data;
+    RushConfig::try_deserialize(&mut slice)?.emission_index_at(now)
+}
+
 pub fn add_liquidity(
     ctx: Context<AddLiquidity>,
     amount_a: u64,
@@ -76,21 +209,15 @@ pub fn add_liquidity(
         ctx.accounts.user_token_b.amount >= amount_b,
         CustomError::InsufficientBalance
     );
+    ctx.accounts.pool.require_active()?;
+    acquire_pool_lock(&mut ctx.accounts.pool)?;
     let pool = &mut ctx.accounts.pool;
-    if pool.total_lp_supply > 0 {
-        validate_ratio_imbalance(amount_a, amount_b, pool.reserve_a, pool.reserve_b)?;
-    }
-    let lp_tokens_to_mint = calculate_lp_tokens_for_add_liquidity(
-        amount_a,
-        amount_b,
-        pool.reserve_a,
-        pool.reserve_b,
-        pool.total_lp_supply,
-    )?;
-    require!(
-        lp_tokens_to_mint >= min_lp_tokens,
-        CustomError::SlippageTooHigh
-    );
+    let rush_config = &ctx.accounts.rush_config;
+    let now = Clock::get()?.unix_timestamp;
+    rush_config.accrue_pool(pool, now)?;
+    let alignment = pool.deposit_alignment(now);
+    let vault_a_before = ctx.accounts.token_a_vault.amount;
+    let vault_b_before = ctx.accounts.token_b_vault.amount;
     transfer(
         CpiContext::new(
             ctx.accounts.token_program.to_account_info(),
@@ -113,6 +240,26 @@ pub fn add_liquidity(
         ),
         amount_b,
     )?;
+    // Credit what actually landed, so fee-on-transfer mints cannot inflate reserves
+    let amount_a = vault_received(&mut ctx.accounts.token_a_vault, vault_a_before)?;
+    let amount_b = vault_received(&mut ctx.accounts.token_b_vault, vault_b_before)?;
+    require!(amount_a > 0 && amount_b > 0, CustomError::InvalidAmount);
+    if pool.total_lp_supply > 0 {
+        validate_ratio_imbalance(amount_a, amount_b, pool.reserve_a, pool.reserve_b)?;
+    } else {
+        validate_initial_liquidity(amount_a, amount_b, MIN_INITIAL_LIQUIDITY)?;
+    }
+    let lp_tokens_to_mint = calculate_lp_tokens_for_add_liquidity(
+        amount_a,
+        amount_b,
+        pool.reserve_a,
+        pool.reserve_b,
+        pool.total_lp_supply,
+    )?;
+    require!(
+        lp_tokens_to_mint >= min_lp_tokens,
+        CustomError::SlippageTooHigh
+    );
     pool.reserve_a = pool
         .reserve_a
         .checked_add(amount_a)
@@ -121,6 +268,11 @@ pub fn add_liquidity(
         .reserve_b
         .checked_add(amount_b)
         .ok_or(error!(CustomError::CalculationOverflow))?;
+    if pool.total_lp_supply == 0 {
+        // Counted in supply but never minted, so no one can redeem it
+        pool.locked_liquidity = MINIMUM_LIQUIDITY;
+        pool.total_lp_supply = MINIMUM_LIQUIDITY;
+    }
     pool.total_lp_supply = pool
         .total_lp_supply
         .checked_add(lp_tokens_to_mint)
@@ -151,14 +303,130 @@ pub fn add_liquidity(
     if user_position.owner == Pubkey::default() {
         user_position.owner = ctx.accounts.user.key();
         user_position.pool = pool_key;
-        user_position.deposit_timestamp = Clock::get()?.unix_timestamp;
+        user_position.deposit_timestamp = now;
+        user_position.last_claim_timestamp = now;
         user_position.bump = ctx.bumps.user_position;
     }
-    user_position.lp_tokens = user_position
-        .lp_tokens
-        .checked_add(lp_tokens_to_mint)
-        .ok_or(error!(CustomError::CalculationOverflow))?;
-    user_position.last_claim_timestamp = Clock::get()?.unix_timestamp;
+    user_position.deposit_lp_tokens(lp_tokens_to_mint, pool, alignment)?;
+    emit!(LiquidityAdded {
+        user: ctx.accounts.user.key(),
+        pool: pool_key,
+        amount_a,
+        amount_b,
+        lp_tokens_minted: lp_tokens_to_mint,
+        new_reserve_a: pool.reserve_a,
+        new_reserve_b: pool.reserve_b,
+    });
+    pool.unlock();
+    Ok(())
+}
+/// Deposit only one token: swap just enough of it to the other side that the
+/// two amounts match the post-swap pool ratio, then add liquidity.
+///
+/// The user pays the pool fee on the swapped part (roughly half the deposit),
+/// so the effective cost is about half the swap fee on `amount_in`. The
+/// protocol's share of that fee stays in the reserves, with the LPs.
+pub fn add_liquidity_single_sided(
+    ctx: Context<AddLiquidity>,
+    amount_in: u64,
+    is_token_a: bool,
+    min_lp_tokens: u64,
+) -> Result<()> {
+    require!(amount_in > 0, CustomError::InvalidAmount);
+    let user_balance = if is_token_a {
+        ctx.accounts.user_token_a.amount
+    } else {
+        ctx.accounts.user_token_b.amount
+    };
+    require!(user_balance >= amount_in, CustomError::InsufficientBalance);
+    ctx.accounts.pool.require_active()?;
+    require!(
+        ctx.accounts.pool.stable_amplification().is_none(),
+        CustomError::SingleSidedUnsupported
+    );
+    acquire_pool_lock(&mut ctx.accounts.pool)?;
+    let pool = &mut ctx.accounts.pool;
+    let rush_config = &ctx.accounts.rush_config;
+    let now = Clock::get()?.unix_timestamp;
+    rush_config.accrue_pool(pool, now)?;
+    let alignment = pool.deposit_alignment(now);
+    let (user_token_in, vault_in) = if is_token_a {
+        (&ctx.accounts.user_token_a, &mut ctx.accounts.token_a_vault)
+    } else {
+        (&ctx.accounts.user_token_b, &mut ctx.accounts.token_b_vault)
+    };
+    let vault_before = vault_in.amount;
+    transfer(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: user_token_in.to_account_info(),
+                to: vault_in.to_account_info(),
+                authority: ctx.accounts.user.to_account_info(),
+            },
+        ),
+        amount_in,
+    )?;
+    // Price what actually landed, so fee-on-transfer mints cannot inflate reserves
+    let amount_in = vault_received(vault_in, vault_before)?;
+    require!(amount_in > 0, CustomError::InvalidAmount);
+    let SingleSidedDeposit {
+        swap_amount,
+        swap_out,
+        fee_amount,
+        amount_a,
+        amount_b,
+        swapped_a,
+        swapped_b,
+        lp_tokens: lp_tokens_to_mint,
+    } = pool.apply_single_sided_deposit(amount_in, is_token_a, now)?;
+    require!(
+        lp_tokens_to_mint >= min_lp_tokens,
+        CustomError::SlippageTooHigh
+    );
+    let pool_key = pool.key();
+    let token_a_mint_key = pool.token_a_mint;
+    let token_b_mint_key = pool.token_b_mint;
+    let bump_seed = pool.bump;
+    let signer_seeds: &[&[&[u8]]] = &[&[
+        b"pool",
+        token_a_mint_key.as_ref(),
+        token_b_mint_key.as_ref(),
+        &[bump_seed],
+    ]];
+    mint_to(
+        CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            MintTo {
+                mint: ctx.accounts.lp_token_mint.to_account_info(),
+                to: ctx.accounts.user_lp_token_account.to_account_info(),
+                authority: pool.to_account_info(),
+            },
+            signer_seeds,
+        ),
+        lp_tokens_to_mint,
+    )?;
+    let user_position = &mut ctx.accounts.user_position;
+    if user_position.owner == Pubkey::default() {
+        user_position.owner = ctx.accounts.user.key();
+        user_position.pool = pool_key;
+        user_position.deposit_timestamp = now;
+        user_position.last_claim_timestamp = now;
+        user_position.bump = ctx.bumps.user_position;
+    }
+    user_position.deposit_lp_tokens(lp_tokens_to_mint, pool, alignment)?;
+    emit!(SwapExecuted {
+        user: ctx.accounts.user.key(),
+        pool: pool_key,
+        amount_in: swap_amount,
+        amount_out: swap_out,
+        fee_amount,
+        protocol_fee: 0,
+        fee_token: pool.input_mint(is_token_a),
+        is_a_to_b: is_token_a,
+        new_reserve_a: swapped_a,
+        new_reserve_b: swapped_b,
+    });
     emit!(LiquidityAdded {
         user: ctx.accounts.user.key(),
         pool: pool_key,
@@ -168,6 +436,7 @@ pub fn add_liquidity(
         new_reserve_a: pool.reserve_a,
         new_reserve_b: pool.reserve_b,
     });
+    pool.unlock();
     Ok(())
 }
 pub fn remove_liquidity(
@@ -175,6 +444,20 @@ pub fn remove_liquidity(
     lp_tokens_to_burn: u64,
     min_amount_a: u64,
     min_amount_b: u64,
+) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    ctx.accounts.user_position.require_unlocked(now)?;
+    withdraw_liquidity(ctx, lp_tokens_to_burn, min_amount_a, min_amount_b, now)
+}
+
+/// Burn `lp_tokens_to_burn` of an unlocked position and pay out its share of
+/// both reserves; the body of `remove_liquidity` and `emergency_remove_liquidity`.
+fn withdraw_liquidity(
+    ctx: Context<RemoveLiquidity>,
+    lp_tokens_to_burn: u64,
+    min_amount_a: u64,
+    min_amount_b: u64,
+    now: i64,
 ) -> Result<()> {
     require!(lp_tokens_to_burn > 0, CustomError::InvalidAmount);
     require!(
@@ -185,7 +468,10 @@ pub fn remove_liquidity(
         ctx.accounts.user_position.lp_tokens >= lp_tokens_to_burn,
         CustomError::InsufficientLPBalance
     );
+    acquire_pool_lock(&mut ctx.accounts.pool)?;
     let pool = &mut ctx.accounts.pool;
+    let rush_config = &ctx.accounts.rush_config;
+    rush_config.accrue_pool(pool, now)?;
     let (amount_a, amount_b) = calculate_remove_liquidity_amounts(
         lp_tokens_to_burn,
         pool.total_lp_supply,
@@ -260,10 +546,7 @@ pub fn remove_liquidity(
         amount_b,
     )?;
     let user_position = &mut ctx.accounts.user_position;
-    user_position.lp_tokens = user_position
-        .lp_tokens
-        .checked_sub(lp_tokens_to_burn)
-        .ok_or(error!(CustomError::InsufficientLPBalance))?;
+    user_position.withdraw_lp_tokens(lp_tokens_to_burn, pool)?;
     emit!(LiquidityRemoved {
         user: ctx.accounts.user.key(),
         pool: pool_key,
@@ -273,9 +556,34 @@ pub fn remove_liquidity(
         new_reserve_a: pool.reserve_a,
         new_reserve_b: pool.reserve_b,
     });
+    pool.unlock();
     Ok(())
 }
 
+/// Withdraw `bps` (1–10000) of the caller's position.
+pub fn remove_liquidity_percent(
+    ctx: Context<RemoveLiquidity>,
+    bps: u16,
+    min_amount_a: u64,
+    min_amount_b: u64,
+) -> Result<()> {
+    let lp_tokens_to_burn = calculate_lp_tokens_for_percent(ctx.accounts.user_position.lp_tokens, bps)?;
+    remove_liquidity(ctx, lp_tokens_to_burn, min_amount_a, min_amount_b)
+}
+
+/// Exit at the proportional share with no slippage floor. Only available while
+/// the pool is paused, when reserves may be in a state no `min_amount` can predict.
+/// A lock does not hold the LP in a paused pool: it ends here and its boost is forfeited.
+pub fn emergency_remove_liquidity(
+    ctx: Context<RemoveLiquidity>,
+    lp_tokens_to_burn: u64,
+) -> Result<()> {
+    ctx.accounts.pool.require_paused()?;
+    let now = Clock::get()?.unix_timestamp;
+    ctx.accounts.user_position.forfeit_lock(now);
+    withdraw_liquidity(ctx, lp_tokens_to_burn, 0, 0, now)
+}
+
 pub fn close_pool(ctx: Context<ClosePool>) -> Result<()> {
     let pool = &ctx.accounts.pool;
     require!(pool.total_lp_supply == 0, CustomError::PoolNotEmpty);
@@ -321,6 +629,247 @@ pub fn close_pool(ctx: Context<ClosePool>) -> Result<()> {
     Ok(())
 }
 
+pub fn set_market_spread(
+    ctx: Context<SetMarketSpread>,
+    buy_premium_bps: u16,
+    sell_discount_bps: u16,
+) -> Result<()> {
+    require!(
+        buy_premium_bps <= MAX_MARKET_SPREAD_BPS && sell_discount_bps <= MAX_MARKET_SPREAD_BPS,
+        CustomError::InvalidFeeParameters
+    );
+    let pool = &mut ctx.accounts.pool;
+    pool.buy_premium_bps = buy_premium_bps;
+    pool.sell_discount_bps = sell_discount_bps;
+    emit!(MarketSpreadUpdated {
+        pool: pool.key(),
+        buy_premium_bps,
+        sell_discount_bps,
+        updated_by: ctx.accounts.authority.key(),
+    });
+    Ok(())
+}
+pub fn set_protocol_fee(
+    ctx: Context<SetProtocolFee>,
+    protocol_fee_numerator: u64,
+    protocol_fee_vault: Pubkey,
+) -> Result<()> {
+    let pool = &mut ctx.accounts.pool;
+    require!(
+        protocol_fee_numerator <= pool.fee_numerator,
+        CustomError::InvalidFeeParameters
+    );
+    pool.protocol_fee_numerator = protocol_fee_numerator;
+    pool.protocol_fee_vault = protocol_fee_vault;
+    emit!(ProtocolFeeUpdated {
+        pool: pool.key(),
+        protocol_fee_numerator,
+        protocol_fee_vault,
+        updated_by: ctx.accounts.authority.key(),
+    });
+    Ok(())
+}
+/// Set the swap fee in bps, within the band on the AMM config.
+pub fn set_pool_fee(ctx: Context<SetPoolFee>, fee_bps: u16) -> Result<()> {
+    require!(
+        ctx.accounts.amm_config.is_fee_in_band(fee_bps),
+        CustomError::InvalidFeeParameters
+    );
+    let pool = &mut ctx.accounts.pool;
+    // Keep the protocol's share over the same denominator as the fee
+    let protocol_fee_numerator = (pool.protocol_fee_numerator as u128)
+        .checked_mul(FEE_BPS_DENOMINATOR as u128)
+        .ok_or(error!(CustomError::CalculationOverflow))?
+        / (pool.fee_denominator as u128);
+    require!(
+        protocol_fee_numerator <= fee_bps as u128,
+        CustomError::InvalidFeeParameters
+    );
+    pool.protocol_fee_numerator = protocol_fee_numerator as u64;
+    pool.fee_numerator = fee_bps as u64;
+    pool.fee_denominator = FEE_BPS_DENOMINATOR;
+    pool.dynamic_fee_min_bps = ctx.accounts.amm_config.min_fee_bps;
+    pool.dynamic_fee_max_bps = ctx.accounts.amm_config.max_fee_bps;
+    emit!(PoolFeeUpdated {
+        pool: pool.key(),
+        fee_numerator: pool.fee_numerator,
+        fee_denominator: pool.fee_denominator,
+        updated_by: ctx.accounts.authority.key(),
+    });
+    Ok(())
+}
+pub fn set_pool_paused(ctx: Context<SetPoolPaused>, is_paused: bool) -> Result<()> {
+    let pool = &mut ctx.accounts.pool;
+    pool.is_paused = is_paused;
+    emit!(PoolPauseUpdated {
+        pool: pool.key(),
+        is_paused,
+        updated_by: ctx.accounts.authority.key(),
+    });
+    Ok(())
+}
+pub fn set_flash_fee(ctx: Context<SetFlashFee>, flash_fee_bps: u16) -> Result<()> {
+    require!(flash_fee_bps <= MAX_FLASH_FEE_BPS, CustomError::InvalidFeeParameters);
+    ctx.accounts.pool.flash_fee_bps = flash_fee_bps;
+    Ok(())
+}
+/// Configure the LP reward boost for deposits made near the pool's TWAP.
+/// `boost_bps` = 0 turns it off; otherwise `band_bps` must be non-zero.
+pub fn set_alignment_boost(ctx: Context<SetAlignmentBoost>, boost_bps: u16, band_bps: u16) -> Result<()> {
+    require!(boost_bps <= MAX_ALIGNMENT_BOOST_BPS, CustomError::InvalidAPY);
+    require!(boost_bps == 0 || band_bps > 0, CustomError::InvalidAPY);
+    let pool = &mut ctx.accounts.pool;
+    pool.alignment_boost_bps = boost_bps;
+    pool.alignment_band_bps = band_bps;
+    Ok(())
+}
+/// Switch the pool to dynamic fees: swaps that pull spot toward the TWAP pay
+/// `dynamic_fee_bps` less of the fee, swaps that push it away pay that much
+/// more, always within the AMM config's fee band and above the protocol fee.
+pub fn set_dynamic_fee(ctx: Context<SetDynamicFee>, dynamic_fee_bps: u16) -> Result<()> {
+    require!(dynamic_fee_bps <= MAX_DYNAMIC_FEE_BPS, CustomError::InvalidFeeParameters);
+    let pool = &mut ctx.accounts.pool;
+    pool.dynamic_fee_bps = dynamic_fee_bps;
+    pool.dynamic_fee_min_bps = ctx.accounts.amm_config.min_fee_bps;
+    pool.dynamic_fee_max_bps = ctx.accounts.amm_config.max_fee_bps;
+    Ok(())
+}
+/// Align stored reserves with the vault balances after direct transfers or drift.
+pub fn sync_reserves(ctx: Context<SyncReserves>) -> Result<()> {
+    let pool = &mut ctx.accounts.pool;
+    require!(!pool.locked, CustomError::PoolLocked);
+    // Close the TWAP interval at the old reserves before they jump
+    pool.update_price_cumulatives(Clock::get()?.unix_timestamp);
+    let (delta_a, delta_b) = pool.sync_to_vaults(
+        ctx.accounts.token_a_vault.amount,
+        ctx.accounts.token_b_vault.amount,
+    );
+    emit!(ReservesSynced {
+        pool: pool.key(),
+        reserve_a: pool.reserve_a,
+        reserve_b: pool.reserve_b,
+        delta_a,
+        delta_b,
+        synced_by: ctx.accounts.authority.key(),
+    });
+    Ok(())
+}
+
+/// Lifetime swap fees of a pool and the share owned by one LP position.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PoolFees {
+    pub cumulative_fees_a: u64,
+    pub cumulative_fees_b: u64,
+    /// `cumulative_fees_a` pro rata to the position's current LP tokens.
+    pub position_fees_a: u64,
+    /// `cumulative_fees_b` pro rata to the position's current LP tokens.
+    pub position_fees_b: u64,
+}
+
+pub fn get_pool_fees(ctx: Context<GetPoolFees>) -> Result<PoolFees> {
+    let pool = &ctx.accounts.pool;
+    let (position_fees_a, position_fees_b) = pool.fee_share(ctx.accounts.position.lp_tokens);
+    Ok(PoolFees {
+        cumulative_fees_a: pool.cumulative_fees_a,
+        cumulative_fees_b: pool.cumulative_fees_b,
+        position_fees_a,
+        position_fees_b,
+    })
+}
+
+#[derive(Accounts)]
+pub struct GetPoolFees<'info> {
+    pub pool: Account<'info, LiquidityPool>,
+    #[account(constraint = position.pool == pool.key() @ CustomError::InvalidPool)]
+    pub position: Account<'info, UserLiquidityPosition>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PositionValue {
+    pub lp_tokens: u64,
+    /// Token A a full `remove_liquidity` of the position would return now.
+    pub amount_a: u64,
+    /// Token B a full `remove_liquidity` of the position would return now.
+    pub amount_b: u64,
+    /// Both amounts valued in token B at the spot price.
+    pub value_in_b: u64,
+    /// `value_in_b` in USD (scaled by 1e6) when a token B / USD oracle is passed.
+    pub usd_value: Option<u64>,
+}
+
+/// Current redemption value of the position's full LP balance.
+pub fn get_position_value(ctx: Context<GetPositionValue>) -> Result<PositionValue> {
+    let pool = &ctx.accounts.pool;
+    let lp_tokens = ctx.accounts.position.lp_tokens;
+    let (amount_a, amount_b) = if lp_tokens == 0 {
+        (0, 0)
+    } else {
+        calculate_remove_liquidity_amounts(lp_tokens, pool.total_lp_supply, pool.reserve_a, pool.reserve_b)?
+    };
+    let a_in_b = if pool.reserve_a == 0 {
+        0
+    } else {
+        (amount_a as u128) * (pool.reserve_b as u128) / (pool.reserve_a as u128)
+    };
+    let value_in_b = u64::try_from(a_in_b + amount_b as u128)
+        .map_err(|_| error!(CustomError::CalculationOverflow))?;
+    let usd_value = match &ctx.accounts.usd_oracle {
+        Some(oracle) => {
+            require!(oracle.price_i64 > 0, CustomError::OraclePriceUnavailable);
+            let usd = (value_in_b as u128) * (oracle.price_i64 as u128)
+                / 10u128.pow(pool.token_b_decimals as u32);
+            Some(u64::try_from(usd).map_err(|_| error!(CustomError::CalculationOverflow))?)
+        }
+        None => None,
+    };
+    Ok(PositionValue {
+        lp_tokens,
+        amount_a,
+        amount_b,
+        value_in_b,
+        usd_value,
+    })
+}
+
+#[derive(Accounts)]
+pub struct GetPositionValue<'info> {
+    pub pool: Account<'info, LiquidityPool>,
+    #[account(constraint = position.pool == pool.key() @ CustomError::InvalidPool)]
+    pub position: Account<'info, UserLiquidityPosition>,
+    /// Token B price in USD, scaled by 1e6 (a `set_perps_oracle_price` feed).
+    pub usd_oracle: Option<Account<'info, PerpsOraclePrice>>,
+}
+
+/// LP supply split into what holders can redeem and what is locked for good.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PoolLiquidity {
+    pub total_lp_supply: u64,
+    /// `MINIMUM_LIQUIDITY` counted in supply on the first deposit but never minted.
+    pub locked_liquidity: u64,
+    pub redeemable_lp_supply: u64,
+    /// Token A backing the locked liquidity at current reserves.
+    pub locked_amount_a: u64,
+    /// Token B backing the locked liquidity at current reserves.
+    pub locked_amount_b: u64,
+}
+
+pub fn get_pool_liquidity(ctx: Context<GetPoolLiquidity>) -> Result<PoolLiquidity> {
+    let pool = &ctx.accounts.pool;
+    let (locked_amount_a, locked_amount_b) = pool.locked_reserves()?;
+    Ok(PoolLiquidity {
+        total_lp_supply: pool.total_lp_supply,
+        locked_liquidity: pool.locked_liquidity,
+        redeemable_lp_supply: pool.redeemable_lp_supply(),
+        locked_amount_a,
+        locked_amount_b,
+    })
+}
+
+#[derive(Accounts)]
+pub struct GetPoolLiquidity<'info> {
+    pub pool: Account<'info, LiquidityPool>,
+}
+
 #[derive(Accounts)]
 pub struct InitializePool<'info> {
     #[account(
@@ -333,6 +882,12 @@ pub struct InitializePool<'info> {
     pub pool: Box<Account<'info, LiquidityPool>>,
     pub token_a_mint: Box<Account<'info, Mint>>,
     pub token_b_mint: Box<Account<'info, Mint>>,
+    /// CHECK: AMM config PDA; may be uninitialized, in which case creation is permissionless
+    #[account(seeds = [b"amm_config"], bump)]
+    pub amm_config: UncheckedAccount<'info>,
+    /// CHECK: RUSH config PDA; may be uninitialized, in which case emission starts from index 0
+    #[account(seeds = [b"rush_config"], bump)]
+    pub rush_config: UncheckedAccount<'info>,
     #[account(
         init,
         payer = authority,
@@ -363,6 +918,19 @@ pub struct InitializePool<'info> {
         associated_token::authority = authority
     )]
     pub lp_token_account: Box<Account<'info, TokenAccount>>,
+    #[account(
+        init,
+        payer = authority,
+        space = UserLiquidityPosition::SIZE,
+        seeds = [b"position", pool.key().as_ref(), authority.key().as_ref()],
+        bump
+    )]
+    pub authority_position: Box<Account<'info, UserLiquidityPosition>>,
+    /// Only needed when seeding initial liquidity.
+    #[account(mut, token::mint = token_a_mint, token::authority = authority)]
+    pub authority_token_a: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(mut, token::mint = token_b_mint, token::authority = authority)]
+    pub authority_token_b: Option<Box<Account<'info, TokenAccount>>>,
     #[account(mut)]
     pub authority: Signer<'info>,
     pub associated_token_program: Program<'info, AssociatedToken>,
@@ -392,10 +960,110 @@ pub struct ClosePool<'info> {
     pub token_program: Program<'info, Token>,
 }
 
+#[derive(Accounts)]
+pub struct SetMarketSpread<'info> {
+    #[account(
+        mut,
+        has_one = authority @ CustomError::InvalidAuthority
+    )]
+    pub pool: Account<'info, LiquidityPool>,
+    pub authority: Signer<'info>,
+}
+#[derive(Accounts)]
+pub struct SetProtocolFee<'info> {
+    #[account(
+        mut,
+        has_one = authority @ CustomError::InvalidAuthority
+    )]
+    pub pool: Account<'info, LiquidityPool>,
+    pub authority: Signer<'info>,
+}
+#[derive(Accounts)]
+pub struct SetPoolPaused<'info> {
+    #[account(
+        mut,
+        has_one = authority @ CustomError::InvalidAuthority
+    )]
+    pub pool: Account<'info, LiquidityPool>,
+    pub authority: Signer<'info>,
+}
+#[derive(Accounts)]
+pub struct SetPoolFee<'info> {
+    #[account(
+        mut,
+        has_one = authority @ CustomError::InvalidAuthority
+    )]
+    pub pool: Account<'info, LiquidityPool>,
+    #[account(
+        seeds = [b"amm_config"],
+        bump = amm_config.bump
+    )]
+    pub amm_config: Account<'info, AmmConfig>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetFlashFee<'info> {
+    #[account(
+        mut,
+        has_one = authority @ CustomError::InvalidAuthority
+    )]
+    pub pool: Account<'info, LiquidityPool>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetAlignmentBoost<'info> {
+    #[account(
+        mut,
+        has_one = authority @ CustomError::InvalidAuthority
+    )]
+    pub pool: Account<'info, LiquidityPool>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetDynamicFee<'info> {
+    #[account(
+        mut,
+        has_one = authority @ CustomError::InvalidAuthority
+    )]
+    pub pool: Account<'info, LiquidityPool>,
+    #[account(
+        seeds = [b"amm_config"],
+        bump = amm_config.bump
+    )]
+    pub amm_config: Account<'info, AmmConfig>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SyncReserves<'info> {
+    #[account(
+        mut,
+        has_one = authority @ CustomError::InvalidAuthority
+    )]
+    pub pool: Account<'info, LiquidityPool>,
+    #[account(
+        constraint = token_a_vault.key() == pool.token_a_vault @ CustomError::InvalidVault
+    )]
+    pub token_a_vault: Account<'info, TokenAccount>,
+    #[account(
+        constraint = token_b_vault.key() == pool.token_b_vault @ CustomError::InvalidVault
+    )]
+    pub token_b_vault: Account<'info, TokenAccount>,
+    pub authority: Signer<'info>,
+}
+
 #[derive(Accounts)]
 pub struct AddLiquidity<'info> {
     #[account(mut)]
     pub pool: Account<'info, LiquidityPool>,
+    #[account(
+        seeds = [b"rush_config"],
+        bump = rush_config.bump
+    )]
+    pub rush_config: Account<'info, RushConfig>,
     #[account(
         mut,
         constraint = lp_token_mint.key() == pool.lp_token_mint @ CustomError::InvalidMint
@@ -411,12 +1079,14 @@ pub struct AddLiquidity<'info> {
     pub user_position: Account<'info, UserLiquidityPosition>,
     #[account(
         mut,
-        constraint = token_a_vault.key() == pool.token_a_vault @ CustomError::InvalidVault
+        constraint = token_a_vault.key() == pool.token_a_vault @ CustomError::InvalidVault,
+        constraint = token_a_vault.mint == pool.token_a_mint @ CustomError::InvalidMint
     )]
     pub token_a_vault: Account<'info, TokenAccount>,
     #[account(
         mut,
-        constraint = token_b_vault.key() == pool.token_b_vault @ CustomError::InvalidVault
+        constraint = token_b_vault.key() == pool.token_b_vault @ CustomError::InvalidVault,
+        constraint = token_b_vault.mint == pool.token_b_mint @ CustomError::InvalidMint
     )]
     pub token_b_vault: Account<'info, TokenAccount>,
     #[account(
@@ -447,6 +1117,11 @@ pub struct AddLiquidity<'info> {
 pub struct RemoveLiquidity<'info> {
     #[account(mut)]
     pub pool: Account<'info, LiquidityPool>,
+    #[account(
+        seeds = [b"rush_config"],
+        bump = rush_config.bump
+    )]
+    pub rush_config: Account<'info, RushConfig>,
     #[account(
         mut,
         constraint = lp_token_mint.key() == pool.lp_token_mint @ CustomError::InvalidMint
@@ -1,32 +1,32 @@
 use anchor_lang::prelude::*;
 use anchor_spl::{
     associated_token::AssociatedToken,
-    token::{Token, TokenAccount, Mint, MintTo, mint_to},
+    token::{Token, TokenAccount, Mint, MintTo, mint_to, Transfer, transfer},
 };
 use crate::state::{RushConfig, UserLiquidityPosition, LiquidityPool};
 use crate::errors::CustomError;
-use crate::events::{RushTokenInitialized, RewardsClaimed, RewardsConfigUpdated, RewardsPaused};
+use crate::events::{LiquidityAdded, RushTokenInitialized, RewardsClaimed, RewardsCompounded, LiquidityLocked, RewardProgramStarted, ProgramRewardsClaimed, RewardsConfigUpdated, RewardsPaused, ClaimIntervalUpdated, MinRewardsLiquidityUpdated, HalvingIntervalUpdated, AuthorityTransferred, PoolWeightUpdated, SwapExecuted};
+use crate::instructions::swap::{quote_pool_swap, SwapQuote};
+use crate::utils::acquire_pool_lock;
+use crate::instructions::perps::read_oracle_price;
+use crate::utils::calculate_remove_liquidity_amounts;
+use crate::constants::{DEFAULT_MIN_CLAIM_INTERVAL_SECS, MAX_MIN_CLAIM_INTERVAL_SECS, DEFAULT_MAX_ORACLE_STALENESS_SECS};
 pub fn initialize_rush_token(
     ctx: Context<InitializeRushToken>,
 ) -> Result<()> {
-    const RUSH_DECIMALS: u8 = 6;
-    const MAX_RUSH_SUPPLY: u64 = 1_000_000;
     const MAX_RUSH_SUPPLY_BASE: u64 = 1_000_000 * 1_000_000;
     const APY_NUMERATOR: u64 = 50;
     const APY_DENOMINATOR: u64 = 100;
-    const SECONDS_PER_YEAR: u64 = 31_536_000;
-    let yearly_rewards = (MAX_RUSH_SUPPLY as u128 * APY_NUMERATOR as u128)
-        .checked_div(APY_DENOMINATOR as u128)
-        .ok_or(error!(CustomError::CalculationOverflow))? as u64;
     require!(
-        yearly_rewards <= MAX_RUSH_SUPPLY,
+        APY_NUMERATOR <= APY_DENOMINATOR,
         CustomError::InvalidAmount
     );
-    let rewards_per_second_base = (yearly_rewards as u128)
-        .checked_mul(10u128.pow(RUSH_DECIMALS as u32))
-        .ok_or(error!(CustomError::CalculationOverflow))?
-        .checked_div(SECONDS_PER_YEAR as u128)
-        .ok_or(error!(CustomError::CalculationOverflow))? as u64;
+    // Same base-unit formula as update_rush_apy
+    let rewards_per_second_base = RushConfig::rewards_per_second_for_apy(
+        MAX_RUSH_SUPPLY_BASE,
+        APY_NUMERATOR,
+        APY_DENOMINATOR,
+    )?;
     let rush_config = &mut ctx.accounts.rush_config;
     let now_timestamp = Clock::get()?.unix_timestamp;
     rush_config.mint = ctx.accounts.rush_mint.key();
@@ -39,6 +39,17 @@ pub fn initialize_rush_token(
     rush_config.start_timestamp = now_timestamp;
     rush_config.is_paused = false;
     rush_config.bump = ctx.bumps.rush_config;
+    rush_config.min_claim_interval_secs = DEFAULT_MIN_CLAIM_INTERVAL_SECS;
+    rush_config.min_lp_supply_for_rewards = 0;
+    rush_config.halving_interval_secs = 0;
+    rush_config.pending_authority = Pubkey::default();
+    rush_config.total_pool_weight_bps = 0;
+    rush_config.pool_weights_since = i64::MAX;
+    rush_config.emission_index = 0;
+    rush_config.emission_index_ts = now_timestamp;
+    rush_config.pool_weights_index = u128::MAX;
+    rush_config.paused_at = 0;
+    rush_config.resumed_at = 0;
     emit!(RushTokenInitialized {
         rush_mint: ctx.accounts.rush_mint.key(),
         rush_config: rush_config.key(),
@@ -51,37 +62,92 @@ pub fn initialize_rush_token(
     });
     Ok(())
 }
+/// RUSH the position could claim now.  Near supply exhaustion this is the
+/// remaining supply rather than an error, matching what a claim would mint.
 pub fn calculate_pending_rewards(
     ctx: Context<CalculateRewards>,
 ) -> Result<u64> {
+    let current_time = Clock::get()?.unix_timestamp;
+    pending_rewards(&ctx.accounts.position, &ctx.accounts.pool, &ctx.accounts.rush_config, current_time)
+}
+fn pending_rewards(
+    position: &UserLiquidityPosition,
+    pool: &LiquidityPool,
+    rush_config: &RushConfig,
+    current_time: i64,
+) -> Result<u64> {
+    let acc_reward_per_share = rush_config.projected_acc_reward_per_share(pool, current_time)?;
+    Ok(rush_config.claimable_rewards(position.accrued_rewards(acc_reward_per_share, current_time)?))
+}
+/// Rewards and value of an LP position returned by `get_user_rewards_info`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct UserRewardsInfo {
+    pub pending_rewards: u64,
+    pub total_rush_claimed: u64,
+    /// Redemption value of the position in USD, scaled by 1e6.
+    pub position_value_usd: u64,
+    /// True when no token price feed was passed and both tokens were counted at $1.
+    pub usd_value_is_estimate: bool,
+    /// Yearly RUSH at the RUSH price over `position_value_usd`; `None` without a RUSH price feed.
+    pub effective_apy_bps: Option<u64>,
+}
+/// Price feeds (Pyth, or a `set_perps_oracle_price` feed) are USD per whole
+/// token scaled by 1e6; one token price is enough to value the position.
+pub fn get_user_rewards_info(ctx: Context<GetUserRewardsInfo>) -> Result<UserRewardsInfo> {
     let position = &ctx.accounts.position;
     let pool = &ctx.accounts.pool;
     let rush_config = &ctx.accounts.rush_config;
     let current_time = Clock::get()?.unix_timestamp;
-    require!(position.lp_tokens > 0, CustomError::InvalidAmount);
-    require!(pool.total_lp_supply > 0, CustomError::InsufficientLiquidity);
+    let read_price = |feed: &Option<UncheckedAccount>| -> Result<Option<u64>> {
+        let Some(feed) = feed else {
+            return Ok(None);
+        };
+        let price = read_oracle_price(&feed.to_account_info(), DEFAULT_MAX_ORACLE_STALENESS_SECS)?;
+        require!(price > 0, CustomError::OraclePriceUnavailable);
+        Ok(Some(price as u64))
+    };
+    let (amount_a, amount_b) = if position.lp_tokens == 0 {
+        (0, 0)
+    } else {
+        calculate_remove_liquidity_amounts(position.lp_tokens, pool.total_lp_supply, pool.reserve_a, pool.reserve_b)?
+    };
+    let priced_value = pool.usd_value(
+        amount_a,
+        amount_b,
+        read_price(&ctx.accounts.token_a_price_feed)?,
+        read_price(&ctx.accounts.token_b_price_feed)?,
+    );
+    let position_value_usd = priced_value.unwrap_or_else(|| pool.naive_usd_value(amount_a, amount_b));
+    let yearly_rewards = rush_config.yearly_position_rewards(position.reward_shares(), pool, current_time);
+    let effective_apy_bps = read_price(&ctx.accounts.rush_price_feed)?
+        .map(|rush_price| RushConfig::effective_apy_bps(yearly_rewards, rush_price, position_value_usd));
+    Ok(UserRewardsInfo {
+        pending_rewards: pending_rewards(position, pool, rush_config, current_time)?,
+        total_rush_claimed: position.total_rush_claimed,
+        position_value_usd,
+        usd_value_is_estimate: priced_value.is_none(),
+        effective_apy_bps,
+    })
+}
+/// Accrue the pool, check a claim can go ahead, and book `position`'s
+/// rewards as claimed.  Returns `(rewards, time_elapsed)`; the caller mints them.
+fn take_claimable_rewards(
+    position: &mut UserLiquidityPosition,
+    pool: &mut LiquidityPool,
+    rush_config: &mut RushConfig,
+    current_time: i64,
+) -> Result<(u64, u64)> {
+    require!(!rush_config.is_paused, CustomError::InvalidAmount);
     let time_elapsed = current_time
         .checked_sub(position.last_claim_timestamp)
         .ok_or(error!(CustomError::CalculationOverflow))? as u64;
-    if time_elapsed == 0 {
-        return Ok(0);
-    }
-    let user_share_fixed = (position.lp_tokens as u128)
-        .checked_mul(1_000_000_000_000u128)
-        .ok_or(error!(CustomError::CalculationOverflow))?
-        .checked_div(pool.total_lp_supply as u128)
-        .ok_or(error!(CustomError::CalculationOverflow))?;
-    let period_rewards_fixed = (rush_config.rewards_per_second as u128)
-        .checked_mul(time_elapsed as u128)
-        .ok_or(error!(CustomError::CalculationOverflow))?;
-    let user_rewards_fixed = period_rewards_fixed
-        .checked_mul(user_share_fixed)
-        .ok_or(error!(CustomError::CalculationOverflow))?
-        .checked_div(1_000_000_000_000u128)
-        .ok_or(error!(CustomError::CalculationOverflow))?;
-    let user_rewards = user_rewards_fixed
-        .try_into()
-        .map_err(|_| error!(CustomError::CalculationOverflow))?;
+    require!(
+        rush_config.claim_interval_elapsed(time_elapsed),
+        CustomError::ClaimTooSoon
+    );
+    rush_config.accrue_pool(pool, current_time)?;
+    let user_rewards = rush_config.claimable_rewards(position.accrued_rewards(pool.acc_reward_per_share, current_time)?);
+    require!(user_rewards > 0, CustomError::InvalidAmount);
     let new_minted_total = rush_config.minted_so_far
         .checked_add(user_rewards)
         .ok_or(error!(CustomError::CalculationOverflow))?;
@@ -89,45 +155,24 @@ pub fn calculate_pending_rewards(
         new_minted_total <= rush_config.total_supply,
         CustomError::InvalidAmount
     );
-    Ok(user_rewards)
+    position.pending_rewards = 0;
+    position.sync_boost_shares(pool)?;
+    position.last_claim_timestamp = current_time;
+    position.total_rush_claimed = position.total_rush_claimed
+        .checked_add(user_rewards)
+        .ok_or(error!(CustomError::CalculationOverflow))?;
+    rush_config.minted_so_far = new_minted_total;
+    Ok((user_rewards, time_elapsed))
 }
 pub fn claim_rush_rewards(
     ctx: Context<ClaimRewards>,
 ) -> Result<()> {
     let position = &mut ctx.accounts.position;
-    let pool = &ctx.accounts.pool;
+    let pool = &mut ctx.accounts.pool;
     let rush_config = &mut ctx.accounts.rush_config;
     let current_time = Clock::get()?.unix_timestamp;
-    require!(!rush_config.is_paused, CustomError::InvalidAmount);
-    require!(position.lp_tokens > 0, CustomError::InvalidAmount);
-    require!(pool.total_lp_supply > 0, CustomError::InsufficientLiquidity);
-    let time_elapsed = current_time
-        .checked_sub(position.last_claim_timestamp)
-        .ok_or(error!(CustomError::CalculationOverflow))? as u64;
-    let user_share_fixed = (position.lp_tokens as u128)
-        .checked_mul(1_000_000_000_000u128)
-        .ok_or(error!(CustomError::CalculationOverflow))?
-        .checked_div(pool.total_lp_supply as u128)
-        .ok_or(error!(CustomError::CalculationOverflow))?;
-    let period_rewards_fixed = (rush_config.rewards_per_second as u128)
-        .checked_mul(time_elapsed as u128)
-        .ok_or(error!(CustomError::CalculationOverflow))?;
-    let user_rewards_fixed = period_rewards_fixed
-        .checked_mul(user_share_fixed)
-        .ok_or(error!(CustomError::CalculationOverflow))?
-        .checked_div(1_000_000_000_000u128)
-        .ok_or(error!(CustomError::CalculationOverflow))?;
-    let user_rewards = user_rewards_fixed
-        .try_into()
-        .map_err(|_| error!(CustomError::CalculationOverflow))?;
-    require!(user_rewards > 0, CustomError::InvalidAmount);
-    let new_minted_total = rush_config.minted_so_far
-        .checked_add(user_rewards)
-        .ok_or(error!(CustomError::CalculationOverflow))?;
-    require!(
-        new_minted_total <= rush_config.total_supply,
-        CustomError::InvalidAmount
-    );
+    let (user_rewards, time_elapsed) =
+        take_claimable_rewards(position, pool, rush_config, current_time)?;
     let bump_seed = rush_config.bump;
     let signer_seeds: &[&[&[u8]]] = &[&[b"rush_config", &[bump_seed]]];
     mint_to(
@@ -142,22 +187,329 @@ pub fn claim_rush_rewards(
         ),
         user_rewards,
     )?;
-    position.last_claim_timestamp = current_time;
-    position.total_rush_claimed = position.total_rush_claimed
-        .checked_add(user_rewards)
-        .ok_or(error!(CustomError::CalculationOverflow))?;
-    rush_config.minted_so_far = new_minted_total;
+    emit_rewards_claimed(ctx.accounts.user.key(), position, pool, user_rewards, time_elapsed, current_time);
+    Ok(())
+}
+fn emit_rewards_claimed(
+    user: Pubkey,
+    position: &Account<UserLiquidityPosition>,
+    pool: &Account<LiquidityPool>,
+    user_rewards: u64,
+    time_elapsed: u64,
+    current_time: i64,
+) {
+    let user_lp_share = if pool.total_lp_supply == 0 {
+        0.0
+    } else {
+        position.lp_tokens as f64 / pool.total_lp_supply as f64
+    };
     emit!(RewardsClaimed {
-        user: ctx.accounts.user.key(),
+        user,
         position: position.key(),
         pool: pool.key(),
         rewards_amount: user_rewards,
         rewards_display: user_rewards as f64 / 1_000_000.0,
         time_elapsed: time_elapsed as i64,
-        user_lp_share: user_share_fixed as f64 / 1_000_000_000_000.0,
+        user_lp_share,
         claimed_at: current_time,
         total_claimed_lifetime: position.total_rush_claimed,
     });
+}
+/// Claim pending RUSH and re-add it to the same pool as liquidity, atomically.
+///
+/// If the pool holds RUSH itself the rewards are deposited single-sided as-is.
+/// Otherwise they are swapped through `rush_pool`, a RUSH pool paired with
+/// one of this pool's tokens, and that token is deposited single-sided.  The
+/// whole `rush_pool` swap fee stays with its LPs (no protocol cut).  Emits
+/// the `RewardsClaimed` and `LiquidityAdded` a claim and deposit would.
+pub fn compound_rewards(ctx: Context<CompoundRewards>, min_lp_tokens: u64) -> Result<()> {
+    let current_time = Clock::get()?.unix_timestamp;
+    let rush_mint = ctx.accounts.rush_mint.key();
+    ctx.accounts.pool.require_active()?;
+    require!(
+        ctx.accounts.pool.stable_amplification().is_none(),
+        CustomError::SingleSidedUnsupported
+    );
+    acquire_pool_lock(&mut ctx.accounts.pool)?;
+    let accounts = &mut *ctx.accounts;
+    let (rewards, time_elapsed) = take_claimable_rewards(
+        &mut accounts.position,
+        &mut accounts.pool,
+        &mut accounts.rush_config,
+        current_time,
+    )?;
+    emit_rewards_claimed(
+        accounts.user.key(),
+        &accounts.position,
+        &accounts.pool,
+        rewards,
+        time_elapsed,
+        current_time,
+    );
+    let rush_signer: &[&[&[u8]]] = &[&[b"rush_config", &[accounts.rush_config.bump]]];
+
+    let pool_holds_rush = accounts.pool.token_a_mint == rush_mint || accounts.pool.token_b_mint == rush_mint;
+    let (deposit_mint, deposit_amount) = if pool_holds_rush {
+        mint_to(
+            CpiContext::new_with_signer(
+                accounts.token_program.to_account_info(),
+                MintTo {
+                    mint: accounts.rush_mint.to_account_info(),
+                    to: accounts.deposit_vault.to_account_info(),
+                    authority: accounts.rush_config.to_account_info(),
+                },
+                rush_signer,
+            ),
+            rewards,
+        )?;
+        (rush_mint, rewards)
+    } else {
+        let (Some(rush_pool), Some(rush_vault), Some(out_vault)) = (
+            accounts.rush_pool.as_mut(),
+            accounts.rush_pool_rush_vault.as_ref(),
+            accounts.rush_pool_out_vault.as_ref(),
+        ) else {
+            return err!(CustomError::NoRushPool);
+        };
+        let rush_is_a = rush_pool.token_a_mint == rush_mint;
+        let out_mint = if rush_is_a { rush_pool.token_b_mint } else { rush_pool.token_a_mint };
+        require!(
+            (rush_is_a || rush_pool.token_b_mint == rush_mint)
+                && (out_mint == accounts.pool.token_a_mint || out_mint == accounts.pool.token_b_mint),
+            CustomError::NoRushPool
+        );
+        let (rush_vault_key, out_vault_key) = if rush_is_a {
+            (rush_pool.token_a_vault, rush_pool.token_b_vault)
+        } else {
+            (rush_pool.token_b_vault, rush_pool.token_a_vault)
+        };
+        require!(
+            rush_vault.key() == rush_vault_key && out_vault.key() == out_vault_key,
+            CustomError::InvalidVault
+        );
+        rush_pool.require_active()?;
+        acquire_pool_lock(rush_pool)?;
+
+        // The rewards are the swap input: mint them straight into the RUSH vault
+        mint_to(
+            CpiContext::new_with_signer(
+                accounts.token_program.to_account_info(),
+                MintTo {
+                    mint: accounts.rush_mint.to_account_info(),
+                    to: rush_vault.to_account_info(),
+                    authority: accounts.rush_config.to_account_info(),
+                },
+                rush_signer,
+            ),
+            rewards,
+        )?;
+        let SwapQuote { amount_out, fee_amount } = quote_pool_swap(rush_pool, rewards, rush_is_a, current_time)?;
+        rush_pool.apply_swap(rewards, rewards, amount_out, rush_is_a)?;
+        rush_pool.record_swap_fee(fee_amount, rush_is_a);
+        let rush_pool_signer: &[&[&[u8]]] = &[&[
+            b"pool",
+            rush_pool.token_a_mint.as_ref(),
+            rush_pool.token_b_mint.as_ref(),
+            &[rush_pool.bump],
+        ]];
+        transfer(
+            CpiContext::new_with_signer(
+                accounts.token_program.to_account_info(),
+                Transfer {
+                    from: out_vault.to_account_info(),
+                    to: accounts.deposit_vault.to_account_info(),
+                    authority: rush_pool.to_account_info(),
+                },
+                rush_pool_signer,
+            ),
+            amount_out,
+        )?;
+        emit!(SwapExecuted {
+            user: accounts.user.key(),
+            pool: rush_pool.key(),
+            amount_in: rewards,
+            amount_out,
+            fee_amount,
+            protocol_fee: 0,
+            fee_token: rush_mint,
+            is_a_to_b: rush_is_a,
+            new_reserve_a: rush_pool.reserve_a,
+            new_reserve_b: rush_pool.reserve_b,
+        });
+        rush_pool.unlock();
+        (out_mint, amount_out)
+    };
+
+    let pool = &mut accounts.pool;
+    let is_token_a = deposit_mint == pool.token_a_mint;
+    let expected_vault = if is_token_a { pool.token_a_vault } else { pool.token_b_vault };
+    require!(accounts.deposit_vault.key() == expected_vault, CustomError::InvalidVault);
+    let alignment = pool.deposit_alignment(current_time);
+    let deposit = pool.apply_single_sided_deposit(deposit_amount, is_token_a, current_time)?;
+    require!(deposit.lp_tokens >= min_lp_tokens, CustomError::SlippageTooHigh);
+    let pool_signer: &[&[&[u8]]] = &[&[
+        b"pool",
+        pool.token_a_mint.as_ref(),
+        pool.token_b_mint.as_ref(),
+        &[pool.bump],
+    ]];
+    mint_to(
+        CpiContext::new_with_signer(
+            accounts.token_program.to_account_info(),
+            MintTo {
+                mint: accounts.lp_token_mint.to_account_info(),
+                to: accounts.user_lp_token_account.to_account_info(),
+                authority: pool.to_account_info(),
+            },
+            pool_signer,
+        ),
+        deposit.lp_tokens,
+    )?;
+    let position = &mut accounts.position;
+    position.deposit_lp_tokens(deposit.lp_tokens, pool, alignment)?;
+    emit!(LiquidityAdded {
+        user: accounts.user.key(),
+        pool: pool.key(),
+        amount_a: deposit.amount_a,
+        amount_b: deposit.amount_b,
+        lp_tokens_minted: deposit.lp_tokens,
+        new_reserve_a: pool.reserve_a,
+        new_reserve_b: pool.reserve_b,
+    });
+    emit!(RewardsCompounded {
+        user: accounts.user.key(),
+        position: position.key(),
+        pool: pool.key(),
+        rewards_amount: rewards,
+        deposit_mint,
+        deposit_amount,
+        lp_tokens_minted: deposit.lp_tokens,
+    });
+    pool.unlock();
+    Ok(())
+}
+/// `compound_rewards` under the name clients look for: claim and re-deposit
+/// in one instruction, with the same accounts and `min_lp_tokens` floor.
+pub fn claim_and_compound(ctx: Context<CompoundRewards>, min_lp_tokens: u64) -> Result<()> {
+    compound_rewards(ctx, min_lp_tokens)
+}
+/// Lock the position's liquidity for `lock_days` in exchange for a multiplier
+/// on its share of the pool's RUSH until the lock ends (see `lock_boost_bps`).
+pub fn lock_position(ctx: Context<LockPosition>, lock_days: u64) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    let pool = &mut ctx.accounts.pool;
+    let rush_config = &ctx.accounts.rush_config;
+    let position = &mut ctx.accounts.position;
+    require!(position.has_liquidity(), CustomError::InsufficientLPBalance);
+    rush_config.accrue_pool(pool, now)?;
+    position.settle_rewards(pool.acc_reward_per_share, now)?;
+    position.lock(lock_days, now)?;
+    position.sync_boost_shares(pool)?;
+    emit!(LiquidityLocked {
+        user: ctx.accounts.user.key(),
+        position: position.key(),
+        pool: pool.key(),
+        lock_days,
+        lock_until_ts: position.lock_until_ts,
+        boost_multiplier_bps: position.boost_multiplier_bps,
+    });
+    Ok(())
+}
+/// Take a lapsed lock's boost shares out of the pool.  Anyone can call it, so
+/// an expired lock stops diluting the other LPs before its owner returns.
+pub fn release_expired_lock(ctx: Context<ReleaseExpiredLock>) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    let pool = &mut ctx.accounts.pool;
+    let position = &mut ctx.accounts.position;
+    position.require_unlocked(now)?;
+    ctx.accounts.rush_config.accrue_pool(pool, now)?;
+    position.settle_rewards(pool.acc_reward_per_share, now)?;
+    position.sync_boost_shares(pool)
+}
+/// Fund and start a partner-token reward program on the pool, emitting
+/// `rewards_per_second` of `reward_mint` to its LPs for `duration_secs`.  The
+/// whole run is paid into the program vault up front.
+pub fn add_reward_program(
+    ctx: Context<AddRewardProgram>,
+    rewards_per_second: u64,
+    duration_secs: i64,
+) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    let funded_amount = rewards_per_second
+        .checked_mul(u64::try_from(duration_secs).map_err(|_| error!(CustomError::InvalidAmount))?)
+        .ok_or(error!(CustomError::CalculationOverflow))?;
+    let pool = &mut ctx.accounts.pool;
+    let reward_mint = ctx.accounts.reward_mint.key();
+    let reward_vault = ctx.accounts.reward_vault.key();
+    let index = pool.start_reward_program(reward_mint, reward_vault, rewards_per_second, duration_secs, now)?;
+    transfer(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.funder_token_account.to_account_info(),
+                to: ctx.accounts.reward_vault.to_account_info(),
+                authority: ctx.accounts.authority.to_account_info(),
+            },
+        ),
+        funded_amount,
+    )?;
+    emit!(RewardProgramStarted {
+        pool: pool.key(),
+        reward_mint,
+        reward_vault,
+        rewards_per_second,
+        ends_at: pool.reward_programs[index].ends_at,
+        funded_amount,
+    });
+    Ok(())
+}
+/// Claim the position's rewards from the pool's `reward_mint` program.  Every
+/// active program is settled along the way; the others stay pending.
+pub fn claim_reward(ctx: Context<ClaimReward>, reward_mint: Pubkey) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    let pool = &mut ctx.accounts.pool;
+    let position = &mut ctx.accounts.position;
+    let index = pool
+        .reward_program_index(&reward_mint)
+        .ok_or(error!(CustomError::RewardProgramNotFound))?;
+    require!(
+        ctx.accounts.reward_vault.key() == pool.reward_programs[index].reward_vault,
+        CustomError::InvalidVault
+    );
+    pool.accrue_reward_programs(now)?;
+    position.settle_program_rewards(&pool.reward_programs)?;
+    // Emission is prefunded; anything a rounding shortfall leaves stays pending
+    let amount = position.program_rewards[index]
+        .pending_rewards
+        .min(ctx.accounts.reward_vault.amount);
+    require!(amount > 0, CustomError::InvalidAmount);
+    position.program_rewards[index].pending_rewards -= amount;
+    let signer_seeds: &[&[&[u8]]] = &[&[
+        b"pool",
+        pool.token_a_mint.as_ref(),
+        pool.token_b_mint.as_ref(),
+        &[pool.bump],
+    ]];
+    transfer(
+        CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.reward_vault.to_account_info(),
+                to: ctx.accounts.user_reward_account.to_account_info(),
+                authority: pool.to_account_info(),
+            },
+            signer_seeds,
+        ),
+        amount,
+    )?;
+    emit!(ProgramRewardsClaimed {
+        user: ctx.accounts.user.key(),
+        position: position.key(),
+        pool: pool.key(),
+        reward_mint,
+        rewards_amount: amount,
+        claimed_at: now,
+    });
     Ok(())
 }
 pub fn update_rush_apy(
@@ -172,20 +524,14 @@ pub fn update_rush_apy(
         CustomError::InvalidAuthority
     );
     require!(new_apy > 0 && new_apy <= 500, CustomError::InvalidAmount);
-    let yearly_rewards = (rush_config.total_supply as u128)
-        .checked_mul(new_apy as u128)
-        .ok_or(error!(CustomError::CalculationOverflow))?
-        .checked_div(rush_config.apy_denominator as u128)
-        .ok_or(error!(CustomError::CalculationOverflow))?;
-    let seconds_per_year: u128 = 31_536_000;
-    let new_rewards_per_second = yearly_rewards
-        .checked_div(seconds_per_year)
-        .ok_or(error!(CustomError::CalculationOverflow))?
-        .try_into()
-        .map_err(|_| error!(CustomError::CalculationOverflow))?;
+    let new_rewards_per_second = RushConfig::rewards_per_second_for_apy(
+        rush_config.total_supply,
+        new_apy,
+        rush_config.apy_denominator,
+    )?;
     let previous_apy = rush_config.apy_numerator;
     rush_config.apy_numerator = new_apy;
-    rush_config.rewards_per_second = new_rewards_per_second;
+    rush_config.set_rewards_per_second(new_rewards_per_second, current_time)?;
     emit!(RewardsConfigUpdated {
         previous_apy_numerator: previous_apy,
         new_apy_numerator: new_apy,
@@ -205,18 +551,121 @@ pub fn pause_rush_rewards(
         rush_config.authority,
         CustomError::InvalidAuthority
     );
-    let was_paused = rush_config.is_paused;
-    rush_config.is_paused = !was_paused;
-    let reason = if rush_config.is_paused {
-        "Emergency pause triggered".to_string()
+    if rush_config.is_paused {
+        rush_config.resume_emission(current_time);
     } else {
-        "Rewards resumed".to_string()
-    };
+        rush_config.pause_emission(current_time)?;
+    }
     emit!(RewardsPaused {
         is_paused: rush_config.is_paused,
         paused_at: current_time,
         paused_by: ctx.accounts.authority.key(),
-        reason: reason.clone(),
+    });
+    Ok(())
+}
+pub fn set_min_claim_interval(
+    ctx: Context<UpdateRushConfig>,
+    min_claim_interval_secs: i64,
+) -> Result<()> {
+    let rush_config = &mut ctx.accounts.rush_config;
+    let current_time = Clock::get()?.unix_timestamp;
+    require_eq!(
+        ctx.accounts.authority.key(),
+        rush_config.authority,
+        CustomError::InvalidAuthority
+    );
+    require!(
+        (0..=MAX_MIN_CLAIM_INTERVAL_SECS).contains(&min_claim_interval_secs),
+        CustomError::InvalidAmount
+    );
+    let previous_interval = rush_config.min_claim_interval_secs;
+    rush_config.min_claim_interval_secs = min_claim_interval_secs;
+    emit!(ClaimIntervalUpdated {
+        previous_interval_secs: previous_interval,
+        new_interval_secs: min_claim_interval_secs,
+        updated_at: current_time,
+        updated_by: ctx.accounts.authority.key(),
+    });
+    Ok(())
+}
+/// Stop emissions to pools holding less than `min_lp_supply` LP.  Pools
+/// pick up the new threshold at their next accrual.
+pub fn set_min_rewards_liquidity(ctx: Context<UpdateRushConfig>, min_lp_supply: u64) -> Result<()> {
+    let rush_config = &mut ctx.accounts.rush_config;
+    require_eq!(
+        ctx.accounts.authority.key(),
+        rush_config.authority,
+        CustomError::InvalidAuthority
+    );
+    let previous_min_lp_supply = rush_config.min_lp_supply_for_rewards;
+    rush_config.min_lp_supply_for_rewards = min_lp_supply;
+    emit!(MinRewardsLiquidityUpdated {
+        previous_min_lp_supply,
+        new_min_lp_supply: min_lp_supply,
+        updated_at: Clock::get()?.unix_timestamp,
+        updated_by: ctx.accounts.authority.key(),
+    });
+    Ok(())
+}
+/// Halve emissions every `halving_interval_secs` counted from
+/// `start_timestamp`; 0 turns halvings off.  Can only be set before the first
+/// halving, so it never reprices time pools have yet to accrue.
+pub fn set_halving_interval(ctx: Context<UpdateRushConfig>, halving_interval_secs: i64) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    let rush_config = &mut ctx.accounts.rush_config;
+    require_eq!(
+        ctx.accounts.authority.key(),
+        rush_config.authority,
+        CustomError::InvalidAuthority
+    );
+    let previous_interval_secs = rush_config.halving_interval_secs;
+    rush_config.set_halving_interval(halving_interval_secs, now)?;
+    emit!(HalvingIntervalUpdated {
+        previous_interval_secs,
+        new_interval_secs: halving_interval_secs,
+        updated_at: now,
+        updated_by: ctx.accounts.authority.key(),
+    });
+    Ok(())
+}
+/// Set `pool`'s share of RUSH emissions.  The pool is accrued at its old
+/// weight first.  Other pools keep their weights, and the switch to weighted
+/// emission is timestamped, so their un-accrued time is never repriced.
+pub fn set_pool_weight(ctx: Context<SetPoolWeight>, weight_bps: u16) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    let rush_config = &mut ctx.accounts.rush_config;
+    let pool = &mut ctx.accounts.pool;
+    require_eq!(
+        ctx.accounts.authority.key(),
+        rush_config.authority,
+        CustomError::InvalidAuthority
+    );
+    rush_config.accrue_pool(pool, now)?;
+    let previous_weight_bps = rush_config.set_pool_weight(pool, weight_bps, now)?;
+    emit!(PoolWeightUpdated {
+        pool: pool.key(),
+        previous_weight_bps,
+        new_weight_bps: weight_bps,
+        total_weight_bps: rush_config.total_pool_weight_bps,
+        updated_at: now,
+        updated_by: ctx.accounts.authority.key(),
+    });
+    Ok(())
+}
+/// Propose `new_authority` for the RUSH config; it takes over once it calls
+/// `accept_rush_authority`, so a mistyped key can't lock the config.
+pub fn transfer_rush_authority(ctx: Context<TransferRushAuthority>, new_authority: Pubkey) -> Result<()> {
+    ctx.accounts
+        .rush_config
+        .propose_authority(ctx.accounts.authority.key(), new_authority)
+}
+pub fn accept_rush_authority(ctx: Context<AcceptRushAuthority>) -> Result<()> {
+    let new_authority = ctx.accounts.new_authority.key();
+    let previous_authority = ctx.accounts.rush_config.accept_authority(new_authority)?;
+    emit!(AuthorityTransferred {
+        previous_authority,
+        new_authority,
+        transferred_at: Clock::get()?.unix_timestamp,
     });
     Ok(())
 }
@@ -246,7 +695,8 @@ pub struct InitializeRushToken<'info> {
 #[derive(Accounts)]
 pub struct CalculateRewards<'info> {
     #[account(
-        constraint = position.owner == user.key() @ CustomError::InvalidAuthority
+        constraint = position.owner == user.key() @ CustomError::InvalidAuthority,
+        constraint = position.pool == pool.key() @ CustomError::InvalidPool
     )]
     pub position: Account<'info, UserLiquidityPosition>,
     pub pool: Account<'info, LiquidityPool>,
@@ -254,10 +704,28 @@ pub struct CalculateRewards<'info> {
     pub user: Signer<'info>,
 }
 #[derive(Accounts)]
+pub struct GetUserRewardsInfo<'info> {
+    #[account(constraint = position.pool == pool.key() @ CustomError::InvalidPool)]
+    pub position: Account<'info, UserLiquidityPosition>,
+    pub pool: Account<'info, LiquidityPool>,
+    #[account(
+        seeds = [b"rush_config"],
+        bump = rush_config.bump
+    )]
+    pub rush_config: Account<'info, RushConfig>,
+    /// CHECK: token A / USD price feed, read by `read_oracle_price`.
+    pub token_a_price_feed: Option<UncheckedAccount<'info>>,
+    /// CHECK: token B / USD price feed, read by `read_oracle_price`.
+    pub token_b_price_feed: Option<UncheckedAccount<'info>>,
+    /// CHECK: RUSH / USD price feed, read by `read_oracle_price`.
+    pub rush_price_feed: Option<UncheckedAccount<'info>>,
+}
+#[derive(Accounts)]
 pub struct ClaimRewards<'info> {
     #[account(
         mut,
-        constraint = position.owner == user.key() @ CustomError::InvalidAuthority
+        constraint = position.owner == user.key() @ CustomError::InvalidAuthority,
+        constraint = position.pool == pool.key() @ CustomError::InvalidPool
     )]
     pub position: Account<'info, UserLiquidityPosition>,
     #[account(mut)]
@@ -280,6 +748,127 @@ pub struct ClaimRewards<'info> {
     pub system_program: Program<'info, System>,
 }
 #[derive(Accounts)]
+pub struct LockPosition<'info> {
+    #[account(
+        mut,
+        seeds = [b"position", pool.key().as_ref(), user.key().as_ref()],
+        bump = position.bump,
+        constraint = position.owner == user.key() @ CustomError::InvalidAuthority,
+        constraint = position.pool == pool.key() @ CustomError::InvalidPool
+    )]
+    pub position: Account<'info, UserLiquidityPosition>,
+    #[account(mut)]
+    pub pool: Account<'info, LiquidityPool>,
+    #[account(
+        seeds = [b"rush_config"],
+        bump = rush_config.bump
+    )]
+    pub rush_config: Account<'info, RushConfig>,
+    pub user: Signer<'info>,
+}
+#[derive(Accounts)]
+pub struct ReleaseExpiredLock<'info> {
+    #[account(
+        mut,
+        seeds = [b"position", pool.key().as_ref(), position.owner.as_ref()],
+        bump = position.bump,
+        constraint = position.pool == pool.key() @ CustomError::InvalidPool
+    )]
+    pub position: Account<'info, UserLiquidityPosition>,
+    #[account(mut)]
+    pub pool: Account<'info, LiquidityPool>,
+    #[account(
+        seeds = [b"rush_config"],
+        bump = rush_config.bump
+    )]
+    pub rush_config: Account<'info, RushConfig>,
+}
+#[derive(Accounts)]
+pub struct AddRewardProgram<'info> {
+    #[account(mut, has_one = authority @ CustomError::InvalidAuthority)]
+    pub pool: Account<'info, LiquidityPool>,
+    pub reward_mint: Account<'info, Mint>,
+    /// Holds the program's unpaid emission; restarting a program reuses it.
+    #[account(
+        init_if_needed,
+        payer = authority,
+        seeds = [b"reward_vault", pool.key().as_ref(), reward_mint.key().as_ref()],
+        bump,
+        token::mint = reward_mint,
+        token::authority = pool
+    )]
+    pub reward_vault: Account<'info, TokenAccount>,
+    #[account(mut, token::mint = reward_mint, token::authority = authority)]
+    pub funder_token_account: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+#[derive(Accounts)]
+pub struct ClaimReward<'info> {
+    #[account(
+        mut,
+        constraint = position.owner == user.key() @ CustomError::InvalidAuthority,
+        constraint = position.pool == pool.key() @ CustomError::InvalidPool
+    )]
+    pub position: Account<'info, UserLiquidityPosition>,
+    #[account(mut)]
+    pub pool: Account<'info, LiquidityPool>,
+    #[account(mut)]
+    pub reward_vault: Account<'info, TokenAccount>,
+    #[account(mut, token::mint = reward_vault.mint)]
+    pub user_reward_account: Account<'info, TokenAccount>,
+    pub user: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+#[derive(Accounts)]
+pub struct CompoundRewards<'info> {
+    #[account(
+        mut,
+        seeds = [b"position", pool.key().as_ref(), user.key().as_ref()],
+        bump = position.bump,
+        constraint = position.owner == user.key() @ CustomError::InvalidAuthority,
+        constraint = position.pool == pool.key() @ CustomError::InvalidPool
+    )]
+    pub position: Account<'info, UserLiquidityPosition>,
+    #[account(mut)]
+    pub pool: Box<Account<'info, LiquidityPool>>,
+    #[account(
+        mut,
+        seeds = [b"rush_config"],
+        bump = rush_config.bump
+    )]
+    pub rush_config: Account<'info, RushConfig>,
+    #[account(mut, address = rush_config.mint @ CustomError::InvalidMint)]
+    pub rush_mint: Account<'info, Mint>,
+    #[account(
+        mut,
+        constraint = lp_token_mint.key() == pool.lp_token_mint @ CustomError::InvalidMint
+    )]
+    pub lp_token_mint: Account<'info, Mint>,
+    #[account(
+        mut,
+        token::mint = lp_token_mint,
+        token::authority = user
+    )]
+    pub user_lp_token_account: Account<'info, TokenAccount>,
+    /// `pool`'s vault for the token the rewards are deposited as.
+    #[account(mut)]
+    pub deposit_vault: Account<'info, TokenAccount>,
+    /// RUSH pool paired with one of `pool`'s tokens; not needed when `pool` holds RUSH.
+    #[account(mut, constraint = rush_pool.key() != pool.key() @ CustomError::InvalidPool)]
+    pub rush_pool: Option<Box<Account<'info, LiquidityPool>>>,
+    /// `rush_pool`'s RUSH vault; the rewards are minted into it.
+    #[account(mut)]
+    pub rush_pool_rush_vault: Option<Account<'info, TokenAccount>>,
+    /// `rush_pool`'s other vault; the swap output leaves from it.
+    #[account(mut)]
+    pub rush_pool_out_vault: Option<Account<'info, TokenAccount>>,
+    pub user: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+#[derive(Accounts)]
 pub struct UpdateRushAPY<'info> {
     #[account(mut)]
     pub rush_config: Account<'info, RushConfig>,
@@ -291,3 +880,94 @@ pub struct PauseRewards<'info> {
     pub rush_config: Account<'info, RushConfig>,
     pub authority: Signer<'info>,
 }
+/// Accounts for the admin setters that only touch `RushConfig`.
+#[derive(Accounts)]
+pub struct UpdateRushConfig<'info> {
+    #[account(
+        mut,
+        seeds = [b"rush_config"],
+        bump = rush_config.bump
+    )]
+    pub rush_config: Account<'info, RushConfig>,
+    pub authority: Signer<'info>,
+}
+#[derive(Accounts)]
+pub struct SetPoolWeight<'info> {
+    #[account(
+        mut,
+        seeds = [b"rush_config"],
+        bump = rush_config.bump
+    )]
+    pub rush_config: Account<'info, RushConfig>,
+    #[account(mut)]
+    pub pool: Account<'info, LiquidityPool>,
+    pub authority: Signer<'info>,
+}
+#[derive(Accounts)]
+pub struct TransferRushAuthority<'info> {
+    #[account(
+        mut,
+        seeds = [b"rush_config"],
+        bump = rush_config.bump
+    )]
+    pub rush_config: Account<'info, RushConfig>,
+    pub authority: Signer<'info>,
+}
+#[derive(Accounts)]
+pub struct AcceptRushAuthority<'info> {
+    #[account(
+        mut,
+        seeds = [b"rush_config"],
+        bump = rush_config.bump
+    )]
+    pub rush_config: Account<'info, RushConfig>,
+    pub new_authority: Signer<'info>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::pool::tests::make_pool;
+    use crate::state::rush_config::tests::make_config;
+    use crate::state::user_position::tests::make_position;
+
+    #[test]
+    fn test_compounding_into_rush_pool_grows_lp_not_claims() {
+        // RUSH is token A of the pool the rewards are compounded into
+        let mut config = make_config(1_000, 0);
+        let mut pool = make_pool(1_000_000_000, 1_000_000_000);
+        pool.total_lp_supply = 1_000_000_000;
+        let mut position = make_position(100_000_000);
+        let (rewards, elapsed) = take_claimable_rewards(&mut position, &mut pool, &mut config, 600).unwrap();
+        assert_eq!(elapsed, 600);
+        assert_eq!(rewards, 60_000);
+        assert_eq!(position.total_rush_claimed, rewards);
+        assert_eq!(config.minted_so_far, rewards);
+
+        // The minted RUSH lands in the pool vault and comes back as LP
+        let alignment = pool.deposit_alignment(600);
+        let deposit = pool.apply_single_sided_deposit(rewards, true, 600).unwrap();
+        position.deposit_lp_tokens(deposit.lp_tokens, &mut pool, alignment).unwrap();
+        assert!(deposit.lp_tokens > 0);
+        assert_eq!(position.lp_tokens, 100_000_000 + deposit.lp_tokens);
+        assert_eq!(pool.reserve_a, 1_000_000_000 + rewards);
+        assert_eq!(position.accrued_rewards(pool.acc_reward_per_share, pool.last_reward_timestamp).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_compounded_rewards_add_lp_worth_their_value() {
+        // RUSH/B pool prices RUSH at 2 B; the target A/B pool is at par
+        let mut rush_pool = make_pool(500_000_000, 1_000_000_000);
+        let mut pool = make_pool(1_000_000_000, 1_000_000_000);
+        pool.total_lp_supply = 1_000_000_000;
+        let rewards = 1_000_000;
+        let quote = quote_pool_swap(&rush_pool, rewards, true, 0).unwrap();
+        rush_pool.apply_swap(rewards, rewards, quote.amount_out, true).unwrap();
+        assert!(quote.amount_out > 1_990_000 && quote.amount_out < 2_000_000);
+
+        let deposit = pool.apply_single_sided_deposit(quote.amount_out, false, 0).unwrap();
+        // ~2M B is 0.1% of the pool's value, so ~0.1% of the LP supply (less fees)
+        assert!(deposit.lp_tokens > 990_000 && deposit.lp_tokens < 1_000_000);
+        assert_eq!(pool.reserve_b, 1_000_000_000 + quote.amount_out);
+    }
+}
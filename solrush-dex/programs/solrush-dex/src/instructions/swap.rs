@@ -1,120 +1,249 @@
 use anchor_lang::prelude::*;
 use anchor_spl::token::{Token, TokenAccount, Transfer, transfer};
-use crate::state::LiquidityPool;
+use crate::state::{LiquidityPool, RushConfig};
+use crate::instructions::amm_config::require_slippage_within_cap;
 use crate::errors::CustomError;
-use crate::events::SwapExecuted;
-use crate::utils::calculate_output_amount;
+use crate::events::{SwapExecuted, RouteExecuted};
+use crate::utils::{calculate_output_amount, calculate_input_amount, calculate_protocol_fee, calculate_swap_fee, calculate_price_impact_bps, acquire_pool_lock, apply_spread_bps, vault_received};
+
+const PRICE_PRECISION: u128 = 1_000_000;
 pub fn swap(
     ctx: Context<Swap>,
     amount_in: u64,
     minimum_amount_out: u64,
     is_a_to_b: bool,
     deadline: i64,
+    max_price_impact_bps: u16,
 ) -> Result<()> {
     let current_time = Clock::get()?.unix_timestamp;
     require!(current_time <= deadline, CustomError::DeadlineExceeded);
     require!(amount_in > 0, CustomError::InvalidAmount);
-    let pool = &mut ctx.accounts.pool;
-    let (input_reserve, output_reserve) = if is_a_to_b {
-        (pool.reserve_a, pool.reserve_b)
-    } else {
-        (pool.reserve_b, pool.reserve_a)
-    };
-    require!(
-        output_reserve > 0 && input_reserve > 0,
-        CustomError::InsufficientLiquidity
-    );
+    ctx.accounts.pool.require_active()?;
+    acquire_pool_lock(&mut ctx.accounts.pool)?;
     require!(
         ctx.accounts.user_token_in.amount >= amount_in,
         CustomError::InsufficientBalance
     );
-    let amount_out = calculate_output_amount(
+    let protocol_fee = calculate_protocol_fee(
         amount_in,
+        ctx.accounts.pool.protocol_fee_numerator,
+        ctx.accounts.pool.fee_denominator,
+    )?;
+    let amm_config = ctx.accounts.amm_config.to_account_info();
+    let mut leg = ctx.accounts.leg();
+    // Price what the vault actually received, not what was requested
+    let pool_amount_in = deposit_swap_input(&mut leg, amount_in, protocol_fee)?;
+    let amount_in = pool_amount_in
+        .checked_add(protocol_fee)
+        .ok_or(error!(CustomError::CalculationOverflow))?;
+    let pool = &*leg.pool;
+    require_slippage_within_cap(
+        &amm_config,
+        spot_amount_out(pool, amount_in, is_a_to_b),
+        minimum_amount_out,
+    )?;
+    let SwapQuote { amount_out, fee_amount } = quote_pool_swap(pool, amount_in, is_a_to_b, current_time)?;
+    require!(
+        amount_out >= minimum_amount_out,
+        CustomError::SlippageTooHigh
+    );
+    // 0 disables the guard
+    if max_price_impact_bps > 0 {
+        let (reserve_in, reserve_out) = if is_a_to_b {
+            (pool.reserve_a, pool.reserve_b)
+        } else {
+            (pool.reserve_b, pool.reserve_a)
+        };
+        let impact_bps = calculate_price_impact_bps(amount_in, amount_out, reserve_in, reserve_out)?;
+        require!(
+            impact_bps <= max_price_impact_bps as u64,
+            CustomError::PriceImpactTooHigh
+        );
+    }
+    require!(
+        leg.vault_out.amount >= amount_out,
+        CustomError::InsufficientPoolReserves
+    );
+    settle_swap(leg, amount_in, pool_amount_in, protocol_fee, amount_out, fee_amount, is_a_to_b)
+}
+pub fn swap_exact_out(
+    ctx: Context<Swap>,
+    amount_out: u64,
+    maximum_amount_in: u64,
+    is_a_to_b: bool,
+    deadline: i64,
+) -> Result<()> {
+    let current_time = Clock::get()?.unix_timestamp;
+    require!(current_time <= deadline, CustomError::DeadlineExceeded);
+    require!(amount_out > 0, CustomError::InvalidAmount);
+    ctx.accounts.pool.require_active()?;
+    acquire_pool_lock(&mut ctx.accounts.pool)?;
+    let pool = &ctx.accounts.pool;
+    require!(pool.stable_amplification().is_none(), CustomError::ExactOutUnsupported);
+    let (input_reserve, output_reserve) = if is_a_to_b {
+        (pool.reserve_a, pool.reserve_b)
+    } else {
+        (pool.reserve_b, pool.reserve_a)
+    };
+    let fee_numerator = pool.exact_out_fee_numerator(amount_out, is_a_to_b, current_time)?;
+    let amount_in = calculate_input_amount(
+        amount_out,
         input_reserve,
         output_reserve,
-        pool.fee_numerator,
+        fee_numerator,
         pool.fee_denominator,
     )?;
     require!(
-        amount_out >= minimum_amount_out,
+        amount_in <= maximum_amount_in,
         CustomError::SlippageTooHigh
     );
+    require!(
+        ctx.accounts.user_token_in.amount >= amount_in,
+        CustomError::InsufficientBalance
+    );
     require!(
         ctx.accounts.pool_vault_out.amount >= amount_out,
         CustomError::InsufficientPoolReserves
     );
-    let fee_numerator_128 = pool.fee_numerator as u128;
-    let fee_denominator_128 = pool.fee_denominator as u128;
-    let fee_amount = ((amount_in as u128) * fee_numerator_128 + fee_denominator_128 - 1) / fee_denominator_128;
-    let fee_amount = fee_amount as u64;
-    let pool_key = pool.key();
-    let token_a_mint = pool.token_a_mint;
-    let token_b_mint = pool.token_b_mint;
-    let bump_seed = pool.bump;
-    if is_a_to_b {
-        pool.reserve_a = pool
-            .reserve_a
-            .checked_add(amount_in)
-            .ok_or(error!(CustomError::CalculationOverflow))?;
-        pool.reserve_b = pool
-            .reserve_b
-            .checked_sub(amount_out)
-            .ok_or(error!(CustomError::InsufficientPoolReserves))?;
-        pool.total_volume_a = pool.total_volume_a.saturating_add(amount_in);
+    let fee_amount = calculate_swap_fee(amount_in, fee_numerator, pool.fee_denominator)?;
+    let protocol_fee = calculate_protocol_fee(amount_in, pool.protocol_fee_numerator, pool.fee_denominator)?;
+    let mut leg = ctx.accounts.leg();
+    let pool_amount_in = deposit_swap_input(&mut leg, amount_in, protocol_fee)?;
+    // The input was priced up front, so a short delivery would underpay the pool
+    require!(
+        pool_amount_in == amount_in - protocol_fee,
+        CustomError::TransferFeeUnsupported
+    );
+    settle_swap(leg, amount_in, pool_amount_in, protocol_fee, amount_out, fee_amount, is_a_to_b)
+}
+/// Output `amount_in` fetches at the pool's spot price, before fees and price
+/// impact.  StableSwap pools, whose spot is not the reserve ratio, use the
+/// fee-free curve output instead.  `None` for unfunded pools.
+fn spot_amount_out(pool: &LiquidityPool, amount_in: u64, is_a_to_b: bool) -> Option<u64> {
+    let (reserve_in, reserve_out) = if is_a_to_b {
+        (pool.reserve_a, pool.reserve_b)
     } else {
-        pool.reserve_b = pool
-            .reserve_b
-            .checked_add(amount_in)
-            .ok_or(error!(CustomError::CalculationOverflow))?;
-        pool.reserve_a = pool
-            .reserve_a
-            .checked_sub(amount_out)
-            .ok_or(error!(CustomError::InsufficientPoolReserves))?;
-        pool.total_volume_b = pool.total_volume_b.saturating_add(amount_in);
+        (pool.reserve_b, pool.reserve_a)
+    };
+    if reserve_in == 0 {
+        return None;
     }
+    if let Some(amp) = pool.stable_amplification() {
+        return calculate_output_amount(amount_in, reserve_in, reserve_out, 0, pool.fee_denominator, Some(amp)).ok();
+    }
+    let out = amount_in as u128 * reserve_out as u128 / reserve_in as u128;
+    Some(u64::try_from(out).unwrap_or(u64::MAX))
+}
+/// Pull a swap's input from the trader: the pool's share into the vault and the
+/// protocol fee to the treasury. Returns what the vault actually received, which
+/// is less than requested for fee-on-transfer mints.
+fn deposit_swap_input(leg: &mut SwapLeg, amount_in: u64, protocol_fee: u64) -> Result<u64> {
+    let balance_before = leg.vault_in.amount;
     transfer(
         CpiContext::new(
-            ctx.accounts.token_program.to_account_info(),
+            leg.token_program.to_account_info(),
             Transfer {
-                from: ctx.accounts.user_token_in.to_account_info(),
-                to: ctx.accounts.pool_vault_in.to_account_info(),
-                authority: ctx.accounts.user.to_account_info(),
+                from: leg.user_token_in.to_account_info(),
+                to: leg.vault_in.to_account_info(),
+                authority: leg.user.to_account_info(),
             },
         ),
+        amount_in - protocol_fee,
+    )?;
+    pay_protocol_fee(leg.token_program, leg.user_token_in, leg.protocol_fee_account, leg.user, protocol_fee)?;
+    vault_received(leg.vault_in, balance_before)
+}
+/// Pay out and update reserves for a swap whose input is already in the vault
+/// and whose output is priced and checked. Expects the pool lock to be held; releases it.
+fn settle_swap(
+    leg: SwapLeg,
+    amount_in: u64,
+    pool_amount_in: u64,
+    protocol_fee: u64,
+    amount_out: u64,
+    fee_amount: u64,
+    is_a_to_b: bool,
+) -> Result<()> {
+    let SwapLeg { pool, vault_in, vault_out, user_token_out, user, token_program, .. } = leg;
+    let (new_reserve_a, new_reserve_b) = pay_out_swap(
+        SwapPayout {
+            pool: &mut *pool,
+            vault_in,
+            vault_out,
+            recipient: user_token_out.to_account_info(),
+            token_program: token_program.to_account_info(),
+        },
         amount_in,
+        pool_amount_in,
+        amount_out,
+        fee_amount,
+        is_a_to_b,
     )?;
+    emit!(SwapExecuted {
+        user: user.key(),
+        pool: pool.key(),
+        amount_in,
+        amount_out,
+        fee_amount,
+        protocol_fee,
+        fee_token: pool.input_mint(is_a_to_b),
+        is_a_to_b,
+        new_reserve_a,
+        new_reserve_b,
+    });
+    pool.unlock();
+    Ok(())
+}
+/// Accounts the output of a swap leaves through.
+pub(crate) struct SwapPayout<'a, 'info> {
+    pub pool: &'a mut Account<'info, LiquidityPool>,
+    pub vault_in: &'a mut Account<'info, TokenAccount>,
+    pub vault_out: &'a mut Account<'info, TokenAccount>,
+    pub recipient: AccountInfo<'info>,
+    pub token_program: AccountInfo<'info>,
+}
+/// Book a priced swap whose input is already in `vault_in`, record its fee and
+/// pay `amount_out` to the recipient, then check the booked reserves are still
+/// in the vaults. Returns the new `(reserve_a, reserve_b)`.
+pub(crate) fn pay_out_swap(
+    payout: SwapPayout,
+    amount_in: u64,
+    pool_amount_in: u64,
+    amount_out: u64,
+    fee_amount: u64,
+    is_a_to_b: bool,
+) -> Result<(u64, u64)> {
+    let SwapPayout { pool, vault_in, vault_out, recipient, token_program } = payout;
+    // All reserve writes happen here; the CPI below only borrows the pool as signer
+    let reserves = pool.apply_swap(amount_in, pool_amount_in, amount_out, is_a_to_b)?;
+    pool.record_swap_fee(fee_amount, is_a_to_b);
     let signer_seeds: &[&[&[u8]]] = &[&[
         b"pool",
-        token_a_mint.as_ref(),
-        token_b_mint.as_ref(),
-        &[bump_seed],
+        pool.token_a_mint.as_ref(),
+        pool.token_b_mint.as_ref(),
+        &[pool.bump],
     ]];
-    let final_reserve_a = pool.reserve_a;
-    let final_reserve_b = pool.reserve_b;
-    let _ = pool;
     transfer(
         CpiContext::new_with_signer(
-            ctx.accounts.token_program.to_account_info(),
+            token_program,
             Transfer {
-                from: ctx.accounts.pool_vault_out.to_account_info(),
-                to: ctx.accounts.user_token_out.to_account_info(),
-                authority: ctx.accounts.pool.to_account_info(),
+                from: vault_out.to_account_info(),
+                to: recipient,
+                authority: pool.to_account_info(),
             },
             signer_seeds,
         ),
         amount_out,
     )?;
-    emit!(SwapExecuted {
-        user: ctx.accounts.user.key(),
-        pool: pool_key,
-        amount_in,
-        amount_out,
-        fee_amount,
-        is_a_to_b,
-        new_reserve_a: final_reserve_a,
-        new_reserve_b: final_reserve_b,
-    });
-    Ok(())
+    // The booked reserves must still be in the vaults after the output left
+    vault_in.reload()?;
+    vault_out.reload()?;
+    if is_a_to_b {
+        pool.require_backed_by_vaults(vault_in.amount, vault_out.amount)?;
+    } else {
+        pool.require_backed_by_vaults(vault_out.amount, vault_in.amount)?;
+    }
+    Ok(reserves)
 }
 pub fn market_buy(
     ctx: Context<MarketBuy>,
@@ -125,90 +254,29 @@ pub fn market_buy(
     let current_time = Clock::get()?.unix_timestamp;
     require!(current_time <= deadline, CustomError::DeadlineExceeded);
     require!(amount_b_in > 0, CustomError::InvalidAmount);
-    let pool = &ctx.accounts.pool;
-    let (input_reserve, output_reserve) = (pool.reserve_b, pool.reserve_a);
-    let fee_numerator = pool.fee_numerator;
-    let fee_denominator = pool.fee_denominator;
-    let token_a_mint = pool.token_a_mint;
-    let token_b_mint = pool.token_b_mint;
-    let bump_seed = pool.bump;
-    let pool_key = pool.key();
-    require!(
-        output_reserve > 0 && input_reserve > 0,
-        CustomError::InsufficientLiquidity
-    );
-    require!(
-        ctx.accounts.user_token_in.amount >= amount_b_in,
-        CustomError::InsufficientBalance
-    );
-    let amount_a_out = calculate_output_amount(
+    ctx.accounts.pool.require_active()?;
+    acquire_pool_lock(&mut ctx.accounts.pool)?;
+    let buy_premium_bps = ctx.accounts.pool.buy_premium_bps;
+    let accounts = ctx.accounts;
+    // Premium stays in the pool as part of reserve_a
+    market_swap(
+        SwapLeg {
+            pool: &mut accounts.pool,
+            vault_in: &mut accounts.pool_vault_in,
+            vault_out: &mut accounts.pool_vault_out,
+            user_token_in: &accounts.user_token_in,
+            user_token_out: &accounts.user_token_out,
+            protocol_fee_account: &accounts.protocol_fee_account,
+            user: &accounts.user,
+            token_program: &accounts.token_program,
+        },
+        &accounts.amm_config,
         amount_b_in,
-        input_reserve,
-        output_reserve,
-        fee_numerator,
-        fee_denominator,
-    )?;
-    require!(
-        amount_a_out >= min_a_received,
-        CustomError::SlippageTooHigh
-    );
-    require!(
-        ctx.accounts.pool_vault_out.amount >= amount_a_out,
-        CustomError::InsufficientPoolReserves
-    );
-    let fee_numerator_128 = fee_numerator as u128;
-    let fee_denominator_128 = fee_denominator as u128;
-    let fee_amount = ((amount_b_in as u128) * fee_numerator_128 / fee_denominator_128) as u64;
-    transfer(
-        CpiContext::new(
-            ctx.accounts.token_program.to_account_info(),
-            Transfer {
-                from: ctx.accounts.user_token_in.to_account_info(),
-                to: ctx.accounts.pool_vault_in.to_account_info(),
-                authority: ctx.accounts.user.to_account_info(),
-            },
-        ),
-        amount_b_in,
-    )?;
-    let pool = &mut ctx.accounts.pool;
-    pool.reserve_b = pool
-        .reserve_b
-        .checked_add(amount_b_in)
-        .ok_or(error!(CustomError::CalculationOverflow))?;
-    pool.reserve_a = pool
-        .reserve_a
-        .checked_sub(amount_a_out)
-        .ok_or(error!(CustomError::InsufficientPoolReserves))?;
-    pool.total_volume_b = pool.total_volume_b.saturating_add(amount_b_in);
-    let signer_seeds: &[&[&[u8]]] = &[&[
-        b"pool",
-        token_a_mint.as_ref(),
-        token_b_mint.as_ref(),
-        &[bump_seed],
-    ]];
-    transfer(
-        CpiContext::new_with_signer(
-            ctx.accounts.token_program.to_account_info(),
-            Transfer {
-                from: ctx.accounts.pool_vault_out.to_account_info(),
-                to: ctx.accounts.user_token_out.to_account_info(),
-                authority: pool.to_account_info(),
-            },
-            signer_seeds,
-        ),
-        amount_a_out,
-    )?;
-    emit!(SwapExecuted {
-        user: ctx.accounts.user.key(),
-        pool: pool_key,
-        amount_in: amount_b_in,
-        amount_out: amount_a_out,
-        fee_amount,
-        is_a_to_b: false,
-        new_reserve_a: pool.reserve_a,
-        new_reserve_b: pool.reserve_b,
-    });
-    Ok(())
+        min_a_received,
+        false,
+        buy_premium_bps,
+        current_time,
+    )
 }
 pub fn market_sell(
     ctx: Context<MarketSell>,
@@ -219,90 +287,80 @@ pub fn market_sell(
     let current_time = Clock::get()?.unix_timestamp;
     require!(current_time <= deadline, CustomError::DeadlineExceeded);
     require!(amount_a_in > 0, CustomError::InvalidAmount);
-    let pool = &ctx.accounts.pool;
-    let (input_reserve, output_reserve) = (pool.reserve_a, pool.reserve_b);
-    let fee_numerator = pool.fee_numerator;
-    let fee_denominator = pool.fee_denominator;
-    let token_a_mint = pool.token_a_mint;
-    let token_b_mint = pool.token_b_mint;
-    let bump_seed = pool.bump;
-    let pool_key = pool.key();
-    require!(
-        output_reserve > 0 && input_reserve > 0,
-        CustomError::InsufficientLiquidity
-    );
-    require!(
-        ctx.accounts.user_token_in.amount >= amount_a_in,
-        CustomError::InsufficientBalance
-    );
-    let amount_b_out = calculate_output_amount(
+    ctx.accounts.pool.require_active()?;
+    acquire_pool_lock(&mut ctx.accounts.pool)?;
+    let sell_discount_bps = ctx.accounts.pool.sell_discount_bps;
+    let accounts = ctx.accounts;
+    // Discount stays in the pool as part of reserve_b
+    market_swap(
+        SwapLeg {
+            pool: &mut accounts.pool,
+            vault_in: &mut accounts.pool_vault_in,
+            vault_out: &mut accounts.pool_vault_out,
+            user_token_in: &accounts.user_token_in,
+            user_token_out: &accounts.user_token_out,
+            protocol_fee_account: &accounts.protocol_fee_account,
+            user: &accounts.user,
+            token_program: &accounts.token_program,
+        },
+        &accounts.amm_config,
         amount_a_in,
-        input_reserve,
-        output_reserve,
-        fee_numerator,
-        fee_denominator,
-    )?;
-    require!(
-        amount_b_out >= min_b_received,
-        CustomError::SlippageTooHigh
-    );
-    require!(
-        ctx.accounts.pool_vault_out.amount >= amount_b_out,
-        CustomError::InsufficientPoolReserves
-    );
-    let fee_numerator_128 = fee_numerator as u128;
-    let fee_denominator_128 = fee_denominator as u128;
-    let fee_amount = ((amount_a_in as u128) * fee_numerator_128 / fee_denominator_128) as u64;
-    transfer(
-        CpiContext::new(
-            ctx.accounts.token_program.to_account_info(),
-            Transfer {
-                from: ctx.accounts.user_token_in.to_account_info(),
-                to: ctx.accounts.pool_vault_in.to_account_info(),
-                authority: ctx.accounts.user.to_account_info(),
-            },
-        ),
-        amount_a_in,
-    )?;
-    let pool = &mut ctx.accounts.pool;
-    pool.reserve_a = pool
-        .reserve_a
-        .checked_add(amount_a_in)
+        min_b_received,
+        true,
+        sell_discount_bps,
+        current_time,
+    )
+}
+/// Body of `market_buy` and `market_sell`: a `swap` whose output is cut by
+/// `spread_bps`, which the pool keeps. Expects the pool lock to be held; releases it.
+fn market_swap(
+    mut leg: SwapLeg,
+    amm_config: &AccountInfo,
+    amount_in: u64,
+    minimum_amount_out: u64,
+    is_a_to_b: bool,
+    spread_bps: u16,
+    now: i64,
+) -> Result<()> {
+    require!(leg.user_token_in.amount >= amount_in, CustomError::InsufficientBalance);
+    let protocol_fee = calculate_protocol_fee(amount_in, leg.pool.protocol_fee_numerator, leg.pool.fee_denominator)?;
+    // Price what the vault actually received, not what was requested
+    let pool_amount_in = deposit_swap_input(&mut leg, amount_in, protocol_fee)?;
+    let amount_in = pool_amount_in
+        .checked_add(protocol_fee)
         .ok_or(error!(CustomError::CalculationOverflow))?;
-    pool.reserve_b = pool
-        .reserve_b
-        .checked_sub(amount_b_out)
-        .ok_or(error!(CustomError::InsufficientPoolReserves))?;
-    pool.total_volume_a = pool.total_volume_a.saturating_add(amount_a_in);
-    let signer_seeds: &[&[&[u8]]] = &[&[
-        b"pool",
-        token_a_mint.as_ref(),
-        token_b_mint.as_ref(),
-        &[bump_seed],
-    ]];
+    require_slippage_within_cap(amm_config, spot_amount_out(leg.pool, amount_in, is_a_to_b), minimum_amount_out)?;
+    let SwapQuote { amount_out, fee_amount } = quote_pool_swap(leg.pool, amount_in, is_a_to_b, now)?;
+    let amount_out = apply_spread_bps(amount_out, spread_bps)?;
+    require!(amount_out >= minimum_amount_out, CustomError::SlippageTooHigh);
+    require!(leg.vault_out.amount >= amount_out, CustomError::InsufficientPoolReserves);
+    settle_swap(leg, amount_in, pool_amount_in, protocol_fee, amount_out, fee_amount, is_a_to_b)
+}
+/// Route the protocol's share of the fee from the trader straight to the treasury.
+fn pay_protocol_fee<'info>(
+    token_program: &Program<'info, Token>,
+    user_token_in: &Account<'info, TokenAccount>,
+    protocol_fee_account: &Option<Account<'info, TokenAccount>>,
+    user: &Signer<'info>,
+    protocol_fee: u64,
+) -> Result<()> {
+    if protocol_fee == 0 {
+        return Ok(());
+    }
+    let treasury = protocol_fee_account
+        .as_ref()
+        .ok_or(error!(CustomError::MissingProtocolFeeAccount))?;
     transfer(
-        CpiContext::new_with_signer(
-            ctx.accounts.token_program.to_account_info(),
+        CpiContext::new(
+            token_program.to_account_info(),
             Transfer {
-                from: ctx.accounts.pool_vault_out.to_account_info(),
-                to: ctx.accounts.user_token_out.to_account_info(),
-                authority: pool.to_account_info(),
+                from: user_token_in.to_account_info(),
+                to: treasury.to_account_info(),
+                authority: user.to_account_info(),
             },
-            signer_seeds,
         ),
-        amount_b_out,
-    )?;
-    emit!(SwapExecuted {
-        user: ctx.accounts.user.key(),
-        pool: pool_key,
-        amount_in: amount_a_in,
-        amount_out: amount_b_out,
-        fee_amount,
-        is_a_to_b: true,
-        new_reserve_a: pool.reserve_a,
-        new_reserve_b: pool.reserve_b,
-    });
-    Ok(())
+        protocol_fee,
+    )
 }
 #[derive(Accounts)]
 pub struct Swap<'info> {
@@ -329,8 +387,18 @@ pub struct Swap<'info> {
         constraint = pool_vault_in.key() != pool_vault_out.key() @ CustomError::InvalidAmount
     )]
     pub pool_vault_out: Account<'info, TokenAccount>,
+    /// Treasury token account for the input mint; required when a protocol fee is set.
+    #[account(
+        mut,
+        token::mint = pool_vault_in.mint,
+        token::authority = pool.protocol_fee_vault
+    )]
+    pub protocol_fee_account: Option<Account<'info, TokenAccount>>,
     #[account(mut)]
     pub user: Signer<'info>,
+    /// CHECK: AMM config PDA; may be uninitialized, in which case no slippage cap applies
+    #[account(seeds = [b"amm_config"], bump)]
+    pub amm_config: UncheckedAccount<'info>,
     pub token_program: Program<'info, Token>,
 }
 #[derive(Accounts)]
@@ -359,8 +427,18 @@ pub struct MarketBuy<'info> {
         constraint = pool_vault_out.key() == pool.token_a_vault @ CustomError::InvalidAmount
     )]
     pub pool_vault_out: Account<'info, TokenAccount>,
+    /// Treasury token account for the input mint; required when a protocol fee is set.
+    #[account(
+        mut,
+        token::mint = pool.token_b_mint,
+        token::authority = pool.protocol_fee_vault
+    )]
+    pub protocol_fee_account: Option<Account<'info, TokenAccount>>,
     #[account(mut)]
     pub user: Signer<'info>,
+    /// CHECK: AMM config PDA; may be uninitialized, in which case no slippage cap applies
+    #[account(seeds = [b"amm_config"], bump)]
+    pub amm_config: UncheckedAccount<'info>,
     pub token_program: Program<'info, Token>,
 }
 #[derive(Accounts)]
@@ -389,7 +467,491 @@ pub struct MarketSell<'info> {
         constraint = pool_vault_out.key() == pool.token_b_vault @ CustomError::InvalidAmount
     )]
     pub pool_vault_out: Account<'info, TokenAccount>,
+    /// Treasury token account for the input mint; required when a protocol fee is set.
+    #[account(
+        mut,
+        token::mint = pool.token_a_mint,
+        token::authority = pool.protocol_fee_vault
+    )]
+    pub protocol_fee_account: Option<Account<'info, TokenAccount>>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    /// CHECK: AMM config PDA; may be uninitialized, in which case no slippage cap applies
+    #[account(seeds = [b"amm_config"], bump)]
+    pub amm_config: UncheckedAccount<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+// ─────────────────────────────────────────────────────
+// Read-only swap quote
+// ─────────────────────────────────────────────────────
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SwapQuote {
+    pub amount_out: u64,
+    pub fee_amount: u64,
+}
+
+/// Exactly what `swap` would pay out and charge against the pool's current state.
+pub fn quote_pool_swap(pool: &LiquidityPool, amount_in: u64, is_a_to_b: bool, now: i64) -> Result<SwapQuote> {
+    let (input_reserve, output_reserve) = if is_a_to_b {
+        (pool.reserve_a, pool.reserve_b)
+    } else {
+        (pool.reserve_b, pool.reserve_a)
+    };
+    require!(
+        output_reserve > 0 && input_reserve > 0,
+        CustomError::InsufficientLiquidity
+    );
+    let fee_numerator = pool.swap_fee_numerator(amount_in, is_a_to_b, now)?;
+    let amount_out = calculate_output_amount(
+        amount_in,
+        input_reserve,
+        output_reserve,
+        fee_numerator,
+        pool.fee_denominator,
+        pool.stable_amplification(),
+    )?;
+    let fee_amount = calculate_swap_fee(amount_in, fee_numerator, pool.fee_denominator)?;
+    Ok(SwapQuote { amount_out, fee_amount })
+}
+
+#[derive(Accounts)]
+pub struct QuoteSwap<'info> {
+    pub pool: Account<'info, LiquidityPool>,
+}
+
+pub fn quote_swap(ctx: Context<QuoteSwap>, amount_in: u64, is_a_to_b: bool) -> Result<SwapQuote> {
+    require!(amount_in > 0, CustomError::InvalidAmount);
+    let now = Clock::get()?.unix_timestamp;
+    quote_pool_swap(&ctx.accounts.pool, amount_in, is_a_to_b, now)
+}
+
+// ─────────────────────────────────────────────────────
+// Read-only swap preview
+// ─────────────────────────────────────────────────────
+
+/// Swap quote for UIs. Prices are output per input, scaled by 1e6.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct EffectivePrice {
+    pub amount_out: u64,
+    pub spot_price: u64,
+    pub effective_price: u64,
+    pub fee_amount: u64,
+    /// Shortfall of the effective price versus the fee-adjusted spot price.
+    pub price_impact_bps: u64,
+}
+
+pub fn quote_effective_price(
+    amount_in: u64,
+    input_reserve: u64,
+    output_reserve: u64,
+    fee_numerator: u64,
+    fee_denominator: u64,
+    amplification: Option<u64>,
+) -> Result<EffectivePrice> {
+    let amount_out = calculate_output_amount(
+        amount_in,
+        input_reserve,
+        output_reserve,
+        fee_numerator,
+        fee_denominator,
+        amplification,
+    )?;
+    let spot_price = (output_reserve as u128) * PRICE_PRECISION / (input_reserve as u128);
+    let effective_price = (amount_out as u128) * PRICE_PRECISION / (amount_in as u128);
+    let fee_amount = ((amount_in as u128) * (fee_numerator as u128)).div_ceil(fee_denominator as u128);
+    let spot_after_fee = spot_price * ((fee_denominator - fee_numerator) as u128) / (fee_denominator as u128);
+    let price_impact_bps = (spot_after_fee.saturating_sub(effective_price) * 10_000)
+        .checked_div(spot_after_fee)
+        .unwrap_or(0);
+    Ok(EffectivePrice {
+        amount_out,
+        spot_price: u64::try_from(spot_price).map_err(|_| error!(CustomError::CalculationOverflow))?,
+        effective_price: u64::try_from(effective_price).map_err(|_| error!(CustomError::CalculationOverflow))?,
+        fee_amount: fee_amount as u64,
+        price_impact_bps: price_impact_bps as u64,
+    })
+}
+
+#[derive(Accounts)]
+pub struct GetEffectivePrice<'info> {
+    pub pool: Account<'info, LiquidityPool>,
+}
+
+pub fn get_effective_price(
+    ctx: Context<GetEffectivePrice>,
+    amount_in: u64,
+    is_a_to_b: bool,
+) -> Result<EffectivePrice> {
+    let pool = &ctx.accounts.pool;
+    let now = Clock::get()?.unix_timestamp;
+    let (input_reserve, output_reserve) = if is_a_to_b {
+        (pool.reserve_a, pool.reserve_b)
+    } else {
+        (pool.reserve_b, pool.reserve_a)
+    };
+    quote_effective_price(
+        amount_in,
+        input_reserve,
+        output_reserve,
+        pool.swap_fee_numerator(amount_in, is_a_to_b, now)?,
+        pool.fee_denominator,
+        pool.stable_amplification(),
+    )
+}
+
+// ─────────────────────────────────────────────────────
+// Two-hop routing
+// ─────────────────────────────────────────────────────
+
+/// Swap through two pools in one instruction, e.g. SOL → USDC → RUSH. The first
+/// hop's output goes whole into the second; only the final output is checked
+/// against `minimum_final_out`.
+pub fn swap_two_hop(
+    ctx: Context<SwapTwoHop>,
+    amount_in: u64,
+    minimum_final_out: u64,
+    deadline: i64,
+) -> Result<()> {
+    let current_time = Clock::get()?.unix_timestamp;
+    require!(current_time <= deadline, CustomError::DeadlineExceeded);
+    require!(amount_in > 0, CustomError::InvalidAmount);
+    require!(
+        ctx.accounts.user_token_in.amount >= amount_in,
+        CustomError::InsufficientBalance
+    );
+    ctx.accounts.first_pool.require_active()?;
+    ctx.accounts.second_pool.require_active()?;
+    acquire_pool_lock(&mut ctx.accounts.first_pool)?;
+    acquire_pool_lock(&mut ctx.accounts.second_pool)?;
+    let accounts = ctx.accounts;
+    // Spot through both pools, taken before either leg moves a price
+    let first_is_a_to_b = accounts.first_vault_in.key() == accounts.first_pool.token_a_vault;
+    let second_is_a_to_b = accounts.second_vault_in.key() == accounts.second_pool.token_a_vault;
+    let spot_out = spot_amount_out(&accounts.first_pool, amount_in, first_is_a_to_b)
+        .and_then(|mid| spot_amount_out(&accounts.second_pool, mid, second_is_a_to_b));
+    require_slippage_within_cap(&accounts.amm_config, spot_out, minimum_final_out)?;
+    let intermediate_amount = execute_swap_leg(
+        SwapLeg {
+            pool: &mut accounts.first_pool,
+            vault_in: &mut accounts.first_vault_in,
+            vault_out: &mut accounts.first_vault_out,
+            user_token_in: &accounts.user_token_in,
+            user_token_out: &accounts.user_token_mid,
+            protocol_fee_account: &accounts.first_protocol_fee_account,
+            user: &accounts.user,
+            token_program: &accounts.token_program,
+        },
+        amount_in,
+        current_time,
+    )?;
+    let amount_out = execute_swap_leg(
+        SwapLeg {
+            pool: &mut accounts.second_pool,
+            vault_in: &mut accounts.second_vault_in,
+            vault_out: &mut accounts.second_vault_out,
+            user_token_in: &accounts.user_token_mid,
+            user_token_out: &accounts.user_token_out,
+            protocol_fee_account: &accounts.second_protocol_fee_account,
+            user: &accounts.user,
+            token_program: &accounts.token_program,
+        },
+        intermediate_amount,
+        current_time,
+    )?;
+    require!(amount_out >= minimum_final_out, CustomError::SlippageTooHigh);
+    emit!(RouteExecuted {
+        user: accounts.user.key(),
+        first_pool: accounts.first_pool.key(),
+        second_pool: accounts.second_pool.key(),
+        amount_in,
+        intermediate_amount,
+        amount_out,
+    });
+    Ok(())
+}
+
+/// Accounts one swap works on: a `swap`, a market order or one hop of a route.
+struct SwapLeg<'a, 'info> {
+    pool: &'a mut Account<'info, LiquidityPool>,
+    vault_in: &'a mut Account<'info, TokenAccount>,
+    vault_out: &'a mut Account<'info, TokenAccount>,
+    user_token_in: &'a Account<'info, TokenAccount>,
+    user_token_out: &'a Account<'info, TokenAccount>,
+    protocol_fee_account: &'a Option<Account<'info, TokenAccount>>,
+    user: &'a Signer<'info>,
+    token_program: &'a Program<'info, Token>,
+}
+
+impl<'info> Swap<'info> {
+    fn leg(&mut self) -> SwapLeg<'_, 'info> {
+        SwapLeg {
+            pool: &mut self.pool,
+            vault_in: &mut self.pool_vault_in,
+            vault_out: &mut self.pool_vault_out,
+            user_token_in: &self.user_token_in,
+            user_token_out: &self.user_token_out,
+            protocol_fee_account: &self.protocol_fee_account,
+            user: &self.user,
+            token_program: &self.token_program,
+        }
+    }
+}
+
+/// Run one hop at market: no slippage check of its own. Direction follows
+/// `vault_in`. Expects the pool lock to be held; releases it. Returns the output.
+fn execute_swap_leg(mut leg: SwapLeg, amount_in: u64, now: i64) -> Result<u64> {
+    let is_a_to_b = leg.vault_in.key() == leg.pool.token_a_vault;
+    let protocol_fee = calculate_protocol_fee(amount_in, leg.pool.protocol_fee_numerator, leg.pool.fee_denominator)?;
+    let pool_amount_in = deposit_swap_input(&mut leg, amount_in, protocol_fee)?;
+    let amount_in = pool_amount_in
+        .checked_add(protocol_fee)
+        .ok_or(error!(CustomError::CalculationOverflow))?;
+    let SwapQuote { amount_out, fee_amount } = quote_pool_swap(leg.pool, amount_in, is_a_to_b, now)?;
+    require!(leg.vault_out.amount >= amount_out, CustomError::InsufficientPoolReserves);
+    settle_swap(leg, amount_in, pool_amount_in, protocol_fee, amount_out, fee_amount, is_a_to_b)?;
+    Ok(amount_out)
+}
+
+#[derive(Accounts)]
+pub struct SwapTwoHop<'info> {
+    #[account(mut)]
+    pub first_pool: Box<Account<'info, LiquidityPool>>,
+    #[account(
+        mut,
+        constraint = second_pool.key() != first_pool.key() @ CustomError::InvalidPool
+    )]
+    pub second_pool: Box<Account<'info, LiquidityPool>>,
+    #[account(
+        mut,
+        token::mint = first_vault_in.mint,
+        token::authority = user
+    )]
+    pub user_token_in: Box<Account<'info, TokenAccount>>,
+    /// Receives the first hop's output and pays it into the second hop.
+    #[account(
+        mut,
+        token::mint = first_vault_out.mint,
+        token::authority = user
+    )]
+    pub user_token_mid: Box<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        token::mint = second_vault_out.mint,
+        token::authority = user
+    )]
+    pub user_token_out: Box<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = first_vault_in.key() == first_pool.token_a_vault || first_vault_in.key() == first_pool.token_b_vault @ CustomError::InvalidVault
+    )]
+    pub first_vault_in: Box<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = first_vault_out.key() == first_pool.token_a_vault || first_vault_out.key() == first_pool.token_b_vault @ CustomError::InvalidVault,
+        constraint = first_vault_in.key() != first_vault_out.key() @ CustomError::InvalidVault
+    )]
+    pub first_vault_out: Box<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = second_vault_in.key() == second_pool.token_a_vault || second_vault_in.key() == second_pool.token_b_vault @ CustomError::InvalidVault,
+        constraint = second_vault_in.mint == first_vault_out.mint @ CustomError::InvalidMint
+    )]
+    pub second_vault_in: Box<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = second_vault_out.key() == second_pool.token_a_vault || second_vault_out.key() == second_pool.token_b_vault @ CustomError::InvalidVault,
+        constraint = second_vault_in.key() != second_vault_out.key() @ CustomError::InvalidVault
+    )]
+    pub second_vault_out: Box<Account<'info, TokenAccount>>,
+    /// Treasury account for the first pool's input mint; required when it charges a protocol fee.
+    #[account(
+        mut,
+        token::mint = first_vault_in.mint,
+        token::authority = first_pool.protocol_fee_vault
+    )]
+    pub first_protocol_fee_account: Option<Account<'info, TokenAccount>>,
+    /// Treasury account for the intermediate mint; required when the second pool charges a protocol fee.
+    #[account(
+        mut,
+        token::mint = second_vault_in.mint,
+        token::authority = second_pool.protocol_fee_vault
+    )]
+    pub second_protocol_fee_account: Option<Account<'info, TokenAccount>>,
     #[account(mut)]
     pub user: Signer<'info>,
+    /// CHECK: AMM config PDA; may be uninitialized, in which case no slippage cap applies
+    #[account(seeds = [b"amm_config"], bump)]
+    pub amm_config: UncheckedAccount<'info>,
     pub token_program: Program<'info, Token>,
 }
+
+// ─────────────────────────────────────────────────────
+// Time-weighted average price
+// ─────────────────────────────────────────────────────
+
+/// TWAP read from the pool's accumulators. Prices are scaled by `TWAP_PRICE_PRECISION`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TwapView {
+    /// Token B per token A.
+    pub price_a: u128,
+    /// Token A per token B.
+    pub price_b: u128,
+    /// Actual averaging window; at least the requested one.
+    pub window_secs: i64,
+}
+
+#[derive(Accounts)]
+pub struct GetTwap<'info> {
+    pub pool: Account<'info, LiquidityPool>,
+}
+
+pub fn get_twap(ctx: Context<GetTwap>, window_secs: i64) -> Result<TwapView> {
+    let now = Clock::get()?.unix_timestamp;
+    let (price_a, price_b, window_secs) = ctx.accounts.pool.twap(window_secs, now)?;
+    Ok(TwapView { price_a, price_b, window_secs })
+}
+
+/// LP yield over a recent window, in bps per year.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PoolApr {
+    /// Annualized swap fees relative to TVL, both valued at the TWAP.
+    pub fee_apr_bps: u64,
+    /// Configured RUSH reward APY.
+    pub reward_apr_bps: u64,
+    pub total_apr_bps: u64,
+    /// Actual lookback; at least the requested one.
+    pub window_secs: i64,
+}
+impl PoolApr {
+    pub fn new(fee_apr_bps: u64, reward_apr_bps: u64, window_secs: i64) -> Self {
+        Self {
+            fee_apr_bps,
+            reward_apr_bps,
+            total_apr_bps: fee_apr_bps.saturating_add(reward_apr_bps),
+            window_secs,
+        }
+    }
+}
+
+#[derive(Accounts)]
+pub struct GetPoolApr<'info> {
+    pub pool: Account<'info, LiquidityPool>,
+    #[account(
+        seeds = [b"rush_config"],
+        bump = rush_config.bump
+    )]
+    pub rush_config: Account<'info, RushConfig>,
+}
+
+pub fn get_pool_apr(ctx: Context<GetPoolApr>, lookback_secs: i64) -> Result<PoolApr> {
+    let now = Clock::get()?.unix_timestamp;
+    let (fee_apr_bps, window_secs) = ctx.accounts.pool.fee_apr_bps(lookback_secs, now)?;
+    Ok(PoolApr::new(fee_apr_bps, ctx.accounts.rush_config.reward_apy_bps(), window_secs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::pool::tests::make_pool;
+
+    #[test]
+    fn test_effective_price_degrades_with_size() {
+        let reserve_a = 1_000_000_000u64;
+        let reserve_b = 100_000_000_000u64;
+        let mut previous: Option<EffectivePrice> = None;
+        for amount_in in [1_000u64, 1_000_000, 10_000_000, 100_000_000] {
+            let quote = quote_effective_price(amount_in, reserve_a, reserve_b, 3, 1000, None).unwrap();
+            assert_eq!(quote.spot_price, 100_000_000);
+            assert!(quote.effective_price < quote.spot_price);
+            if let Some(prev) = previous {
+                assert!(quote.effective_price < prev.effective_price);
+                assert!(quote.price_impact_bps >= prev.price_impact_bps);
+            }
+            previous = Some(quote);
+        }
+    }
+
+    #[test]
+    fn test_small_swap_pays_only_fee() {
+        let quote = quote_effective_price(1_000_000, 1_000_000_000_000, 1_000_000_000_000, 3, 1000, None).unwrap();
+        assert_eq!(quote.fee_amount, 3_000);
+        assert_eq!(quote.price_impact_bps, 0);
+        assert_eq!(quote.effective_price, 996_999);
+    }
+
+    #[test]
+    fn test_price_impact_matches_curve() {
+        // 10% of reserves in: x*y=k impact is in/(x+in) ≈ 9.1% before fees
+        let quote = quote_effective_price(100_000_000, 1_000_000_000, 1_000_000_000, 3, 1000, None).unwrap();
+        assert!((900..=910).contains(&quote.price_impact_bps));
+        assert_eq!(quote.fee_amount, 300_000);
+    }
+
+    #[test]
+    fn test_quote_matches_swap_math() {
+        let pool = make_pool(1_000_000_000, 250_000_000_000);
+        let quote = quote_pool_swap(&pool, 10_000_000, true, 0).unwrap();
+        let expected = calculate_output_amount(10_000_000, 1_000_000_000, 250_000_000_000, 3, 1000, None).unwrap();
+        assert_eq!(quote.amount_out, expected);
+        assert_eq!(quote.fee_amount, 30_000);
+        // Fee rounds up, matching the charge in swap()
+        assert_eq!(quote_pool_swap(&pool, 1_001, false, 0).unwrap().fee_amount, 4);
+    }
+
+    #[test]
+    fn test_pool_apr_total_includes_rewards() {
+        let apr = PoolApr::new(1_200, 5_000, 3_600);
+        assert_eq!(apr.total_apr_bps, 6_200);
+        assert_eq!(PoolApr::new(0, 5_000, 3_600).total_apr_bps, 5_000);
+    }
+
+    #[test]
+    fn test_swap_and_market_paths_book_the_same_fee() {
+        // 0.3% of 1_001 is 3.003: every path rounds it up to 4
+        let pool = make_pool(1_000_000, 1_000_000);
+        for is_a_to_b in [true, false] {
+            let swap_fee = quote_pool_swap(&pool, 1_001, is_a_to_b, 0).unwrap().fee_amount;
+            // market_buy (B → A) and market_sell (A → B) price the fee this way
+            let fee_numerator = pool.swap_fee_numerator(1_001, is_a_to_b, 0).unwrap();
+            let market_fee = calculate_swap_fee(1_001, fee_numerator, pool.fee_denominator).unwrap();
+            assert_eq!(swap_fee, 4);
+            assert_eq!(market_fee, swap_fee);
+        }
+    }
+
+    #[test]
+    fn test_spot_amount_out_uses_reserve_ratio() {
+        let pool = make_pool(1_000_000, 4_000_000);
+        assert_eq!(spot_amount_out(&pool, 1_000, true), Some(4_000));
+        assert_eq!(spot_amount_out(&pool, 4_000, false), Some(1_000));
+        assert_eq!(spot_amount_out(&make_pool(0, 0), 1_000, true), None);
+    }
+
+    #[test]
+    fn test_spot_amount_out_uses_stable_curve() {
+        let mut pool = make_pool(1_000_000, 1_000_000);
+        pool.is_stablecoin_pool = true;
+        pool.amplification_coefficient = 100;
+        // Near 1:1 on a balanced curve, and never below the fee-charged quote
+        let spot = spot_amount_out(&pool, 1_000, true).unwrap();
+        assert!((990..=1_000).contains(&spot));
+        pool.fee_numerator = 30;
+        assert!(quote_pool_swap(&pool, 1_000, true, 0).unwrap().amount_out < spot);
+    }
+
+    #[test]
+    fn test_fee_on_transfer_input_keeps_reserves_in_sync() {
+        // 1% transfer fee: 10_000 sent, 9_900 lands in the vault
+        let mut pool = make_pool(1_000_000, 1_000_000);
+        let vault_a_after = pool.reserve_a + 9_900;
+        let quote = quote_pool_swap(&pool, 9_900, true, 0).unwrap();
+        assert!(quote.amount_out < quote_pool_swap(&pool, 10_000, true, 0).unwrap().amount_out);
+        let reserves = pool.apply_swap(9_900, 9_900, quote.amount_out, true).unwrap();
+        assert_eq!(reserves, (pool.reserve_a, pool.reserve_b));
+        assert_eq!(pool.reserve_a, vault_a_after);
+        assert_eq!(pool.reserve_b, 1_000_000 - quote.amount_out);
+        assert_eq!(pool.total_volume_a, 9_900);
+    }
+}
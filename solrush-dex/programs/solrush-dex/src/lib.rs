@@ -19,8 +19,37 @@ pub mod solrush_dex {
     use super::*;
     pub fn initialize_pool(
         ctx: Context<InitializePool>,
+        amplification_coefficient: u64,
+        initial_deposit_a: u64,
+        initial_deposit_b: u64,
     ) -> Result<()> {
-        instructions::pool::initialize_pool(ctx)
+        instructions::pool::initialize_pool(
+            ctx,
+            amplification_coefficient,
+            initial_deposit_a,
+            initial_deposit_b,
+        )
+    }
+    pub fn initialize_amm_config(ctx: Context<InitializeAmmConfig>) -> Result<()> {
+        instructions::amm_config::initialize_amm_config(ctx)
+    }
+    pub fn set_mint_whitelist_enabled(ctx: Context<UpdateAmmConfig>, enabled: bool) -> Result<()> {
+        instructions::amm_config::set_mint_whitelist_enabled(ctx, enabled)
+    }
+    pub fn set_fee_bounds(ctx: Context<UpdateAmmConfig>, min_fee_bps: u16, max_fee_bps: u16) -> Result<()> {
+        instructions::amm_config::set_fee_bounds(ctx, min_fee_bps, max_fee_bps)
+    }
+    pub fn set_max_allowed_slippage(ctx: Context<UpdateAmmConfig>, max_allowed_slippage_bps: u16) -> Result<()> {
+        instructions::amm_config::set_max_allowed_slippage(ctx, max_allowed_slippage_bps)
+    }
+    pub fn set_min_partial_fill(ctx: Context<UpdateAmmConfig>, min_partial_fill_bps: u16) -> Result<()> {
+        instructions::amm_config::set_min_partial_fill(ctx, min_partial_fill_bps)
+    }
+    pub fn add_whitelisted_mint(ctx: Context<UpdateAmmConfig>, mint: Pubkey) -> Result<()> {
+        instructions::amm_config::add_whitelisted_mint(ctx, mint)
+    }
+    pub fn remove_whitelisted_mint(ctx: Context<UpdateAmmConfig>, mint: Pubkey) -> Result<()> {
+        instructions::amm_config::remove_whitelisted_mint(ctx, mint)
     }
     pub fn add_liquidity(
         ctx: Context<AddLiquidity>,
@@ -30,6 +59,14 @@ pub mod solrush_dex {
     ) -> Result<()> {
         instructions::pool::add_liquidity(ctx, amount_a, amount_b, min_lp_tokens)
     }
+    pub fn add_liquidity_single_sided(
+        ctx: Context<AddLiquidity>,
+        amount_in: u64,
+        is_token_a: bool,
+        min_lp_tokens: u64,
+    ) -> Result<()> {
+        instructions::pool::add_liquidity_single_sided(ctx, amount_in, is_token_a, min_lp_tokens)
+    }
     pub fn remove_liquidity(
         ctx: Context<RemoveLiquidity>,
         lp_tokens_to_burn: u64,
@@ -38,17 +75,95 @@ pub mod solrush_dex {
     ) -> Result<()> {
         instructions::pool::remove_liquidity(ctx, lp_tokens_to_burn, min_amount_a, min_amount_b)
     }
+    pub fn remove_liquidity_percent(
+        ctx: Context<RemoveLiquidity>,
+        bps: u16,
+        min_amount_a: u64,
+        min_amount_b: u64,
+    ) -> Result<()> {
+        instructions::pool::remove_liquidity_percent(ctx, bps, min_amount_a, min_amount_b)
+    }
+    pub fn emergency_remove_liquidity(
+        ctx: Context<RemoveLiquidity>,
+        lp_tokens_to_burn: u64,
+    ) -> Result<()> {
+        instructions::pool::emergency_remove_liquidity(ctx, lp_tokens_to_burn)
+    }
     pub fn close_pool(ctx: Context<ClosePool>) -> Result<()> {
         instructions::pool::close_pool(ctx)
     }
+    pub fn set_market_spread(
+        ctx: Context<SetMarketSpread>,
+        buy_premium_bps: u16,
+        sell_discount_bps: u16,
+    ) -> Result<()> {
+        instructions::pool::set_market_spread(ctx, buy_premium_bps, sell_discount_bps)
+    }
+    pub fn set_protocol_fee(
+        ctx: Context<SetProtocolFee>,
+        protocol_fee_numerator: u64,
+        protocol_fee_vault: Pubkey,
+    ) -> Result<()> {
+        instructions::pool::set_protocol_fee(ctx, protocol_fee_numerator, protocol_fee_vault)
+    }
+    pub fn set_pool_paused(ctx: Context<SetPoolPaused>, is_paused: bool) -> Result<()> {
+        instructions::pool::set_pool_paused(ctx, is_paused)
+    }
+    pub fn set_pool_fee(ctx: Context<SetPoolFee>, fee_bps: u16) -> Result<()> {
+        instructions::pool::set_pool_fee(ctx, fee_bps)
+    }
+    pub fn set_flash_fee(ctx: Context<SetFlashFee>, flash_fee_bps: u16) -> Result<()> {
+        instructions::pool::set_flash_fee(ctx, flash_fee_bps)
+    }
+    pub fn set_alignment_boost(ctx: Context<SetAlignmentBoost>, boost_bps: u16, band_bps: u16) -> Result<()> {
+        instructions::pool::set_alignment_boost(ctx, boost_bps, band_bps)
+    }
+    pub fn set_dynamic_fee(ctx: Context<SetDynamicFee>, dynamic_fee_bps: u16) -> Result<()> {
+        instructions::pool::set_dynamic_fee(ctx, dynamic_fee_bps)
+    }
+    pub fn sync_reserves(ctx: Context<SyncReserves>) -> Result<()> {
+        instructions::pool::sync_reserves(ctx)
+    }
+    pub fn get_pool_fees(ctx: Context<GetPoolFees>) -> Result<PoolFees> {
+        instructions::pool::get_pool_fees(ctx)
+    }
+    pub fn get_position_value(ctx: Context<GetPositionValue>) -> Result<PositionValue> {
+        instructions::pool::get_position_value(ctx)
+    }
+    pub fn get_pool_liquidity(ctx: Context<GetPoolLiquidity>) -> Result<PoolLiquidity> {
+        instructions::pool::get_pool_liquidity(ctx)
+    }
+    pub fn flash_loan_borrow(ctx: Context<FlashLoanBorrow>, amount: u64) -> Result<()> {
+        instructions::flash_loan::flash_loan_borrow(ctx, amount)
+    }
+    pub fn flash_loan_repay(ctx: Context<FlashLoanRepay>) -> Result<()> {
+        instructions::flash_loan::flash_loan_repay(ctx)
+    }
     pub fn swap(
         ctx: Context<Swap>,
         amount_in: u64,
         minimum_amount_out: u64,
         is_a_to_b: bool,
         deadline: i64,
+        max_price_impact_bps: u16,
+    ) -> Result<()> {
+        instructions::swap::swap(
+            ctx,
+            amount_in,
+            minimum_amount_out,
+            is_a_to_b,
+            deadline,
+            max_price_impact_bps,
+        )
+    }
+    pub fn swap_exact_out(
+        ctx: Context<Swap>,
+        amount_out: u64,
+        maximum_amount_in: u64,
+        is_a_to_b: bool,
+        deadline: i64,
     ) -> Result<()> {
-        instructions::swap::swap(ctx, amount_in, minimum_amount_out, is_a_to_b, deadline)
+        instructions::swap::swap_exact_out(ctx, amount_out, maximum_amount_in, is_a_to_b, deadline)
     }
     pub fn market_buy(
         ctx: Context<MarketBuy>,
@@ -66,6 +181,31 @@ pub mod solrush_dex {
     ) -> Result<()> {
         instructions::swap::market_sell(ctx, amount_a_in, min_b_received, deadline)
     }
+    pub fn get_effective_price(
+        ctx: Context<GetEffectivePrice>,
+        amount_in: u64,
+        is_a_to_b: bool,
+    ) -> Result<EffectivePrice> {
+        instructions::swap::get_effective_price(ctx, amount_in, is_a_to_b)
+    }
+    pub fn quote_swap(ctx: Context<QuoteSwap>, amount_in: u64, is_a_to_b: bool) -> Result<SwapQuote> {
+        instructions::swap::quote_swap(ctx, amount_in, is_a_to_b)
+    }
+    pub fn swap_two_hop(
+        ctx: Context<SwapTwoHop>,
+        amount_in: u64,
+        minimum_final_out: u64,
+        deadline: i64,
+    ) -> Result<()> {
+        instructions::swap::swap_two_hop(ctx, amount_in, minimum_final_out, deadline)
+    }
+    pub fn get_twap(ctx: Context<GetTwap>, window_secs: i64) -> Result<TwapView> {
+        instructions::swap::get_twap(ctx, window_secs)
+    }
+    pub fn get_pool_apr(ctx: Context<GetPoolApr>, lookback_secs: i64) -> Result<PoolApr> {
+        instructions::swap::get_pool_apr(ctx, lookback_secs)
+    }
+    #[allow(clippy::too_many_arguments)]
     pub fn create_limit_order(
         ctx: Context<CreateLimitOrder>,
         sell_amount: u64,
@@ -73,6 +213,10 @@ pub mod solrush_dex {
         minimum_receive: u64,
         expiry_days: i64,
         order_id: u64,
+        keeper_tip_lamports: u64,
+        order_kind: OrderKind,
+        execution_reward_bps: u16,
+        time_in_force: TimeInForce,
     ) -> Result<()> {
         instructions::limit_orders::create_limit_order(
             ctx,
@@ -81,29 +225,98 @@ pub mod solrush_dex {
             minimum_receive,
             expiry_days,
             order_id,
+            keeper_tip_lamports,
+            order_kind,
+            execution_reward_bps,
+            time_in_force,
         )
     }
-    pub fn execute_limit_order(ctx: Context<ExecuteLimitOrder>) -> Result<()> {
-        instructions::limit_orders::execute_limit_order(ctx)
+    pub fn execute_limit_order(ctx: Context<ExecuteLimitOrder>, fill_amount: u64) -> Result<()> {
+        instructions::limit_orders::execute_limit_order(ctx, fill_amount)
+    }
+    pub fn execute_limit_orders_batch<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ExecuteLimitOrdersBatch<'info>>,
+    ) -> Result<()> {
+        instructions::limit_orders::execute_limit_orders_batch(ctx)
+    }
+    pub fn get_order_price_gap(ctx: Context<GetOrderPriceGap>) -> Result<OrderPriceGap> {
+        instructions::limit_orders::get_order_price_gap(ctx)
     }
     pub fn cancel_limit_order(ctx: Context<CancelLimitOrder>) -> Result<()> {
         instructions::limit_orders::cancel_limit_order(ctx)
     }
+    pub fn modify_limit_order(
+        ctx: Context<ModifyLimitOrder>,
+        new_target_price: u64,
+        new_minimum_receive: u64,
+        new_expiry_days: i64,
+    ) -> Result<()> {
+        instructions::limit_orders::modify_limit_order(ctx, new_target_price, new_minimum_receive, new_expiry_days)
+    }
+    pub fn close_expired_order(ctx: Context<CloseExpiredOrder>) -> Result<()> {
+        instructions::limit_orders::close_expired_order(ctx)
+    }
     pub fn initialize_rush_token(ctx: Context<InitializeRushToken>) -> Result<()> {
         instructions::rewards::initialize_rush_token(ctx)
     }
     pub fn calculate_pending_rewards(ctx: Context<CalculateRewards>) -> Result<u64> {
         instructions::rewards::calculate_pending_rewards(ctx)
     }
+    pub fn get_user_rewards_info(ctx: Context<GetUserRewardsInfo>) -> Result<UserRewardsInfo> {
+        instructions::rewards::get_user_rewards_info(ctx)
+    }
     pub fn claim_rush_rewards(ctx: Context<ClaimRewards>) -> Result<()> {
         instructions::rewards::claim_rush_rewards(ctx)
     }
+    pub fn lock_position(ctx: Context<LockPosition>, lock_days: u64) -> Result<()> {
+        instructions::rewards::lock_position(ctx, lock_days)
+    }
+    pub fn release_expired_lock(ctx: Context<ReleaseExpiredLock>) -> Result<()> {
+        instructions::rewards::release_expired_lock(ctx)
+    }
+    pub fn add_reward_program(
+        ctx: Context<AddRewardProgram>,
+        rewards_per_second: u64,
+        duration_secs: i64,
+    ) -> Result<()> {
+        instructions::rewards::add_reward_program(ctx, rewards_per_second, duration_secs)
+    }
+    pub fn claim_reward(ctx: Context<ClaimReward>, reward_mint: Pubkey) -> Result<()> {
+        instructions::rewards::claim_reward(ctx, reward_mint)
+    }
+    pub fn compound_rewards(ctx: Context<CompoundRewards>, min_lp_tokens: u64) -> Result<()> {
+        instructions::rewards::compound_rewards(ctx, min_lp_tokens)
+    }
+    pub fn claim_and_compound(ctx: Context<CompoundRewards>, min_lp_tokens: u64) -> Result<()> {
+        instructions::rewards::claim_and_compound(ctx, min_lp_tokens)
+    }
     pub fn update_rush_apy(ctx: Context<UpdateRushAPY>, new_apy: u64) -> Result<()> {
         instructions::rewards::update_rush_apy(ctx, new_apy)
     }
     pub fn pause_rush_rewards(ctx: Context<PauseRewards>) -> Result<()> {
         instructions::rewards::pause_rush_rewards(ctx)
     }
+    pub fn set_min_claim_interval(
+        ctx: Context<UpdateRushConfig>,
+        min_claim_interval_secs: i64,
+    ) -> Result<()> {
+        instructions::rewards::set_min_claim_interval(ctx, min_claim_interval_secs)
+    }
+    pub fn set_min_rewards_liquidity(ctx: Context<UpdateRushConfig>, min_lp_supply: u64) -> Result<()> {
+        instructions::rewards::set_min_rewards_liquidity(ctx, min_lp_supply)
+    }
+    pub fn set_halving_interval(ctx: Context<UpdateRushConfig>, halving_interval_secs: i64) -> Result<()> {
+        instructions::rewards::set_halving_interval(ctx, halving_interval_secs)
+    }
+    pub fn set_pool_weight(ctx: Context<SetPoolWeight>, weight_bps: u16) -> Result<()> {
+        instructions::rewards::set_pool_weight(ctx, weight_bps)
+    }
+    pub fn transfer_rush_authority(ctx: Context<TransferRushAuthority>, new_authority: Pubkey) -> Result<()> {
+        instructions::rewards::transfer_rush_authority(ctx, new_authority)
+    }
+    pub fn accept_rush_authority(ctx: Context<AcceptRushAuthority>) -> Result<()> {
+        instructions::rewards::accept_rush_authority(ctx)
+    }
     pub fn initialize_perps_global(ctx: Context<InitializePerpsGlobal>, fee_bps: u16) -> Result<()> {
         instructions::perps::initialize_global(ctx, fee_bps)
     }
@@ -114,8 +327,83 @@ pub mod solrush_dex {
         maintenance_margin_bps: u16,
         max_funding_rate: i64,
         funding_interval_secs: i64,
+        max_open_interest_i128: i128,
+    ) -> Result<()> {
+        instructions::perps::create_market(
+            ctx,
+            pyth_feed_id,
+            max_leverage,
+            maintenance_margin_bps,
+            max_funding_rate,
+            funding_interval_secs,
+            max_open_interest_i128,
+        )
+    }
+    pub fn set_confidence_margin_multiplier(
+        ctx: Context<UpdatePerpsMarket>,
+        conf_mm_multiplier_bps: u16,
+    ) -> Result<()> {
+        instructions::perps::set_confidence_margin_multiplier(ctx, conf_mm_multiplier_bps)
+    }
+    pub fn set_insurance_floor(ctx: Context<UpdatePerpsMarket>, insurance_floor_u64: u64) -> Result<()> {
+        instructions::perps::set_insurance_floor(ctx, insurance_floor_u64)
+    }
+    pub fn set_min_insurance_seed(ctx: Context<UpdatePerpsMarket>, min_insurance_seed_u64: u64) -> Result<()> {
+        instructions::perps::set_min_insurance_seed(ctx, min_insurance_seed_u64)
+    }
+    pub fn set_collateral_haircut(ctx: Context<UpdatePerpsMarket>, collateral_haircut_bps: u16) -> Result<()> {
+        instructions::perps::set_collateral_haircut(ctx, collateral_haircut_bps)
+    }
+    pub fn set_max_open_interest(ctx: Context<UpdatePerpsMarket>, max_open_interest_i128: i128) -> Result<()> {
+        instructions::perps::set_max_open_interest(ctx, max_open_interest_i128)
+    }
+    pub fn set_market_paused(ctx: Context<UpdatePerpsMarket>, paused: bool) -> Result<()> {
+        instructions::perps::set_market_paused(ctx, paused)
+    }
+    pub fn set_liquidation_fee_source(ctx: Context<UpdatePerpsMarket>, insurance_first: bool) -> Result<()> {
+        instructions::perps::set_liquidation_fee_source(ctx, insurance_first)
+    }
+    pub fn set_oi_imbalance_limit(
+        ctx: Context<UpdatePerpsMarket>,
+        max_oi_imbalance_bps: u16,
+        free_base_u64: u64,
+    ) -> Result<()> {
+        instructions::perps::set_oi_imbalance_limit(ctx, max_oi_imbalance_bps, free_base_u64)
+    }
+    pub fn set_mark_twap_window(ctx: Context<UpdatePerpsMarket>, twap_window_secs: i64) -> Result<()> {
+        instructions::perps::set_mark_twap_window(ctx, twap_window_secs)
+    }
+    pub fn set_liquidation_incentive(
+        ctx: Context<UpdatePerpsMarket>,
+        liquidation_fee_bps: u16,
+        max_liquidation_fee_bps: u16,
+    ) -> Result<()> {
+        instructions::perps::set_liquidation_incentive(ctx, liquidation_fee_bps, max_liquidation_fee_bps)
+    }
+    pub fn set_min_liquidation_fee(ctx: Context<UpdatePerpsMarket>, min_liquidation_fee_u64: u64) -> Result<()> {
+        instructions::perps::set_min_liquidation_fee(ctx, min_liquidation_fee_u64)
+    }
+    pub fn set_funding_skew(ctx: Context<UpdatePerpsMarket>, funding_skew_bps: u16) -> Result<()> {
+        instructions::perps::set_funding_skew(ctx, funding_skew_bps)
+    }
+    pub fn set_funding_keeper_reward(ctx: Context<UpdatePerpsMarket>, funding_keeper_reward_u64: u64) -> Result<()> {
+        instructions::perps::set_funding_keeper_reward(ctx, funding_keeper_reward_u64)
+    }
+    pub fn set_oracle_staleness(ctx: Context<UpdatePerpsMarket>, max_oracle_staleness_secs: u32) -> Result<()> {
+        instructions::perps::set_oracle_staleness(ctx, max_oracle_staleness_secs)
+    }
+    pub fn set_perps_pause(
+        ctx: Context<UpdatePerpsGlobal>,
+        trading_paused: bool,
+        liquidations_enabled: bool,
     ) -> Result<()> {
-        instructions::perps::create_market(ctx, pyth_feed_id, max_leverage, maintenance_margin_bps, max_funding_rate, funding_interval_secs)
+        instructions::perps::set_perps_pause(ctx, trading_paused, liquidations_enabled)
+    }
+    pub fn set_max_perps_markets(ctx: Context<UpdatePerpsGlobal>, max_markets: u16) -> Result<()> {
+        instructions::perps::set_max_markets(ctx, max_markets)
+    }
+    pub fn list_perps_markets(ctx: Context<ListMarkets>) -> Result<Vec<Pubkey>> {
+        instructions::perps::list_markets(ctx)
     }
     pub fn initialize_perps_oracle(ctx: Context<InitializeOraclePrice>, price_i64: i64) -> Result<()> {
         instructions::perps::initialize_oracle_price(ctx, price_i64)
@@ -135,17 +423,63 @@ pub mod solrush_dex {
         size_i64: i64,
         leverage_u16: u16,
         order_type: OrderType,
+        margin_mode: MarginMode,
+        reduce_only: bool,
+    ) -> Result<()> {
+        instructions::perps::open_position(ctx, side, size_i64, leverage_u16, order_type, margin_mode, reduce_only)
+    }
+    #[allow(clippy::too_many_arguments)]
+    pub fn place_perps_limit_order(
+        ctx: Context<PlacePerpsLimitOrder>,
+        side: PositionSide,
+        size_i64: i64,
+        limit_price_i64: i64,
+        leverage_u16: u16,
+        expiry_ts: i64,
+        order_id: u64,
+        reduce_only: bool,
     ) -> Result<()> {
-        instructions::perps::open_position(ctx, side, size_i64, leverage_u16, order_type)
+        instructions::perps::place_perps_limit_order(
+            ctx,
+            side,
+            size_i64,
+            limit_price_i64,
+            leverage_u16,
+            expiry_ts,
+            order_id,
+            reduce_only,
+        )
+    }
+    pub fn cancel_perps_limit_order(ctx: Context<CancelPerpsLimitOrder>) -> Result<()> {
+        instructions::perps::cancel_perps_limit_order(ctx)
+    }
+    pub fn execute_perps_limit_order(ctx: Context<ExecutePerpsLimitOrder>) -> Result<()> {
+        instructions::perps::execute_perps_limit_order(ctx)
     }
     pub fn close_perps_position(ctx: Context<ClosePosition>, amount_base: u64) -> Result<()> {
         instructions::perps::close_position(ctx, amount_base)
     }
+    pub fn close_perps_position_partial(ctx: Context<ClosePosition>, close_size_i64: i64) -> Result<()> {
+        instructions::perps::close_position_partial(ctx, close_size_i64)
+    }
+    pub fn set_position_triggers(
+        ctx: Context<SetPositionTriggers>,
+        take_profit_price_i64: i64,
+        stop_loss_price_i64: i64,
+    ) -> Result<()> {
+        instructions::perps::set_position_triggers(ctx, take_profit_price_i64, stop_loss_price_i64)
+    }
+    pub fn execute_position_trigger(ctx: Context<ExecutePositionTrigger>) -> Result<()> {
+        instructions::perps::execute_position_trigger(ctx)
+    }
     pub fn withdraw_perps_collateral(ctx: Context<WithdrawCollateral>, amount: u64) -> Result<()> {
         instructions::perps::withdraw_collateral(ctx, amount)
     }
-    pub fn update_perps_funding(ctx: Context<UpdateFunding>, mark_price_i64: i64) -> Result<()> {
-        instructions::perps::update_funding(ctx, mark_price_i64)
+    pub fn update_perps_funding(ctx: Context<UpdateFunding>) -> Result<()> {
+        instructions::perps::update_funding(ctx)
+    }
+    pub fn settle_perps_funding(ctx: Context<SettleFunding>) -> Result<()> {
+        instructions::perps::settle_funding(ctx)
     }
     pub fn initialize_insurance_vault(ctx: Context<InitializeInsuranceVault>) -> Result<()> {
         instructions::perps::initialize_insurance_vault(ctx)
@@ -153,7 +487,37 @@ pub mod solrush_dex {
     pub fn deposit_insurance(ctx: Context<DepositInsurance>, amount: u64) -> Result<()> {
         instructions::perps::deposit_insurance(ctx, amount)
     }
+    pub fn withdraw_insurance(ctx: Context<WithdrawInsurance>, amount: u64) -> Result<()> {
+        instructions::perps::withdraw_insurance(ctx, amount)
+    }
+    pub fn activate_perps_market(ctx: Context<ActivateMarket>) -> Result<()> {
+        instructions::perps::activate_market(ctx)
+    }
     pub fn liquidate_position(ctx: Context<LiquidatePosition>) -> Result<()> {
         instructions::perps::liquidate_position(ctx)
     }
+    pub fn preview_liquidation(ctx: Context<PreviewLiquidation>) -> Result<LiquidationPreview> {
+        instructions::perps::preview_liquidation(ctx)
+    }
+    pub fn close_liquidated_position(ctx: Context<CloseLiquidatedPosition>) -> Result<()> {
+        instructions::perps::close_liquidated_position(ctx)
+    }
+    pub fn adl_position(ctx: Context<AdlPosition>) -> Result<()> {
+        instructions::perps::adl_position(ctx)
+    }
+    pub fn get_position(ctx: Context<GetPosition>) -> Result<PositionView> {
+        instructions::perps::get_position(ctx)
+    }
+    pub fn get_position_health(ctx: Context<GetPositionHealth>) -> Result<PositionHealth> {
+        instructions::perps::get_position_health(ctx)
+    }
+    pub fn get_pending_funding(ctx: Context<GetPendingFunding>) -> Result<i128> {
+        instructions::perps::get_pending_funding(ctx)
+    }
+    pub fn get_liquidation_price(ctx: Context<GetLiquidationPrice>) -> Result<i64> {
+        instructions::perps::get_liquidation_price(ctx)
+    }
+    pub fn get_cross_margin_health(ctx: Context<GetCrossMarginHealth>) -> Result<CrossMarginHealthView> {
+        instructions::perps::get_cross_margin_health(ctx)
+    }
 }
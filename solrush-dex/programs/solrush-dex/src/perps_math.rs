@@ -5,6 +5,7 @@
 /// No floating-point math is used anywhere in this module.
 use anchor_lang::prelude::*;
 use crate::errors::CustomError;
+use crate::state::PerpsPosition;
 
 // ─────────────────────────────────────────────
 // Scale constants
@@ -384,10 +385,57 @@ pub fn maintenance_margin(notional: i128, mm_bps: u16) -> Result<i128> {
     ceil_div(numerator, 10_000)
 }
 
+/// Collateral valued after the market's haircut:
+/// `collateral × (10_000 − haircut_bps) / 10_000`, rounded down.
+pub fn haircut_collateral(collateral: u64, haircut_bps: u16) -> Result<u64> {
+    require!(haircut_bps <= 10_000, CustomError::InvalidRiskParams);
+    let valued = (collateral as u128) * ((10_000 - haircut_bps) as u128) / 10_000;
+    Ok(valued as u64)
+}
+
+/// Fee of `fee_bps` on a traded notional, in quote atoms:
+/// `notional × fee_bps / 10_000 / PRICE_SCALE`, rounded down.  Zero when `fee_bps` is 0.
+pub fn trading_fee(notional: i128, fee_bps: u16) -> Result<u64> {
+    let scaled = notional
+        .unsigned_abs()
+        .checked_mul(fee_bps as u128)
+        .ok_or_else(|| error!(CustomError::CalculationOverflow))?
+        / 10_000;
+    u64::try_from(scaled / PRICE_SCALE as u128).map_err(|_| error!(CustomError::CalculationOverflow))
+}
+
+/// Collateral released and quote returned when closing `close_size` of a
+/// position of `abs_position` base holding `collateral`.
+///
+/// `release = collateral × close_size / abs_position` (all of it on a full
+/// close); `return = max(release + pnl_delta, 0)`.  Returns `(release, return)`.
+pub fn close_collateral_return(
+    collateral: u64,
+    close_size: u64,
+    abs_position: u64,
+    pnl_delta: i128,
+) -> Result<(u64, u64)> {
+    require!(close_size > 0 && close_size <= abs_position, CustomError::CloseAmountExceedsPosition);
+    let release = if close_size == abs_position {
+        collateral
+    } else {
+        mul_div(collateral as u128, close_size as u128, abs_position as u128)? as u64
+    };
+    let returned = i128::from(release)
+        .checked_add(pnl_delta)
+        .ok_or_else(|| error!(CustomError::CalculationOverflow))?
+        .max(0);
+    let returned = u64::try_from(returned).map_err(|_| error!(CustomError::CalculationOverflow))?;
+    Ok((release, returned))
+}
+
 /// Full equity calculation for a position.
 ///
 /// `equity = collateral + realized_pnl + unrealized_pnl − funding_owed`
 ///
+/// Callers pass `collateral` through `haircut_collateral` first so it is
+/// valued at the market's haircut.
+///
 /// Parameters:
 /// - `collateral` — locked quote-token collateral (atomic units).
 /// - `realized_pnl` — accumulated realized PnL (signed, same scale as uPnL).
@@ -415,6 +463,139 @@ pub fn compute_equity(
         .ok_or_else(|| error!(CustomError::CalculationOverflow))
 }
 
+/// Margin summed over a user's positions in every market.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct AccountMargin {
+    /// `Σ (collateral + unrealized_pnl)`.
+    pub equity: i128,
+    /// `Σ initial_margin` of each position's notional at its own leverage.
+    pub initial_margin: i128,
+}
+
+impl AccountMargin {
+    /// Quote the user may withdraw: `free + equity − initial_margin`, floored at 0.
+    pub fn withdrawable(&self, free_collateral: u64) -> Result<u64> {
+        let available = (free_collateral as i128)
+            .checked_add(self.equity)
+            .ok_or_else(|| error!(CustomError::CalculationOverflow))?
+            .checked_sub(self.initial_margin)
+            .ok_or_else(|| error!(CustomError::CalculationOverflow))?
+            .max(0);
+        Ok(u64::try_from(available).unwrap_or(u64::MAX))
+    }
+
+    /// Guard: `free + equity ≥ initial_margin` across the whole account.
+    pub fn covers_initial_margin(&self, free_collateral: u64) -> bool {
+        (free_collateral as i128).saturating_add(self.equity) >= self.initial_margin
+    }
+}
+
+/// Equity and initial margin of one position valued at `price` (PRICE_SCALE).
+/// Empty positions contribute their collateral only.
+pub fn position_margin(position: &PerpsPosition, price: i64) -> Result<AccountMargin> {
+    let equity = position_equity(
+        position.collateral_u64,
+        position.base_position_i64,
+        position.entry_price_i64,
+        price,
+    )?;
+    let initial_margin = if position.is_empty() {
+        0
+    } else {
+        initial_margin(notional_value(position.base_position_i64, price)?, position.leverage_u16)?
+    };
+    Ok(AccountMargin { equity, initial_margin })
+}
+
+/// Sum equity and initial margin over the cross-margin `positions`, each
+/// valued at the matching entry of `prices`.  Isolated positions carry their
+/// own margin and are left out.
+///
+/// Callers pass collateral through `haircut_collateral` first, with each
+/// position's own market haircut.
+pub fn aggregate_account_margin(positions: &[PerpsPosition], prices: &[i64]) -> Result<AccountMargin> {
+    require!(positions.len() == prices.len(), CustomError::IncompleteMarginAccounts);
+    let mut margin = AccountMargin { equity: 0, initial_margin: 0 };
+    for (position, &price) in positions.iter().zip(prices) {
+        if position.is_isolated() {
+            continue;
+        }
+        let one = position_margin(position, price)?;
+        margin.equity = margin
+            .equity
+            .checked_add(one.equity)
+            .ok_or_else(|| error!(CustomError::CalculationOverflow))?;
+        margin.initial_margin = margin
+            .initial_margin
+            .checked_add(one.initial_margin)
+            .ok_or_else(|| error!(CustomError::CalculationOverflow))?;
+    }
+    Ok(margin)
+}
+
+/// Collateral and PnL netted across a user's cross-margin positions.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct CrossMarginHealth {
+    /// `Σ (collateral + unrealized_pnl)`.
+    pub equity: i128,
+    pub unrealized_pnl: i128,
+    /// `Σ maintenance_margin` of each position's notional at its market's rate.
+    pub maintenance_margin: i128,
+}
+
+impl CrossMarginHealth {
+    /// `equity / maintenance_margin` in bps, floored at 0; below 10_000 the
+    /// netted account would be liquidatable.  `u64::MAX` with no exposure.
+    pub fn health_factor_bps(&self) -> u64 {
+        if self.maintenance_margin <= 0 {
+            return u64::MAX;
+        }
+        let ratio = self.equity.max(0).saturating_mul(10_000) / self.maintenance_margin;
+        u64::try_from(ratio).unwrap_or(u64::MAX)
+    }
+}
+
+/// Net the cross-margin `positions` into one health figure, each valued at
+/// the matching entry of `prices` against the matching `mm_bps`.  Isolated
+/// positions are left out, as in `aggregate_account_margin`.
+pub fn cross_margin_health(
+    positions: &[PerpsPosition],
+    prices: &[i64],
+    mm_bps: &[u16],
+) -> Result<CrossMarginHealth> {
+    require!(
+        positions.len() == prices.len() && positions.len() == mm_bps.len(),
+        CustomError::IncompleteMarginAccounts
+    );
+    let mut health = CrossMarginHealth { equity: 0, unrealized_pnl: 0, maintenance_margin: 0 };
+    for ((position, &price), &bps) in positions.iter().zip(prices).zip(mm_bps) {
+        if position.is_isolated() {
+            continue;
+        }
+        let upnl = unrealized_pnl(position.base_position_i64, position.entry_price_i64, price)?;
+        let mm = maintenance_margin(notional_value(position.base_position_i64, price)?, bps)?;
+        health.unrealized_pnl = checked_add_signed(health.unrealized_pnl, upnl)?;
+        health.equity = checked_add_signed(
+            checked_add_signed(health.equity, position.collateral_u64 as i128)?,
+            upnl,
+        )?;
+        health.maintenance_margin = checked_add_signed(health.maintenance_margin, mm)?;
+    }
+    Ok(health)
+}
+
+/// Free collateral a cross-margin position's liquidation draws to cover a
+/// shortfall (`collateral_after_pnl < 0`) before the insurance fund does.
+/// Isolated positions never touch free collateral.
+pub fn shortfall_from_free_collateral(collateral_after_pnl: i128, free_collateral: u64, isolated: bool) -> u64 {
+    if isolated || collateral_after_pnl >= 0 {
+        return 0;
+    }
+    u64::try_from(collateral_after_pnl.unsigned_abs())
+        .unwrap_or(u64::MAX)
+        .min(free_collateral)
+}
+
 /// Guard: can a position be increased?
 ///
 /// Returns `true` when `equity_after ≥ initial_margin`.
@@ -528,6 +709,282 @@ pub fn compute_liquidation_close_size(
     Ok(close_i64.max(1))
 }
 
+/// Maintenance margin widened by oracle uncertainty.
+///
+/// `effective = mm_bps + (conf / price × 10_000) × multiplier_bps / 10_000`
+///
+/// Rounded up and capped at 10_000 (100 % of notional).  A zero confidence
+/// or a zero multiplier leaves `mm_bps` unchanged.
+pub fn confidence_adjusted_mm_bps(
+    mm_bps: u16,
+    price: i64,
+    conf: u64,
+    multiplier_bps: u16,
+) -> Result<u16> {
+    if conf == 0 || multiplier_bps == 0 {
+        return Ok(mm_bps);
+    }
+    if price <= 0 {
+        return Err(error!(CustomError::OraclePriceUnavailable));
+    }
+    // conf_bps × multiplier / 10_000 = conf × multiplier / price
+    let extra = ceil_div(
+        (conf as i128)
+            .checked_mul(multiplier_bps as i128)
+            .ok_or_else(|| error!(CustomError::CalculationOverflow))?,
+        price as i128,
+    )?;
+    let effective = (mm_bps as i128).saturating_add(extra).min(10_000);
+    Ok(effective as u16)
+}
+
+/// Liquidator fee scaled by how far the position is underwater.
+///
+/// `fee = base + (max − base) × depth`, with `depth = (mm − equity) / mm`
+/// clamped to [0, 1]: a position just under maintenance pays `base_fee_bps`,
+/// one with no equity left pays `max_fee_bps`.  Rounded down; `max ≤ base`
+/// (or a zero MM) keeps the flat `base_fee_bps`.
+pub fn scaled_liquidation_fee_bps(base_fee_bps: u16, max_fee_bps: u16, equity: i128, mm: i128) -> Result<u16> {
+    if max_fee_bps <= base_fee_bps || mm <= 0 {
+        return Ok(base_fee_bps);
+    }
+    let shortfall = mm.saturating_sub(equity).clamp(0, mm);
+    let extra = mul_div(
+        (max_fee_bps - base_fee_bps) as u128,
+        shortfall as u128,
+        mm as u128,
+    )?;
+    Ok(base_fee_bps + extra as u16)
+}
+
+/// Fold the price held since `last_ts` into a price × time cumulative.
+///
+/// `cumulative' = cumulative + last_price × (now − last_ts)`
+///
+/// Each interval is weighted by the price in force over it, so a new sample
+/// only starts to count from the moment it is taken.
+pub fn accumulate_mark_price(cumulative: i128, last_price: i64, last_ts: i64, now: i64) -> Result<i128> {
+    let elapsed = now.saturating_sub(last_ts).max(0) as i128;
+    (last_price as i128)
+        .checked_mul(elapsed)
+        .and_then(|weighted| cumulative.checked_add(weighted))
+        .ok_or_else(|| error!(CustomError::CalculationOverflow))
+}
+
+/// Time-weighted price between two cumulative snapshots `elapsed` seconds apart.
+pub fn mark_twap(cumulative_start: i128, cumulative_end: i128, elapsed: i64) -> Result<i128> {
+    require!(elapsed > 0, CustomError::CalculationOverflow);
+    cumulative_end
+        .checked_sub(cumulative_start)
+        .map(|weighted| weighted / elapsed as i128)
+        .ok_or_else(|| error!(CustomError::CalculationOverflow))
+}
+
+// ─────────────────────────────────────────────
+// Funding — unit conversions
+// ─────────────────────────────────────────────
+
+/// Funding rate for one interval, scaled by PRICE_SCALE (positive = longs pay).
+///
+/// `rate = clamp((mark − index) / index + skew, ±max_rate)`, where
+/// `skew = skew_bps / 10_000 × (long − short) / (long + short)`, so the heavier
+/// side of the open interest pays more.  `skew_bps` = 0 leaves the premium alone.
+pub fn funding_rate(
+    mark_price: i64,
+    index_price: i64,
+    long_oi: u64,
+    short_oi: u64,
+    skew_bps: u16,
+    max_rate: i64,
+) -> Result<i128> {
+    require!(index_price > 0, CustomError::OraclePriceUnavailable);
+    let premium = signed_mul_div((mark_price as i128) - (index_price as i128), PRICE_SCALE, index_price as i128)?;
+    let total_oi = long_oi as i128 + short_oi as i128;
+    let skew = if total_oi == 0 {
+        0
+    } else {
+        let imbalance = signed_mul_div(long_oi as i128 - short_oi as i128, PRICE_SCALE, total_oi)?;
+        signed_mul_div(imbalance, skew_bps as i128, 10_000)?
+    };
+    let max_rate = (max_rate as i128).abs();
+    Ok(checked_add_signed(premium, skew)?.clamp(-max_rate, max_rate))
+}
+
+/// Per-interval funding index increment.
+///
+/// `index_price` is atomic quote per whole base unit (PRICE_SCALE) and
+/// `funding_rate` is a fraction scaled by PRICE_SCALE, so the result is
+/// atomic quote owed per whole base unit (BASE_SCALE base atoms) per interval.
+///
+/// Truncates toward zero.
+pub fn funding_increment(index_price: i64, funding_rate: i128) -> Result<i128> {
+    signed_mul_div(index_price as i128, funding_rate, PRICE_SCALE)
+}
+
+/// Funding owed by a position for a move in the cumulative funding index.
+///
+/// `base_position` is in base atoms and `cum_diff` is atomic quote per whole
+/// base unit, so `base × cum_diff / BASE_SCALE` is atomic quote.
+///
+/// Positive → the position pays; negative → the position receives.
+/// Rounds toward +inf: payers round up, receivers round toward zero.
+pub fn funding_payment(base_position: i64, cum_diff: i128) -> Result<i128> {
+    signed_mul_div_ceil(base_position as i128, cum_diff, BASE_SCALE)
+}
+
+/// Balances after routing one position's funding through the market funding pool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FundingTransfer {
+    pub collateral: u64,
+    pub unpaid_funding: u64,
+    pub funding_pool: u64,
+}
+
+/// Move a funding payment between a position and the market funding pool.
+///
+/// Payers deposit into the pool (capped at their collateral); receivers are
+/// paid only from what the pool holds. Any receivable the pool cannot cover
+/// yet is carried in `unpaid_funding` and netted on later settlements, so
+/// `collateral + funding_pool` is conserved across every call.
+pub fn apply_funding_transfer(
+    collateral: u64,
+    unpaid_funding: u64,
+    funding_delta: i128,
+    funding_pool: u64,
+) -> Result<FundingTransfer> {
+    let net_owed = funding_delta
+        .checked_sub(unpaid_funding as i128)
+        .ok_or(error!(CustomError::CalculationOverflow))?;
+    if net_owed >= 0 {
+        let paid = net_owed.min(collateral as i128) as u64;
+        return Ok(FundingTransfer {
+            collateral: collateral - paid,
+            unpaid_funding: 0,
+            funding_pool: funding_pool
+                .checked_add(paid)
+                .ok_or(error!(CustomError::CalculationOverflow))?,
+        });
+    }
+    let receivable = u64::try_from(net_owed.unsigned_abs())
+        .map_err(|_| error!(CustomError::CalculationOverflow))?;
+    let received = receivable.min(funding_pool);
+    Ok(FundingTransfer {
+        collateral: collateral
+            .checked_add(received)
+            .ok_or(error!(CustomError::CalculationOverflow))?,
+        unpaid_funding: receivable - received,
+        funding_pool: funding_pool - received,
+    })
+}
+
+/// Where each part of a liquidation is paid from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LiquidationFunding {
+    pub fee_from_collateral: u64,
+    pub fee_from_insurance: u64,
+    pub penalty_from_collateral: u64,
+    /// Negative equity absorbed by the insurance fund.
+    pub bad_debt: u64,
+    /// Collateral left to the position after fees and penalty.
+    pub remaining_collateral: u64,
+    /// Bad debt exceeded the insurance balance.
+    pub insurance_exhausted: bool,
+}
+
+/// Split a liquidation's fee and penalty between the position's own
+/// collateral (after the closed PnL) and the insurance fund.
+///
+/// Only `collateral_after_pnl` is drawn from the shared collateral vault, so
+/// other users' collateral is never touched. Bad debt is covered first, then
+/// the liquidator fee: from collateral first, overflowing into insurance, or
+/// the other way round when `insurance_first`. The penalty is owed to the
+/// insurance fund, so any part collateral cannot cover is simply waived.
+pub fn source_liquidation_fees(
+    collateral_after_pnl: i128,
+    liquidation_fee: u64,
+    penalty: u64,
+    insurance_balance: u64,
+    insurance_first: bool,
+) -> LiquidationFunding {
+    let mut own = u64::try_from(collateral_after_pnl.max(0)).unwrap_or(u64::MAX);
+    let deficit = u64::try_from(collateral_after_pnl.min(0).unsigned_abs()).unwrap_or(u64::MAX);
+    let bad_debt = deficit.min(insurance_balance);
+    let insurance = insurance_balance - bad_debt;
+
+    let (fee_from_collateral, fee_from_insurance) = if insurance_first {
+        let from_insurance = liquidation_fee.min(insurance);
+        ((liquidation_fee - from_insurance).min(own), from_insurance)
+    } else {
+        let from_collateral = liquidation_fee.min(own);
+        (from_collateral, (liquidation_fee - from_collateral).min(insurance))
+    };
+    own -= fee_from_collateral;
+
+    let penalty_from_collateral = penalty.min(own);
+    own -= penalty_from_collateral;
+
+    LiquidationFunding {
+        fee_from_collateral,
+        fee_from_insurance,
+        penalty_from_collateral,
+        bad_debt,
+        remaining_collateral: own,
+        insurance_exhausted: deficit > bad_debt,
+    }
+}
+
+/// Analytic liquidation price — the boundary where `is_liquidatable` flips.
+///
+/// Long  (b = |base|): liquidatable iff `P × b × (10_000 − mm_bps) < 10_000 × (b × E − C)`
+/// Short (b = |base|): liquidatable iff `P × b × (10_000 + mm_bps) > 10_000 × (b × E + C)`
+///
+/// Returns, for a long, the highest integer price that is still liquidatable
+/// (0 if no positive price liquidates it); for a short, the lowest integer
+/// price that is liquidatable.  Returns 0 for an empty position.
+pub fn liquidation_price(
+    collateral: u64,
+    base_position: i64,
+    entry_price: i64,
+    maintenance_margin_bps: u16,
+) -> Result<i64> {
+    if base_position == 0 {
+        return Ok(0);
+    }
+    let b = (base_position as i128).abs();
+    let cost = b
+        .checked_mul(entry_price as i128)
+        .ok_or_else(|| error!(CustomError::CalculationOverflow))?;
+    let bps = maintenance_margin_bps as i128;
+    if base_position > 0 {
+        if bps >= 10_000 {
+            // MM ≥ notional: liquidatable at any price
+            return Ok(i64::MAX);
+        }
+        let numerator = checked_sub_signed(cost, collateral as i128)?
+            .checked_mul(10_000)
+            .ok_or_else(|| error!(CustomError::CalculationOverflow))?;
+        if numerator <= 0 {
+            return Ok(0);
+        }
+        let denom = b
+            .checked_mul(10_000 - bps)
+            .ok_or_else(|| error!(CustomError::CalculationOverflow))?;
+        // Largest P with P × denom < numerator
+        let p = (numerator - 1) / denom;
+        i64::try_from(p).map_err(|_| error!(CustomError::CalculationOverflow))
+    } else {
+        let numerator = checked_add_signed(cost, collateral as i128)?
+            .checked_mul(10_000)
+            .ok_or_else(|| error!(CustomError::CalculationOverflow))?;
+        let denom = b
+            .checked_mul(10_000 + bps)
+            .ok_or_else(|| error!(CustomError::CalculationOverflow))?;
+        // Smallest P with P × denom > numerator
+        let p = numerator / denom + 1;
+        i64::try_from(p).map_err(|_| error!(CustomError::CalculationOverflow))
+    }
+}
+
 // ─────────────────────────────────────────────
 // Internal: 256-bit widening multiplication
 // ─────────────────────────────────────────────
@@ -783,6 +1240,137 @@ mod tests {
         assert_eq!(r.new_realized_pnl, expected_pnl);
     }
 
+    #[test]
+    fn test_half_close_long_returns_half_collateral_plus_pnl() {
+        // Long 10 @ 50 with 100 collateral, close 5 @ 60
+        let pos = make_pos(10_000_000, 50_000_000, 0);
+        let r = apply_trade_to_position(&pos, -5_000_000, 60_000_000).unwrap();
+        assert_eq!(r.new_entry_price, 50_000_000);
+        let (release, returned) =
+            close_collateral_return(100_000_000, 5_000_000, 10_000_000, r.pnl_delta).unwrap();
+        assert_eq!(release, 50_000_000);
+        assert_eq!(returned as i128, 50_000_000 + r.pnl_delta);
+
+        // A loss larger than the released collateral returns nothing
+        assert_eq!(close_collateral_return(100, 5, 10, -80).unwrap(), (50, 0));
+        assert_eq!(close_collateral_return(100, 10, 10, 0).unwrap(), (100, 100));
+        assert!(close_collateral_return(100, 11, 10, 0).is_err());
+    }
+
+    use crate::state::MarginMode;
+
+    fn make_account_position(base: i64, entry: i64, collateral: u64, leverage: u16) -> PerpsPosition {
+        PerpsPosition {
+            owner: Pubkey::default(),
+            market: Pubkey::default(),
+            side: 0,
+            base_position_i64: base,
+            entry_price_i64: entry,
+            collateral_u64: collateral,
+            leverage_u16: leverage,
+            last_funding_i128: 0,
+            bump: 0,
+            realized_pnl_i128: 0,
+            unpaid_funding_u64: 0,
+            take_profit_price_i64: 0,
+            stop_loss_price_i64: 0,
+            margin_mode_u8: MarginMode::Cross as u8,
+        }
+    }
+
+    #[test]
+    fn test_withdrawable_margin_with_open_position() {
+        let margin_at = |price: i64| {
+            aggregate_account_margin(&[make_account_position(10, 100, 200, 5)], &[price]).unwrap()
+        };
+        // Long 10 @ 100 at 5x: IM 200, posted exactly
+        assert_eq!(margin_at(100).withdrawable(0).unwrap(), 0);
+        // Free collateral is always withdrawable on top
+        assert_eq!(margin_at(100).withdrawable(50).unwrap(), 50);
+        // At 110: equity 300, IM 220 → 80 of excess margin
+        assert_eq!(margin_at(110).withdrawable(0).unwrap(), 80);
+        // Underwater positions eat into free collateral
+        assert_eq!(margin_at(95).withdrawable(50).unwrap(), 10);
+    }
+
+    #[test]
+    fn test_two_market_account_margin() {
+        // Long 10 @ 100 at 5x (IM 200) and short 4 @ 50 at 2x (IM 100)
+        let positions = [make_account_position(10, 100, 200, 5), make_account_position(-4, 50, 100, 2)];
+        let margin = aggregate_account_margin(&positions, &[100, 50]).unwrap();
+        assert_eq!(margin, AccountMargin { equity: 300, initial_margin: 300 });
+        assert!(margin.covers_initial_margin(0));
+        assert_eq!(margin.withdrawable(0).unwrap(), 0);
+
+        // The long's profit covers the short's loss: equity 300 + 84 vs IM 220 + 108
+        let margin = aggregate_account_margin(&positions, &[110, 54]).unwrap();
+        assert_eq!(margin, AccountMargin { equity: 384, initial_margin: 328 });
+        assert_eq!(margin.withdrawable(0).unwrap(), 56);
+
+        // Both move against the user: only free collateral makes up the 100 shortfall
+        let margin = aggregate_account_margin(&positions, &[95, 60]).unwrap();
+        assert_eq!(margin, AccountMargin { equity: 210, initial_margin: 310 });
+        assert!(!margin.covers_initial_margin(99));
+        assert!(margin.covers_initial_margin(100));
+
+        assert!(aggregate_account_margin(&positions, &[100]).is_err());
+    }
+
+    #[test]
+    fn test_two_market_cross_margin_health() {
+        // Long 10 @ 100 (mm 5%) and short 4 @ 50 (mm 10%)
+        let positions = [make_account_position(10, 100, 200, 5), make_account_position(-4, 50, 100, 2)];
+        let mm_bps = [500, 1_000];
+
+        // upnl +100 − 16; mm ceil(55) + ceil(21.6)
+        let health = cross_margin_health(&positions, &[110, 54], &mm_bps).unwrap();
+        assert_eq!(health, CrossMarginHealth { equity: 384, unrealized_pnl: 84, maintenance_margin: 77 });
+        assert_eq!(health.health_factor_bps(), 49_870);
+
+        // The long alone is liquidatable at 80, but the short's profit carries it
+        assert!(is_liquidatable(200, 10, 100, 80, 500).unwrap());
+        let health = cross_margin_health(&positions, &[80, 40], &mm_bps).unwrap();
+        assert_eq!(health, CrossMarginHealth { equity: 140, unrealized_pnl: -160, maintenance_margin: 56 });
+        assert_eq!(health.health_factor_bps(), 25_000);
+
+        // Netted bad debt reads as zero health
+        let health = cross_margin_health(&positions, &[70, 80], &mm_bps).unwrap();
+        assert_eq!(health.equity, -120);
+        assert_eq!(health.health_factor_bps(), 0);
+
+        assert!(cross_margin_health(&positions, &[100, 50], &[500]).is_err());
+        assert_eq!(cross_margin_health(&[], &[], &[]).unwrap().health_factor_bps(), u64::MAX);
+    }
+
+    #[test]
+    fn test_isolated_position_stays_out_of_account_margin() {
+        // The underwater short is isolated: the long's margin is judged alone
+        let mut short = make_account_position(-4, 50, 100, 2);
+        short.margin_mode_u8 = MarginMode::Isolated as u8;
+        let positions = [make_account_position(10, 100, 200, 5), short.clone()];
+        let margin = aggregate_account_margin(&positions, &[100, 60]).unwrap();
+        assert_eq!(margin, AccountMargin { equity: 200, initial_margin: 200 });
+        assert_eq!(position_margin(&short, 60).unwrap(), AccountMargin { equity: 60, initial_margin: 120 });
+    }
+
+    #[test]
+    fn test_isolated_liquidation_leaves_free_collateral() {
+        // A shortfall of 70 against 50 of free collateral
+        assert_eq!(shortfall_from_free_collateral(-70, 50, false), 50);
+        assert_eq!(shortfall_from_free_collateral(-30, 50, false), 30);
+        assert_eq!(shortfall_from_free_collateral(-70, 50, true), 0);
+        assert_eq!(shortfall_from_free_collateral(10, 50, false), 0);
+    }
+
+    #[test]
+    fn test_trading_fee_on_traded_notional() {
+        // 5 base @ 60 at 50 bps
+        let notional = notional_value(5_000_000, 60_000_000).unwrap();
+        assert_eq!(trading_fee(notional, 50).unwrap(), 1_500_000);
+        assert_eq!(trading_fee(notional, 0).unwrap(), 0);
+        assert_eq!(trading_fee(-notional, 50).unwrap(), 1_500_000);
+    }
+
     #[test]
     fn test_partial_close_long_loss() {
         // Long 10 @ 50, close 5 @ 40
@@ -1111,6 +1699,24 @@ mod tests {
         assert_eq!(compute_equity(500, 0, 10, 100, 100, 100).unwrap(), 400);
     }
 
+    #[test]
+    fn test_haircut_reduces_equity() {
+        assert_eq!(haircut_collateral(1_000, 0).unwrap(), 1_000);
+        assert_eq!(haircut_collateral(1_000, 2_500).unwrap(), 750);
+        let full = compute_equity(1_000, 0, 10, 100, 100, 0).unwrap();
+        let cut = compute_equity(haircut_collateral(1_000, 2_500).unwrap(), 0, 10, 100, 100, 0).unwrap();
+        assert_eq!(full - cut, 250);
+        assert!(haircut_collateral(1_000, 10_001).is_err());
+    }
+
+    #[test]
+    fn test_haircut_makes_borderline_position_liquidatable() {
+        // notional 1000, mm 5% = 50: equity exactly 50 is safe
+        assert!(!is_liquidatable(50, 10, 100, 100, 500).unwrap());
+        let valued = haircut_collateral(50, 100).unwrap();
+        assert!(is_liquidatable(valued, 10, 100, 100, 500).unwrap());
+    }
+
     #[test]
     fn test_can_increase_position_sufficient() {
         assert!(can_increase_position(200, 200));  // equality allowed
@@ -1241,4 +1847,293 @@ mod tests {
         // notional = 999, mm_bps = 333 → exact = 999*333/10000 = 33.2667 → ceil = 34
         assert_eq!(maintenance_margin(999, 333).unwrap(), 34);
     }
+
+    // ── liquidation_price tests ──
+
+    #[test]
+    fn test_liquidation_price_long_matches_flip() {
+        // Long 10 @ 100, collateral 100, mm 5%
+        // liquidatable iff P × 10 × 9500 < 10_000 × (1000 − 100) → P < 94.73…
+        let liq = liquidation_price(100, 10, 100, 500).unwrap();
+        assert_eq!(liq, 94);
+        assert!(is_liquidatable(100, 10, 100, liq, 500).unwrap());
+        assert!(!is_liquidatable(100, 10, 100, liq + 1, 500).unwrap());
+    }
+
+    #[test]
+    fn test_liquidation_price_short_matches_flip() {
+        // Short 10 @ 100, collateral 100, mm 5%
+        // liquidatable iff P × 10 × 10500 > 10_000 × (1000 + 100) → P > 104.76…
+        let liq = liquidation_price(100, -10, 100, 500).unwrap();
+        assert_eq!(liq, 105);
+        assert!(is_liquidatable(100, -10, 100, liq, 500).unwrap());
+        assert!(!is_liquidatable(100, -10, 100, liq - 1, 500).unwrap());
+    }
+
+    #[test]
+    fn test_liquidation_price_exact_boundary() {
+        // Long 10 @ 100, collateral 50, mm 0 → liquidatable iff P < 95
+        let liq = liquidation_price(50, 10, 100, 0).unwrap();
+        assert_eq!(liq, 94);
+        assert!(!is_liquidatable(50, 10, 100, 95, 0).unwrap());
+    }
+
+    #[test]
+    fn test_liquidation_price_overcollateralized_long() {
+        // Collateral exceeds notional → no positive price liquidates
+        assert_eq!(liquidation_price(2_000, 10, 100, 500).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_liquidation_price_matches_flip_at_atomic_scale() {
+        // Long 10 base @ 100, 100 quote collateral, 5% mm — in atomic units
+        let (base, entry, coll) = (10 * BASE_SCALE as i64, 100 * PRICE_SCALE as i64, 100_000_000u64);
+        for side in [1, -1] {
+            let liq = liquidation_price(coll, side * base, entry, 500).unwrap();
+            assert!(is_liquidatable(coll, side * base, entry, liq, 500).unwrap());
+            assert!(!is_liquidatable(coll, side * base, entry, liq + side, 500).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_liquidation_price_empty_position() {
+        assert_eq!(liquidation_price(100, 0, 100, 500).unwrap(), 0);
+    }
+
+    // ── funding unit scaling tests ──
+
+    #[test]
+    fn test_funding_long_pays_notional_times_rate() {
+        // Long 2.0 base (6 dp) @ $50 (6 dp), funding rate 0.1% for one interval
+        let base: i64 = 2_000_000;
+        let price: i64 = 50_000_000;
+        let rate: i128 = 1_000; // 0.1% × PRICE_SCALE
+        let inc = funding_increment(price, rate).unwrap();
+        // $0.05 per whole base unit
+        assert_eq!(inc, 50_000);
+        // notional = $100, × 0.1% = $0.10 = 100_000 atomic quote
+        assert_eq!(funding_payment(base, inc).unwrap(), 100_000);
+    }
+
+    #[test]
+    fn test_funding_rate_skew_charges_heavier_side() {
+        // Mark 0.1% over index; skew worth up to 0.5% for a one-sided book
+        let balanced = funding_rate(100_100_000, 100_000_000, 500, 500, 50, 10_000).unwrap();
+        assert_eq!(balanced, 1_000);
+        let long_heavy = funding_rate(100_100_000, 100_000_000, 900, 100, 50, 10_000).unwrap();
+        assert_eq!(long_heavy, 1_000 + 4_000);
+        let short_heavy = funding_rate(100_100_000, 100_000_000, 100, 900, 50, 10_000).unwrap();
+        assert_eq!(short_heavy, 1_000 - 4_000);
+        assert!(long_heavy > balanced && balanced > short_heavy);
+        // Without a skew weight only the premium counts
+        assert_eq!(funding_rate(100_100_000, 100_000_000, 900, 100, 0, 10_000).unwrap(), 1_000);
+    }
+
+    #[test]
+    fn test_funding_rate_clamped_after_skew() {
+        let one_sided = funding_rate(100_100_000, 100_000_000, 1_000, 0, 10_000, 10_000).unwrap();
+        assert_eq!(one_sided, 10_000);
+        let crashed = funding_rate(50_000_000, 100_000_000, 0, 1_000, 10_000, 10_000).unwrap();
+        assert_eq!(crashed, -10_000);
+    }
+
+    #[test]
+    fn test_funding_short_receives_notional_times_rate() {
+        let inc = funding_increment(50_000_000, 1_000).unwrap();
+        assert_eq!(funding_payment(-2_000_000, inc).unwrap(), -100_000);
+    }
+
+    #[test]
+    fn test_funding_negative_rate_longs_receive() {
+        let inc = funding_increment(50_000_000, -1_000).unwrap();
+        assert_eq!(inc, -50_000);
+        assert_eq!(funding_payment(2_000_000, inc).unwrap(), -100_000);
+        assert_eq!(funding_payment(-2_000_000, inc).unwrap(), 100_000);
+    }
+
+    #[test]
+    fn test_funding_payment_rounding_favors_protocol() {
+        // 1 base atom × 1 quote-per-base → 1e-6 quote atom
+        assert_eq!(funding_payment(1, 1).unwrap(), 1); // payer rounds up
+        assert_eq!(funding_payment(-1, 1).unwrap(), 0); // receiver rounds toward zero
+    }
+
+    // ── confidence-adjusted MM tests ──
+
+    #[test]
+    fn test_tight_confidence_leaves_mm_unchanged() {
+        assert_eq!(confidence_adjusted_mm_bps(500, 100_000_000, 0, 10_000).unwrap(), 500);
+        // Linkage disabled
+        assert_eq!(confidence_adjusted_mm_bps(500, 100_000_000, 5_000_000, 0).unwrap(), 500);
+    }
+
+    #[test]
+    fn test_wide_confidence_raises_mm() {
+        // conf = 2% of price, multiplier 1× → +200 bps
+        assert_eq!(
+            confidence_adjusted_mm_bps(500, 100_000_000, 2_000_000, 10_000).unwrap(),
+            700
+        );
+        // multiplier 0.5× → +100 bps
+        assert_eq!(
+            confidence_adjusted_mm_bps(500, 100_000_000, 2_000_000, 5_000).unwrap(),
+            600
+        );
+    }
+
+    #[test]
+    fn test_wide_confidence_makes_borderline_position_liquidatable() {
+        // Long 10 @ 100, mark 95, collateral 100: equity 50, mm(5%) = 47.5 → safe
+        assert!(!is_liquidatable(100, 10, 100, 95, 500).unwrap());
+        // conf = 1 on price 95 at 1× → +106 bps (rounded up) → mm ≈ 57.6 → liquidatable
+        let widened = confidence_adjusted_mm_bps(500, 95, 1, 10_000).unwrap();
+        assert!(widened > 500);
+        assert!(is_liquidatable(100, 10, 100, 95, widened).unwrap());
+    }
+
+    #[test]
+    fn test_deeper_liquidation_pays_higher_fee() {
+        // MM 1_000; fee scales from 250 to 750 bps
+        let barely = scaled_liquidation_fee_bps(250, 750, 990, 1_000).unwrap();
+        let halfway = scaled_liquidation_fee_bps(250, 750, 500, 1_000).unwrap();
+        let deep = scaled_liquidation_fee_bps(250, 750, -4_000, 1_000).unwrap();
+        assert_eq!((barely, halfway, deep), (255, 500, 750));
+        assert!(barely < halfway && halfway < deep);
+        // Healthy equity pays the base; a cap at or below the base keeps it flat
+        assert_eq!(scaled_liquidation_fee_bps(250, 750, 1_500, 1_000).unwrap(), 250);
+        assert_eq!(scaled_liquidation_fee_bps(250, 250, -4_000, 1_000).unwrap(), 250);
+        assert_eq!(scaled_liquidation_fee_bps(250, 750, -4_000, 0).unwrap(), 250);
+    }
+
+    #[test]
+    fn test_confidence_adjusted_mm_capped() {
+        assert_eq!(
+            confidence_adjusted_mm_bps(500, 100, 1_000, 10_000).unwrap(),
+            10_000
+        );
+    }
+
+    #[test]
+    fn test_mark_cumulative_weights_the_held_price() {
+        // 100 held for 570s, then a sample of 80 that has stood for 30s
+        let cumulative = accumulate_mark_price(0, 100, 1_000, 1_570).unwrap();
+        assert_eq!(cumulative, 57_000);
+        // The fresh sample adds nothing until time passes at it
+        assert_eq!(accumulate_mark_price(cumulative, 80, 1_570, 1_570).unwrap(), cumulative);
+        let cumulative = accumulate_mark_price(cumulative, 80, 1_570, 1_600).unwrap();
+        assert_eq!(mark_twap(0, cumulative, 600).unwrap(), 99);
+        assert!(mark_twap(0, cumulative, 0).is_err());
+    }
+
+    // ── funding transfer tests ──
+
+    #[test]
+    fn test_funding_conserved_payer_first() {
+        // Long and short of 1 base unit each; index moves by 1_000 quote atoms
+        let long_delta = funding_payment(1_000_000, 1_000).unwrap();
+        let short_delta = funding_payment(-1_000_000, 1_000).unwrap();
+        assert_eq!(long_delta, 1_000);
+        assert_eq!(short_delta, -1_000);
+
+        let long = apply_funding_transfer(10_000, 0, long_delta, 0).unwrap();
+        let short = apply_funding_transfer(10_000, 0, short_delta, long.funding_pool).unwrap();
+        assert_eq!(long.collateral, 9_000);
+        assert_eq!(short.collateral, 11_000);
+        assert_eq!(short.funding_pool, 0);
+        assert_eq!(long.collateral + short.collateral + short.funding_pool, 20_000);
+    }
+
+    #[test]
+    fn test_funding_conserved_receiver_first() {
+        // Receiver settles against an empty pool: nothing is minted, the claim carries over
+        let short = apply_funding_transfer(10_000, 0, -1_000, 0).unwrap();
+        assert_eq!(short.collateral, 10_000);
+        assert_eq!(short.unpaid_funding, 1_000);
+
+        let long = apply_funding_transfer(10_000, 0, 1_000, short.funding_pool).unwrap();
+        assert_eq!(long.funding_pool, 1_000);
+
+        // Next settlement with no new funding collects the carried claim
+        let short = apply_funding_transfer(short.collateral, short.unpaid_funding, 0, long.funding_pool).unwrap();
+        assert_eq!(short.collateral, 11_000);
+        assert_eq!(short.unpaid_funding, 0);
+        assert_eq!(long.collateral + short.collateral + short.funding_pool, 20_000);
+    }
+
+    #[test]
+    fn test_funding_payer_capped_at_collateral() {
+        let long = apply_funding_transfer(400, 0, 1_000, 0).unwrap();
+        assert_eq!(long.collateral, 0);
+        assert_eq!(long.funding_pool, 400);
+        // Short can only receive what was actually collected
+        let short = apply_funding_transfer(10_000, 0, -1_000, long.funding_pool).unwrap();
+        assert_eq!(short.collateral, 10_400);
+        assert_eq!(short.unpaid_funding, 600);
+    }
+
+    #[test]
+    fn test_funding_nets_unpaid_against_new_payment() {
+        // Owed 300 from earlier, now owes 1_000: pays only the net 700
+        let t = apply_funding_transfer(10_000, 300, 1_000, 50).unwrap();
+        assert_eq!(t.collateral, 9_300);
+        assert_eq!(t.unpaid_funding, 0);
+        assert_eq!(t.funding_pool, 750);
+    }
+
+    #[test]
+    fn test_liquidation_fees_from_own_collateral() {
+        // Well collateralized: 100 left after PnL covers fee 10 and penalty 5
+        let f = source_liquidation_fees(100, 10, 5, 1_000, false);
+        assert_eq!(f.fee_from_collateral, 10);
+        assert_eq!(f.penalty_from_collateral, 5);
+        assert_eq!(f.fee_from_insurance, 0);
+        assert_eq!(f.bad_debt, 0);
+        assert_eq!(f.remaining_collateral, 85);
+        assert!(!f.insurance_exhausted);
+    }
+
+    #[test]
+    fn test_liquidation_fee_overflow_draws_insurance() {
+        // 6 left: fee takes it all, 4 of the fee comes from insurance, penalty waived
+        let f = source_liquidation_fees(6, 10, 5, 1_000, false);
+        assert_eq!(f.fee_from_collateral, 6);
+        assert_eq!(f.fee_from_insurance, 4);
+        assert_eq!(f.penalty_from_collateral, 0);
+        assert_eq!(f.remaining_collateral, 0);
+
+        // Negative equity: bad debt first, then the fee from what is left
+        let f = source_liquidation_fees(-30, 10, 5, 35, false);
+        assert_eq!(f.bad_debt, 30);
+        assert_eq!(f.fee_from_insurance, 5);
+        assert!(!f.insurance_exhausted);
+
+        let f = source_liquidation_fees(-30, 10, 5, 20, false);
+        assert_eq!(f.bad_debt, 20);
+        assert_eq!(f.fee_from_insurance, 0);
+        assert!(f.insurance_exhausted);
+    }
+
+    #[test]
+    fn test_liquidation_keeps_collateral_vault_backed() {
+        // The vault keeps the position's collateral, pays out the fee and the
+        // penalty, takes the bad debt in from insurance and still owes the
+        // remaining collateral plus the winners' side of the PnL.  Only debt
+        // insurance could not cover may be left for auto-deleveraging.
+        for (equity, insurance) in [(100i128, 1_000u64), (6, 1_000), (-30, 35), (-30, 20), (-500, 0)] {
+            let f = source_liquidation_fees(equity, 10, 5, insurance, false);
+            let uncovered = (equity.min(0).unsigned_abs() as u64) - f.bad_debt;
+            let backing = equity + i128::from(f.bad_debt) + i128::from(uncovered);
+            let owed = f.fee_from_collateral + f.penalty_from_collateral + f.remaining_collateral;
+            assert_eq!(backing, i128::from(owed), "equity {equity}, insurance {insurance}");
+        }
+    }
+
+    #[test]
+    fn test_liquidation_fee_insurance_first() {
+        let f = source_liquidation_fees(100, 10, 5, 4, true);
+        assert_eq!(f.fee_from_insurance, 4);
+        assert_eq!(f.fee_from_collateral, 6);
+        assert_eq!(f.penalty_from_collateral, 5);
+        assert_eq!(f.remaining_collateral, 89);
+    }
 }
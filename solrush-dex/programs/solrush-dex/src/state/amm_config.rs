@@ -0,0 +1,110 @@
+use anchor_lang::prelude::*;
+use crate::constants::{MAX_WHITELISTED_MINTS, FEE_BPS_DENOMINATOR};
+
+/// Governance-managed AMM settings.
+///
+/// Optional: while this account does not exist, or while
+/// `whitelist_enabled` is false, pool creation is permissionless.
+#[account]
+pub struct AmmConfig {
+    pub authority: Pubkey,
+    /// When set, `initialize_pool` only accepts mints in `allowed_mints`.
+    pub whitelist_enabled: bool,
+    pub allowed_mints: Vec<Pubkey>,
+    pub bump: u8,
+    /// Lowest swap fee a pool authority may set.
+    pub min_fee_bps: u16,
+    /// Highest swap fee a pool authority may set.
+    pub max_fee_bps: u16,
+    /// Most a swap's `minimum_amount_out` may sit below its spot output (the
+    /// fee-free curve output on StableSwap pools), so zero-minimum swaps are
+    /// rejected (0 = no cap).  Exact-output swaps are not covered.
+    pub max_allowed_slippage_bps: u16,
+    /// Smallest partial fill of a limit order, in bps of its size at creation.
+    pub min_partial_fill_bps: u16,
+}
+
+impl AmmConfig {
+    pub const SIZE: usize = 8 + 32 + 1 + 4 + 32 * MAX_WHITELISTED_MINTS + 1 + 2 + 2 + 2 + 2;
+
+    pub fn is_mint_allowed(&self, mint: &Pubkey) -> bool {
+        !self.whitelist_enabled || self.allowed_mints.contains(mint)
+    }
+
+    pub fn is_valid_fee_band(min_fee_bps: u16, max_fee_bps: u16) -> bool {
+        min_fee_bps <= max_fee_bps && (max_fee_bps as u64) < FEE_BPS_DENOMINATOR
+    }
+
+    pub fn is_fee_in_band(&self, fee_bps: u16) -> bool {
+        (self.min_fee_bps..=self.max_fee_bps).contains(&fee_bps)
+    }
+
+    /// Lowest `minimum_amount_out` the slippage cap accepts for `spot_out`,
+    /// rounded down; 0 while the cap is off.
+    pub fn slippage_floor(&self, spot_out: u64) -> u64 {
+        if self.max_allowed_slippage_bps == 0 {
+            return 0;
+        }
+        let keep_bps = FEE_BPS_DENOMINATOR.saturating_sub(self.max_allowed_slippage_bps as u64);
+        (spot_out as u128 * keep_bps as u128 / FEE_BPS_DENOMINATOR as u128) as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_config(whitelist_enabled: bool, allowed_mints: Vec<Pubkey>) -> AmmConfig {
+        AmmConfig {
+            authority: Pubkey::default(),
+            whitelist_enabled,
+            allowed_mints,
+            bump: 0,
+            min_fee_bps: 10,
+            max_fee_bps: 100,
+            max_allowed_slippage_bps: 0,
+            min_partial_fill_bps: 0,
+        }
+    }
+
+    #[test]
+    fn test_disabled_whitelist_allows_any_mint() {
+        let config = make_config(false, vec![]);
+        assert!(config.is_mint_allowed(&Pubkey::new_unique()));
+    }
+
+    #[test]
+    fn test_enabled_whitelist_rejects_unlisted_mint() {
+        let listed = Pubkey::new_unique();
+        let config = make_config(true, vec![listed]);
+        assert!(config.is_mint_allowed(&listed));
+        assert!(!config.is_mint_allowed(&Pubkey::new_unique()));
+    }
+
+    #[test]
+    fn test_fee_band_bounds_pool_fees() {
+        let config = make_config(false, vec![]);
+        assert!(!config.is_fee_in_band(9));
+        assert!(config.is_fee_in_band(10));
+        assert!(config.is_fee_in_band(30));
+        assert!(config.is_fee_in_band(100));
+        assert!(!config.is_fee_in_band(101));
+    }
+
+    #[test]
+    fn test_slippage_floor_rejects_zero_minimum_only_when_capped() {
+        let mut config = make_config(false, vec![]);
+        assert_eq!(config.slippage_floor(1_000_000), 0);
+        config.max_allowed_slippage_bps = 100;
+        assert_eq!(config.slippage_floor(1_000_000), 990_000);
+        assert_eq!(config.slippage_floor(0), 0);
+    }
+
+    #[test]
+    fn test_fee_band_validation() {
+        assert!(AmmConfig::is_valid_fee_band(0, 0));
+        assert!(AmmConfig::is_valid_fee_band(1, 100));
+        assert!(!AmmConfig::is_valid_fee_band(100, 1));
+        assert!(!AmmConfig::is_valid_fee_band(0, 10_000));
+    }
+}
@@ -0,0 +1,25 @@
+use anchor_lang::prelude::*;
+
+/// Outstanding flash loan against one pool vault.
+///
+/// Created by `flash_loan_borrow` and closed by `flash_loan_repay` within the
+/// same transaction; it never outlives the loan.
+#[account]
+pub struct FlashLoanReceipt {
+    pub pool: Pubkey,
+    pub borrower: Pubkey,
+    /// Pool vault the principal was drawn from and must be returned to.
+    pub vault: Pubkey,
+    pub principal: u64,
+    pub fee: u64,
+    pub bump: u8,
+}
+
+impl FlashLoanReceipt {
+    // 8 (discriminator) + 32 + 32 + 32 + 8 + 8 + 1 = 121
+    pub const LEN: usize = 8 + 32 + 32 + 32 + 8 + 8 + 1;
+
+    pub fn amount_owed(&self) -> Option<u64> {
+        self.principal.checked_add(self.fee)
+    }
+}
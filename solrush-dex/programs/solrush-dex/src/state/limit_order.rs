@@ -1,4 +1,8 @@
 use anchor_lang::prelude::*;
+use crate::errors::CustomError;
+use crate::constants::LIMIT_ORDER_EXPIRY_GRACE_SECS;
+/// Order prices (`target_price`) are quote per base, scaled by 1e6.
+const ORDER_PRICE_PRECISION: u128 = 1_000_000;
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
 pub enum OrderStatus {
     Pending = 0,
@@ -17,6 +21,24 @@ impl OrderStatus {
         matches!(self, OrderStatus::Executed | OrderStatus::Cancelled | OrderStatus::Expired)
     }
 }
+/// Which way the pool price must move to trigger an order (see `check_price_condition`).
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum OrderKind {
+    /// Sell once the price rises to the target, buy once it falls to it.
+    TakeProfit = 0,
+    /// Sell once the price falls to the target, buy once it rises to it.
+    StopLoss = 1,
+}
+/// How long an order may wait for its price: `GoodTilCancelled` rests until
+/// filled, cancelled or expired; the others fill at creation or not at all.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TimeInForce {
+    GoodTilCancelled = 0,
+    /// Fill as much as the pool takes now and refund the rest.
+    ImmediateOrCancel = 1,
+    /// Fill the whole order now or fail the transaction.
+    FillOrKill = 2,
+}
 #[account]
 pub struct LimitOrder {
     pub owner: Pubkey,
@@ -31,11 +53,22 @@ pub struct LimitOrder {
     pub status: OrderStatus,
     pub bump: u8,
     pub order_id: u64,
+    /// Lamports the owner escrowed on top of rent for whichever keeper completes the order.
+    pub keeper_tip_lamports: u64,
+    pub order_kind: OrderKind,
+    /// Keeper reward in bps of `sell_amount`, escrowed in the sell token at creation.
+    pub execution_reward_bps: u16,
+    /// Escrowed reward not yet paid out, held in the order vault next to `sell_amount`.
+    pub execution_reward: u64,
+    /// Smallest partial fill, fixed at creation from the original `sell_amount`.
+    pub min_fill_amount: u64,
 }
 impl LimitOrder {
-    pub const SIZE: usize = 8 + 32*4 + 8*5 + 1 + 1 + 8;
+    pub const SIZE: usize = 8 + 32*4 + 8*5 + 1 + 1 + 8 + 8 + 1 + 2 + 8 + 8;
+    /// An order is live through `expires_at` inclusive, plus
+    /// `LIMIT_ORDER_EXPIRY_GRACE_SECS`; it expires the second after that.
     pub fn is_expired(&self, current_timestamp: i64) -> bool {
-        current_timestamp >= self.expires_at
+        current_timestamp > self.expires_at.saturating_add(LIMIT_ORDER_EXPIRY_GRACE_SECS)
     }
     pub fn can_execute(&self, current_timestamp: i64) -> bool {
         self.status.is_executable() && !self.is_expired(current_timestamp)
@@ -53,4 +86,314 @@ impl LimitOrder {
     pub fn get_asking_price(&self) -> u64 {
         self.target_price
     }
+    /// Share of `minimum_receive` a fill of `fill_amount` must meet, rounded up
+    /// so splitting an order never lowers the price it accepts.
+    pub fn minimum_receive_for(&self, fill_amount: u64) -> Result<u64> {
+        require!(fill_amount > 0 && fill_amount <= self.sell_amount, CustomError::InvalidAmount);
+        let owed = (self.minimum_receive as u128) * (fill_amount as u128);
+        Ok(owed.div_ceil(self.sell_amount as u128) as u64)
+    }
+    /// Lamports the keeper completing the order earns: the owner's tip, but never
+    /// out of the rent the closed order account returns to its owner.
+    pub fn keeper_tip_payout(&self, order_lamports: u64, rent_exempt_minimum: u64) -> u64 {
+        self.keeper_tip_lamports.min(order_lamports.saturating_sub(rent_exempt_minimum))
+    }
+    /// Take a fill of `fill_amount` off the order together with its share of
+    /// `minimum_receive` and of the escrowed execution reward, which is returned
+    /// for the keeper. The order stays `Pending` until nothing is left to sell.
+    pub fn record_fill(&mut self, fill_amount: u64) -> Result<u64> {
+        let filled_minimum = self.minimum_receive_for(fill_amount)?;
+        // The final fill takes whatever reward rounding left behind
+        let reward = if fill_amount == self.sell_amount {
+            self.execution_reward
+        } else {
+            ((self.execution_reward as u128) * (fill_amount as u128) / (self.sell_amount as u128)) as u64
+        };
+        self.sell_amount -= fill_amount;
+        self.minimum_receive = self.minimum_receive.saturating_sub(filled_minimum);
+        self.execution_reward -= reward;
+        if self.sell_amount == 0 {
+            self.status = OrderStatus::Executed;
+        }
+        Ok(reward)
+    }
+    /// Reprice a pending order in place, checking the new terms as
+    /// `create_limit_order` does. The expiry runs `new_expiry_days` from `now`;
+    /// the amount, token pair and escrow are unchanged.
+    pub fn modify(&mut self, new_target_price: u64, new_minimum_receive: u64, new_expiry_days: i64, now: i64) -> Result<()> {
+        require!(self.status == OrderStatus::Pending, CustomError::InvalidOrderStatus);
+        require!(new_target_price > 0, CustomError::InvalidAmount);
+        require!(new_minimum_receive > 0, CustomError::InvalidAmount);
+        require!(new_expiry_days > 0, CustomError::InvalidExpiryTime);
+        self.target_price = new_target_price;
+        self.minimum_receive = new_minimum_receive;
+        self.expires_at = new_expiry_days
+            .checked_mul(86400)
+            .and_then(|secs| now.checked_add(secs))
+            .ok_or(error!(CustomError::InvalidExpiryTime))?;
+        Ok(())
+    }
+}
+/// Outcome of matching a sell order (base for quote) against a buy order.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct OrderMatch {
+    /// Resting (older) order's `target_price`.
+    pub price: u64,
+    pub base_filled: u64,
+    pub quote_filled: u64,
+    /// Base left on the sell order.
+    pub sell_residual: u64,
+    /// Quote left on the buy order.
+    pub buy_residual: u64,
+    /// True when the sell order was resting and set the price.
+    pub sell_is_maker: bool,
+}
+impl LimitOrder {
+    /// Time priority: earlier `created_at` wins, then lower `order_id`, then owner key,
+    /// so the outcome never depends on which order is passed first.
+    pub fn has_priority_over(&self, other: &LimitOrder) -> bool {
+        (self.created_at, self.order_id, self.owner.to_bytes())
+            < (other.created_at, other.order_id, other.owner.to_bytes())
+    }
+}
+/// Match two crossing orders. Fills at the resting order's price; the smaller
+/// side fills completely and the larger keeps the pro-rated residual.
+pub fn match_crossing_orders(
+    sell: &LimitOrder,
+    buy: &LimitOrder,
+    base_mint: &Pubkey,
+) -> Result<OrderMatch> {
+    require!(
+        sell.pool == buy.pool
+            && sell.is_sell_order(base_mint)
+            && !buy.is_sell_order(base_mint)
+            && sell.sell_token == buy.buy_token
+            && sell.buy_token == buy.sell_token,
+        CustomError::OrdersDoNotCross
+    );
+    require!(
+        sell.status.is_executable() && buy.status.is_executable(),
+        CustomError::InvalidOrderStatus
+    );
+    require!(sell.target_price <= buy.target_price, CustomError::OrdersDoNotCross);
+    let sell_is_maker = sell.has_priority_over(buy);
+    let price = if sell_is_maker { sell.target_price } else { buy.target_price };
+    require!(price > 0, CustomError::InvalidAmount);
+    // Base the buy order can afford at the fill price
+    let buy_capacity = (buy.sell_amount as u128) * ORDER_PRICE_PRECISION / (price as u128);
+    let base_filled = (sell.sell_amount as u128).min(buy_capacity);
+    let quote_filled = base_filled * (price as u128) / ORDER_PRICE_PRECISION;
+    let base_filled = base_filled as u64;
+    let quote_filled = u64::try_from(quote_filled).map_err(|_| error!(CustomError::CalculationOverflow))?;
+    Ok(OrderMatch {
+        price,
+        base_filled,
+        quote_filled,
+        sell_residual: sell.sell_amount - base_filled,
+        buy_residual: buy.sell_amount - quote_filled,
+        sell_is_maker,
+    })
+}
+/// A partial fill must take at least `min_fill_amount`, so a keeper cannot grind
+/// an order down in dust-sized fills. The final fill of the whole `remaining`
+/// amount is always allowed, even below the minimum.
+pub fn validate_fill_amount(fill_amount: u64, remaining: u64, min_fill_amount: u64) -> Result<()> {
+    require!(fill_amount > 0 && fill_amount <= remaining, CustomError::InvalidAmount);
+    require!(
+        fill_amount >= min_fill_amount || fill_amount == remaining,
+        CustomError::InvalidAmount
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_order(sell_token: Pubkey, buy_token: Pubkey, sell_amount: u64, target_price: u64, created_at: i64) -> LimitOrder {
+        LimitOrder {
+            owner: Pubkey::default(),
+            pool: Pubkey::default(),
+            sell_token,
+            buy_token,
+            sell_amount,
+            target_price,
+            minimum_receive: 1,
+            created_at,
+            expires_at: i64::MAX,
+            status: OrderStatus::Pending,
+            bump: 0,
+            order_id: 0,
+            keeper_tip_lamports: 0,
+            order_kind: OrderKind::TakeProfit,
+            execution_reward_bps: 0,
+            execution_reward: 0,
+            min_fill_amount: 0,
+        }
+    }
+
+    #[test]
+    fn test_order_live_through_expiry_plus_grace() {
+        let mut order = make_order(Pubkey::default(), Pubkey::default(), 1, 1, 0);
+        order.expires_at = 1_000;
+        assert!(order.can_execute(999));
+        assert!(order.can_execute(1_000));
+        assert_eq!(order.time_until_expiry(1_000), 0);
+        assert!(order.can_execute(1_000 + LIMIT_ORDER_EXPIRY_GRACE_SECS));
+        assert!(order.is_expired(1_001 + LIMIT_ORDER_EXPIRY_GRACE_SECS));
+        assert!(!order.can_execute(1_001 + LIMIT_ORDER_EXPIRY_GRACE_SECS));
+        // Orders that never expire stay live
+        order.expires_at = i64::MAX;
+        assert!(!order.is_expired(i64::MAX));
+    }
+
+    #[test]
+    fn test_modify_reprices_pending_order_only() {
+        let mut order = make_order(Pubkey::default(), Pubkey::default(), 1_000, 2_000_000, 0);
+        order.modify(2_500_000, 2_400, 3, 5_000).unwrap();
+        assert_eq!(order.target_price, 2_500_000);
+        assert_eq!(order.minimum_receive, 2_400);
+        assert_eq!(order.expires_at, 5_000 + 3 * 86400);
+        assert_eq!(order.sell_amount, 1_000);
+
+        // Rejected terms leave the order as it was
+        for (target, minimum, days, err) in [
+            (0, 2_400, 3, CustomError::InvalidAmount),
+            (2_500_000, 0, 3, CustomError::InvalidAmount),
+            (2_500_000, 2_400, 0, CustomError::InvalidExpiryTime),
+            (2_500_000, 2_400, i64::MAX, CustomError::InvalidExpiryTime),
+        ] {
+            assert_eq!(order.modify(target, minimum, days, 6_000).unwrap_err(), error!(err));
+        }
+        assert_eq!(order.expires_at, 5_000 + 3 * 86400);
+
+        order.status = OrderStatus::Cancelled;
+        assert_eq!(
+            order.modify(2_500_000, 2_400, 3, 6_000).unwrap_err(),
+            error!(CustomError::InvalidOrderStatus)
+        );
+    }
+
+    #[test]
+    fn test_older_sell_sets_price_and_larger_buy_keeps_residual() {
+        let (base, quote) = (Pubkey::new_unique(), Pubkey::new_unique());
+        // Resting: sell 10 base at 2.0. Incoming: buy with 50 quote, limit 2.5
+        let sell = make_order(base, quote, 10_000_000, 2_000_000, 100);
+        let buy = make_order(quote, base, 50_000_000, 2_500_000, 200);
+        let m = match_crossing_orders(&sell, &buy, &base).unwrap();
+        assert!(m.sell_is_maker);
+        assert_eq!(m.price, 2_000_000);
+        assert_eq!(m.base_filled, 10_000_000);
+        assert_eq!(m.quote_filled, 20_000_000);
+        assert_eq!(m.sell_residual, 0);
+        assert_eq!(m.buy_residual, 30_000_000);
+    }
+
+    #[test]
+    fn test_older_buy_sets_price_and_larger_sell_keeps_residual() {
+        let (base, quote) = (Pubkey::new_unique(), Pubkey::new_unique());
+        // Resting: buy with 10 quote at 2.5. Incoming: sell 10 base, limit 2.0
+        let buy = make_order(quote, base, 10_000_000, 2_500_000, 100);
+        let sell = make_order(base, quote, 10_000_000, 2_000_000, 200);
+        let m = match_crossing_orders(&sell, &buy, &base).unwrap();
+        assert!(!m.sell_is_maker);
+        assert_eq!(m.price, 2_500_000);
+        assert_eq!(m.base_filled, 4_000_000);
+        assert_eq!(m.quote_filled, 10_000_000);
+        assert_eq!(m.sell_residual, 6_000_000);
+        assert_eq!(m.buy_residual, 0);
+    }
+
+    #[test]
+    fn test_tie_on_created_at_broken_by_order_id() {
+        let (base, quote) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut sell = make_order(base, quote, 10_000_000, 2_000_000, 100);
+        let mut buy = make_order(quote, base, 50_000_000, 2_500_000, 100);
+        sell.order_id = 7;
+        buy.order_id = 3;
+        let m = match_crossing_orders(&sell, &buy, &base).unwrap();
+        assert!(!m.sell_is_maker);
+        assert_eq!(m.price, 2_500_000);
+        assert!(buy.has_priority_over(&sell) && !sell.has_priority_over(&buy));
+    }
+
+    #[test]
+    fn test_non_crossing_orders_rejected() {
+        let (base, quote) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let sell = make_order(base, quote, 10_000_000, 3_000_000, 100);
+        let buy = make_order(quote, base, 50_000_000, 2_500_000, 200);
+        assert_eq!(
+            match_crossing_orders(&sell, &buy, &base).unwrap_err(),
+            error!(CustomError::OrdersDoNotCross)
+        );
+        // Two sells never cross
+        let other_sell = make_order(base, quote, 1_000_000, 1_000_000, 50);
+        assert_eq!(
+            match_crossing_orders(&sell, &other_sell, &base).unwrap_err(),
+            error!(CustomError::OrdersDoNotCross)
+        );
+    }
+
+    #[test]
+    fn test_two_partial_fills_complete_the_order() {
+        let mut order = make_order(Pubkey::default(), Pubkey::default(), 10_000, 1, 0);
+        order.minimum_receive = 25_000;
+        assert_eq!(order.minimum_receive_for(4_000).unwrap(), 10_000);
+        order.record_fill(4_000).unwrap();
+        assert_eq!(order.sell_amount, 6_000);
+        assert_eq!(order.minimum_receive, 15_000);
+        assert_eq!(order.status, OrderStatus::Pending);
+        // Overfilling the rest is rejected; filling it exactly completes the order
+        assert_eq!(order.record_fill(6_001).unwrap_err(), error!(CustomError::InvalidAmount));
+        order.record_fill(6_000).unwrap();
+        assert_eq!(order.sell_amount, 0);
+        assert_eq!(order.minimum_receive, 0);
+        assert_eq!(order.status, OrderStatus::Executed);
+    }
+
+    #[test]
+    fn test_execution_reward_paid_pro_rata_across_fills() {
+        let mut order = make_order(Pubkey::default(), Pubkey::default(), 3_000, 1, 0);
+        order.execution_reward = 10;
+        assert_eq!(order.record_fill(1_000).unwrap(), 3);
+        assert_eq!(order.execution_reward, 7);
+        // The completing fill pays out the rest of the escrow
+        assert_eq!(order.record_fill(2_000).unwrap(), 7);
+        assert_eq!(order.execution_reward, 0);
+    }
+
+    #[test]
+    fn test_keeper_tip_never_taken_from_rent() {
+        let mut order = make_order(Pubkey::default(), Pubkey::default(), 1, 1, 0);
+        let rent = 2_000_000;
+        assert_eq!(order.keeper_tip_payout(rent, rent), 0);
+        order.keeper_tip_lamports = 5_000;
+        assert_eq!(order.keeper_tip_payout(rent + 5_000, rent), 5_000);
+        // Lamports beyond rent and tip go back to the owner with the rent
+        assert_eq!(order.keeper_tip_payout(rent + 9_000, rent), 5_000);
+        assert_eq!(order.keeper_tip_payout(rent + 3_000, rent), 3_000);
+    }
+
+    #[test]
+    fn test_partial_minimum_receive_rounds_up() {
+        let mut order = make_order(Pubkey::default(), Pubkey::default(), 3, 1, 0);
+        order.minimum_receive = 10;
+        assert_eq!(order.minimum_receive_for(1).unwrap(), 4);
+        assert_eq!(order.minimum_receive_for(3).unwrap(), 10);
+    }
+
+    #[test]
+    fn test_fill_below_minimum_rejected() {
+        assert_eq!(validate_fill_amount(1, 10_000, 1_000).unwrap_err(), error!(CustomError::InvalidAmount));
+        assert_eq!(validate_fill_amount(999, 10_000, 1_000).unwrap_err(), error!(CustomError::InvalidAmount));
+        assert!(validate_fill_amount(1_000, 10_000, 1_000).is_ok());
+        assert_eq!(validate_fill_amount(10_001, 10_000, 1_000).unwrap_err(), error!(CustomError::InvalidAmount));
+    }
+
+    #[test]
+    fn test_final_residual_fill_below_minimum_allowed() {
+        // 9_500 filled in earlier fills; the last 500 completes the order
+        assert!(validate_fill_amount(500, 500, 1_000).is_ok());
+        assert_eq!(validate_fill_amount(0, 500, 1_000).unwrap_err(), error!(CustomError::InvalidAmount));
+    }
 }
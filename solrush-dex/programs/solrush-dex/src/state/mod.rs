@@ -4,17 +4,23 @@ pub mod limit_order;
 pub mod rush_config;
 pub mod perps_market;
 pub mod perps_position;
+pub mod perps_limit_order;
 pub mod perps_global_state;
 pub mod perps_user_account;
 pub mod perps_oracle_price;
 pub mod insurance_vault;
+pub mod flash_loan;
+pub mod amm_config;
 pub use pool::*;
 pub use user_position::*;
 pub use limit_order::*;
 pub use rush_config::*;
 pub use perps_market::*;
 pub use perps_position::*;
+pub use perps_limit_order::*;
 pub use perps_global_state::*;
 pub use perps_user_account::*;
 pub use perps_oracle_price::*;
 pub use insurance_vault::*;
+pub use flash_loan::*;
+pub use amm_config::*;
@@ -1,13 +1,107 @@
 use anchor_lang::prelude::*;
+use crate::constants::MAX_PERPS_MARKETS;
+use crate::errors::CustomError;
 
 #[account]
 pub struct PerpsGlobalState {
     pub authority: Pubkey,
-    pub paused: bool,
+    /// Blocks new risk: market creation, deposits, opens, withdrawals and funding updates.
+    pub trading_paused: bool,
     pub fee_bps: u16,
     pub bump: u8,
+    /// Admin-configurable cap on `markets.len()`, at most `MAX_PERPS_MARKETS`.
+    pub max_markets: u16,
+    /// Every market created under this global, in creation order.
+    pub markets: Vec<Pubkey>,
+    /// Liquidations keep running through a trading pause unless this is cleared.
+    pub liquidations_enabled: bool,
 }
 
 impl PerpsGlobalState {
-    pub const LEN: usize = 8 + 32 + 1 + 2 + 1;
+    pub const LEN: usize = 8 + 32 + 1 + 2 + 1 + 2 + 4 + 32 * MAX_PERPS_MARKETS + 1;
+
+    pub fn require_trading_active(&self) -> Result<()> {
+        require!(!self.trading_paused, CustomError::PerpsPaused);
+        Ok(())
+    }
+
+    pub fn require_liquidations_enabled(&self) -> Result<()> {
+        require!(self.liquidations_enabled, CustomError::LiquidationsDisabled);
+        Ok(())
+    }
+
+    pub fn register_market(&mut self, market: Pubkey) -> Result<()> {
+        require!(
+            self.markets.len() < self.max_markets as usize,
+            CustomError::MarketLimitReached
+        );
+        self.markets.push(market);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_global(max_markets: u16) -> PerpsGlobalState {
+        PerpsGlobalState {
+            authority: Pubkey::default(),
+            trading_paused: false,
+            fee_bps: 50,
+            bump: 0,
+            max_markets,
+            markets: Vec::new(),
+            liquidations_enabled: true,
+        }
+    }
+
+    #[test]
+    fn test_register_up_to_cap() {
+        let mut global = make_global(3);
+        let markets: Vec<Pubkey> = (0..3).map(|_| Pubkey::new_unique()).collect();
+        for market in &markets {
+            global.register_market(*market).unwrap();
+        }
+        assert_eq!(global.markets, markets);
+    }
+
+    #[test]
+    fn test_register_beyond_cap_rejected() {
+        let mut global = make_global(2);
+        global.register_market(Pubkey::new_unique()).unwrap();
+        global.register_market(Pubkey::new_unique()).unwrap();
+        let err = global.register_market(Pubkey::new_unique()).unwrap_err();
+        assert_eq!(err, error!(CustomError::MarketLimitReached));
+        assert_eq!(global.markets.len(), 2);
+    }
+
+    #[test]
+    fn test_full_registry_fits_account() {
+        let mut global = make_global(MAX_PERPS_MARKETS as u16);
+        for _ in 0..MAX_PERPS_MARKETS {
+            global.register_market(Pubkey::new_unique()).unwrap();
+        }
+        let data = global.try_to_vec().unwrap();
+        assert_eq!(8 + data.len(), PerpsGlobalState::LEN);
+    }
+
+    #[test]
+    fn test_trading_pause_keeps_liquidations_open() {
+        let mut global = make_global(1);
+        global.trading_paused = true;
+        assert_eq!(global.require_trading_active().unwrap_err(), error!(CustomError::PerpsPaused));
+        assert!(global.require_liquidations_enabled().is_ok());
+    }
+
+    #[test]
+    fn test_liquidations_can_be_halted_separately() {
+        let mut global = make_global(1);
+        global.liquidations_enabled = false;
+        assert!(global.require_trading_active().is_ok());
+        assert_eq!(
+            global.require_liquidations_enabled().unwrap_err(),
+            error!(CustomError::LiquidationsDisabled)
+        );
+    }
 }
@@ -0,0 +1,91 @@
+use anchor_lang::prelude::*;
+
+/// Resting perps limit order.
+///
+/// `side` follows `PerpsPosition::side`: 0 = long (buy), 1 = short (sell).
+/// Initial margin at `limit_price_i64` is moved out of the user's free
+/// collateral into `reserved_collateral_u64` when the order is placed and
+/// handed back on cancel or fill.
+#[account]
+pub struct PerpsLimitOrder {
+    pub owner: Pubkey,
+    pub market: Pubkey,
+    pub side: u8,
+    /// Unsigned base size (always > 0); direction comes from `side`.
+    pub size_i64: i64,
+    /// Limit price (PRICE_SCALE units).
+    pub limit_price_i64: i64,
+    pub leverage: u16,
+    pub expiry_ts: i64,
+    pub order_id: u64,
+    pub reserved_collateral_u64: u64,
+    pub bump: u8,
+    /// Fill only if it shrinks the position (see `PerpsPosition::validate_reduce_only`).
+    pub reduce_only: bool,
+}
+
+impl PerpsLimitOrder {
+    // 8 (discriminator) + 32 + 32 + 1 + 8 + 8 + 2 + 8 + 8 + 8 + 1 + 1 = 117
+    pub const LEN: usize = 8 + 32 + 32 + 1 + 8 + 8 + 2 + 8 + 8 + 8 + 1 + 1;
+
+    pub fn is_buy(&self) -> bool {
+        self.side == 0
+    }
+
+    pub fn is_expired(&self, now: i64) -> bool {
+        now >= self.expiry_ts
+    }
+
+    /// Buys fill at or below the limit, sells at or above it.
+    pub fn price_condition_met(&self, price: i64) -> bool {
+        if self.is_buy() {
+            price <= self.limit_price_i64
+        } else {
+            price >= self.limit_price_i64
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_order(side: u8, limit_price: i64) -> PerpsLimitOrder {
+        PerpsLimitOrder {
+            owner: Pubkey::default(),
+            market: Pubkey::default(),
+            side,
+            size_i64: 1_000_000,
+            limit_price_i64: limit_price,
+            leverage: 5,
+            expiry_ts: 1_000,
+            order_id: 1,
+            reserved_collateral_u64: 0,
+            bump: 0,
+            reduce_only: false,
+        }
+    }
+
+    #[test]
+    fn buy_fills_at_or_below_limit() {
+        let order = make_order(0, 100_000_000);
+        assert!(order.price_condition_met(99_000_000));
+        assert!(order.price_condition_met(100_000_000));
+        assert!(!order.price_condition_met(100_000_001));
+    }
+
+    #[test]
+    fn sell_fills_at_or_above_limit() {
+        let order = make_order(1, 100_000_000);
+        assert!(order.price_condition_met(101_000_000));
+        assert!(order.price_condition_met(100_000_000));
+        assert!(!order.price_condition_met(99_999_999));
+    }
+
+    #[test]
+    fn expires_at_expiry_ts() {
+        let order = make_order(0, 100_000_000);
+        assert!(!order.is_expired(999));
+        assert!(order.is_expired(1_000));
+    }
+}
@@ -1,4 +1,6 @@
 use anchor_lang::prelude::*;
+use crate::errors::CustomError;
+use crate::perps_math;
 
 #[account]
 pub struct PerpsMarket {
@@ -10,7 +12,8 @@ pub struct PerpsMarket {
     pub maintenance_margin_bps: u16,
     pub funding_rate_i64: i64,
     pub open_interest_i128: i128,
-    /// Cumulative funding per base unit (atomic quote units, signed).
+    /// Cumulative funding per whole base unit (atomic quote units, signed).
+    /// A position of `b` base atoms owes `b × Δ / BASE_SCALE` quote atoms.
     /// Long positions pay when this increases; shorts receive.
     pub cumulative_funding_i128: i128,
     pub last_funding_ts: i64,
@@ -26,9 +29,487 @@ pub struct PerpsMarket {
     pub liquidation_penalty_bps: u16,
     /// Emergency flag — set when insurance fund is depleted on bad-debt liquidation.
     pub emergency: bool,
+    /// Scales the oracle confidence ratio into extra MM bps (0 = disabled).
+    pub conf_mm_multiplier_bps: u16,
+    /// Markets accept new positions only once activated (insurance seeded).
+    pub active: bool,
+    /// Minimum insurance-vault balance required before `activate_market`.
+    pub min_insurance_seed_u64: u64,
+    /// Funding collected from paying positions, not yet paid to receivers.
+    pub funding_pool_u64: u64,
+    /// Discount applied to collateral when valuing equity (0 = valued 1:1).
+    pub collateral_haircut_bps: u16,
+    /// Pay the liquidator fee from the insurance fund before the position's collateral.
+    pub liquidation_fee_insurance_first: bool,
+    /// Sum of open long sizes (base atoms).
+    pub long_open_interest_u64: u64,
+    /// Sum of open short sizes (base atoms).
+    pub short_open_interest_u64: u64,
+    /// Cap on `|long − short| / (long + short)` in bps for opens that widen the skew (0 = no cap).
+    pub max_oi_imbalance_bps: u16,
+    /// Absolute skew (base atoms) allowed regardless of the ratio, so a fresh market can open.
+    pub oi_imbalance_free_base_u64: u64,
+    /// Trading fees taken from users' collateral; the tokens stay in `collateral_vault`.
+    pub trading_fees_u64: u64,
+    /// Sum of oracle price × seconds held, sampled by trades, liquidations and
+    /// `update_funding`; see `sample_mark_price`.
+    pub mark_price_cumulative_i128: i128,
+    /// When the mark was last sampled (0 = never).
+    pub last_mark_ts: i64,
+    /// Averaging window of the liquidation mark (0 = liquidate on spot).
+    pub twap_window_secs: i64,
+    /// Bad debt the insurance fund could not cover; socialized by `adl_position`.
+    pub uncovered_bad_debt_u64: u64,
+    /// Side (0 = long, 1 = short) of the bankrupt position behind the bad debt.
+    pub bad_debt_side_u8: u8,
+    /// Oldest Pyth price the market accepts, in seconds.
+    pub max_oracle_staleness_secs: u32,
+    /// Liquidator fee (bps) paid once a position's equity reaches 0; the fee
+    /// scales up to it from `liquidation_fee_bps` (≤ that = flat fee).
+    pub max_liquidation_fee_bps: u16,
+    /// Cap on `open_interest_i128`; trades that raise it past the cap are rejected (0 = no cap).
+    pub max_open_interest_i128: i128,
+    /// Paid to whoever cranks `update_funding` once the interval has elapsed,
+    /// out of `trading_fees_u64` (0 = no reward).
+    pub funding_keeper_reward_u64: u64,
+    /// Floor on the liquidator fee (atomic quote), so dust positions are
+    /// still worth liquidating; paid as far as collateral and insurance allow.
+    pub min_liquidation_fee_u64: u64,
+    /// Insurance balance `withdraw_insurance` must leave behind unless the
+    /// market is in emergency.
+    pub insurance_floor_u64: u64,
+    /// Per-market halt of opens, funding updates and liquidations; closes stay open.
+    pub paused: bool,
+    /// Funding rate (bps) a fully one-sided book adds on top of the premium;
+    /// scaled by the long/short imbalance (0 = premium only).
+    pub funding_skew_bps: u16,
+    /// Oracle price at `last_mark_ts`, held until the next sample.
+    pub last_mark_price_i64: i64,
+    /// `mark_price_cumulative_i128` where the liquidation mark's average starts.
+    pub mark_window_start_cumulative_i128: i128,
+    pub mark_window_start_ts: i64,
+    /// Snapshot that becomes the window start once it is a window old.
+    pub mark_next_window_cumulative_i128: i128,
+    pub mark_next_window_ts: i64,
 }
 
 impl PerpsMarket {
-    // 8 + 32 + 32 + 32 + 32 + 2 + 2 + 8 + 16 + 16 + 8 + 8 + 8 + 32 + 1 + 2 + 2 + 1 = 232
-    pub const LEN: usize = 8 + 32 + 32 + 32 + 32 + 2 + 2 + 8 + 16 + 16 + 8 + 8 + 8 + 32 + 1 + 2 + 2 + 1;
+    // 8 + 32 + 32 + 32 + 32 + 2 + 2 + 8 + 16 + 16 + 8 + 8 + 8 + 32 + 1 + 2 + 2 + 1 + 2 + 1 + 8 + 8 + 2 + 1 + 8 + 8 + 2 + 8 + 8 + 16 + 8 + 8 + 8 + 1 + 4 + 2 + 16 + 8 + 8 + 8 + 1 + 2 + 8 + 16 + 8 + 16 + 8 = 434
+    pub const LEN: usize = 8 + 32 + 32 + 32 + 32 + 2 + 2 + 8 + 16 + 16 + 8 + 8 + 8 + 32 + 1 + 2 + 2 + 1 + 2 + 1 + 8 + 8 + 2 + 1
+        + 8 + 8 + 2 + 8 + 8 + 16 + 8 + 8 + 8 + 1 + 4 + 2 + 16 + 8 + 8 + 8 + 1 + 2 + 8 + 16 + 8 + 16 + 8;
+
+    pub fn require_not_paused(&self) -> Result<()> {
+        require!(!self.paused, CustomError::MarketPaused);
+        Ok(())
+    }
+
+    /// Whether the insurance fund is seeded enough to activate the market.
+    pub fn insurance_seed_met(&self, insurance_balance: u64) -> bool {
+        insurance_balance >= self.min_insurance_seed_u64
+    }
+
+    /// Most the admin may withdraw from an insurance fund holding
+    /// `insurance_balance`: down to `insurance_floor_u64`, and never below
+    /// `min_insurance_seed_u64` while the market is active or has open
+    /// interest.  Nothing during an emergency, when the fund covers bad debt.
+    pub fn withdrawable_insurance(&self, insurance_balance: u64) -> u64 {
+        if self.emergency {
+            return 0;
+        }
+        let has_open_interest = self.long_open_interest_u64 > 0 || self.short_open_interest_u64 > 0;
+        let floor = if self.active || has_open_interest {
+            self.insurance_floor_u64.max(self.min_insurance_seed_u64)
+        } else {
+            self.insurance_floor_u64
+        };
+        insurance_balance.saturating_sub(floor)
+    }
+
+    /// Record the oracle `price` seen at `now` for the liquidation mark.  The
+    /// previous price is credited for the time it held; `price` only counts
+    /// from now on.  The first sample, or one after a gap longer than the
+    /// window, restarts the average here rather than stretch a stale price.
+    pub fn sample_mark_price(&mut self, price: i64, now: i64) -> Result<()> {
+        if self.last_mark_ts == 0 || now.saturating_sub(self.last_mark_ts) > self.twap_window_secs {
+            self.mark_price_cumulative_i128 = 0;
+            self.mark_window_start_cumulative_i128 = 0;
+            self.mark_window_start_ts = now;
+            self.mark_next_window_cumulative_i128 = 0;
+            self.mark_next_window_ts = now;
+        } else {
+            self.mark_price_cumulative_i128 = perps_math::accumulate_mark_price(
+                self.mark_price_cumulative_i128,
+                self.last_mark_price_i64,
+                self.last_mark_ts,
+                now,
+            )?;
+            if now.saturating_sub(self.mark_next_window_ts) >= self.twap_window_secs {
+                self.mark_window_start_cumulative_i128 = self.mark_next_window_cumulative_i128;
+                self.mark_window_start_ts = self.mark_next_window_ts;
+                self.mark_next_window_cumulative_i128 = self.mark_price_cumulative_i128;
+                self.mark_next_window_ts = now;
+            }
+        }
+        self.last_mark_price_i64 = price;
+        self.last_mark_ts = now.max(self.last_mark_ts);
+        Ok(())
+    }
+
+    /// Time-weighted mark as of `now` over at least `twap_window_secs`, or
+    /// `None` while the TWAP is off, has under a window of history, or was
+    /// last sampled more than a window ago.
+    pub fn mark_twap(&self, now: i64) -> Option<i64> {
+        if self.twap_window_secs == 0
+            || self.last_mark_ts == 0
+            || now.saturating_sub(self.last_mark_ts) > self.twap_window_secs
+        {
+            return None;
+        }
+        let elapsed = now.checked_sub(self.mark_window_start_ts)?;
+        if elapsed < self.twap_window_secs {
+            return None;
+        }
+        let cumulative = perps_math::accumulate_mark_price(
+            self.mark_price_cumulative_i128,
+            self.last_mark_price_i64,
+            self.last_mark_ts,
+            now,
+        )
+        .ok()?;
+        let twap = perps_math::mark_twap(self.mark_window_start_cumulative_i128, cumulative, elapsed).ok()?;
+        i64::try_from(twap).ok()
+    }
+
+    /// Price liquidation eligibility is judged at: the TWAP mark while it is
+    /// live, otherwise the oracle `spot`.
+    pub fn liquidation_mark_price(&self, spot: i64, now: i64) -> i64 {
+        self.mark_twap(now).unwrap_or(spot)
+    }
+
+    /// Record bad debt left by a bankrupt position on `side` that insurance
+    /// could not cover, and put the market in emergency.
+    pub fn record_uncovered_bad_debt(&mut self, amount: u64, side: u8) {
+        if amount == 0 {
+            return;
+        }
+        self.uncovered_bad_debt_u64 = self.uncovered_bad_debt_u64.saturating_add(amount);
+        self.bad_debt_side_u8 = side;
+        self.emergency = true;
+    }
+
+    /// Cover up to `available` of the uncovered bad debt; the emergency clears
+    /// once none is left.  Returns the amount absorbed.
+    pub fn absorb_bad_debt(&mut self, available: u64) -> u64 {
+        let absorbed = available.min(self.uncovered_bad_debt_u64);
+        self.uncovered_bad_debt_u64 -= absorbed;
+        if self.uncovered_bad_debt_u64 == 0 {
+            self.emergency = false;
+        }
+        absorbed
+    }
+
+    /// Long and short open interest after a position moves from `old_base` to `new_base`.
+    fn side_open_interest_after(&self, old_base: i64, new_base: i64) -> (u64, u64) {
+        let (mut long, mut short) = (self.long_open_interest_u64, self.short_open_interest_u64);
+        if old_base > 0 {
+            long = long.saturating_sub(old_base.unsigned_abs());
+        } else {
+            short = short.saturating_sub(old_base.unsigned_abs());
+        }
+        if new_base > 0 {
+            long = long.saturating_add(new_base.unsigned_abs());
+        } else {
+            short = short.saturating_add(new_base.unsigned_abs());
+        }
+        (long, short)
+    }
+
+    /// Record a position moving from `old_base` to `new_base` in the per-side open interest.
+    pub fn apply_position_oi(&mut self, old_base: i64, new_base: i64) {
+        let (long, short) = self.side_open_interest_after(old_base, new_base);
+        self.long_open_interest_u64 = long;
+        self.short_open_interest_u64 = short;
+    }
+
+    /// `|long − short| / (long + short)` in bps; 0 for an empty market.
+    pub fn oi_imbalance_bps(long: u64, short: u64) -> u64 {
+        let total = long as u128 + short as u128;
+        if total == 0 {
+            return 0;
+        }
+        (long.abs_diff(short) as u128 * 10_000 / total) as u64
+    }
+
+    /// Book the funding keeper's reward against the collected trading fees.
+    /// Capped by them, so the reward never touches users' collateral.
+    pub fn take_funding_keeper_reward(&mut self) -> u64 {
+        let reward = self.funding_keeper_reward_u64.min(self.trading_fees_u64);
+        self.trading_fees_u64 -= reward;
+        reward
+    }
+
+    /// Reject a trade that raises `open_interest_i128` by `oi_delta` past
+    /// `max_open_interest_i128`.  Reductions and closes always pass.
+    pub fn check_open_interest_cap(&self, oi_delta: i128) -> Result<()> {
+        if self.max_open_interest_i128 == 0 || oi_delta <= 0 {
+            return Ok(());
+        }
+        let after = self
+            .open_interest_i128
+            .checked_add(oi_delta)
+            .ok_or(error!(CustomError::CalculationOverflow))?;
+        require!(
+            after <= self.max_open_interest_i128,
+            CustomError::OpenInterestCapExceeded
+        );
+        Ok(())
+    }
+
+    /// Reject an open that leaves the skew above both `oi_imbalance_free_base_u64`
+    /// and `max_oi_imbalance_bps`, unless it narrows the skew.
+    pub fn check_oi_imbalance(&self, old_base: i64, new_base: i64) -> Result<()> {
+        if self.max_oi_imbalance_bps == 0 {
+            return Ok(());
+        }
+        let skew_before = self.long_open_interest_u64.abs_diff(self.short_open_interest_u64);
+        let (long, short) = self.side_open_interest_after(old_base, new_base);
+        let skew_after = long.abs_diff(short);
+        let within_cap = skew_after <= self.oi_imbalance_free_base_u64
+            || Self::oi_imbalance_bps(long, short) <= self.max_oi_imbalance_bps as u64;
+        require!(
+            within_cap || skew_after <= skew_before,
+            CustomError::OiImbalanceExceeded
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) fn make_market(min_seed: u64) -> PerpsMarket {
+        PerpsMarket {
+            base_mint: Pubkey::default(),
+            quote_mint: Pubkey::default(),
+            pyth_feed_id: [0; 32],
+            oracle_price_account: Pubkey::default(),
+            max_leverage: 10,
+            maintenance_margin_bps: 500,
+            funding_rate_i64: 0,
+            open_interest_i128: 0,
+            cumulative_funding_i128: 0,
+            last_funding_ts: 0,
+            max_funding_rate_i64: 10_000,
+            funding_interval_secs: 3600,
+            collateral_vault: Pubkey::default(),
+            bump: 0,
+            liquidation_fee_bps: 250,
+            liquidation_penalty_bps: 250,
+            emergency: false,
+            conf_mm_multiplier_bps: 0,
+            active: false,
+            min_insurance_seed_u64: min_seed,
+            funding_pool_u64: 0,
+            collateral_haircut_bps: 0,
+            liquidation_fee_insurance_first: false,
+            long_open_interest_u64: 0,
+            short_open_interest_u64: 0,
+            max_oi_imbalance_bps: 0,
+            oi_imbalance_free_base_u64: 0,
+            trading_fees_u64: 0,
+            mark_price_cumulative_i128: 0,
+            last_mark_ts: 0,
+            twap_window_secs: 0,
+            uncovered_bad_debt_u64: 0,
+            bad_debt_side_u8: 0,
+            max_oracle_staleness_secs: 60,
+            max_liquidation_fee_bps: 250,
+            max_open_interest_i128: 0,
+            funding_keeper_reward_u64: 0,
+            min_liquidation_fee_u64: 0,
+            insurance_floor_u64: 0,
+            paused: false,
+            funding_skew_bps: 0,
+            last_mark_price_i64: 0,
+            mark_window_start_cumulative_i128: 0,
+            mark_window_start_ts: 0,
+            mark_next_window_cumulative_i128: 0,
+            mark_next_window_ts: 0,
+        }
+    }
+
+    #[test]
+    fn test_paused_market_does_not_block_others() {
+        let mut troubled = make_market(0);
+        let healthy = make_market(0);
+        troubled.paused = true;
+        assert_eq!(troubled.require_not_paused().unwrap_err(), error!(CustomError::MarketPaused));
+        assert!(healthy.require_not_paused().is_ok());
+        troubled.paused = false;
+        assert!(troubled.require_not_paused().is_ok());
+    }
+
+    #[test]
+    fn test_insurance_withdrawal_stops_at_floor() {
+        let mut market = make_market(0);
+        assert_eq!(market.withdrawable_insurance(1_000), 1_000);
+        market.insurance_floor_u64 = 600;
+        assert_eq!(market.withdrawable_insurance(1_000), 400);
+        assert_eq!(market.withdrawable_insurance(500), 0);
+        // Bad debt needs the whole fund
+        market.emergency = true;
+        assert_eq!(market.withdrawable_insurance(1_000), 0);
+    }
+
+    #[test]
+    fn test_live_market_keeps_insurance_seed() {
+        let mut market = make_market(800);
+        market.insurance_floor_u64 = 300;
+        // Before activation only the configured floor applies
+        assert_eq!(market.withdrawable_insurance(1_000), 700);
+        market.active = true;
+        assert_eq!(market.withdrawable_insurance(1_000), 200);
+        // Open positions keep the seed in place after deactivation
+        market.active = false;
+        market.short_open_interest_u64 = 1;
+        assert_eq!(market.withdrawable_insurance(1_000), 200);
+        market.insurance_floor_u64 = 900;
+        assert_eq!(market.withdrawable_insurance(1_000), 100);
+    }
+
+    #[test]
+    fn test_unfunded_market_cannot_activate() {
+        let market = make_market(5_000_000);
+        assert!(!market.insurance_seed_met(0));
+        assert!(!market.insurance_seed_met(4_999_999));
+    }
+
+    #[test]
+    fn test_seeded_market_can_activate() {
+        let market = make_market(5_000_000);
+        assert!(market.insurance_seed_met(5_000_000));
+        assert!(market.insurance_seed_met(10_000_000));
+    }
+
+    #[test]
+    fn test_liquidation_mark_ignores_transient_spike() {
+        let mut market = make_market(0);
+        market.twap_window_secs = 600;
+        // Trades sample 100 once a minute for a window
+        for ts in (1_000..=1_600).step_by(60) {
+            market.sample_mark_price(100, ts).unwrap();
+        }
+        assert_eq!(market.liquidation_mark_price(80, 1_600), 100);
+        // A wick to 80 sampled by a trade: 30s later the mark has barely moved
+        market.sample_mark_price(80, 1_620).unwrap();
+        assert_eq!(market.liquidation_mark_price(80, 1_650), 99);
+        // Held at 80 for a full window it drags the mark all the way down
+        for ts in (1_680..=2_280).step_by(60) {
+            market.sample_mark_price(80, ts).unwrap();
+        }
+        assert_eq!(market.liquidation_mark_price(80, 2_880), 80);
+    }
+
+    #[test]
+    fn test_liquidation_mark_falls_back_to_spot() {
+        let mut market = make_market(0);
+        market.sample_mark_price(100, 1_000).unwrap();
+        // TWAP off
+        assert_eq!(market.liquidation_mark_price(80, 1_000), 80);
+        market.twap_window_secs = 600;
+        market.sample_mark_price(100, 1_000).unwrap();
+        // Under a window of history
+        market.sample_mark_price(100, 1_300).unwrap();
+        assert_eq!(market.liquidation_mark_price(80, 1_500), 80);
+        market.sample_mark_price(100, 1_600).unwrap();
+        assert_eq!(market.liquidation_mark_price(80, 1_700), 100);
+        // Unsampled for over a window: the held price is stale
+        assert_eq!(market.liquidation_mark_price(80, 2_201), 80);
+        // And the next sample restarts the average instead of stretching it
+        market.sample_mark_price(80, 2_300).unwrap();
+        assert_eq!(market.liquidation_mark_price(90, 2_300), 90);
+    }
+
+    #[test]
+    fn test_adl_absorbs_bad_debt_until_solvent() {
+        let mut market = make_market(0);
+        market.record_uncovered_bad_debt(0, 0);
+        assert!(!market.emergency);
+        market.record_uncovered_bad_debt(1_000, 0);
+        assert!(market.emergency);
+
+        // A small winner covers part of the hole; the emergency holds
+        assert_eq!(market.absorb_bad_debt(400), 400);
+        assert_eq!(market.uncovered_bad_debt_u64, 600);
+        assert!(market.emergency);
+        // A big winner gives up only what is still owed
+        assert_eq!(market.absorb_bad_debt(5_000), 600);
+        assert_eq!(market.uncovered_bad_debt_u64, 0);
+        assert!(!market.emergency);
+    }
+
+    #[test]
+    fn test_oi_imbalance_blocks_heavy_side_only() {
+        let mut market = make_market(0);
+        market.max_oi_imbalance_bps = 2_000;
+        market.oi_imbalance_free_base_u64 = 100;
+        // A fresh market can open within the free skew
+        assert!(market.check_oi_imbalance(0, 100).is_ok());
+        market.apply_position_oi(0, 100);
+        market.apply_position_oi(0, -80);
+        assert_eq!(PerpsMarket::oi_imbalance_bps(100, 80), 1_111);
+
+        // 150 long vs 80 short: skew 70 is free, but 220 vs 80 (4666 bps) is not
+        assert!(market.check_oi_imbalance(0, 50).is_ok());
+        assert_eq!(
+            market.check_oi_imbalance(0, 120).unwrap_err(),
+            error!(CustomError::OiImbalanceExceeded)
+        );
+        // Opening the light side is fine, but overshooting makes it the heavy side
+        assert!(market.check_oi_imbalance(0, -20).is_ok());
+        assert!(market.check_oi_imbalance(0, -500).is_err());
+
+        // Narrowing an existing over-cap skew is allowed
+        market.apply_position_oi(0, 400);
+        assert!(market.check_oi_imbalance(0, -10).is_ok());
+        assert!(market.check_oi_imbalance(0, 10).is_err());
+
+        // Closing removes the position's size from its side
+        market.apply_position_oi(400, 0);
+        assert_eq!((market.long_open_interest_u64, market.short_open_interest_u64), (100, 80));
+        market.max_oi_imbalance_bps = 0;
+        assert!(market.check_oi_imbalance(0, 10_000).is_ok());
+    }
+
+    #[test]
+    fn test_open_interest_cap_blocks_increases_only() {
+        let mut market = make_market(0);
+        market.max_open_interest_i128 = 1_000;
+        // Fill exactly to the cap
+        assert!(market.check_open_interest_cap(1_000).is_ok());
+        market.open_interest_i128 = 1_000;
+        assert_eq!(
+            market.check_open_interest_cap(1).unwrap_err(),
+            error!(CustomError::OpenInterestCapExceeded)
+        );
+        // Closing is always allowed, even above a lowered cap
+        market.max_open_interest_i128 = 500;
+        assert!(market.check_open_interest_cap(-1_000).is_ok());
+        assert!(market.check_open_interest_cap(0).is_ok());
+        market.max_open_interest_i128 = 0;
+        assert!(market.check_open_interest_cap(i64::MAX as i128).is_ok());
+    }
+
+    #[test]
+    fn test_funding_keeper_reward_capped_by_trading_fees() {
+        let mut market = make_market(0);
+        market.funding_keeper_reward_u64 = 1_000;
+        market.trading_fees_u64 = 2_500;
+        assert_eq!(market.take_funding_keeper_reward(), 1_000);
+        assert_eq!(market.take_funding_keeper_reward(), 1_000);
+        assert_eq!(market.take_funding_keeper_reward(), 500);
+        assert_eq!(market.take_funding_keeper_reward(), 0);
+        assert_eq!(market.trading_fees_u64, 0);
+    }
 }
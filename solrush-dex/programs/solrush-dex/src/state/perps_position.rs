@@ -1,4 +1,6 @@
 use anchor_lang::prelude::*;
+use crate::errors::CustomError;
+use crate::perps_math::{self, PositionState};
 
 /// Perps position account.
 ///
@@ -6,9 +8,10 @@ use anchor_lang::prelude::*;
 /// The legacy `side` field is kept for ABI compatibility but is now derived
 /// from the sign of `base_position_i64`.
 ///
-/// `realized_pnl_i128` accumulates PnL from partial/full closes, in
+/// `realized_pnl_i128` accumulates PnL from partial closes, in
 /// PRICE_SCALE-squared units (base_units × price_units).  To get quote
-/// value divide by PRICE_SCALE.
+/// value divide by PRICE_SCALE.  It is zeroed on full close, once the
+/// PnL has been paid out to collateral.
 #[account]
 pub struct PerpsPosition {
     pub owner: Pubkey,
@@ -26,21 +29,274 @@ pub struct PerpsPosition {
     /// Cumulative funding index at last settlement (placeholder for Phase 3).
     pub last_funding_i128: i128,
     pub bump: u8,
-    /// Accumulated realized PnL from partial/full closes (signed, scaled).
+    /// Realized PnL of the current position's partial closes (signed, scaled).
     pub realized_pnl_i128: i128,
+    /// Funding owed to this position that the market funding pool could not cover yet.
+    pub unpaid_funding_u64: u64,
+    /// Take-profit trigger price (PRICE_SCALE units); 0 = unset.
+    pub take_profit_price_i64: i64,
+    /// Stop-loss trigger price (PRICE_SCALE units); 0 = unset.
+    pub stop_loss_price_i64: i64,
+    /// `MarginMode` chosen when the position was opened.
+    pub margin_mode_u8: u8,
+}
+
+/// How a position's margin relates to the user's shared free collateral.
+///
+/// Cross positions are margined together with the user's other cross
+/// positions and free collateral, which also covers a liquidation shortfall.
+/// Isolated positions stand on their own collateral; a liquidation draws on
+/// it and the insurance fund only.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MarginMode {
+    Cross = 0,
+    Isolated = 1,
+}
+
+/// Exit trigger that closed a position; stored as `u8` in `TriggerExecuted`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PositionTrigger {
+    TakeProfit = 0,
+    StopLoss = 1,
 }
 
 impl PerpsPosition {
-    // 8 (discriminator) + 32 + 32 + 1 + 8 + 8 + 8 + 2 + 16 + 1 + 16 = 132
-    pub const LEN: usize = 8 + 32 + 32 + 1 + 8 + 8 + 8 + 2 + 16 + 1 + 16;
+    // 8 (discriminator) + 32 + 32 + 1 + 8 + 8 + 8 + 2 + 16 + 1 + 16 + 8 + 8 + 8 + 1 = 157
+    pub const LEN: usize = 8 + 32 + 32 + 1 + 8 + 8 + 8 + 2 + 16 + 1 + 16 + 8 + 8 + 8 + 1;
 
     /// Helper: is this position empty / closed?
     pub fn is_empty(&self) -> bool {
         self.base_position_i64 == 0
     }
 
+    /// Nothing left to settle: no size, no collateral, no funding owed.
+    pub fn is_closable(&self) -> bool {
+        self.is_empty() && self.collateral_u64 == 0 && self.unpaid_funding_u64 == 0
+    }
+
+    pub fn is_isolated(&self) -> bool {
+        self.margin_mode_u8 == MarginMode::Isolated as u8
+    }
+
     /// Helper: derive side from signed base.
     pub fn derived_side(&self) -> u8 {
         if self.base_position_i64 >= 0 { 0 } else { 1 }
     }
+
+    /// Check a take-profit / stop-loss pair against the current direction:
+    /// a long needs `tp > sl`, a short `tp < sl`.  Either may be 0 (unset).
+    pub fn validate_triggers(&self, take_profit: i64, stop_loss: i64) -> Result<()> {
+        require!(!self.is_empty(), CustomError::NoOpenPosition);
+        require!(take_profit >= 0 && stop_loss >= 0, CustomError::InvalidTriggerPrice);
+        if take_profit > 0 && stop_loss > 0 {
+            let ordered = if self.base_position_i64 > 0 {
+                take_profit > stop_loss
+            } else {
+                take_profit < stop_loss
+            };
+            require!(ordered, CustomError::InvalidTriggerPrice);
+        }
+        Ok(())
+    }
+
+    /// A reduce-only trade must be against the open position and no larger
+    /// than it, so it can shrink or close the position but never grow or flip it.
+    pub fn validate_reduce_only(&self, trade_base_delta: i64) -> Result<()> {
+        let base = self.base_position_i64;
+        require!(
+            base != 0
+                && trade_base_delta.signum() == -base.signum()
+                && trade_base_delta.unsigned_abs() <= base.unsigned_abs(),
+            CustomError::ReduceOnlyViolation
+        );
+        Ok(())
+    }
+
+    /// Trigger that fires at `price`, if any.  Longs take profit at or above
+    /// the TP and stop out at or below the SL; shorts mirror that.
+    pub fn triggered_exit(&self, price: i64) -> Option<PositionTrigger> {
+        let (tp, sl) = (self.take_profit_price_i64, self.stop_loss_price_i64);
+        let long = self.base_position_i64 > 0;
+        if self.is_empty() {
+            None
+        } else if sl > 0 && (if long { price <= sl } else { price >= sl }) {
+            Some(PositionTrigger::StopLoss)
+        } else if tp > 0 && (if long { price >= tp } else { price <= tp }) {
+            Some(PositionTrigger::TakeProfit)
+        } else {
+            None
+        }
+    }
+
+    pub fn clear_triggers(&mut self) {
+        self.take_profit_price_i64 = 0;
+        self.stop_loss_price_i64 = 0;
+    }
+
+    /// Funding accrued since the last settlement against a market index of
+    /// `market_cum_funding` (atomic quote; positive = the position pays).
+    pub fn pending_funding(&self, market_cum_funding: i128) -> Result<i128> {
+        let cum_diff = market_cum_funding
+            .checked_sub(self.last_funding_i128)
+            .ok_or(error!(CustomError::CalculationOverflow))?;
+        if self.is_empty() || cum_diff == 0 {
+            return Ok(0);
+        }
+        perps_math::funding_payment(self.base_position_i64, cum_diff)
+    }
+
+    /// Snapshot fed to `perps_math::apply_trade_to_position`.
+    pub fn engine_state(&self) -> PositionState {
+        PositionState {
+            base_position: self.base_position_i64,
+            entry_price: self.entry_price_i64,
+            realized_pnl: self.realized_pnl_i128,
+            last_cum_funding: self.last_funding_i128,
+        }
+    }
+
+    /// Clear everything but identity and unpaid funding after a full close
+    /// or full liquidation.
+    pub fn reset(&mut self) {
+        self.base_position_i64 = 0;
+        self.entry_price_i64 = 0;
+        self.collateral_u64 = 0;
+        self.leverage_u16 = 0;
+        self.last_funding_i128 = 0;
+        self.realized_pnl_i128 = 0;
+        self.side = 0;
+        self.margin_mode_u8 = MarginMode::Cross as u8;
+        self.clear_triggers();
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) fn make_position(base: i64) -> PerpsPosition {
+        PerpsPosition {
+            owner: Pubkey::default(),
+            market: Pubkey::default(),
+            side: 0,
+            base_position_i64: base,
+            entry_price_i64: 100,
+            collateral_u64: 100,
+            leverage_u16: 5,
+            last_funding_i128: 0,
+            bump: 0,
+            realized_pnl_i128: 0,
+            unpaid_funding_u64: 0,
+            take_profit_price_i64: 0,
+            stop_loss_price_i64: 0,
+            margin_mode_u8: MarginMode::Cross as u8,
+        }
+    }
+
+    #[test]
+    fn test_reduce_only_shrinks_but_never_grows_or_flips() {
+        let long = make_position(10);
+        assert!(long.validate_reduce_only(-4).is_ok());
+        assert!(long.validate_reduce_only(-10).is_ok());
+        let increase = long.validate_reduce_only(5).unwrap_err();
+        assert_eq!(increase, error!(CustomError::ReduceOnlyViolation));
+        assert!(long.validate_reduce_only(-11).is_err());
+        assert!(long.validate_reduce_only(0).is_err());
+
+        let short = make_position(-10);
+        assert!(short.validate_reduce_only(10).is_ok());
+        assert!(short.validate_reduce_only(-1).is_err());
+        assert!(short.validate_reduce_only(12).is_err());
+        // Nothing to reduce
+        assert!(make_position(0).validate_reduce_only(-1).is_err());
+    }
+
+    #[test]
+    fn test_pending_funding_since_checkpoint() {
+        // 2 base against a 1.5 quote-per-base index move
+        let mut long = make_position(2_000_000);
+        long.last_funding_i128 = 500_000;
+        assert_eq!(long.pending_funding(2_000_000).unwrap(), 3_000_000);
+        assert_eq!(long.pending_funding(500_000).unwrap(), 0);
+        let mut short = make_position(-2_000_000);
+        short.last_funding_i128 = 500_000;
+        assert_eq!(short.pending_funding(2_000_000).unwrap(), -3_000_000);
+        assert_eq!(make_position(0).pending_funding(2_000_000).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_derived_side_matches_base_sign() {
+        assert_eq!(make_position(10).derived_side(), 0);
+        assert_eq!(make_position(-10).derived_side(), 1);
+        assert_eq!(make_position(0).derived_side(), 0);
+        assert!(make_position(0).is_empty());
+    }
+
+    #[test]
+    fn test_only_settled_empty_positions_closable() {
+        // Full liquidation leaves base and collateral at zero
+        let mut position = make_position(0);
+        position.collateral_u64 = 0;
+        assert!(position.is_closable());
+        assert!(!make_position(10).is_closable());
+        assert!(!make_position(-10).is_closable());
+        position.unpaid_funding_u64 = 1;
+        assert!(!position.is_closable());
+    }
+
+    #[test]
+    fn test_long_triggers_fire_on_the_right_side() {
+        let mut position = make_position(10);
+        position.take_profit_price_i64 = 120;
+        position.stop_loss_price_i64 = 90;
+        assert_eq!(position.triggered_exit(100), None);
+        assert_eq!(position.triggered_exit(120), Some(PositionTrigger::TakeProfit));
+        assert_eq!(position.triggered_exit(90), Some(PositionTrigger::StopLoss));
+        position.clear_triggers();
+        assert_eq!(position.triggered_exit(200), None);
+    }
+
+    #[test]
+    fn test_short_triggers_mirror_long() {
+        let mut position = make_position(-10);
+        position.take_profit_price_i64 = 80;
+        position.stop_loss_price_i64 = 110;
+        assert_eq!(position.triggered_exit(100), None);
+        assert_eq!(position.triggered_exit(80), Some(PositionTrigger::TakeProfit));
+        assert_eq!(position.triggered_exit(110), Some(PositionTrigger::StopLoss));
+    }
+
+    #[test]
+    fn test_trigger_ordering_validated() {
+        let long = make_position(10);
+        assert!(long.validate_triggers(120, 90).is_ok());
+        assert!(long.validate_triggers(0, 90).is_ok());
+        assert_eq!(long.validate_triggers(90, 120).unwrap_err(), error!(CustomError::InvalidTriggerPrice));
+        let short = make_position(-10);
+        assert!(short.validate_triggers(90, 120).is_ok());
+        assert_eq!(short.validate_triggers(-1, 0).unwrap_err(), error!(CustomError::InvalidTriggerPrice));
+        assert_eq!(make_position(0).validate_triggers(0, 0).unwrap_err(), error!(CustomError::NoOpenPosition));
+    }
+
+    #[test]
+    fn test_reopen_after_full_close_has_no_stale_pnl() {
+        use crate::perps_math::apply_trade_to_position;
+
+        // Long 10 @ 100, half closed at 110, rest at 120
+        let mut position = make_position(10);
+        let half = apply_trade_to_position(&position.engine_state(), -5, 110).unwrap();
+        position.base_position_i64 = half.new_base_position;
+        position.realized_pnl_i128 = half.new_realized_pnl;
+        assert_eq!(position.realized_pnl_i128, 50);
+        let full = apply_trade_to_position(&position.engine_state(), -5, 120).unwrap();
+        assert_eq!(full.new_base_position, 0);
+        position.reset();
+        assert!(position.is_empty());
+        assert_eq!(position.realized_pnl_i128, 0);
+
+        // Reopen: the engine starts from zero realized PnL
+        let reopened = apply_trade_to_position(&position.engine_state(), 3, 90).unwrap();
+        assert_eq!(reopened.new_realized_pnl, 0);
+        assert_eq!((reopened.new_base_position, reopened.new_entry_price), (3, 90));
+    }
 }
@@ -1,4 +1,89 @@
 use anchor_lang::prelude::*;
+use crate::errors::CustomError;
+use crate::state::RushConfig;
+use crate::utils::{
+    dynamic_fee_numerator,
+    calculate_single_sided_swap_amount,
+    calculate_output_amount,
+    calculate_input_amount,
+    calculate_swap_fee,
+    calculate_lp_tokens_for_add_liquidity,
+    calculate_remove_liquidity_amounts,
+};
+use crate::constants::{
+    ACC_REWARD_PRECISION,
+    TWAP_PRICE_PRECISION,
+    TWAP_OBSERVATION_SLOTS,
+    TWAP_OBSERVATION_INTERVAL_SECS,
+    ALIGNMENT_TWAP_WINDOW_SECS,
+    DYNAMIC_FEE_TWAP_WINDOW_SECS,
+    FEE_BPS_DENOMINATOR,
+    MAX_REWARD_PROGRAMS,
+};
+/// Outcome of `LiquidityPool::apply_single_sided_deposit`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SingleSidedDeposit {
+    /// Part of the input swapped internally for the other token.
+    pub swap_amount: u64,
+    pub swap_out: u64,
+    pub fee_amount: u64,
+    /// Amounts deposited as liquidity after the internal swap.
+    pub amount_a: u64,
+    pub amount_b: u64,
+    /// Reserves between the internal swap and the deposit.
+    pub swapped_a: u64,
+    pub swapped_b: u64,
+    pub lp_tokens: u64,
+}
+/// Snapshot of the TWAP accumulators, kept in a small ring on the pool.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct PriceObservation {
+    pub timestamp: i64,
+    pub price_cumulative_a: u128,
+    pub price_cumulative_b: u128,
+    pub cumulative_fees_a: u64,
+    pub cumulative_fees_b: u64,
+}
+impl PriceObservation {
+    pub const SIZE: usize = 8 + 16 + 16 + 8 + 8;
+}
+/// Partner-token incentive run by a pool next to RUSH, one per reward mint.
+/// Funded up front into `reward_vault` and emitted to LPs at
+/// `rewards_per_second` until `ends_at`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct RewardProgram {
+    /// `Pubkey::default()` marks an unused slot.
+    pub reward_mint: Pubkey,
+    pub reward_vault: Pubkey,
+    pub rewards_per_second: u64,
+    pub ends_at: i64,
+    /// Reward owed per LP token since the program started, scaled by `ACC_REWARD_PRECISION`.
+    pub acc_reward_per_share: u128,
+    pub last_update_ts: i64,
+}
+impl RewardProgram {
+    pub const SIZE: usize = 32 + 32 + 8 + 8 + 16 + 8;
+    pub fn is_active(&self) -> bool {
+        self.reward_mint != Pubkey::default()
+    }
+    /// Accumulator as of `now`. Emission stops at `ends_at` and while the pool has no LP.
+    pub fn projected_acc_reward_per_share(&self, total_lp_supply: u64, now: i64) -> Result<u128> {
+        let elapsed = now.min(self.ends_at).saturating_sub(self.last_update_ts);
+        if elapsed <= 0 || total_lp_supply == 0 {
+            return Ok(self.acc_reward_per_share);
+        }
+        let increment = (self.rewards_per_second as u128)
+            .checked_mul(elapsed as u128)
+            .ok_or(error!(CustomError::CalculationOverflow))?
+            .checked_mul(ACC_REWARD_PRECISION)
+            .ok_or(error!(CustomError::CalculationOverflow))?
+            / (total_lp_supply as u128);
+        self.acc_reward_per_share
+            .checked_add(increment)
+            .ok_or(error!(CustomError::CalculationOverflow))
+    }
+}
+
 #[account]
 pub struct LiquidityPool {
     pub authority: Pubkey,
@@ -20,12 +105,352 @@ pub struct LiquidityPool {
     pub total_volume_b: u64,
     pub locked_liquidity: u64,
     pub bump: u8,
+    /// Re-entrancy guard, held for the duration of any reserve-mutating instruction.
+    pub locked: bool,
+    /// Extra spread (bps of output) charged by `market_buy` on top of the pool fee.
+    pub buy_premium_bps: u16,
+    /// Extra spread (bps of output) charged by `market_sell` on top of the pool fee.
+    pub sell_discount_bps: u16,
+    /// RUSH owed per LP token since pool creation, scaled by `ACC_REWARD_PRECISION`.
+    pub acc_reward_per_share: u128,
+    /// Last time `acc_reward_per_share` was brought up to date.
+    pub last_reward_timestamp: i64,
+    /// StableSwap amplification `A`; only used when `is_stablecoin_pool` is set.
+    pub amplification_coefficient: u64,
+    /// Share of the swap fee sent to the treasury, over `fee_denominator`; at most `fee_numerator`.
+    pub protocol_fee_numerator: u64,
+    /// Treasury owner; protocol fees go to its token account for the input mint.
+    pub protocol_fee_vault: Pubkey,
+    /// Emergency pause: blocks swaps and deposits; withdrawals stay open.
+    pub is_paused: bool,
+    /// Fee on flash-loan principal, paid into reserves on repayment.
+    pub flash_fee_bps: u16,
+    /// Sum of `reserve_b / reserve_a * elapsed_secs`, scaled by `TWAP_PRICE_PRECISION`; wraps.
+    pub price_cumulative_a: u128,
+    /// Sum of `reserve_a / reserve_b * elapsed_secs`, scaled by `TWAP_PRICE_PRECISION`; wraps.
+    pub price_cumulative_b: u128,
+    /// Last time the price accumulators were brought up to date.
+    pub last_price_update_ts: i64,
+    pub observations: [PriceObservation; TWAP_OBSERVATION_SLOTS],
+    /// Slot of the most recent entry in `observations`.
+    pub observation_index: u8,
+    /// Swap fees charged in token A since creation; already compounded into reserves.
+    pub cumulative_fees_a: u64,
+    /// Swap fees charged in token B since creation; already compounded into reserves.
+    pub cumulative_fees_b: u64,
+    /// Extra RUSH (bps) for deposits made exactly at the TWAP; 0 disables the boost.
+    pub alignment_boost_bps: u16,
+    /// Spot-vs-TWAP deviation (bps) at which the boost has tapered to nothing.
+    pub alignment_band_bps: u16,
+    /// Dynamic-fee mode: share (bps) of the swap fee taken off swaps that pull
+    /// spot toward the TWAP and added to swaps that push it away; 0 charges the flat fee.
+    pub dynamic_fee_bps: u16,
+    /// Partner-token reward programs; RUSH is paid through `acc_reward_per_share` above.
+    pub reward_programs: [RewardProgram; MAX_REWARD_PROGRAMS],
+    /// Share (bps) of RUSH emissions this pool receives once pools are
+    /// weighted; see `RushConfig::pool_rewards_per_second`.
+    pub reward_weight_bps: u16,
+    /// RUSH shares boosted positions hold on top of their LP; the sum of
+    /// every position's `boost_shares`.
+    pub boost_shares: u64,
+    /// AMM config fee band (bps) as of the last `set_dynamic_fee` or
+    /// `set_pool_fee`; dynamic fees are clamped to it.
+    pub dynamic_fee_min_bps: u16,
+    pub dynamic_fee_max_bps: u16,
+    /// `RushConfig::emission_index` as of `last_reward_timestamp`.
+    pub rush_emission_index: u128,
 }
 impl LiquidityPool {
-    pub const SIZE: usize = 8 + 32*6 + 8*5 + 2 + 1 + 8 + 8 + 8 + 8 + 1;
+    pub const SIZE: usize = 8 + 32*6 + 8*5 + 2 + 1 + 8 + 8 + 8 + 8 + 1 + 1 + 2 + 2 + 16 + 8 + 8 + 8 + 32 + 1 + 2
+        + 16 + 16 + 8 + PriceObservation::SIZE * TWAP_OBSERVATION_SLOTS + 1 + 8 + 8 + 2 + 2 + 2
+        + RewardProgram::SIZE * MAX_REWARD_PROGRAMS + 2 + 8 + 2 + 2 + 16;
     pub fn is_stable_pair(&self) -> bool {
         self.is_stablecoin_pool
     }
+    /// Amplification to pass to `calculate_output_amount`, `None` for x * y = k pools.
+    pub fn stable_amplification(&self) -> Option<u64> {
+        (self.is_stablecoin_pool && self.amplification_coefficient > 0)
+            .then_some(self.amplification_coefficient)
+    }
+    /// Fee numerator (over `fee_denominator`) charged on a swap of `amount_in`
+    /// in this direction; the flat `fee_numerator` unless `dynamic_fee_bps` is
+    /// set and the pool has a TWAP over `DYNAMIC_FEE_TWAP_WINDOW_SECS`.
+    pub fn swap_fee_numerator(&self, amount_in: u64, is_a_to_b: bool, now: i64) -> Result<u64> {
+        if self.dynamic_fee_bps == 0 {
+            return Ok(self.fee_numerator);
+        }
+        // Without price history there is nothing to rebalance toward
+        let Ok((reference_price, _, _)) = self.twap(DYNAMIC_FEE_TWAP_WINDOW_SECS, now) else {
+            return Ok(self.fee_numerator);
+        };
+        let (input_reserve, output_reserve) = if is_a_to_b {
+            (self.reserve_a, self.reserve_b)
+        } else {
+            (self.reserve_b, self.reserve_a)
+        };
+        let base_out = calculate_output_amount(
+            amount_in,
+            input_reserve,
+            output_reserve,
+            self.fee_numerator,
+            self.fee_denominator,
+            self.stable_amplification(),
+        )?;
+        let input_after = input_reserve as u128 + amount_in as u128;
+        let output_after = (output_reserve - base_out) as u128;
+        let (reserve_a_after, reserve_b_after) = if is_a_to_b {
+            (input_after, output_after)
+        } else {
+            (output_after, input_after)
+        };
+        let price_before = (self.reserve_b as u128) * TWAP_PRICE_PRECISION / (self.reserve_a as u128);
+        let price_after = reserve_b_after * TWAP_PRICE_PRECISION / reserve_a_after;
+        let (fee_floor, fee_ceiling) = self.dynamic_fee_bounds();
+        Ok(dynamic_fee_numerator(
+            price_before,
+            price_after,
+            reference_price,
+            self.fee_numerator,
+            self.dynamic_fee_bps,
+            fee_floor,
+            fee_ceiling,
+        ))
+    }
+    /// `swap_fee_numerator` for a swap paying out exactly `amount_out`, sized
+    /// by the input the flat fee would need.
+    pub fn exact_out_fee_numerator(&self, amount_out: u64, is_a_to_b: bool, now: i64) -> Result<u64> {
+        if self.dynamic_fee_bps == 0 {
+            return Ok(self.fee_numerator);
+        }
+        let (input_reserve, output_reserve) = if is_a_to_b {
+            (self.reserve_a, self.reserve_b)
+        } else {
+            (self.reserve_b, self.reserve_a)
+        };
+        let flat_amount_in = calculate_input_amount(
+            amount_out,
+            input_reserve,
+            output_reserve,
+            self.fee_numerator,
+            self.fee_denominator,
+        )?;
+        self.swap_fee_numerator(flat_amount_in, is_a_to_b, now)
+    }
+    /// `(floor, ceiling)` a dynamic fee is clamped to, over `fee_denominator`:
+    /// the snapshotted AMM config band, but never below the protocol's cut.
+    fn dynamic_fee_bounds(&self) -> (u64, u64) {
+        let scaled = |bps: u16| bps as u128 * self.fee_denominator as u128;
+        let bps_denominator = FEE_BPS_DENOMINATOR as u128;
+        (
+            (scaled(self.dynamic_fee_min_bps).div_ceil(bps_denominator) as u64).max(self.protocol_fee_numerator),
+            ((scaled(self.dynamic_fee_max_bps) / bps_denominator) as u64).min(self.fee_denominator - 1),
+        )
+    }
+    /// Mint a swap in this direction takes in, and so charges its fees in.
+    pub fn input_mint(&self, is_a_to_b: bool) -> Pubkey {
+        if is_a_to_b {
+            self.token_a_mint
+        } else {
+            self.token_b_mint
+        }
+    }
+    /// Credit `pool_amount_in` (what reached the vault) to the input reserve and
+    /// debit `amount_out`; `amount_in` is the trader's full input, counted as volume.
+    /// Returns the updated `(reserve_a, reserve_b)`.
+    pub fn apply_swap(
+        &mut self,
+        amount_in: u64,
+        pool_amount_in: u64,
+        amount_out: u64,
+        is_a_to_b: bool,
+    ) -> Result<(u64, u64)> {
+        let (reserve_in, reserve_out, volume_in) = if is_a_to_b {
+            (&mut self.reserve_a, &mut self.reserve_b, &mut self.total_volume_a)
+        } else {
+            (&mut self.reserve_b, &mut self.reserve_a, &mut self.total_volume_b)
+        };
+        *reserve_in = reserve_in
+            .checked_add(pool_amount_in)
+            .ok_or(error!(CustomError::CalculationOverflow))?;
+        *reserve_out = reserve_out
+            .checked_sub(amount_out)
+            .ok_or(error!(CustomError::InsufficientPoolReserves))?;
+        *volume_in = volume_in.saturating_add(amount_in);
+        Ok((self.reserve_a, self.reserve_b))
+    }
+    /// Count a swap fee against the input side. Accounting only; reserves are untouched.
+    pub fn record_swap_fee(&mut self, fee_amount: u64, is_a_to_b: bool) {
+        if is_a_to_b {
+            self.cumulative_fees_a = self.cumulative_fees_a.saturating_add(fee_amount);
+        } else {
+            self.cumulative_fees_b = self.cumulative_fees_b.saturating_add(fee_amount);
+        }
+    }
+    /// Deposit `amount_in` of one token, already in (or headed for) its vault:
+    /// swap the optimal share for the other token in place, then add both as
+    /// liquidity.  Updates reserves, volume, fees and LP supply; the caller
+    /// mints `lp_tokens`.  x * y = k pools only.  The internal swap pays the
+    /// same `swap_fee_numerator` an ordinary swap of its size would.
+    pub fn apply_single_sided_deposit(&mut self, amount_in: u64, is_token_a: bool, now: i64) -> Result<SingleSidedDeposit> {
+        require!(self.total_lp_supply > 0, CustomError::InsufficientLiquidity);
+        let (reserve_in, reserve_out) = if is_token_a {
+            (self.reserve_a, self.reserve_b)
+        } else {
+            (self.reserve_b, self.reserve_a)
+        };
+        let flat_swap_amount = calculate_single_sided_swap_amount(
+            amount_in,
+            reserve_in,
+            self.fee_numerator,
+            self.fee_denominator,
+        )?;
+        let fee_numerator = self.swap_fee_numerator(flat_swap_amount, is_token_a, now)?;
+        let swap_amount = calculate_single_sided_swap_amount(
+            amount_in,
+            reserve_in,
+            fee_numerator,
+            self.fee_denominator,
+        )?;
+        let swap_out = calculate_output_amount(
+            swap_amount,
+            reserve_in,
+            reserve_out,
+            fee_numerator,
+            self.fee_denominator,
+            None,
+        )?;
+        let fee_amount = calculate_swap_fee(swap_amount, fee_numerator, self.fee_denominator)?;
+        // Reserves after the internal swap; the swap output never leaves the vault
+        let swapped_in = reserve_in
+            .checked_add(swap_amount)
+            .ok_or(error!(CustomError::CalculationOverflow))?;
+        let swapped_out = reserve_out
+            .checked_sub(swap_out)
+            .ok_or(error!(CustomError::InsufficientPoolReserves))?;
+        let deposit_in = amount_in - swap_amount;
+        let (amount_a, amount_b, swapped_a, swapped_b) = if is_token_a {
+            (deposit_in, swap_out, swapped_in, swapped_out)
+        } else {
+            (swap_out, deposit_in, swapped_out, swapped_in)
+        };
+        let lp_tokens = calculate_lp_tokens_for_add_liquidity(
+            amount_a,
+            amount_b,
+            swapped_a,
+            swapped_b,
+            self.total_lp_supply,
+        )?;
+        self.reserve_a = swapped_a
+            .checked_add(amount_a)
+            .ok_or(error!(CustomError::CalculationOverflow))?;
+        self.reserve_b = swapped_b
+            .checked_add(amount_b)
+            .ok_or(error!(CustomError::CalculationOverflow))?;
+        if is_token_a {
+            self.total_volume_a = self.total_volume_a.saturating_add(swap_amount);
+        } else {
+            self.total_volume_b = self.total_volume_b.saturating_add(swap_amount);
+        }
+        self.record_swap_fee(fee_amount, is_token_a);
+        self.total_lp_supply = self
+            .total_lp_supply
+            .checked_add(lp_tokens)
+            .ok_or(error!(CustomError::CalculationOverflow))?;
+        Ok(SingleSidedDeposit {
+            swap_amount,
+            swap_out,
+            fee_amount,
+            amount_a,
+            amount_b,
+            swapped_a,
+            swapped_b,
+            lp_tokens,
+        })
+    }
+    /// The pool's vault for `mint`; `Pubkey::default()` (matching no token
+    /// account) for a mint the pool doesn't trade.
+    pub fn vault_for(&self, mint: &Pubkey) -> Pubkey {
+        if *mint == self.token_a_mint {
+            self.token_a_vault
+        } else if *mint == self.token_b_mint {
+            self.token_b_vault
+        } else {
+            Pubkey::default()
+        }
+    }
+    /// Fail unless the vaults hold at least the booked reserves.  Vaults may
+    /// hold more (donations not yet synced), never less.
+    pub fn require_backed_by_vaults(&self, vault_a: u64, vault_b: u64) -> Result<()> {
+        require!(
+            vault_a >= self.reserve_a && vault_b >= self.reserve_b,
+            CustomError::InsufficientPoolReserves
+        );
+        Ok(())
+    }
+    /// Reset reserves to the actual vault balances. Returns `(delta_a, delta_b)`.
+    pub fn sync_to_vaults(&mut self, vault_a: u64, vault_b: u64) -> (i128, i128) {
+        let delta_a = vault_a as i128 - self.reserve_a as i128;
+        let delta_b = vault_b as i128 - self.reserve_b as i128;
+        self.reserve_a = vault_a;
+        self.reserve_b = vault_b;
+        (delta_a, delta_b)
+    }
+    /// LP holders can redeem: the supply less the never-minted `locked_liquidity`.
+    pub fn redeemable_lp_supply(&self) -> u64 {
+        self.total_lp_supply.saturating_sub(self.locked_liquidity)
+    }
+    /// Reserves backing `locked_liquidity`, which stay in the pool for good.
+    pub fn locked_reserves(&self) -> Result<(u64, u64)> {
+        if self.locked_liquidity == 0 {
+            return Ok((0, 0));
+        }
+        calculate_remove_liquidity_amounts(self.locked_liquidity, self.total_lp_supply, self.reserve_a, self.reserve_b)
+    }
+    /// USD value (1e6 scale) of `amount_a` and `amount_b` at USD prices per whole
+    /// token (1e6 scale).  A missing price is implied from the other one through
+    /// the spot price; `None` when neither is known.
+    pub fn usd_value(&self, amount_a: u64, amount_b: u64, price_a: Option<u64>, price_b: Option<u64>) -> Option<u64> {
+        let value = |amount: u128, price: u64, decimals: u8| {
+            amount.saturating_mul(price as u128) / 10u128.pow(decimals as u32)
+        };
+        let in_other = |amount: u64, reserve_from: u64, reserve_to: u64| {
+            if reserve_from == 0 {
+                0
+            } else {
+                (amount as u128) * (reserve_to as u128) / (reserve_from as u128)
+            }
+        };
+        let usd = match (price_a, price_b) {
+            (Some(price_a), Some(price_b)) => {
+                value(amount_a as u128, price_a, self.token_a_decimals)
+                    .saturating_add(value(amount_b as u128, price_b, self.token_b_decimals))
+            }
+            (Some(price_a), None) => {
+                let total_a = amount_a as u128 + in_other(amount_b, self.reserve_b, self.reserve_a);
+                value(total_a, price_a, self.token_a_decimals)
+            }
+            (None, Some(price_b)) => {
+                let total_b = amount_b as u128 + in_other(amount_a, self.reserve_a, self.reserve_b);
+                value(total_b, price_b, self.token_b_decimals)
+            }
+            (None, None) => return None,
+        };
+        Some(u64::try_from(usd).unwrap_or(u64::MAX))
+    }
+    /// Naive USD value with both tokens counted at $1, for when no price is known.
+    pub fn naive_usd_value(&self, amount_a: u64, amount_b: u64) -> u64 {
+        self.usd_value(amount_a, amount_b, Some(1_000_000), Some(1_000_000)).unwrap_or(0)
+    }
+    /// Share of the cumulative fees attributable to `lp_tokens` at the current supply.
+    pub fn fee_share(&self, lp_tokens: u64) -> (u64, u64) {
+        if self.total_lp_supply == 0 {
+            return (0, 0);
+        }
+        let share = |fees: u64| {
+            ((fees as u128) * (lp_tokens as u128) / (self.total_lp_supply as u128)) as u64
+        };
+        (share(self.cumulative_fees_a), share(self.cumulative_fees_b))
+    }
     pub fn get_price_a_to_b(&self) -> u64 {
         if self.reserve_a == 0 {
             return 0;
@@ -48,4 +473,687 @@ impl LiquidityPool {
     pub fn get_fee_percentage(&self) -> f64 {
         (self.fee_numerator as f64) / (self.fee_denominator as f64) * 100.0
     }
+    pub fn lock(&mut self) -> Result<()> {
+        require!(!self.locked, CustomError::PoolLocked);
+        self.locked = true;
+        Ok(())
+    }
+    pub fn unlock(&mut self) {
+        self.locked = false;
+    }
+    pub fn require_active(&self) -> Result<()> {
+        require!(!self.is_paused, CustomError::PoolPaused);
+        Ok(())
+    }
+    /// Both reserves non-zero, so the pool has a spot price.
+    pub fn require_funded(&self) -> Result<()> {
+        require!(self.reserve_a > 0 && self.reserve_b > 0, CustomError::PoolNotFunded);
+        Ok(())
+    }
+    pub fn require_paused(&self) -> Result<()> {
+        require!(self.is_paused, CustomError::PoolNotPaused);
+        Ok(())
+    }
+    /// RUSH accumulator once `emitted` more is spread over `boosted_supply`.
+    pub fn acc_reward_per_share_after(&self, emitted: u128) -> Result<u128> {
+        let boosted_supply = self.boosted_supply();
+        if emitted == 0 || boosted_supply == 0 {
+            return Ok(self.acc_reward_per_share);
+        }
+        let increment = emitted
+            .checked_mul(ACC_REWARD_PRECISION)
+            .ok_or(error!(CustomError::CalculationOverflow))?
+            / boosted_supply;
+        self.acc_reward_per_share
+            .checked_add(increment)
+            .ok_or(error!(CustomError::CalculationOverflow))
+    }
+    /// Shares RUSH emission is split over: the LP supply plus the boost
+    /// shares, so a boost reweights the pool's emission instead of adding to it.
+    pub fn boosted_supply(&self) -> u128 {
+        self.total_lp_supply as u128 + self.boost_shares as u128
+    }
+    fn emission_since_last_accrual(&self, rewards_per_second: u64, now: i64) -> Result<u128> {
+        let elapsed = now.saturating_sub(self.last_reward_timestamp).max(0);
+        (rewards_per_second as u128)
+            .checked_mul(elapsed as u128)
+            .ok_or(error!(CustomError::CalculationOverflow))
+    }
+    /// Bring the RUSH and reward-program accumulators up to `now` at a flat
+    /// `rewards_per_second`. Must run before `total_lp_supply` or `boost_shares` change.
+    pub fn accrue_rewards(&mut self, rewards_per_second: u64, now: i64) -> Result<()> {
+        let emitted = self.emission_since_last_accrual(rewards_per_second, now)?;
+        self.accrue_emission(emitted, now)
+    }
+    /// Spread `emitted` RUSH, everything the pool earned since its last
+    /// accrual, and bring the reward programs up to `now`.
+    pub fn accrue_emission(&mut self, emitted: u128, now: i64) -> Result<()> {
+        self.acc_reward_per_share = self.acc_reward_per_share_after(emitted)?;
+        self.last_reward_timestamp = now.max(self.last_reward_timestamp);
+        self.accrue_reward_programs(now)
+    }
+    /// Bring only the reward-program accumulators up to `now`.
+    pub fn accrue_reward_programs(&mut self, now: i64) -> Result<()> {
+        let total_lp_supply = self.total_lp_supply;
+        for program in self.reward_programs.iter_mut().filter(|program| program.is_active()) {
+            program.acc_reward_per_share = program.projected_acc_reward_per_share(total_lp_supply, now)?;
+            program.last_update_ts = now.max(program.last_update_ts);
+        }
+        Ok(())
+    }
+    pub fn reward_program_index(&self, reward_mint: &Pubkey) -> Option<usize> {
+        self.reward_programs
+            .iter()
+            .position(|program| program.is_active() && program.reward_mint == *reward_mint)
+    }
+    /// Start emitting `reward_mint` at `rewards_per_second` for `duration_secs`.
+    /// A mint keeps its slot for good, so positions' debts stay meaningful; it can
+    /// be restarted once its previous run has ended. Returns the slot.
+    pub fn start_reward_program(
+        &mut self,
+        reward_mint: Pubkey,
+        reward_vault: Pubkey,
+        rewards_per_second: u64,
+        duration_secs: i64,
+        now: i64,
+    ) -> Result<usize> {
+        require!(rewards_per_second > 0 && duration_secs > 0, CustomError::InvalidAmount);
+        self.accrue_reward_programs(now)?;
+        let index = match self.reward_program_index(&reward_mint) {
+            Some(index) => {
+                require!(now >= self.reward_programs[index].ends_at, CustomError::RewardProgramActive);
+                index
+            }
+            None => self
+                .reward_programs
+                .iter()
+                .position(|program| !program.is_active())
+                .ok_or(error!(CustomError::RewardProgramsFull))?,
+        };
+        let program = &mut self.reward_programs[index];
+        program.reward_mint = reward_mint;
+        program.reward_vault = reward_vault;
+        program.rewards_per_second = rewards_per_second;
+        program.ends_at = now
+            .checked_add(duration_secs)
+            .ok_or(error!(CustomError::CalculationOverflow))?;
+        program.last_update_ts = now;
+        Ok(index)
+    }
+    /// Price accumulators as of `now`, without mutating the pool.
+    pub fn projected_price_cumulatives(&self, now: i64) -> (u128, u128) {
+        let elapsed = now.saturating_sub(self.last_price_update_ts);
+        if elapsed <= 0 || self.reserve_a == 0 || self.reserve_b == 0 {
+            return (self.price_cumulative_a, self.price_cumulative_b);
+        }
+        let price_a = (self.reserve_b as u128) * TWAP_PRICE_PRECISION / (self.reserve_a as u128);
+        let price_b = (self.reserve_a as u128) * TWAP_PRICE_PRECISION / (self.reserve_b as u128);
+        // Wrapping, as in Uniswap V2: only differences between snapshots are meaningful
+        (
+            self.price_cumulative_a.wrapping_add(price_a.wrapping_mul(elapsed as u128)),
+            self.price_cumulative_b.wrapping_add(price_b.wrapping_mul(elapsed as u128)),
+        )
+    }
+    /// Accumulate the pre-trade price up to `now`. Must run before reserves change.
+    pub fn update_price_cumulatives(&mut self, now: i64) {
+        let (cumulative_a, cumulative_b) = self.projected_price_cumulatives(now);
+        self.price_cumulative_a = cumulative_a;
+        self.price_cumulative_b = cumulative_b;
+        self.last_price_update_ts = now.max(self.last_price_update_ts);
+        let latest = self.observations[self.observation_index as usize];
+        if now.saturating_sub(latest.timestamp) >= TWAP_OBSERVATION_INTERVAL_SECS {
+            self.observation_index = ((self.observation_index as usize + 1) % TWAP_OBSERVATION_SLOTS) as u8;
+            self.observations[self.observation_index as usize] = PriceObservation {
+                timestamp: now,
+                price_cumulative_a: cumulative_a,
+                price_cumulative_b: cumulative_b,
+                cumulative_fees_a: self.cumulative_fees_a,
+                cumulative_fees_b: self.cumulative_fees_b,
+            };
+        }
+    }
+    /// Reset the accumulators and seed the first snapshot at pool creation.
+    pub fn init_price_observations(&mut self, now: i64) {
+        self.price_cumulative_a = 0;
+        self.price_cumulative_b = 0;
+        self.last_price_update_ts = now;
+        self.observations = [PriceObservation::default(); TWAP_OBSERVATION_SLOTS];
+        self.observations[0].timestamp = now;
+        self.observation_index = 0;
+    }
+    /// Newest snapshot at least `window_secs` old.
+    fn observation_before(&self, window_secs: i64, now: i64) -> Result<&PriceObservation> {
+        require!(window_secs > 0, CustomError::InvalidAmount);
+        self.observations
+            .iter()
+            .filter(|obs| obs.timestamp > 0 && now.saturating_sub(obs.timestamp) >= window_secs)
+            .max_by_key(|obs| obs.timestamp)
+            .ok_or(error!(CustomError::TwapWindowUnavailable))
+    }
+    /// Swap fees over at least `window_secs`, annualized against current TVL, in bps.
+    /// Both fees and TVL are valued in token B at the TWAP over the same window.
+    /// Returns `(fee_apr_bps, actual_window_secs)`.
+    pub fn fee_apr_bps(&self, window_secs: i64, now: i64) -> Result<(u64, i64)> {
+        let start = *self.observation_before(window_secs, now)?;
+        let (price_a, _, elapsed) = self.twap(window_secs, now)?;
+        let fees_a = self.cumulative_fees_a.saturating_sub(start.cumulative_fees_a) as u128;
+        let fees_b = self.cumulative_fees_b.saturating_sub(start.cumulative_fees_b) as u128;
+        let fee_value = fees_b + fees_a * price_a / TWAP_PRICE_PRECISION;
+        let tvl = self.reserve_b as u128 + (self.reserve_a as u128) * price_a / TWAP_PRICE_PRECISION;
+        if tvl == 0 {
+            return Ok((0, elapsed));
+        }
+        let apr = fee_value
+            .checked_mul(RushConfig::SECONDS_PER_YEAR as u128 * 10_000)
+            .ok_or(error!(CustomError::CalculationOverflow))?
+            / (tvl * elapsed as u128);
+        Ok((u64::try_from(apr).unwrap_or(u64::MAX), elapsed))
+    }
+    /// Distance of the spot `price_a` from its TWAP over `window_secs`, in bps of the TWAP.
+    pub fn twap_deviation_bps(&self, window_secs: i64, now: i64) -> Result<u64> {
+        self.require_funded()?;
+        let (twap_a, _, _) = self.twap(window_secs, now)?;
+        require!(twap_a > 0, CustomError::TwapWindowUnavailable);
+        let spot_a = (self.reserve_b as u128) * TWAP_PRICE_PRECISION / (self.reserve_a as u128);
+        let deviation = spot_a.abs_diff(twap_a) * 10_000 / twap_a;
+        Ok(u64::try_from(deviation).unwrap_or(u64::MAX))
+    }
+    /// TWAP of token A in token B over at least `window_secs`, scaled by 1e6
+    /// like `calculate_pool_price`.
+    pub fn twap_price_a(&self, window_secs: i64, now: i64) -> Result<u64> {
+        let (price_a, _, _) = self.twap(window_secs, now)?;
+        u64::try_from(price_a / (TWAP_PRICE_PRECISION / 1_000_000))
+            .map_err(|_| error!(CustomError::CalculationOverflow))
+    }
+    /// Reward multiplier (bps, 10_000 = 1x) for a deposit `deviation_bps` off the TWAP.
+    /// Falls linearly from `10_000 + alignment_boost_bps` at zero deviation to 1x at the band edge.
+    pub fn alignment_multiplier_bps(&self, deviation_bps: u64) -> u16 {
+        let band = self.alignment_band_bps as u64;
+        if self.alignment_boost_bps == 0 || deviation_bps >= band {
+            return 10_000;
+        }
+        let boost = (self.alignment_boost_bps as u64) * (band - deviation_bps) / band;
+        10_000 + boost as u16
+    }
+    /// `(deviation_bps, multiplier_bps)` to snapshot on a deposit made now, or
+    /// `None` while the boost is off or the pool has too little price history.
+    pub fn deposit_alignment(&self, now: i64) -> Option<(u64, u16)> {
+        if self.alignment_boost_bps == 0 {
+            return None;
+        }
+        let deviation = self.twap_deviation_bps(ALIGNMENT_TWAP_WINDOW_SECS, now).ok()?;
+        Some((deviation, self.alignment_multiplier_bps(deviation)))
+    }
+    /// Time-weighted prices over at least `window_secs`, measured from the newest
+    /// snapshot that old. Returns `(price_a, price_b, actual_window_secs)`.
+    pub fn twap(&self, window_secs: i64, now: i64) -> Result<(u128, u128, i64)> {
+        let start = self.observation_before(window_secs, now)?;
+        let elapsed = now - start.timestamp;
+        let (cumulative_a, cumulative_b) = self.projected_price_cumulatives(now);
+        Ok((
+            cumulative_a.wrapping_sub(start.price_cumulative_a) / (elapsed as u128),
+            cumulative_b.wrapping_sub(start.price_cumulative_b) / (elapsed as u128),
+            elapsed,
+        ))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::state::UserLiquidityPosition;
+    use crate::state::user_position::tests::make_position;
+
+    pub(crate) fn make_pool(reserve_a: u64, reserve_b: u64) -> LiquidityPool {
+        LiquidityPool {
+            authority: Pubkey::default(),
+            token_a_mint: Pubkey::default(),
+            token_b_mint: Pubkey::default(),
+            token_a_vault: Pubkey::default(),
+            token_b_vault: Pubkey::default(),
+            lp_token_mint: Pubkey::default(),
+            reserve_a,
+            reserve_b,
+            total_lp_supply: 0,
+            fee_numerator: 3,
+            fee_denominator: 1000,
+            token_a_decimals: 6,
+            token_b_decimals: 6,
+            is_stablecoin_pool: false,
+            created_at: 0,
+            total_volume_a: 0,
+            total_volume_b: 0,
+            locked_liquidity: 0,
+            bump: 0,
+            locked: false,
+            buy_premium_bps: 0,
+            sell_discount_bps: 0,
+            acc_reward_per_share: 0,
+            last_reward_timestamp: 0,
+            amplification_coefficient: 0,
+            protocol_fee_numerator: 0,
+            protocol_fee_vault: Pubkey::default(),
+            is_paused: false,
+            flash_fee_bps: 0,
+            price_cumulative_a: 0,
+            price_cumulative_b: 0,
+            last_price_update_ts: 0,
+            observations: [PriceObservation::default(); TWAP_OBSERVATION_SLOTS],
+            observation_index: 0,
+            cumulative_fees_a: 0,
+            cumulative_fees_b: 0,
+            alignment_boost_bps: 0,
+            alignment_band_bps: 0,
+            dynamic_fee_bps: 0,
+            reward_programs: [RewardProgram::default(); MAX_REWARD_PROGRAMS],
+            reward_weight_bps: 0,
+            boost_shares: 0,
+            dynamic_fee_min_bps: 0,
+            dynamic_fee_max_bps: 0,
+            rush_emission_index: 0,
+        }
+    }
+
+    #[test]
+    fn test_locked_liquidity_is_never_redeemable() {
+        use crate::constants::MINIMUM_LIQUIDITY;
+        use crate::utils::calculate_lp_tokens;
+        // First deposit: MINIMUM_LIQUIDITY of the geometric mean is locked, the rest minted
+        let mut pool = make_pool(1_000_000, 4_000_000);
+        let minted = calculate_lp_tokens(1_000_000, 4_000_000).unwrap() - MINIMUM_LIQUIDITY;
+        pool.locked_liquidity = MINIMUM_LIQUIDITY;
+        pool.total_lp_supply = minted + MINIMUM_LIQUIDITY;
+        assert_eq!(pool.redeemable_lp_supply(), minted);
+        assert_eq!(pool.locked_reserves().unwrap(), (500, 2_000));
+
+        // Burning every minted LP token leaves exactly the locked share behind
+        let (out_a, out_b) =
+            calculate_remove_liquidity_amounts(minted, pool.total_lp_supply, pool.reserve_a, pool.reserve_b).unwrap();
+        assert_eq!((pool.reserve_a - out_a, pool.reserve_b - out_b), (500, 2_000));
+        pool.reserve_a -= out_a;
+        pool.reserve_b -= out_b;
+        pool.total_lp_supply -= minted;
+        assert_eq!(pool.redeemable_lp_supply(), 0);
+        assert_eq!(pool.locked_reserves().unwrap(), (500, 2_000));
+    }
+
+    #[test]
+    fn test_usd_value_uses_real_prices() {
+        // 10 SOL (9 decimals) and 1_500 USDC (6 decimals) in a pool at $150 per SOL
+        let mut pool = make_pool(1_000_000_000_000, 150_000_000_000);
+        pool.token_a_decimals = 9;
+        let (sol, usdc) = (10_000_000_000, 1_500_000_000);
+        // $3_000, where the 1:1 sum says $1_510
+        assert_eq!(pool.usd_value(sol, usdc, Some(150_000_000), Some(1_000_000)), Some(3_000_000_000));
+        assert_eq!(pool.naive_usd_value(sol, usdc), 1_510_000_000);
+        // One known price values the other token through the pool
+        assert_eq!(pool.usd_value(sol, usdc, None, Some(1_000_000)), Some(3_000_000_000));
+        assert_eq!(pool.usd_value(sol, usdc, Some(150_000_000), None), Some(3_000_000_000));
+        assert_eq!(pool.usd_value(sol, usdc, None, None), None);
+    }
+
+    #[test]
+    fn test_usd_value_saturates_instead_of_overflowing() {
+        let mut pool = make_pool(1, u64::MAX);
+        pool.token_a_decimals = 0;
+        pool.token_b_decimals = 0;
+        let max = Some(u64::MAX);
+        assert_eq!(pool.usd_value(u64::MAX, u64::MAX, max, max), Some(u64::MAX));
+        // Token B converted through a 1:u64::MAX pool before pricing
+        assert_eq!(pool.usd_value(0, u64::MAX, max, None), Some(u64::MAX));
+    }
+
+    #[test]
+    fn test_lock_then_unlock() {
+        let mut pool = make_pool(1_000, 1_000);
+        assert!(pool.lock().is_ok());
+        assert!(pool.locked);
+        pool.unlock();
+        assert!(!pool.locked);
+        assert!(pool.lock().is_ok());
+    }
+
+    #[test]
+    fn test_nested_lock_rejected() {
+        // A re-entrant call sees the lock held by the outer instruction.
+        let mut pool = make_pool(1_000, 1_000);
+        pool.lock().unwrap();
+        let err = pool.lock().unwrap_err();
+        assert_eq!(err, error!(CustomError::PoolLocked));
+        assert!(pool.locked);
+    }
+
+    #[test]
+    fn test_emergency_exit_requires_pause() {
+        let mut pool = make_pool(1_000, 1_000);
+        assert_eq!(pool.require_paused().unwrap_err(), error!(CustomError::PoolNotPaused));
+        assert!(pool.require_active().is_ok());
+        pool.is_paused = true;
+        assert!(pool.require_paused().is_ok());
+        assert_eq!(pool.require_active().unwrap_err(), error!(CustomError::PoolPaused));
+    }
+
+    #[test]
+    fn test_accumulator_preserves_small_holder_rewards() {
+        // 1 LP out of 1e15: the legacy 1e12 share truncates to zero
+        let (user_lp, total_lp, rps, elapsed) = (1u64, 1_000_000_000_000_000u64, 1_000_000_000u64, 1_000_000u64);
+        let legacy_share = (user_lp as u128) * 1_000_000_000_000 / (total_lp as u128);
+        let legacy = (rps as u128) * (elapsed as u128) * legacy_share / 1_000_000_000_000;
+        assert_eq!(legacy, 0);
+
+        let mut pool = make_pool(0, 0);
+        pool.total_lp_supply = total_lp;
+        let position = make_position(user_lp);
+        pool.accrue_rewards(rps, elapsed as i64).unwrap();
+        assert_eq!(position.accrued_rewards(pool.acc_reward_per_share, pool.last_reward_timestamp).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_emissions_conserved_as_lp_supply_changes() {
+        let rps = 1_000u64;
+        let mut pool = make_pool(0, 0);
+        let mut positions = [make_position(0), make_position(0), make_position(0)];
+        // (time, position, lp change) in the order add/remove liquidity would apply them
+        let steps: [(i64, usize, i64); 5] = [
+            (0, 0, 1_000),
+            (100, 1, 3_000),
+            (250, 0, -1_000),
+            (300, 2, 500),
+            (330, 1, -1_200),
+        ];
+        for (now, who, delta) in steps {
+            pool.accrue_rewards(rps, now).unwrap();
+            let position = &mut positions[who];
+            position.settle_rewards(pool.acc_reward_per_share, pool.last_reward_timestamp).unwrap();
+            position.lp_tokens = position.lp_tokens.checked_add_signed(delta).unwrap();
+            position.sync_reward_debt(pool.acc_reward_per_share, pool.last_reward_timestamp).unwrap();
+            pool.total_lp_supply = pool.total_lp_supply.checked_add_signed(delta).unwrap();
+        }
+        pool.accrue_rewards(rps, 400).unwrap();
+
+        // The first LP alone for 100s, then a quarter of the pool until leaving
+        assert_eq!(positions[0].accrued_rewards(pool.acc_reward_per_share, pool.last_reward_timestamp).unwrap(), 100_000 + 37_500);
+        // Every second of emission went to someone, less per-settlement rounding
+        let paid: u64 = positions
+            .iter()
+            .map(|position| position.accrued_rewards(pool.acc_reward_per_share, pool.last_reward_timestamp).unwrap())
+            .sum();
+        let emitted = rps * 400;
+        assert!(paid <= emitted && emitted - paid <= steps.len() as u64, "paid {paid} of {emitted}");
+    }
+
+    fn earned(position: &UserLiquidityPosition, pool: &LiquidityPool, index: usize) -> u64 {
+        position.accrued_program_rewards(index, &pool.reward_programs[index]).unwrap()
+    }
+
+    #[test]
+    fn test_two_reward_programs_accrue_at_their_own_rates() {
+        let (partner, other) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut pool = make_pool(0, 0);
+        let mut first = make_position(0);
+        first.deposit_lp_tokens(1_000, &mut pool, None).unwrap();
+        pool.total_lp_supply = 1_000;
+        // 10/s of one token for 1000s from t=0, 3/s of another for 200s from t=100
+        assert_eq!(pool.start_reward_program(partner, Pubkey::new_unique(), 10, 1_000, 0).unwrap(), 0);
+        pool.accrue_rewards(0, 100).unwrap();
+        assert_eq!(pool.start_reward_program(other, Pubkey::new_unique(), 3, 200, 100).unwrap(), 1);
+        assert_eq!(
+            pool.start_reward_program(Pubkey::new_unique(), Pubkey::new_unique(), 1, 1, 100).unwrap_err(),
+            error!(CustomError::RewardProgramsFull)
+        );
+
+        // A second LP triples the supply at t=200
+        pool.accrue_rewards(0, 200).unwrap();
+        let mut second = make_position(0);
+        second.deposit_lp_tokens(2_000, &mut pool, None).unwrap();
+        pool.total_lp_supply = 3_000;
+
+        pool.accrue_rewards(0, 400).unwrap();
+        assert_eq!(earned(&first, &pool, 0), 2_000 + 2_000 / 3);
+        assert_eq!(earned(&second, &pool, 0), 4_000 / 3);
+        // The second program ended at t=300: 300 alone, then 300 split 1:2
+        assert_eq!(earned(&first, &pool, 1), 300 + 100);
+        assert_eq!(earned(&second, &pool, 1), 200);
+
+        // Restarting the ended program keeps its slot and accumulator
+        let acc_before = pool.reward_programs[1].acc_reward_per_share;
+        assert_eq!(pool.start_reward_program(other, Pubkey::new_unique(), 6, 100, 400).unwrap(), 1);
+        assert_eq!(pool.reward_programs[1].acc_reward_per_share, acc_before);
+        assert_eq!(
+            pool.start_reward_program(partner, Pubkey::new_unique(), 1, 1, 400).unwrap_err(),
+            error!(CustomError::RewardProgramActive)
+        );
+        pool.accrue_rewards(0, 500).unwrap();
+        assert_eq!(earned(&second, &pool, 1), 200 + 400);
+        // Withdrawing settles every program into pending
+        first.withdraw_lp_tokens(1_000, &mut pool).unwrap();
+        // A third of 3_000 over the shared 300s, less a unit of accumulator rounding
+        assert_eq!(first.program_rewards[0].pending_rewards, 2_000 + 1_000 - 1);
+        assert_eq!(first.program_rewards[1].pending_rewards, 400 + 200);
+    }
+
+    #[test]
+    fn test_twap_weights_prices_by_time() {
+        let mut pool = make_pool(1_000, 2_000);
+        pool.init_price_observations(1_000);
+        // Price 2.0 for 600s, then 4.0 for 600s
+        pool.update_price_cumulatives(1_600);
+        pool.reserve_b = 4_000;
+        let (price_a, price_b, window) = pool.twap(1_200, 2_200).unwrap();
+        assert_eq!(window, 1_200);
+        assert_eq!(price_a, 3 * TWAP_PRICE_PRECISION);
+        assert_eq!(price_b, (TWAP_PRICE_PRECISION / 2 + TWAP_PRICE_PRECISION / 4) / 2);
+    }
+
+    #[test]
+    fn test_twap_resists_single_block_manipulation() {
+        let mut pool = make_pool(1_000_000, 1_000_000);
+        pool.init_price_observations(1_000);
+        pool.update_price_cumulatives(4_600);
+        // Attacker skews reserves 100x; the swap lands in the same second as the read
+        pool.update_price_cumulatives(4_600);
+        pool.reserve_b = 100_000_000;
+        let (price_a, _, _) = pool.twap(3_600, 4_600).unwrap();
+        assert_eq!(price_a, TWAP_PRICE_PRECISION);
+    }
+
+    #[test]
+    fn test_twap_requires_history() {
+        let mut pool = make_pool(1_000, 1_000);
+        pool.init_price_observations(1_000);
+        assert_eq!(pool.twap(600, 1_300).unwrap_err(), error!(CustomError::TwapWindowUnavailable));
+        assert_eq!(pool.twap(0, 1_300).unwrap_err(), error!(CustomError::InvalidAmount));
+    }
+
+    #[test]
+    fn test_observations_spaced_by_interval() {
+        let mut pool = make_pool(1_000, 1_000);
+        pool.init_price_observations(1_000);
+        pool.update_price_cumulatives(1_000 + TWAP_OBSERVATION_INTERVAL_SECS - 1);
+        assert_eq!(pool.observation_index, 0);
+        pool.update_price_cumulatives(1_000 + TWAP_OBSERVATION_INTERVAL_SECS);
+        assert_eq!(pool.observation_index, 1);
+        assert_eq!(pool.observations[1].timestamp, 1_000 + TWAP_OBSERVATION_INTERVAL_SECS);
+    }
+
+    #[test]
+    fn test_fee_token_follows_input_side() {
+        let mut pool = make_pool(1_000, 1_000);
+        pool.token_a_mint = Pubkey::new_unique();
+        pool.token_b_mint = Pubkey::new_unique();
+        // market_sell / swap A→B charge in A; market_buy / swap B→A charge in B
+        assert_eq!(pool.input_mint(true), pool.token_a_mint);
+        assert_eq!(pool.input_mint(false), pool.token_b_mint);
+    }
+
+    #[test]
+    fn test_swap_fees_counted_per_side() {
+        let mut pool = make_pool(1_000, 1_000);
+        pool.record_swap_fee(30, true);
+        pool.record_swap_fee(12, false);
+        pool.record_swap_fee(5, true);
+        assert_eq!(pool.cumulative_fees_a, 35);
+        assert_eq!(pool.cumulative_fees_b, 12);
+        assert_eq!((pool.reserve_a, pool.reserve_b), (1_000, 1_000));
+    }
+
+    #[test]
+    fn test_fee_share_follows_lp_share() {
+        let mut pool = make_pool(1_000, 1_000);
+        assert_eq!(pool.fee_share(100), (0, 0));
+        pool.total_lp_supply = 1_000;
+        pool.cumulative_fees_a = 400;
+        pool.cumulative_fees_b = 90;
+        assert_eq!(pool.fee_share(250), (100, 22));
+        assert_eq!(pool.fee_share(1_000), (400, 90));
+    }
+
+    #[test]
+    fn test_fee_apr_responds_to_volume() {
+        let mut quiet = make_pool(1_000_000, 1_000_000);
+        quiet.init_price_observations(1_000);
+        let mut busy = make_pool(1_000_000, 1_000_000);
+        busy.init_price_observations(1_000);
+        for pool in [&mut quiet, &mut busy] {
+            pool.update_price_cumulatives(1_000 + TWAP_OBSERVATION_INTERVAL_SECS);
+        }
+        quiet.record_swap_fee(100, true);
+        busy.record_swap_fee(100, true);
+        busy.record_swap_fee(300, false);
+        let now = 1_000 + 2 * TWAP_OBSERVATION_INTERVAL_SECS;
+        let (quiet_apr, window) = quiet.fee_apr_bps(TWAP_OBSERVATION_INTERVAL_SECS, now).unwrap();
+        let (busy_apr, _) = busy.fee_apr_bps(TWAP_OBSERVATION_INTERVAL_SECS, now).unwrap();
+        assert_eq!(window, TWAP_OBSERVATION_INTERVAL_SECS);
+        // 100 of 2_000_000 TVL over 300s: 100 × 31_536_000 × 10_000 / (2_000_000 × 300)
+        assert_eq!(quiet_apr, 52_560);
+        assert_eq!(busy_apr, 4 * quiet_apr);
+    }
+
+    #[test]
+    fn test_fee_apr_ignores_fees_before_window() {
+        let mut pool = make_pool(1_000_000, 1_000_000);
+        pool.init_price_observations(1_000);
+        pool.record_swap_fee(500, true);
+        pool.update_price_cumulatives(1_000 + TWAP_OBSERVATION_INTERVAL_SECS);
+        let now = 1_000 + 2 * TWAP_OBSERVATION_INTERVAL_SECS;
+        assert_eq!(pool.fee_apr_bps(TWAP_OBSERVATION_INTERVAL_SECS, now).unwrap().0, 0);
+    }
+
+    #[test]
+    fn test_sync_picks_up_airdrop() {
+        let mut pool = make_pool(1_000, 2_000);
+        // 500 A sent straight to the vault, 3 B of rounding dust missing
+        assert_eq!(pool.sync_to_vaults(1_500, 1_997), (500, -3));
+        assert_eq!((pool.reserve_a, pool.reserve_b), (1_500, 1_997));
+        assert_eq!(pool.sync_to_vaults(1_500, 1_997), (0, 0));
+    }
+
+    #[test]
+    fn test_vault_for_maps_mints_to_their_vault() {
+        let mut pool = make_pool(1_000, 1_000);
+        pool.token_a_mint = Pubkey::new_unique();
+        pool.token_b_mint = Pubkey::new_unique();
+        pool.token_a_vault = Pubkey::new_unique();
+        pool.token_b_vault = Pubkey::new_unique();
+        assert_eq!(pool.vault_for(&pool.token_a_mint), pool.token_a_vault);
+        assert_eq!(pool.vault_for(&pool.token_b_mint), pool.token_b_vault);
+        assert_eq!(pool.vault_for(&Pubkey::new_unique()), Pubkey::default());
+    }
+
+    #[test]
+    fn test_reserves_must_be_backed_by_vaults() {
+        let pool = make_pool(1_000, 2_000);
+        assert!(pool.require_backed_by_vaults(1_000, 2_000).is_ok());
+        assert!(pool.require_backed_by_vaults(1_500, 2_000).is_ok());
+        assert_eq!(
+            pool.require_backed_by_vaults(1_000, 1_999).unwrap_err(),
+            error!(CustomError::InsufficientPoolReserves)
+        );
+    }
+
+    #[test]
+    fn test_unfunded_pool_rejected() {
+        assert_eq!(make_pool(0, 0).require_funded().unwrap_err(), error!(CustomError::PoolNotFunded));
+        assert_eq!(make_pool(1_000, 0).require_funded().unwrap_err(), error!(CustomError::PoolNotFunded));
+        assert!(make_pool(1_000, 1_000).require_funded().is_ok());
+    }
+
+    #[test]
+    fn test_aligned_deposit_earns_higher_boost() {
+        let mut aligned = make_pool(1_000_000, 1_000_000);
+        aligned.alignment_boost_bps = 5_000;
+        aligned.alignment_band_bps = 1_000;
+        aligned.init_price_observations(1_000);
+        aligned.update_price_cumulatives(1_000 + ALIGNMENT_TWAP_WINDOW_SECS);
+        let now = 1_000 + 2 * ALIGNMENT_TWAP_WINDOW_SECS;
+
+        let mut dislocated = make_pool(1_000_000, 1_000_000);
+        dislocated.alignment_boost_bps = 5_000;
+        dislocated.alignment_band_bps = 1_000;
+        dislocated.init_price_observations(1_000);
+        dislocated.update_price_cumulatives(1_000 + ALIGNMENT_TWAP_WINDOW_SECS);
+        // Spot pushed 5% above a TWAP that has not caught up yet
+        dislocated.update_price_cumulatives(now);
+        dislocated.reserve_b = 1_050_000;
+
+        let (aligned_dev, aligned_boost) = aligned.deposit_alignment(now).unwrap();
+        let (dislocated_dev, dislocated_boost) = dislocated.deposit_alignment(now).unwrap();
+        assert_eq!(aligned_dev, 0);
+        assert_eq!(aligned_boost, 15_000);
+        assert_eq!(dislocated_dev, 500);
+        assert_eq!(dislocated_boost, 12_500);
+        assert!(aligned_boost > dislocated_boost);
+
+        // Beyond the band there is no boost; with the boost off nothing is snapshotted
+        dislocated.reserve_b = 1_200_000;
+        assert_eq!(dislocated.deposit_alignment(now).unwrap().1, 10_000);
+        aligned.alignment_boost_bps = 0;
+        assert_eq!(aligned.deposit_alignment(now), None);
+    }
+
+    #[test]
+    fn test_dynamic_fee_follows_twap_within_band() {
+        let mut pool = make_pool(1_000_000, 1_000_000);
+        pool.fee_numerator = 30;
+        pool.fee_denominator = 10_000;
+        pool.dynamic_fee_bps = 2_500;
+        pool.dynamic_fee_min_bps = 10;
+        pool.dynamic_fee_max_bps = 100;
+        pool.init_price_observations(1_000);
+        // No TWAP yet: the flat fee
+        assert_eq!(pool.swap_fee_numerator(1_000, true, 1_000).unwrap(), 30);
+        pool.update_price_cumulatives(1_000 + DYNAMIC_FEE_TWAP_WINDOW_SECS);
+        let now = 1_000 + 2 * DYNAMIC_FEE_TWAP_WINDOW_SECS;
+        // Spot pushed 5% above a TWAP of 1.0: selling A pulls it back, buying A pushes on
+        pool.update_price_cumulatives(now);
+        pool.reserve_b = 1_050_000;
+        assert_eq!(pool.swap_fee_numerator(1_000, true, now).unwrap(), 23);
+        assert_eq!(pool.swap_fee_numerator(1_000, false, now).unwrap(), 37);
+        assert_eq!(pool.exact_out_fee_numerator(1_000, true, now).unwrap(), 23);
+
+        // Clamped to the band, and never under the protocol's cut
+        pool.dynamic_fee_min_bps = 25;
+        pool.dynamic_fee_max_bps = 35;
+        assert_eq!(pool.swap_fee_numerator(1_000, true, now).unwrap(), 25);
+        assert_eq!(pool.swap_fee_numerator(1_000, false, now).unwrap(), 35);
+        pool.protocol_fee_numerator = 28;
+        assert_eq!(pool.swap_fee_numerator(1_000, true, now).unwrap(), 28);
+    }
+
+    #[test]
+    fn test_single_sided_deposit_mints_value_share() {
+        let mut pool = make_pool(1_000_000_000, 1_000_000_000);
+        pool.total_lp_supply = 1_000_000_000;
+        let deposit = pool.apply_single_sided_deposit(10_000_000, true, 0).unwrap();
+        // The input is split so both legs land at the post-swap ratio
+        assert_eq!(deposit.amount_a + deposit.swap_amount, 10_000_000);
+        assert_eq!(deposit.lp_tokens, pool.total_lp_supply - 1_000_000_000);
+        assert_eq!(pool.reserve_a, 1_010_000_000);
+        assert_eq!(pool.reserve_b, 1_000_000_000);
+        // Half the deposit's value, less the swap fee on the swapped share
+        assert!(deposit.lp_tokens > 4_980_000 && deposit.lp_tokens < 5_000_000);
+
+        pool.total_lp_supply = 0;
+        assert!(pool.apply_single_sided_deposit(10_000_000, true, 0).is_err());
+    }
 }
@@ -1,4 +1,6 @@
 use anchor_lang::prelude::*;
+use crate::errors::CustomError;
+use crate::state::LiquidityPool;
 #[account]
 pub struct RushConfig {
     pub mint: Pubkey,
@@ -11,28 +13,265 @@ pub struct RushConfig {
     pub start_timestamp: i64,
     pub is_paused: bool,
     pub bump: u8,
+    pub min_claim_interval_secs: i64,
+    /// Pools with less LP supply than this accrue no RUSH (0 = no minimum).
+    pub min_lp_supply_for_rewards: u64,
+    /// `rewards_per_second` halves every this many seconds after
+    /// `start_timestamp` (0 = flat emission).
+    pub halving_interval_secs: i64,
+    /// Proposed by `transfer_rush_authority`; becomes `authority` once it
+    /// accepts.  `Pubkey::default()` when no handover is in progress.
+    pub pending_authority: Pubkey,
+    /// Sum of every pool's `reward_weight_bps`, at most 10_000.
+    pub total_pool_weight_bps: u16,
+    /// When the first pool weight was set.  Before it every pool accrues the
+    /// full emission rate, from it each its `reward_weight_bps` share;
+    /// `i64::MAX` while no weight has been set.
+    pub pool_weights_since: i64,
+    /// RUSH a full-rate pool has earned from `start_timestamp` up to
+    /// `emission_index_ts`.  Checkpointed whenever `rewards_per_second`
+    /// changes, so every second keeps the rate it was emitted at.
+    pub emission_index: u128,
+    pub emission_index_ts: i64,
+    /// Emission index at `pool_weights_since`; `u128::MAX` while no weight has been set.
+    pub pool_weights_index: u128,
+    /// When emission was last paused and resumed; nothing is emitted in between.
+    pub paused_at: i64,
+    pub resumed_at: i64,
 }
 impl RushConfig {
-    pub const SIZE: usize = 8 + 32*2 + 8*6 + 2;
+    pub const SIZE: usize = 8 + 32*2 + 8*6 + 2 + 8 + 8 + 8 + 32 + 2 + 8 + 16 + 8 + 16 + 8 + 8;
     pub const SECONDS_PER_YEAR: u64 = 31_536_000;
+    /// Per-second emission (base units) paying `apy_numerator / apy_denominator`
+    /// of `total_supply` (base units) per year. Shared by init and APY updates so
+    /// both round the same way.
+    pub fn rewards_per_second_for_apy(
+        total_supply: u64,
+        apy_numerator: u64,
+        apy_denominator: u64,
+    ) -> Result<u64> {
+        (total_supply as u128)
+            .checked_mul(apy_numerator as u128)
+            .ok_or(error!(CustomError::CalculationOverflow))?
+            .checked_div(apy_denominator as u128)
+            .ok_or(error!(CustomError::CalculationOverflow))?
+            .checked_div(Self::SECONDS_PER_YEAR as u128)
+            .ok_or(error!(CustomError::CalculationOverflow))?
+            .try_into()
+            .map_err(|_| error!(CustomError::CalculationOverflow))
+    }
     pub fn yearly_rewards(&self) -> u64 {
         (self.total_supply * self.apy_numerator) / self.apy_denominator
     }
     pub fn remaining_rewards(&self) -> u64 {
         self.total_supply.saturating_sub(self.minted_so_far)
     }
+    /// Part of `accrued` that can still be minted: clamped to the remaining
+    /// supply, so it winds down to 0 at exhaustion instead of failing.
+    pub fn claimable_rewards(&self, accrued: u64) -> u64 {
+        accrued.min(self.remaining_rewards())
+    }
+    /// First step of an authority handover; proposing again replaces the
+    /// pending key, proposing `Pubkey::default()` cancels it.
+    pub fn propose_authority(&mut self, caller: Pubkey, new_authority: Pubkey) -> Result<()> {
+        require_eq!(caller, self.authority, CustomError::InvalidAuthority);
+        self.pending_authority = new_authority;
+        Ok(())
+    }
+    /// Second step: the pending key takes over.  Returns the previous authority.
+    pub fn accept_authority(&mut self, caller: Pubkey) -> Result<Pubkey> {
+        require_neq!(self.pending_authority, Pubkey::default(), CustomError::InvalidAuthority);
+        require_eq!(caller, self.pending_authority, CustomError::InvalidAuthority);
+        let previous_authority = self.authority;
+        self.authority = caller;
+        self.pending_authority = Pubkey::default();
+        Ok(previous_authority)
+    }
+    /// `rewards_per_second` after the halvings completed by `now`.
+    pub fn current_emission_rate(&self, now: i64) -> u64 {
+        if self.halving_interval_secs <= 0 {
+            return self.rewards_per_second;
+        }
+        let halvings = now.saturating_sub(self.start_timestamp).max(0) / self.halving_interval_secs;
+        self.rewards_per_second.checked_shr(halvings.min(64) as u32).unwrap_or(0)
+    }
+    /// RUSH emitted in total between `from` and `to`, one segment per halving
+    /// epoch, so a window crossing a halving pays each side its own rate.
+    pub fn emission_between(&self, from: i64, to: i64) -> Result<u128> {
+        if to <= from {
+            return Ok(0);
+        }
+        if self.halving_interval_secs <= 0 {
+            return (self.rewards_per_second as u128)
+                .checked_mul((to - from) as u128)
+                .ok_or(error!(CustomError::CalculationOverflow));
+        }
+        let mut emitted: u128 = 0;
+        let mut segment_start = from;
+        // At most 65 rounds: the rate is 0 after 64 halvings
+        while segment_start < to {
+            let rate = self.current_emission_rate(segment_start);
+            if rate == 0 {
+                break;
+            }
+            let epoch = segment_start.saturating_sub(self.start_timestamp).max(0) / self.halving_interval_secs;
+            let epoch_end = self
+                .start_timestamp
+                .saturating_add((epoch + 1).saturating_mul(self.halving_interval_secs));
+            let segment_end = epoch_end.min(to);
+            emitted = (rate as u128)
+                .checked_mul((segment_end - segment_start) as u128)
+                .and_then(|segment| emitted.checked_add(segment))
+                .ok_or(error!(CustomError::CalculationOverflow))?;
+            segment_start = segment_end;
+        }
+        Ok(emitted)
+    }
+    /// Emission index at `now`: the checkpoint plus what the current rate has
+    /// emitted since, frozen while paused.  `now` must not precede `emission_index_ts`.
+    pub fn emission_index_at(&self, now: i64) -> Result<u128> {
+        if self.is_paused {
+            return Ok(self.emission_index);
+        }
+        self.emission_between(self.emission_index_ts, now)?
+            .checked_add(self.emission_index)
+            .ok_or(error!(CustomError::CalculationOverflow))
+    }
+    /// Fold the emission up to `now` into `emission_index`.
+    pub fn checkpoint_emission(&mut self, now: i64) -> Result<()> {
+        self.emission_index = self.emission_index_at(now)?;
+        self.emission_index_ts = self.emission_index_ts.max(now);
+        Ok(())
+    }
+    /// Switch to `rewards_per_second` from `now`; time before it, accrued
+    /// or not, keeps the old rate.
+    pub fn set_rewards_per_second(&mut self, rewards_per_second: u64, now: i64) -> Result<()> {
+        self.checkpoint_emission(now)?;
+        self.rewards_per_second = rewards_per_second;
+        Ok(())
+    }
+    /// Stop emission at `now`.  What pools earned up to it stays owed to them.
+    pub fn pause_emission(&mut self, now: i64) -> Result<()> {
+        self.checkpoint_emission(now)?;
+        self.is_paused = true;
+        self.paused_at = now;
+        Ok(())
+    }
+    /// Restart emission at `now`; the paused stretch emits nothing.
+    pub fn resume_emission(&mut self, now: i64) {
+        self.is_paused = false;
+        self.emission_index_ts = self.emission_index_ts.max(now);
+        self.resumed_at = now;
+    }
+    /// `pool`'s share of the emission between its last accrual and index
+    /// `index_now`, on the same terms as `pool_rewards_per_second`: a window
+    /// straddling the weight switch accrues the full rate up to it and the
+    /// pool's weight after.
+    fn pool_emission_to(&self, pool: &LiquidityPool, index_now: u128) -> u128 {
+        if pool.total_lp_supply < self.min_lp_supply_for_rewards {
+            return 0;
+        }
+        let from = pool.rush_emission_index;
+        let to = index_now.max(from);
+        let weighted_from = self.pool_weights_index.clamp(from, to);
+        (weighted_from - from) + (to - weighted_from) * (pool.reward_weight_bps as u128) / 10_000
+    }
+    /// Bring `pool` up to `now`, paying each second since its last accrual
+    /// the rate in force at that second.
+    pub fn accrue_pool(&self, pool: &mut LiquidityPool, now: i64) -> Result<()> {
+        let index_now = self.emission_index_at(now)?;
+        let emitted = self.pool_emission_to(pool, index_now);
+        pool.rush_emission_index = index_now.max(pool.rush_emission_index);
+        pool.accrue_emission(emitted, now)
+    }
+    /// `pool`'s RUSH accumulator as `accrue_pool` would leave it at `now`.
+    pub fn projected_acc_reward_per_share(&self, pool: &LiquidityPool, now: i64) -> Result<u128> {
+        let emitted = self.pool_emission_to(pool, self.emission_index_at(now)?);
+        pool.acc_reward_per_share_after(emitted)
+    }
+    /// Change the halving interval.  Only allowed while no halving has passed
+    /// under either the current or the new interval, so no pool's un-accrued
+    /// window is repriced.
+    pub fn set_halving_interval(&mut self, halving_interval_secs: i64, now: i64) -> Result<()> {
+        require!(halving_interval_secs >= 0, CustomError::InvalidAmount);
+        let halved_by_now = |interval: i64| interval > 0 && now >= self.start_timestamp.saturating_add(interval);
+        require!(
+            !halved_by_now(self.halving_interval_secs) && !halved_by_now(halving_interval_secs),
+            CustomError::HalvingScheduleStarted
+        );
+        self.halving_interval_secs = halving_interval_secs;
+        Ok(())
+    }
+    /// Emission rate for `pool`: its `reward_weight_bps` share of the
+    /// current rate once pools are weighted, and zero below
+    /// `min_lp_supply_for_rewards`, so a lone LP in a tiny pool cannot farm a
+    /// full pool's share of emissions.
+    pub fn pool_rewards_per_second(&self, pool: &LiquidityPool, now: i64) -> u64 {
+        if pool.total_lp_supply < self.min_lp_supply_for_rewards {
+            return 0;
+        }
+        let rate = self.current_emission_rate(now);
+        if now < self.pool_weights_since {
+            return rate;
+        }
+        ((rate as u128) * (pool.reward_weight_bps as u128) / 10_000) as u64
+    }
+    /// Give `pool` `weight_bps` of the emission from `now`, keeping the total
+    /// across pools within 10_000.  The first weight switches every pool to
+    /// weighted emission for good.  Returns the previous weight.
+    pub fn set_pool_weight(&mut self, pool: &mut LiquidityPool, weight_bps: u16, now: i64) -> Result<u16> {
+        let previous_weight_bps = pool.reward_weight_bps;
+        let total = (self.total_pool_weight_bps as u32) - (previous_weight_bps as u32) + (weight_bps as u32);
+        require!(total <= 10_000, CustomError::PoolWeightsExceeded);
+        if weight_bps > 0 && self.pool_weights_since == i64::MAX {
+            self.pool_weights_since = now;
+            self.pool_weights_index = self.emission_index_at(now)?;
+        }
+        self.total_pool_weight_bps = total as u16;
+        pool.reward_weight_bps = weight_bps;
+        Ok(previous_weight_bps)
+    }
     pub fn has_remaining_rewards(&self) -> bool {
         self.remaining_rewards() > 0
     }
+    pub fn claim_interval_elapsed(&self, time_elapsed: u64) -> bool {
+        time_elapsed >= self.min_claim_interval_secs.max(0) as u64
+    }
     pub fn is_active(&self) -> bool {
         !self.is_paused && self.has_remaining_rewards()
     }
+    /// Configured RUSH APY in bps; zero while emissions are paused or exhausted.
+    pub fn reward_apy_bps(&self) -> u64 {
+        if !self.is_active() || self.apy_denominator == 0 {
+            return 0;
+        }
+        ((self.apy_numerator as u128) * 10_000 / (self.apy_denominator as u128)) as u64
+    }
     pub fn distribution_percentage(&self) -> f64 {
         if self.total_supply == 0 {
             return 0.0;
         }
         (self.minted_so_far as f64) / (self.total_supply as f64) * 100.0
     }
+    /// RUSH `reward_shares` of `pool` earn over a year at the emission rate of `now`.
+    pub fn yearly_position_rewards(&self, reward_shares: u128, pool: &LiquidityPool, now: i64) -> u128 {
+        if pool.boosted_supply() == 0 || !self.is_active() {
+            return 0;
+        }
+        (self.pool_rewards_per_second(pool, now) as u128)
+            * (Self::SECONDS_PER_YEAR as u128)
+            * reward_shares
+            / pool.boosted_supply()
+    }
+    /// `yearly_rewards` RUSH (6 decimals) valued at `rush_price_usd` (1e6 scale)
+    /// over `position_value_usd` (1e6 scale), in bps.  Zero for a worthless position.
+    pub fn effective_apy_bps(yearly_rewards: u128, rush_price_usd: u64, position_value_usd: u64) -> u64 {
+        if position_value_usd == 0 {
+            return 0;
+        }
+        let rewards_usd = yearly_rewards * (rush_price_usd as u128) / 1_000_000;
+        u64::try_from(rewards_usd * 10_000 / (position_value_usd as u128)).unwrap_or(u64::MAX)
+    }
     pub fn calculate_rewards(
         &self,
         time_elapsed: u64,
@@ -49,3 +288,359 @@ impl RushConfig {
         final_rewards as u64
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::state::pool::tests::make_pool;
+    use crate::state::user_position::tests::make_position;
+
+    pub(crate) fn make_config(rewards_per_second: u64, min_claim_interval_secs: i64) -> RushConfig {
+        RushConfig {
+            mint: Pubkey::default(),
+            authority: Pubkey::default(),
+            total_supply: 1_000_000_000_000,
+            minted_so_far: 0,
+            rewards_per_second,
+            apy_numerator: 50,
+            apy_denominator: 100,
+            start_timestamp: 0,
+            is_paused: false,
+            bump: 0,
+            min_claim_interval_secs,
+            min_lp_supply_for_rewards: 0,
+            halving_interval_secs: 0,
+            pending_authority: Pubkey::default(),
+            total_pool_weight_bps: 0,
+            pool_weights_since: i64::MAX,
+            emission_index: 0,
+            emission_index_ts: 0,
+            pool_weights_index: u128::MAX,
+            paused_at: 0,
+            resumed_at: 0,
+        }
+    }
+
+    fn pool_with_supply(total_lp_supply: u64) -> LiquidityPool {
+        let mut pool = make_pool(0, 0);
+        pool.total_lp_supply = total_lp_supply;
+        pool
+    }
+
+    #[test]
+    fn test_effective_apy_values_rewards_at_the_rush_price() {
+        // 1 RUSH per second, a tenth of the pool's LP
+        let config = make_config(1_000_000, 0);
+        let yearly = config.yearly_position_rewards(100, &pool_with_supply(1_000), 0);
+        assert_eq!(yearly, 3_153_600_000_000);
+        // 3_153_600 RUSH at $0.01 on a $1_000_000 position: 3.1536%
+        assert_eq!(RushConfig::effective_apy_bps(yearly, 10_000, 1_000_000_000_000), 315);
+        // The same rewards on a position worth a tenth as much
+        assert_eq!(RushConfig::effective_apy_bps(yearly, 10_000, 100_000_000_000), 3_153);
+        assert_eq!(RushConfig::effective_apy_bps(yearly, 10_000, 0), 0);
+    }
+
+    #[test]
+    fn test_claim_too_soon_rejected() {
+        let config = make_config(1_000, 60);
+        assert!(!config.claim_interval_elapsed(0));
+        assert!(!config.claim_interval_elapsed(59));
+    }
+
+    #[test]
+    fn test_claim_after_interval_accrues_full_amount() {
+        let config = make_config(1_000, 60);
+        assert!(config.claim_interval_elapsed(60));
+        // Rewards keep accruing across the interval: 90s × 1000/s × 50% share
+        assert!(config.claim_interval_elapsed(90));
+        assert_eq!(config.calculate_rewards(90, 500, 1_000), 45_000);
+    }
+
+    #[test]
+    fn test_pending_rewards_clamped_near_supply_cap() {
+        let mut config = make_config(1_000, 0);
+        assert_eq!(config.claimable_rewards(5_000), 5_000);
+        config.minted_so_far = config.total_supply - 1_200;
+        assert_eq!(config.claimable_rewards(5_000), 1_200);
+        assert_eq!(config.claimable_rewards(1_200), 1_200);
+        config.minted_so_far = config.total_supply;
+        assert_eq!(config.claimable_rewards(5_000), 0);
+    }
+
+    #[test]
+    fn test_pending_rewards_clamp_cannot_overflow() {
+        let mut config = make_config(1_000, 0);
+        config.total_supply = u64::MAX;
+        config.minted_so_far = u64::MAX - 10;
+        // minted_so_far + accrued would wrap; the clamp works off the remainder
+        assert_eq!(config.claimable_rewards(u64::MAX), 10);
+        config.minted_so_far = u64::MAX;
+        assert_eq!(config.claimable_rewards(u64::MAX), 0);
+        // A config minted past its cap (e.g. supply lowered) pays nothing
+        config.total_supply = 1_000;
+        assert_eq!(config.claimable_rewards(1), 0);
+    }
+
+    #[test]
+    fn test_rewards_rate_zero_below_min_lp_supply() {
+        let mut config = make_config(1_000, 0);
+        assert_eq!(config.pool_rewards_per_second(&pool_with_supply(1), 0), 1_000);
+        config.min_lp_supply_for_rewards = 1_000_000;
+        assert_eq!(config.pool_rewards_per_second(&pool_with_supply(999_999), 0), 0);
+        assert_eq!(config.pool_rewards_per_second(&pool_with_supply(1_000_000), 0), 1_000);
+    }
+
+    #[test]
+    fn test_emission_rate_halves_each_interval() {
+        let mut config = make_config(1_000, 0);
+        config.start_timestamp = 100;
+        assert_eq!(config.current_emission_rate(100 + 1_000_000), 1_000);
+        config.halving_interval_secs = 86_400;
+        assert_eq!(config.current_emission_rate(100), 1_000);
+        assert_eq!(config.current_emission_rate(100 + 86_399), 1_000);
+        assert_eq!(config.current_emission_rate(100 + 86_400), 500);
+        assert_eq!(config.current_emission_rate(100 + 2 * 86_400 - 1), 500);
+        assert_eq!(config.current_emission_rate(100 + 2 * 86_400), 250);
+        assert_eq!(config.pool_rewards_per_second(&pool_with_supply(1), 100 + 86_400), 500);
+        // Enough halvings run the rate down to zero
+        assert_eq!(config.current_emission_rate(100 + 10 * 86_400), 0);
+        assert_eq!(config.current_emission_rate(i64::MAX), 0);
+    }
+
+    #[test]
+    fn test_accrual_across_halving_pays_each_side_its_rate() {
+        let mut config = make_config(1_000, 0);
+        config.halving_interval_secs = 100;
+        let mut pool = pool_with_supply(1_000);
+        pool.last_reward_timestamp = 50;
+        pool.rush_emission_index = config.emission_index_at(50).unwrap();
+        // 50s at 1000/s before the halving at t=100, 50s at 500/s after
+        config.accrue_pool(&mut pool, 150).unwrap();
+        assert_eq!(make_position(1_000).accrued_rewards(pool.acc_reward_per_share, pool.last_reward_timestamp).unwrap(), 50_000 + 25_000);
+        assert_eq!(pool.last_reward_timestamp, 150);
+        // Several epochs in one window
+        assert_eq!(config.emission_between(0, 350).unwrap(), 100_000 + 50_000 + 25_000 + 6_250);
+        assert_eq!(config.emission_between(350, 350).unwrap(), 0);
+        // The projection matches the accrual
+        let mut projected = pool_with_supply(1_000);
+        projected.last_reward_timestamp = 50;
+        projected.rush_emission_index = config.emission_index_at(50).unwrap();
+        let acc = config.projected_acc_reward_per_share(&projected, 150).unwrap();
+        assert_eq!(acc, pool.acc_reward_per_share);
+        // Before start_timestamp the full rate runs until the first halving
+        config.start_timestamp = 100;
+        assert_eq!(config.emission_between(0, 250).unwrap(), 200_000 + 25_000);
+    }
+
+    #[test]
+    fn test_halving_interval_fixed_once_a_halving_has_passed() {
+        let mut config = make_config(1_000, 0);
+        config.start_timestamp = 1_000;
+        config.set_halving_interval(500, 1_200).unwrap();
+        // A new interval whose first halving would already be behind us
+        assert_eq!(
+            config.set_halving_interval(100, 1_200).unwrap_err(),
+            error!(CustomError::HalvingScheduleStarted)
+        );
+        config.set_halving_interval(300, 1_200).unwrap();
+        // Once the first halving passes the schedule is fixed, even to turn it off
+        assert!(config.set_halving_interval(0, 1_300).is_err());
+        assert!(config.set_halving_interval(-1, 1_000).is_err());
+    }
+
+    #[test]
+    fn test_pool_weights_capped_at_full_emission() {
+        let mut config = make_config(1_000, 0);
+        let (mut a, mut b) = (pool_with_supply(1), pool_with_supply(1));
+        // Unweighted, every pool accrues the full rate
+        assert_eq!(config.pool_rewards_per_second(&a, 0), 1_000);
+        assert_eq!(config.set_pool_weight(&mut a, 7_000, 0).unwrap(), 0);
+        assert_eq!(config.pool_rewards_per_second(&b, 0), 0);
+        assert_eq!(
+            config.set_pool_weight(&mut b, 3_001, 0).unwrap_err(),
+            error!(CustomError::PoolWeightsExceeded)
+        );
+        config.set_pool_weight(&mut b, 3_000, 0).unwrap();
+        assert_eq!(config.total_pool_weight_bps, 10_000);
+        // Reweighting a pool only counts its new weight
+        assert_eq!(config.set_pool_weight(&mut a, 5_000, 0).unwrap(), 7_000);
+        assert_eq!(config.total_pool_weight_bps, 8_000);
+        assert_eq!(b.reward_weight_bps, 3_000);
+    }
+
+    #[test]
+    fn test_pool_weights_do_not_reprice_unaccrued_time() {
+        let mut config = make_config(1_000, 0);
+        let (mut weighted, mut unweighted) = (pool_with_supply(1_000), pool_with_supply(1_000));
+        // Weighting starts at t=100 while the other pool last accrued at t=0
+        config.accrue_pool(&mut weighted, 100).unwrap();
+        config.set_pool_weight(&mut weighted, 6_000, 100).unwrap();
+        assert_eq!(config.pool_weights_since, 100);
+        config.accrue_pool(&mut weighted, 200).unwrap();
+        config.accrue_pool(&mut unweighted, 200).unwrap();
+        let earned = |pool: &LiquidityPool| make_position(1_000).accrued_rewards(pool.acc_reward_per_share, 200).unwrap();
+        assert_eq!(earned(&weighted), 100_000 + 60_000);
+        // Its first 100s keep the full rate; weight 0 only applies from t=100
+        assert_eq!(earned(&unweighted), 100_000);
+
+        // Dropping the last weight keeps pools weighted instead of restoring the full rate
+        config.set_pool_weight(&mut weighted, 0, 200).unwrap();
+        assert_eq!(config.pool_weights_since, 100);
+        assert_eq!(config.pool_rewards_per_second(&unweighted, 300), 0);
+    }
+
+    #[test]
+    fn test_rate_change_does_not_reprice_unaccrued_time() {
+        let mut config = make_config(1_000, 0);
+        let (mut early, mut stale) = (pool_with_supply(1_000), pool_with_supply(1_000));
+        config.accrue_pool(&mut early, 100).unwrap();
+        // Two rate changes while `stale` has not accrued since t=0
+        config.set_rewards_per_second(3_000, 100).unwrap();
+        config.set_rewards_per_second(500, 150).unwrap();
+        assert_eq!(config.emission_index_at(200).unwrap(), 100_000 + 150_000 + 25_000);
+        let projected = config.projected_acc_reward_per_share(&stale, 200).unwrap();
+        config.accrue_pool(&mut stale, 200).unwrap();
+        config.accrue_pool(&mut early, 200).unwrap();
+        assert_eq!(projected, stale.acc_reward_per_share);
+        // Each stretch is paid its own rate, however long the pool went unaccrued
+        assert_eq!(stale.acc_reward_per_share, early.acc_reward_per_share);
+        assert_eq!(make_position(1_000).accrued_rewards(stale.acc_reward_per_share, 200).unwrap(), 275_000);
+    }
+
+    #[test]
+    fn test_pause_keeps_emission_earned_before_it() {
+        let mut config = make_config(1_000, 0);
+        let mut pool = pool_with_supply(1_000);
+        // Paused at t=100 with the pool last accrued at t=0
+        config.pause_emission(100).unwrap();
+        assert_eq!((config.paused_at, config.emission_index_at(250).unwrap()), (100, 100_000));
+        config.accrue_pool(&mut pool, 250).unwrap();
+        let earned = |pool: &LiquidityPool| make_position(1_000).accrued_rewards(pool.acc_reward_per_share, 400).unwrap();
+        assert_eq!(earned(&pool), 100_000);
+        // Resumed at t=300: the 200s paused emit nothing, the time after pays again
+        config.resume_emission(300);
+        assert_eq!(config.resumed_at, 300);
+        assert_eq!(config.projected_acc_reward_per_share(&pool, 400).unwrap(), pool.acc_reward_per_share_after(100_000).unwrap());
+        config.accrue_pool(&mut pool, 400).unwrap();
+        assert_eq!(earned(&pool), 200_000);
+    }
+
+    #[test]
+    fn test_authority_handover_needs_acceptance() {
+        let mut config = make_config(1_000, 0);
+        let (current, next) = (Pubkey::new_unique(), Pubkey::new_unique());
+        config.authority = current;
+        config.propose_authority(current, next).unwrap();
+        // Proposing alone changes nothing
+        assert_eq!(config.authority, current);
+        assert_eq!(config.accept_authority(next).unwrap(), current);
+        assert_eq!(config.authority, next);
+        assert_eq!(config.pending_authority, Pubkey::default());
+        // The old key no longer controls the config
+        assert_eq!(
+            config.propose_authority(current, current).unwrap_err(),
+            error!(CustomError::InvalidAuthority)
+        );
+    }
+
+    #[test]
+    fn test_authority_handover_rejects_other_signers() {
+        let mut config = make_config(1_000, 0);
+        let (current, next, intruder) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        config.authority = current;
+        // Nothing pending: not even the authority itself can accept
+        assert!(config.accept_authority(current).is_err());
+        assert!(config.propose_authority(intruder, intruder).is_err());
+        config.propose_authority(current, next).unwrap();
+        assert!(config.accept_authority(intruder).is_err());
+        assert!(config.accept_authority(current).is_err());
+        assert_eq!(config.authority, current);
+        assert_eq!(config.pending_authority, next);
+    }
+
+    #[test]
+    fn test_zero_interval_allows_any_claim() {
+        let config = make_config(1_000, 0);
+        assert!(config.claim_interval_elapsed(0));
+    }
+
+    #[test]
+    fn test_reward_apy_stops_when_paused() {
+        let mut config = make_config(1_000, 0);
+        assert_eq!(config.reward_apy_bps(), 5_000);
+        config.is_paused = true;
+        assert_eq!(config.reward_apy_bps(), 0);
+    }
+
+    #[test]
+    fn test_apy_update_round_trips_to_init_rate() {
+        // initialize_rush_token: 1M RUSH (6 decimals) at 50/100
+        let init_rate = RushConfig::rewards_per_second_for_apy(1_000_000_000_000, 50, 100).unwrap();
+        assert_eq!(init_rate, 500_000 * 1_000_000 / RushConfig::SECONDS_PER_YEAR);
+        let mut config = make_config(init_rate, 0);
+        for apy in [120, 7, 50] {
+            let rate = RushConfig::rewards_per_second_for_apy(config.total_supply, apy, config.apy_denominator).unwrap();
+            config.set_rewards_per_second(rate, 0).unwrap();
+        }
+        assert_eq!(config.rewards_per_second, init_rate);
+    }
+
+    #[test]
+    fn test_accumulator_matches_time_elapsed_model() {
+        let config = make_config(15_854, 0);
+        for (user_lp, total_lp, elapsed) in [
+            (500u64, 1_000u64, 90u64),
+            (1, 3, 7),
+            (123_456, 9_999_991, 86_400),
+            (7_000_000_000, 7_000_000_001, 31_536_000),
+        ] {
+            let mut pool = make_pool(0, 0);
+            pool.total_lp_supply = total_lp;
+            let mut position = make_position(user_lp);
+            position.sync_reward_debt(pool.acc_reward_per_share, pool.last_reward_timestamp).unwrap();
+
+            pool.accrue_rewards(config.rewards_per_second, elapsed as i64).unwrap();
+            let accumulated = position.accrued_rewards(pool.acc_reward_per_share, pool.last_reward_timestamp).unwrap();
+            let time_based = config.calculate_rewards(elapsed, user_lp, total_lp);
+            assert!(accumulated <= time_based && time_based - accumulated <= 1);
+        }
+    }
+
+    #[test]
+    fn test_weighted_pools_split_emissions_70_30() {
+        let mut config = make_config(1_000, 0);
+        let mut major = make_pool(0, 0);
+        let mut minor = make_pool(0, 0);
+        config.set_pool_weight(&mut major, 7_000, 0).unwrap();
+        config.set_pool_weight(&mut minor, 3_000, 0).unwrap();
+        // Same LP supply, so only the weight sets each pool's pie
+        for pool in [&mut major, &mut minor] {
+            pool.total_lp_supply = 1_000;
+            let rate = config.pool_rewards_per_second(pool, 0);
+            pool.accrue_rewards(rate, 100).unwrap();
+        }
+        let major_rewards = make_position(1_000).accrued_rewards(major.acc_reward_per_share, major.last_reward_timestamp).unwrap();
+        let minor_rewards = make_position(1_000).accrued_rewards(minor.acc_reward_per_share, minor.last_reward_timestamp).unwrap();
+        assert_eq!(major_rewards, 70_000);
+        assert_eq!(minor_rewards, 30_000);
+        assert_eq!(major_rewards + minor_rewards, 100 * config.rewards_per_second);
+    }
+
+    #[test]
+    fn test_pool_below_min_lp_supply_accrues_nothing() {
+        let mut config = make_config(100, 0);
+        config.min_lp_supply_for_rewards = 10_000;
+        let mut small = make_pool(0, 0);
+        small.total_lp_supply = 1_000;
+        let mut large = make_pool(0, 0);
+        large.total_lp_supply = 10_000;
+        for pool in [&mut small, &mut large] {
+            let rate = config.pool_rewards_per_second(pool, 0);
+            pool.accrue_rewards(rate, 100).unwrap();
+            assert_eq!(pool.last_reward_timestamp, 100);
+        }
+        assert_eq!(make_position(1_000).accrued_rewards(small.acc_reward_per_share, small.last_reward_timestamp).unwrap(), 0);
+        // The lone LP of the large pool gets the full 100s × 100/s
+        assert_eq!(make_position(10_000).accrued_rewards(large.acc_reward_per_share, large.last_reward_timestamp).unwrap(), 10_000);
+    }
+}
@@ -1,4 +1,18 @@
 use anchor_lang::prelude::*;
+use crate::errors::CustomError;
+use crate::constants::{ACC_REWARD_PRECISION, MAX_LOCK_BOOST_BPS, MAX_LOCK_DAYS, MAX_REWARD_PROGRAMS};
+use crate::state::{LiquidityPool, RewardProgram};
+/// A position's standing in one of its pool's `reward_programs`, same slot.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct PositionReward {
+    /// `lp_tokens × acc_reward_per_share` of the program at the last settlement.
+    pub reward_debt: u128,
+    /// Settled but not yet claimed.
+    pub pending_rewards: u64,
+}
+impl PositionReward {
+    pub const SIZE: usize = 16 + 8;
+}
 #[account]
 pub struct UserLiquidityPosition {
     pub owner: Pubkey,
@@ -8,9 +22,29 @@ pub struct UserLiquidityPosition {
     pub last_claim_timestamp: i64,
     pub total_rush_claimed: u64,
     pub bump: u8,
+    /// `reward_shares() × acc_reward_per_share` at the last settlement, scaled by `ACC_REWARD_PRECISION`.
+    pub reward_debt: u128,
+    /// Rewards settled on LP changes but not yet claimed.
+    pub pending_rewards: u64,
+    /// Spot-vs-TWAP deviation (bps) at the latest deposit; 0 when no snapshot was taken.
+    pub deposit_deviation_bps: u64,
+    /// RUSH multiplier (bps) on the position's shares since the latest deposit; 0 means 1x.
+    pub reward_boost_bps: u16,
+    /// Partner-token rewards, indexed like the pool's `reward_programs`.
+    pub program_rewards: [PositionReward; MAX_REWARD_PROGRAMS],
+    /// `remove_liquidity` is refused before this time; 0 when never locked.
+    pub lock_until_ts: i64,
+    /// RUSH multiplier (bps) earned by the lock; 0 means 1x.  Earns nothing
+    /// past `lock_until_ts` and lapses at the next sync of `boost_shares`.
+    pub boost_multiplier_bps: u16,
+    /// RUSH shares on top of `lp_tokens` from the two multipliers, counted in
+    /// the pool's `boost_shares`.
+    pub boost_shares: u64,
+    /// When `reward_debt` was last synced.
+    pub rewards_synced_ts: i64,
 }
 impl UserLiquidityPosition {
-    pub const SIZE: usize = 8 + 32*2 + 8*4 + 1;
+    pub const SIZE: usize = 8 + 32*2 + 8*4 + 1 + 16 + 8 + 8 + 2 + PositionReward::SIZE * MAX_REWARD_PROGRAMS + 8 + 2 + 8 + 8;
     pub fn get_pool_share(&self, total_lp_supply: u64) -> u64 {
         if total_lp_supply == 0 {
             return 0;
@@ -28,4 +62,382 @@ impl UserLiquidityPosition {
         let seconds_elapsed = (current_timestamp - self.deposit_timestamp) as u64;
         seconds_elapsed / 86400
     }
+    /// Shares of the pool's RUSH emission: `lp_tokens` plus `boost_shares`.
+    pub fn reward_shares(&self) -> u128 {
+        self.lp_tokens as u128 + self.boost_shares as u128
+    }
+    fn accumulated(shares: u128, acc_reward_per_share: u128) -> Result<u128> {
+        shares
+            .checked_mul(acc_reward_per_share)
+            .ok_or(error!(CustomError::CalculationOverflow))
+    }
+    /// Settled plus newly accrued rewards at the given accumulator value.
+    pub fn accrued_rewards(&self, acc_reward_per_share: u128, now: i64) -> Result<u64> {
+        let fresh = Self::accumulated(self.reward_shares(), acc_reward_per_share)?
+            .saturating_sub(self.reward_debt)
+            / ACC_REWARD_PRECISION;
+        let fresh = self.cap_lock_boost(fresh, now)?;
+        (self.pending_rewards as u128)
+            .checked_add(fresh)
+            .ok_or(error!(CustomError::CalculationOverflow))?
+            .try_into()
+            .map_err(|_| error!(CustomError::CalculationOverflow))
+    }
+    /// `fresh` less what the lock's shares earned after `lock_until_ts`, taken
+    /// pro rata by time over the window since `rewards_synced_ts`.
+    fn cap_lock_boost(&self, fresh: u128, now: i64) -> Result<u128> {
+        let shares = self.reward_shares();
+        let unlocked_shares = self.boosted_shares(0)?;
+        if now <= self.lock_until_ts || unlocked_shares >= shares {
+            return Ok(fresh);
+        }
+        let window = now.saturating_sub(self.rewards_synced_ts).max(1) as u128;
+        let locked_secs = self.lock_until_ts.saturating_sub(self.rewards_synced_ts).max(0) as u128;
+        let unlocked = fresh
+            .checked_mul(unlocked_shares)
+            .ok_or(error!(CustomError::CalculationOverflow))?
+            / shares;
+        let lock_earned = (fresh - unlocked)
+            .checked_mul(locked_secs.min(window))
+            .ok_or(error!(CustomError::CalculationOverflow))?
+            / window;
+        Ok(unlocked + lock_earned)
+    }
+    /// `lp_tokens` under the alignment multiplier and `lock_boost_bps`.
+    fn boosted_shares(&self, lock_boost_bps: u16) -> Result<u128> {
+        let shares = Self::apply_boost(self.lp_tokens as u128, self.reward_boost_bps)?;
+        Self::apply_boost(shares, lock_boost_bps)
+    }
+    fn apply_boost(rewards: u128, boost_bps: u16) -> Result<u128> {
+        match boost_bps {
+            0 => Ok(rewards),
+            boost => Ok(rewards
+                .checked_mul(boost as u128)
+                .ok_or(error!(CustomError::CalculationOverflow))?
+                / 10_000),
+        }
+    }
+    /// Multiplier (bps) for a lock of `lock_days`: 1x plus a linear share of
+    /// the extra up to `MAX_LOCK_BOOST_BPS` at `MAX_LOCK_DAYS`.
+    pub fn lock_boost_bps(lock_days: u64) -> Result<u16> {
+        require!(
+            (1..=MAX_LOCK_DAYS).contains(&lock_days),
+            CustomError::InvalidLockDuration
+        );
+        let extra = (MAX_LOCK_BOOST_BPS - 10_000) as u64 * lock_days / MAX_LOCK_DAYS;
+        Ok(10_000 + extra as u16)
+    }
+    /// Lock the position for `lock_days` from `now`.  A lock can be extended
+    /// but never shortened.  Call between `settle_rewards` and
+    /// `sync_boost_shares`, so rewards already earned keep the old multiplier.
+    pub fn lock(&mut self, lock_days: u64, now: i64) -> Result<()> {
+        let boost = Self::lock_boost_bps(lock_days)?;
+        let lock_until_ts = now
+            .checked_add(lock_days as i64 * 86400)
+            .ok_or(error!(CustomError::CalculationOverflow))?;
+        require!(lock_until_ts >= self.lock_until_ts, CustomError::InvalidLockDuration);
+        self.lock_until_ts = lock_until_ts;
+        self.boost_multiplier_bps = boost;
+        Ok(())
+    }
+    pub fn is_locked(&self, now: i64) -> bool {
+        now < self.lock_until_ts
+    }
+    pub fn require_unlocked(&self, now: i64) -> Result<()> {
+        require!(!self.is_locked(now), CustomError::PositionLocked);
+        Ok(())
+    }
+    /// End the lock at `now` ahead of time, forfeiting the rest of its boost;
+    /// rewards earned so far keep it.  Call before settling, with the next
+    /// `sync_boost_shares` dropping the multiplier.
+    pub fn forfeit_lock(&mut self, now: i64) {
+        self.lock_until_ts = self.lock_until_ts.min(now);
+    }
+    /// Drop the lock multiplier once the lock is over; `sync_boost_shares` does
+    /// this before re-deriving the shares.
+    pub fn release_expired_lock(&mut self, now: i64) {
+        if !self.is_locked(now) {
+            self.boost_multiplier_bps = 0;
+        }
+    }
+    /// Record the deposit-time alignment snapshot from `LiquidityPool::deposit_alignment`.
+    /// Call after `settle_rewards`, so rewards already earned keep the old multiplier.
+    pub fn record_deposit_alignment(&mut self, alignment: Option<(u64, u16)>) {
+        let (deviation, boost) = alignment.unwrap_or((0, 0));
+        self.deposit_deviation_bps = deviation;
+        self.reward_boost_bps = boost;
+    }
+    /// Move accrued rewards into `pending_rewards`; call before `lp_tokens` changes.
+    pub fn settle_rewards(&mut self, acc_reward_per_share: u128, now: i64) -> Result<()> {
+        self.pending_rewards = self.accrued_rewards(acc_reward_per_share, now)?;
+        self.sync_reward_debt(acc_reward_per_share, now)
+    }
+    /// Re-derive `boost_shares` from the balance and multipliers, dropping an
+    /// expired lock, and carry the change into the pool's `boost_shares`.
+    /// Call after settling, with `pool` accrued to now.
+    pub fn sync_boost_shares(&mut self, pool: &mut LiquidityPool) -> Result<()> {
+        let now = pool.last_reward_timestamp;
+        self.release_expired_lock(now);
+        let boost_shares = self
+            .boosted_shares(self.boost_multiplier_bps)?
+            .saturating_sub(self.lp_tokens as u128)
+            .try_into()
+            .map_err(|_| error!(CustomError::CalculationOverflow))?;
+        pool.boost_shares = pool
+            .boost_shares
+            .saturating_sub(self.boost_shares)
+            .checked_add(boost_shares)
+            .ok_or(error!(CustomError::CalculationOverflow))?;
+        self.boost_shares = boost_shares;
+        self.sync_reward_debt(pool.acc_reward_per_share, now)
+    }
+    /// Credit newly minted LP.  Rewards earned on the existing balance are
+    /// settled first, so neither the new stake nor the new boost touches them.
+    /// `pool` must already be accrued to now.
+    pub fn deposit_lp_tokens(
+        &mut self,
+        lp_tokens: u64,
+        pool: &mut LiquidityPool,
+        alignment: Option<(u64, u16)>,
+    ) -> Result<()> {
+        self.settle_rewards(pool.acc_reward_per_share, pool.last_reward_timestamp)?;
+        self.settle_program_rewards(&pool.reward_programs)?;
+        self.record_deposit_alignment(alignment);
+        self.lp_tokens = self
+            .lp_tokens
+            .checked_add(lp_tokens)
+            .ok_or(error!(CustomError::CalculationOverflow))?;
+        self.sync_boost_shares(pool)?;
+        self.sync_program_debts(&pool.reward_programs)
+    }
+    /// Debit burned LP after settling what the full balance earned.
+    /// `pool` must already be accrued to now.
+    pub fn withdraw_lp_tokens(&mut self, lp_tokens: u64, pool: &mut LiquidityPool) -> Result<()> {
+        self.settle_rewards(pool.acc_reward_per_share, pool.last_reward_timestamp)?;
+        self.settle_program_rewards(&pool.reward_programs)?;
+        self.lp_tokens = self
+            .lp_tokens
+            .checked_sub(lp_tokens)
+            .ok_or(error!(CustomError::InsufficientLPBalance))?;
+        self.sync_boost_shares(pool)?;
+        self.sync_program_debts(&pool.reward_programs)
+    }
+    /// Reset the debt to the current shares; call after `lp_tokens` or `boost_shares` changes.
+    pub fn sync_reward_debt(&mut self, acc_reward_per_share: u128, now: i64) -> Result<()> {
+        self.reward_debt = Self::accumulated(self.reward_shares(), acc_reward_per_share)?;
+        self.rewards_synced_ts = now;
+        Ok(())
+    }
+    /// Settled plus newly accrued rewards of the program in slot `index`.
+    pub fn accrued_program_rewards(&self, index: usize, program: &RewardProgram) -> Result<u64> {
+        let standing = &self.program_rewards[index];
+        let fresh = Self::accumulated(self.lp_tokens as u128, program.acc_reward_per_share)?
+            .saturating_sub(standing.reward_debt)
+            / ACC_REWARD_PRECISION;
+        (standing.pending_rewards as u128)
+            .checked_add(fresh)
+            .ok_or(error!(CustomError::CalculationOverflow))?
+            .try_into()
+            .map_err(|_| error!(CustomError::CalculationOverflow))
+    }
+    /// `settle_rewards` for every active reward program.
+    pub fn settle_program_rewards(&mut self, programs: &[RewardProgram; MAX_REWARD_PROGRAMS]) -> Result<()> {
+        for (index, program) in programs.iter().enumerate().filter(|(_, program)| program.is_active()) {
+            self.program_rewards[index].pending_rewards = self.accrued_program_rewards(index, program)?;
+        }
+        self.sync_program_debts(programs)
+    }
+    /// `sync_reward_debt` for every active reward program.
+    pub fn sync_program_debts(&mut self, programs: &[RewardProgram; MAX_REWARD_PROGRAMS]) -> Result<()> {
+        for (index, program) in programs.iter().enumerate().filter(|(_, program)| program.is_active()) {
+            self.program_rewards[index].reward_debt = Self::accumulated(self.lp_tokens as u128, program.acc_reward_per_share)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::state::pool::tests::make_pool;
+
+    pub(crate) fn make_position(lp_tokens: u64) -> UserLiquidityPosition {
+        UserLiquidityPosition {
+            owner: Pubkey::default(),
+            pool: Pubkey::default(),
+            lp_tokens,
+            deposit_timestamp: 0,
+            last_claim_timestamp: 0,
+            total_rush_claimed: 0,
+            bump: 0,
+            reward_debt: 0,
+            pending_rewards: 0,
+            deposit_deviation_bps: 0,
+            reward_boost_bps: 0,
+            program_rewards: [PositionReward::default(); MAX_REWARD_PROGRAMS],
+            lock_until_ts: 0,
+            boost_multiplier_bps: 0,
+            boost_shares: 0,
+            rewards_synced_ts: 0,
+        }
+    }
+
+    #[test]
+    fn test_deposit_does_not_capture_past_rewards() {
+        let mut pool = make_pool(0, 0);
+        pool.total_lp_supply = 1_000;
+        let mut early = make_position(1_000);
+        pool.accrue_rewards(100, 100).unwrap();
+
+        // A new depositor joins at t=100 with the same stake
+        let mut late = make_position(0);
+        late.settle_rewards(pool.acc_reward_per_share, 100).unwrap();
+        late.lp_tokens = 1_000;
+        late.sync_reward_debt(pool.acc_reward_per_share, 100).unwrap();
+        pool.total_lp_supply = 2_000;
+
+        pool.accrue_rewards(100, 200).unwrap();
+        early.settle_rewards(pool.acc_reward_per_share, 200).unwrap();
+        assert_eq!(early.pending_rewards, 10_000 + 5_000);
+        assert_eq!(late.accrued_rewards(pool.acc_reward_per_share, 200).unwrap(), 5_000);
+    }
+
+    #[test]
+    fn test_topping_up_keeps_rewards_on_prior_balance() {
+        let mut pool = make_pool(0, 0);
+        let mut position = make_position(0);
+        let mut other = make_position(0);
+        position.deposit_lp_tokens(1_000, &mut pool, None).unwrap();
+        other.deposit_lp_tokens(1_000, &mut pool, None).unwrap();
+        pool.total_lp_supply = 2_000;
+        pool.accrue_rewards(100, 100).unwrap();
+
+        // Double the stake at t=100 with a 2x boost that must not apply retroactively
+        position
+            .deposit_lp_tokens(1_000, &mut pool, Some((0, 20_000)))
+            .unwrap();
+        pool.total_lp_supply = 3_000;
+        assert_eq!(position.pending_rewards, 5_000);
+        assert_eq!(pool.boost_shares, 2_000);
+
+        // 4_000 of the 5_000 shares for the next 100s
+        pool.accrue_rewards(100, 200).unwrap();
+        let acc = pool.acc_reward_per_share;
+        assert_eq!(position.accrued_rewards(acc, 200).unwrap(), 5_000 + 8_000);
+        assert_eq!(other.accrued_rewards(acc, 200).unwrap(), 5_000 + 2_000);
+    }
+
+    #[test]
+    fn test_boost_reweights_the_pool_emission() {
+        let mut pool = make_pool(0, 0);
+        let mut boosted = make_position(0);
+        let mut plain = make_position(0);
+        boosted.deposit_lp_tokens(1_000, &mut pool, Some((0, 15_000))).unwrap();
+        plain.deposit_lp_tokens(1_000, &mut pool, None).unwrap();
+        pool.total_lp_supply = 2_000;
+        assert_eq!(pool.boosted_supply(), 2_500);
+        pool.accrue_rewards(100, 100).unwrap();
+        // 1.5x the plain position, out of the same 10_000 emitted
+        let acc = pool.acc_reward_per_share;
+        assert_eq!(boosted.accrued_rewards(acc, 100).unwrap(), 6_000);
+        assert_eq!(plain.accrued_rewards(acc, 100).unwrap(), 4_000);
+
+        // Withdrawing hands the boost shares back
+        boosted.withdraw_lp_tokens(1_000, &mut pool).unwrap();
+        assert_eq!((boosted.boost_shares, pool.boost_shares), (0, 0));
+        assert_eq!(boosted.pending_rewards, 6_000);
+    }
+
+    #[test]
+    fn test_lock_doubles_share_of_rewards_earned_while_locked() {
+        let mut pool = make_pool(0, 0);
+        let mut locked = make_position(0);
+        let mut plain = make_position(0);
+        locked.deposit_lp_tokens(1_000, &mut pool, None).unwrap();
+        plain.deposit_lp_tokens(1_000, &mut pool, None).unwrap();
+        pool.total_lp_supply = 2_000;
+        pool.accrue_rewards(30, 100).unwrap();
+        locked.settle_rewards(pool.acc_reward_per_share, 100).unwrap();
+        locked.lock(120, 100).unwrap();
+        locked.sync_boost_shares(&mut pool).unwrap();
+        assert_eq!(locked.boost_multiplier_bps, 20_000);
+        pool.accrue_rewards(30, 200).unwrap();
+        // An even split for the first 100s, then 2:1
+        let acc = pool.acc_reward_per_share;
+        assert_eq!(locked.accrued_rewards(acc, 200).unwrap(), 1_500 + 2_000);
+        assert_eq!(plain.accrued_rewards(acc, 200).unwrap(), 1_500 + 1_000);
+
+        assert_eq!(UserLiquidityPosition::lock_boost_bps(MAX_LOCK_DAYS).unwrap(), MAX_LOCK_BOOST_BPS);
+        assert!(UserLiquidityPosition::lock_boost_bps(0).is_err());
+        assert!(UserLiquidityPosition::lock_boost_bps(MAX_LOCK_DAYS + 1).is_err());
+    }
+
+    #[test]
+    fn test_lock_boost_stops_at_lock_until() {
+        let mut pool = make_pool(0, 0);
+        let mut locked = make_position(0);
+        let mut plain = make_position(0);
+        locked.deposit_lp_tokens(1_000, &mut pool, None).unwrap();
+        plain.deposit_lp_tokens(1_000, &mut pool, None).unwrap();
+        pool.total_lp_supply = 2_000;
+        locked.lock(120, 0).unwrap();
+        locked.sync_boost_shares(&mut pool).unwrap();
+
+        // Left untouched for as long again after the lock ends
+        let now = 2 * locked.lock_until_ts;
+        pool.accrue_rewards(3, now).unwrap();
+        let acc = pool.acc_reward_per_share;
+        let emitted = 3 * now as u64;
+        // Two thirds of the locked half, a third of the other: its 1x share
+        assert_eq!(locked.accrued_rewards(acc, now).unwrap(), emitted / 3 + emitted / 6);
+        assert_eq!(plain.accrued_rewards(acc, now).unwrap(), emitted / 3);
+
+        // The next sync drops the lapsed boost from the pool
+        locked.settle_rewards(acc, now).unwrap();
+        locked.sync_boost_shares(&mut pool).unwrap();
+        assert_eq!((locked.boost_multiplier_bps, locked.boost_shares, pool.boost_shares), (0, 0, 0));
+        pool.accrue_rewards(3, now + 100).unwrap();
+        let acc = pool.acc_reward_per_share;
+        assert_eq!(locked.accrued_rewards(acc, now + 100).unwrap(), emitted / 2 + 150);
+        assert_eq!(plain.accrued_rewards(acc, now + 100).unwrap(), emitted / 3 + 150);
+    }
+
+    #[test]
+    fn test_forfeited_lock_releases_lp_and_boost() {
+        let mut pool = make_pool(0, 0);
+        let mut position = make_position(0);
+        position.deposit_lp_tokens(1_000, &mut pool, None).unwrap();
+        pool.total_lp_supply = 1_000;
+        position.lock(120, 0).unwrap();
+        position.sync_boost_shares(&mut pool).unwrap();
+        assert_eq!(pool.boost_shares, 1_000);
+
+        // A day into the lock the LP can still leave, without the boost
+        let now = 86400;
+        pool.accrue_rewards(3, now).unwrap();
+        assert_eq!(position.require_unlocked(now).unwrap_err(), error!(CustomError::PositionLocked));
+        position.forfeit_lock(now);
+        assert!(position.require_unlocked(now).is_ok());
+        position.withdraw_lp_tokens(400, &mut pool).unwrap();
+        assert_eq!((position.boost_multiplier_bps, position.boost_shares, pool.boost_shares), (0, 0, 0));
+        assert_eq!(position.pending_rewards, 3 * 86400);
+        // An expired lock is left alone
+        position.forfeit_lock(2 * now);
+        assert_eq!(position.lock_until_ts, now);
+    }
+
+    #[test]
+    fn test_locked_position_blocks_early_withdrawal() {
+        let mut position = make_position(1_000);
+        position.lock(30, 0).unwrap();
+        let unlock = 30 * 86400;
+        assert_eq!(position.require_unlocked(unlock - 1).unwrap_err(), error!(CustomError::PositionLocked));
+        // A lock can be extended, not shortened
+        assert_eq!(position.lock(10, 86400).unwrap_err(), error!(CustomError::InvalidLockDuration));
+        assert!(position.require_unlocked(unlock).is_ok());
+        position.release_expired_lock(unlock - 1);
+        assert_ne!(position.boost_multiplier_bps, 0);
+        position.release_expired_lock(unlock);
+        assert_eq!(position.boost_multiplier_bps, 0);
+    }
 }
@@ -1,7 +1,10 @@
 use anchor_lang::prelude::*;
 
+use anchor_spl::token::TokenAccount;
 use crate::errors::CustomError;
-#[allow(dead_code)]
+use crate::state::{LiquidityPool, OrderKind};
+use crate::perps_math::mul_div;
+use crate::constants::MINIMUM_LIQUIDITY;
 pub fn calculate_lp_tokens(amount_a: u64, amount_b: u64) -> Result<u64> {
     let product = (amount_a as u128)
         .checked_mul(amount_b as u128)
@@ -19,11 +22,13 @@ pub fn calculate_lp_tokens_for_add_liquidity(
     require!(amount_a > 0 && amount_b > 0, CustomError::InvalidAmount);
     
     if total_lp_supply == 0 {
-        // Initial liquidity provision
+        // Initial liquidity provision; MINIMUM_LIQUIDITY of it is locked in the pool
         let product = (amount_a as u128)
             .checked_mul(amount_b as u128)
             .ok_or(error!(CustomError::CalculationOverflow))?;
-        return Ok(isqrt(product) as u64);
+        let liquidity = isqrt(product) as u64;
+        require!(liquidity > MINIMUM_LIQUIDITY, CustomError::InitialLiquidityTooLow);
+        return Ok(liquidity - MINIMUM_LIQUIDITY);
     }
 
     require!(reserve_a > 0 && reserve_b > 0, CustomError::InsufficientLiquidity);
@@ -40,6 +45,57 @@ pub fn calculate_lp_tokens_for_add_liquidity(
     Ok(if lp_from_a < lp_from_b { lp_from_a } else { lp_from_b })
 }
 
+/// Portion of a single-sided deposit to swap so the remainder and the swap
+/// output land exactly on the post-swap pool ratio (constant product only).
+///
+/// Solves `(amount_in - s) / (reserve_in + s) = out(s) / (reserve_out - out(s))`:
+/// `s = r * (sqrt((d + g)^2 + 4 * g * d * a / r) - (d + g)) / (2 * g)`,
+/// with `d` the fee denominator and `g = d - fee_numerator`.
+pub fn calculate_single_sided_swap_amount(
+    amount_in: u64,
+    reserve_in: u64,
+    fee_numerator: u64,
+    fee_denominator: u64,
+) -> Result<u64> {
+    require!(amount_in > 0, CustomError::InvalidAmount);
+    require!(reserve_in > 0, CustomError::InsufficientLiquidity);
+    // Fixed-point scale so the square root keeps sub-unit precision
+    const SCALE: u128 = 1_000_000_000_000;
+    let d = fee_denominator as u128;
+    let g = (fee_denominator - fee_numerator) as u128;
+    let base = SCALE * (d + g);
+    let growth = mul_div(4 * g * d * (amount_in as u128), SCALE * SCALE, reserve_in as u128)?;
+    let root = isqrt(
+        base.checked_mul(base)
+            .and_then(|sq| sq.checked_add(growth))
+            .ok_or(error!(CustomError::CalculationOverflow))?,
+    );
+    let swap_amount = mul_div(reserve_in as u128, root - base, 2 * g * SCALE)?;
+    Ok((swap_amount as u64).min(amount_in))
+}
+
+/// Shortfall of the execution price (`amount_out / amount_in`) against the
+/// pre-trade spot price (`reserve_out / reserve_in`), in bps. Includes the fee.
+pub fn calculate_price_impact_bps(
+    amount_in: u64,
+    amount_out: u64,
+    reserve_in: u64,
+    reserve_out: u64,
+) -> Result<u64> {
+    require!(amount_in > 0, CustomError::InvalidAmount);
+    require!(reserve_in > 0 && reserve_out > 0, CustomError::InsufficientLiquidity);
+    let at_spot = (amount_in as u128) * (reserve_out as u128);
+    let executed = (amount_out as u128) * (reserve_in as u128);
+    Ok(mul_div(at_spot.saturating_sub(executed), 10_000, at_spot)? as u64)
+}
+
+/// Reject bootstrap deposits whose geometric-mean LP is below `min_liquidity`.
+pub fn validate_initial_liquidity(amount_a: u64, amount_b: u64, min_liquidity: u64) -> Result<()> {
+    let lp = calculate_lp_tokens(amount_a, amount_b)?;
+    require!(lp >= min_liquidity, CustomError::InvalidInitialDeposit);
+    Ok(())
+}
+
 pub fn calculate_remove_liquidity_amounts(
     lp_tokens_to_burn: u64,
     total_lp_supply: u64,
@@ -61,6 +117,15 @@ pub fn calculate_remove_liquidity_amounts(
     Ok((amount_a, amount_b))
 }
 
+/// LP to burn for a withdrawal of `bps` (1–10000) of a position; 100% is exact.
+pub fn calculate_lp_tokens_for_percent(position_lp_tokens: u64, bps: u16) -> Result<u64> {
+    require!(bps > 0 && bps <= 10_000, CustomError::InvalidAmount);
+    if bps == 10_000 {
+        return Ok(position_lp_tokens);
+    }
+    Ok(((position_lp_tokens as u128) * (bps as u128) / 10_000) as u64)
+}
+
 pub fn validate_ratio_imbalance(
     amount_a: u64,
     amount_b: u64,
@@ -87,18 +152,31 @@ pub fn validate_ratio_imbalance(
     Ok(())
 }
 
+/// Swap output after fees. `amplification` selects the StableSwap curve
+/// (see `LiquidityPool::stable_amplification`); `None` uses x * y = k.
 pub fn calculate_output_amount(
     input_amount: u64,
     input_reserve: u64,
     output_reserve: u64,
     fee_numerator: u64,
     fee_denominator: u64,
+    amplification: Option<u64>,
 ) -> Result<u64> {
     require!(input_amount > 0, CustomError::InvalidAmount);
     require!(
         input_reserve > 0 && output_reserve > 0,
         CustomError::InsufficientLiquidity
     );
+    if let Some(amp) = amplification {
+        return calculate_stable_output_amount(
+            input_amount,
+            input_reserve,
+            output_reserve,
+            fee_numerator,
+            fee_denominator,
+            amp,
+        );
+    }
     
     // Standard AMM formula: output = (input * (1 - fee) * output_reserve) / (input_reserve + input * (1 - fee))
     // Using x * y = k constant product formula
@@ -128,6 +206,213 @@ pub fn calculate_output_amount(
     Ok(output_amount as u64)
 }
 
+/// Fee numerator for a dynamic-fee pool (`LiquidityPool::dynamic_fee_bps`).
+///
+/// Prices are token B per token A before and after the swap (priced at the
+/// base fee) and the pool's TWAP as the reference. If the swap moves spot
+/// toward the reference the fee is cut by `adjust_bps` of itself, otherwise it
+/// is raised by the same share; either way it ends up in `[fee_floor, fee_ceiling]`.
+pub fn dynamic_fee_numerator(
+    price_before: u128,
+    price_after: u128,
+    reference_price: u128,
+    fee_numerator: u64,
+    adjust_bps: u16,
+    fee_floor: u64,
+    fee_ceiling: u64,
+) -> u64 {
+    let shift = (fee_numerator as u128 * adjust_bps as u128 / 10_000) as u64;
+    let fee = if price_after.abs_diff(reference_price) < price_before.abs_diff(reference_price) {
+        fee_numerator - shift
+    } else {
+        fee_numerator + shift
+    };
+    // The floor wins: the protocol's cut must always fit inside the fee
+    fee.min(fee_ceiling).max(fee_floor)
+}
+
+/// Constant-product input needed to receive exactly `amount_out`, rounded up:
+/// `reserve_in * amount_out * fee_den / ((reserve_out - amount_out) * (fee_den - fee_num)) + 1`.
+pub fn calculate_input_amount(
+    amount_out: u64,
+    input_reserve: u64,
+    output_reserve: u64,
+    fee_numerator: u64,
+    fee_denominator: u64,
+) -> Result<u64> {
+    require!(amount_out > 0, CustomError::InvalidAmount);
+    require!(
+        input_reserve > 0 && amount_out < output_reserve,
+        CustomError::InsufficientLiquidity
+    );
+    let numerator = (input_reserve as u128)
+        .checked_mul(amount_out as u128)
+        .ok_or(error!(CustomError::CalculationOverflow))?
+        .checked_mul(fee_denominator as u128)
+        .ok_or(error!(CustomError::CalculationOverflow))?;
+    let denominator = ((output_reserve - amount_out) as u128)
+        .checked_mul((fee_denominator - fee_numerator) as u128)
+        .ok_or(error!(CustomError::CalculationOverflow))?;
+    let input_amount = numerator
+        .checked_div(denominator)
+        .ok_or(error!(CustomError::CalculationOverflow))?
+        .checked_add(1)
+        .ok_or(error!(CustomError::CalculationOverflow))?;
+    u64::try_from(input_amount).map_err(|_| error!(CustomError::CalculationOverflow))
+}
+
+const STABLE_MAX_ITERATIONS: usize = 255;
+
+/// Two-coin StableSwap output: fee is taken from the input, then the new
+/// output reserve is solved from the D invariant.
+fn calculate_stable_output_amount(
+    input_amount: u64,
+    input_reserve: u64,
+    output_reserve: u64,
+    fee_numerator: u64,
+    fee_denominator: u64,
+    amp: u64,
+) -> Result<u64> {
+    require!(amp > 0, CustomError::InvalidAmount);
+    let amount_in_after_fee = (input_amount as u128)
+        .checked_mul((fee_denominator - fee_numerator) as u128)
+        .ok_or(error!(CustomError::CalculationOverflow))?
+        .checked_div(fee_denominator as u128)
+        .ok_or(error!(CustomError::CalculationOverflow))?;
+    let d = stable_invariant_d(input_reserve as u128, output_reserve as u128, amp)?;
+    let new_input_reserve = (input_reserve as u128)
+        .checked_add(amount_in_after_fee)
+        .ok_or(error!(CustomError::CalculationOverflow))?;
+    let new_output_reserve = stable_solve_y(new_input_reserve, d, amp)?;
+    // Round one unit in the pool's favour
+    let output_amount = (output_reserve as u128)
+        .saturating_sub(new_output_reserve)
+        .saturating_sub(1);
+    require!(output_amount > 0, CustomError::InsufficientLiquidity);
+    u64::try_from(output_amount).map_err(|_| error!(CustomError::CalculationOverflow))
+}
+
+/// Newton iteration for D in `A·n^n·Σx + D = A·D·n^n + D^(n+1) / (n^n·Πx)`, n = 2.
+fn stable_invariant_d(x: u128, y: u128, amp: u64) -> Result<u128> {
+    let sum = x.checked_add(y).ok_or(error!(CustomError::CalculationOverflow))?;
+    let ann = (amp as u128) * 4;
+    let mut d = sum;
+    for _ in 0..STABLE_MAX_ITERATIONS {
+        let mut d_p = d;
+        d_p = mul_div(d_p, d, x * 2)?;
+        d_p = mul_div(d_p, d, y * 2)?;
+        let d_prev = d;
+        let numerator = ann
+            .checked_mul(sum)
+            .and_then(|v| v.checked_add(d_p * 2))
+            .ok_or(error!(CustomError::CalculationOverflow))?;
+        let denominator = (ann - 1)
+            .checked_mul(d)
+            .and_then(|v| v.checked_add(d_p * 3))
+            .ok_or(error!(CustomError::CalculationOverflow))?;
+        d = mul_div(numerator, d, denominator)?;
+        if d.abs_diff(d_prev) <= 1 {
+            return Ok(d);
+        }
+    }
+    Err(error!(CustomError::CalculationOverflow))
+}
+
+/// Solve the invariant for the other reserve given one reserve and D.
+fn stable_solve_y(x: u128, d: u128, amp: u64) -> Result<u128> {
+    let ann = (amp as u128) * 4;
+    let c = mul_div(d, d, x * 2)?;
+    let c = mul_div(c, d, ann * 2)?;
+    let b = x + d / ann;
+    let mut y = d;
+    for _ in 0..STABLE_MAX_ITERATIONS {
+        let y_prev = y;
+        let numerator = y
+            .checked_mul(y)
+            .and_then(|v| v.checked_add(c))
+            .ok_or(error!(CustomError::CalculationOverflow))?;
+        let denominator = (y * 2 + b)
+            .checked_sub(d)
+            .filter(|v| *v > 0)
+            .ok_or(error!(CustomError::CalculationOverflow))?;
+        y = numerator / denominator;
+        if y.abs_diff(y_prev) <= 1 {
+            return Ok(y);
+        }
+    }
+    Err(error!(CustomError::CalculationOverflow))
+}
+
+/// Swap fee charged on `amount_in`, rounded up. Every swap path reports its
+/// `fee_amount` through this so the same trade books the same fee.
+pub fn calculate_swap_fee(amount_in: u64, fee_numerator: u64, fee_denominator: u64) -> Result<u64> {
+    require!(fee_denominator > 0, CustomError::InvalidFeeParameters);
+    let fee = (amount_in as u128)
+        .checked_mul(fee_numerator as u128)
+        .ok_or(error!(CustomError::CalculationOverflow))?
+        .div_ceil(fee_denominator as u128);
+    u64::try_from(fee).map_err(|_| error!(CustomError::CalculationOverflow))
+}
+
+/// Protocol's cut of a swap fee, rounded down so it never exceeds the pool fee.
+pub fn calculate_protocol_fee(
+    amount_in: u64,
+    protocol_fee_numerator: u64,
+    fee_denominator: u64,
+) -> Result<u64> {
+    let protocol_fee = (amount_in as u128)
+        .checked_mul(protocol_fee_numerator as u128)
+        .ok_or(error!(CustomError::CalculationOverflow))?
+        .checked_div(fee_denominator as u128)
+        .ok_or(error!(CustomError::CalculationOverflow))?;
+    Ok(protocol_fee as u64)
+}
+
+/// Flash-loan fee on `principal`, rounded up so a non-zero rate never rounds to free.
+pub fn calculate_flash_fee(principal: u64, flash_fee_bps: u16) -> Result<u64> {
+    let fee = (principal as u128)
+        .checked_mul(flash_fee_bps as u128)
+        .ok_or(error!(CustomError::CalculationOverflow))?
+        .div_ceil(10_000);
+    u64::try_from(fee).map_err(|_| error!(CustomError::CalculationOverflow))
+}
+
+/// Take the pool re-entrancy lock and persist it to account data immediately,
+/// so a nested invocation during a CPI observes `locked == true`.
+pub fn acquire_pool_lock(pool: &mut Account<LiquidityPool>) -> Result<()> {
+    pool.lock()?;
+    // Every reserve-mutating instruction takes the lock first, so this sees pre-trade reserves
+    pool.update_price_cumulatives(Clock::get()?.unix_timestamp);
+    pool.exit(&crate::ID)
+}
+
+/// Tokens that landed in `vault` since `balance_before` was read. Less than the
+/// transferred amount for fee-on-transfer mints.
+pub fn vault_received(vault: &mut Account<TokenAccount>, balance_before: u64) -> Result<u64> {
+    vault.reload()?;
+    vault
+        .amount
+        .checked_sub(balance_before)
+        .ok_or(error!(CustomError::CalculationOverflow))
+}
+
+/// Withhold `spread_bps` of an output amount, rounding the withheld part up
+/// so the spread never rounds in the trader's favour.
+pub fn apply_spread_bps(amount: u64, spread_bps: u16) -> Result<u64> {
+    if spread_bps == 0 {
+        return Ok(amount);
+    }
+    let withheld = (amount as u128)
+        .checked_mul(spread_bps as u128)
+        .ok_or(error!(CustomError::CalculationOverflow))?
+        .div_ceil(10_000);
+    let net = (amount as u128)
+        .checked_sub(withheld)
+        .ok_or(error!(CustomError::CalculationOverflow))? as u64;
+    require!(net > 0, CustomError::InsufficientLiquidity);
+    Ok(net)
+}
+
 pub fn isqrt(n: u128) -> u128 {
     if n < 2 {
         return n;
@@ -151,14 +436,280 @@ pub fn calculate_pool_price(reserve_a: u64, reserve_b: u64) -> Result<u64> {
     Ok(price)
 }
 
+/// Take-profit sells fire once the price rises to the target and buys once it
+/// falls to it; stop-loss orders fire on the opposite move.
 pub fn check_price_condition(
     pool_price: u64,
     target_price: u64,
     is_sell: bool,
+    kind: OrderKind,
 ) -> bool {
-    if is_sell {
+    if is_sell == (kind == OrderKind::TakeProfit) {
         pool_price >= target_price
     } else {
         pool_price <= target_price
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Buy A with `b_in`, then sell all A back; returns B received.
+    fn round_trip(b_in: u64, reserve_a: u64, reserve_b: u64, buy_bps: u16, sell_bps: u16) -> u64 {
+        let a_out = calculate_output_amount(b_in, reserve_b, reserve_a, 3, 1000, None).unwrap();
+        let a_out = apply_spread_bps(a_out, buy_bps).unwrap();
+        let (reserve_a, reserve_b) = (reserve_a - a_out, reserve_b + b_in);
+        let b_out = calculate_output_amount(a_out, reserve_a, reserve_b, 3, 1000, None).unwrap();
+        apply_spread_bps(b_out, sell_bps).unwrap()
+    }
+
+    #[test]
+    fn test_stop_loss_inverts_the_trigger() {
+        // Selling at 2.0: take-profit waits for the price to rise, stop-loss for it to fall
+        assert!(check_price_condition(2_100_000, 2_000_000, true, OrderKind::TakeProfit));
+        assert!(!check_price_condition(2_100_000, 2_000_000, true, OrderKind::StopLoss));
+        assert!(check_price_condition(1_900_000, 2_000_000, true, OrderKind::StopLoss));
+        assert!(check_price_condition(2_000_000, 2_000_000, true, OrderKind::StopLoss));
+        // Buying: stop-loss (stop-buy) fires once the price rises to the target
+        assert!(check_price_condition(2_100_000, 2_000_000, false, OrderKind::StopLoss));
+        assert!(!check_price_condition(1_900_000, 2_000_000, false, OrderKind::StopLoss));
+        assert!(check_price_condition(1_900_000, 2_000_000, false, OrderKind::TakeProfit));
+    }
+
+    #[test]
+    fn test_dust_initial_liquidity_rejected() {
+        // sqrt(10 * 1000) = 100 < 100_000
+        assert_eq!(
+            validate_initial_liquidity(10, 1_000, 100_000).unwrap_err(),
+            error!(CustomError::InvalidInitialDeposit)
+        );
+    }
+
+    #[test]
+    fn test_reasonable_initial_liquidity_accepted() {
+        // 1 token of each side at 6 decimals → sqrt(1e12) = 1e6
+        assert!(validate_initial_liquidity(1_000_000, 1_000_000, 100_000).is_ok());
+        // Exactly at the threshold is allowed
+        assert!(validate_initial_liquidity(100_000, 100_000, 100_000).is_ok());
+    }
+
+    #[test]
+    fn test_apply_spread_zero_is_identity() {
+        assert_eq!(apply_spread_bps(123_456, 0).unwrap(), 123_456);
+    }
+
+    #[test]
+    fn test_apply_spread_rounds_against_trader() {
+        // 1% of 999 = 9.99 → 10 withheld
+        assert_eq!(apply_spread_bps(999, 100).unwrap(), 989);
+    }
+
+    #[test]
+    fn test_round_trip_spread_widens_loss() {
+        let b_in = 1_000_000;
+        let plain = round_trip(b_in, 1_000_000_000_000, 1_000_000_000_000, 0, 0);
+        let spread = round_trip(b_in, 1_000_000_000_000, 1_000_000_000_000, 100, 100);
+        let plain_loss = b_in - plain;
+        let spread_loss = b_in - spread;
+        // Without spread the round trip costs ~2× the 0.3% fee
+        assert!(plain_loss >= 2 * 3 * b_in / 1000 - 10);
+        // Each leg withholds 1% more (the second on an already-reduced amount),
+        // so the extra loss is ~1.99% of the input
+        assert!(spread_loss - plain_loss >= 199 * b_in / 10_000 - 200);
+    }
+
+    #[test]
+    fn test_stable_curve_tighter_near_peg() {
+        // Balanced 1M/1M USDC-USDT pool at 6 decimals, 10k swap
+        let reserve = 1_000_000_000_000u64;
+        let amount_in = 10_000_000_000u64;
+        let cp = calculate_output_amount(amount_in, reserve, reserve, 3, 1000, None).unwrap();
+        let stable = calculate_output_amount(amount_in, reserve, reserve, 3, 1000, Some(100)).unwrap();
+        assert!(stable > cp);
+        // Only the 0.3% fee and a sliver of impact separate input from output
+        let fee_only = amount_in - amount_in * 3 / 1000;
+        assert!(stable <= fee_only);
+        assert!(fee_only - stable < fee_only / 10_000);
+        // Constant product loses about 1% to price impact at this size
+        assert!(fee_only - cp > fee_only / 200);
+    }
+
+    #[test]
+    fn test_stable_curve_never_exceeds_input() {
+        let reserve = 1_000_000_000u64;
+        for amount_in in [1_000u64, 1_000_000, 100_000_000] {
+            let out = calculate_output_amount(amount_in, reserve, reserve, 0, 1000, Some(1_000)).unwrap();
+            assert!(out < amount_in);
+        }
+    }
+
+    #[test]
+    fn test_stable_curve_degrades_when_imbalanced() {
+        // Draining the scarce side costs more than trading near balance
+        let balanced = calculate_output_amount(1_000_000, 50_000_000, 50_000_000, 3, 1000, Some(100)).unwrap();
+        let skewed = calculate_output_amount(1_000_000, 95_000_000, 5_000_000, 3, 1000, Some(100)).unwrap();
+        assert!(skewed < balanced);
+    }
+
+    #[test]
+    fn test_protocol_fee_is_share_of_pool_fee() {
+        // 0.3% pool fee, a third of it to the protocol
+        let amount_in = 1_000_000u64;
+        let pool_fee = amount_in * 3 / 1000;
+        let protocol_fee = calculate_protocol_fee(amount_in, 1, 1000).unwrap();
+        assert_eq!(protocol_fee, 1_000);
+        assert!(protocol_fee <= pool_fee);
+        assert_eq!(calculate_protocol_fee(amount_in, 0, 1000).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_protocol_fee_rounds_down() {
+        assert_eq!(calculate_protocol_fee(999, 1, 1000).unwrap(), 0);
+        assert_eq!(calculate_protocol_fee(1_999, 3, 1000).unwrap(), 5);
+    }
+
+    #[test]
+    fn test_exact_out_round_trip() {
+        for (amount_out, reserve_in, reserve_out) in [
+            (1_000u64, 1_000_000u64, 1_000_000u64),
+            (12_345, 1_000_000_000, 250_000_000_000),
+            (900_000_000, 5_000_000_000, 1_000_000_000),
+        ] {
+            let amount_in = calculate_input_amount(amount_out, reserve_in, reserve_out, 3, 1000).unwrap();
+            let received = calculate_output_amount(amount_in, reserve_in, reserve_out, 3, 1000, None).unwrap();
+            assert!(received >= amount_out);
+            // Quoted input is within 1 unit of the minimum that still delivers amount_out
+            let below = calculate_output_amount(amount_in - 2, reserve_in, reserve_out, 3, 1000, None).unwrap_or(0);
+            assert!(below < amount_out);
+        }
+    }
+
+    #[test]
+    fn test_exact_out_cannot_drain_reserve() {
+        assert_eq!(
+            calculate_input_amount(1_000, 1_000, 1_000, 3, 1000).unwrap_err(),
+            error!(CustomError::InsufficientLiquidity)
+        );
+    }
+
+    #[test]
+    fn test_flash_fee_rounds_up() {
+        assert_eq!(calculate_flash_fee(1_000_000, 9).unwrap(), 900);
+        assert_eq!(calculate_flash_fee(1, 9).unwrap(), 1);
+        assert_eq!(calculate_flash_fee(1_000_000, 0).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_first_deposit_locks_minimum_liquidity() {
+        assert_eq!(
+            calculate_lp_tokens_for_add_liquidity(1_000_000, 1_000_000, 0, 0, 0).unwrap(),
+            1_000_000 - MINIMUM_LIQUIDITY
+        );
+        assert_eq!(
+            calculate_lp_tokens_for_add_liquidity(1_000, 1_000, 0, 0, 0).unwrap_err(),
+            error!(CustomError::InitialLiquidityTooLow)
+        );
+    }
+
+    #[test]
+    fn test_first_deposit_inflation_attack_unprofitable() {
+        // Attacker seeds the smallest pool allowed, then donates straight to the vaults
+        let (seed, donation) = (1_001u64, 1_000_000_000u64);
+        let attacker_lp = calculate_lp_tokens_for_add_liquidity(seed, seed, 0, 0, 0).unwrap();
+        assert_eq!(attacker_lp, 1);
+        let mut supply = attacker_lp + MINIMUM_LIQUIDITY;
+        let reserve = seed + donation;
+
+        // The victim still gets shares instead of rounding down to zero
+        let deposit = 500_000_000u64;
+        let victim_lp = calculate_lp_tokens_for_add_liquidity(deposit, deposit, reserve, reserve, supply).unwrap();
+        assert!(victim_lp > 0);
+        supply += victim_lp;
+        let reserve = reserve + deposit;
+
+        let (victim_out, _) = calculate_remove_liquidity_amounts(victim_lp, supply, reserve, reserve).unwrap();
+        assert!(deposit - victim_out < deposit / 100);
+        // Most of the donation is stranded behind the locked shares
+        let (attacker_out, _) = calculate_remove_liquidity_amounts(attacker_lp, supply, reserve, reserve).unwrap();
+        assert!(attacker_out < donation / 100);
+    }
+
+    #[test]
+    fn test_price_impact_guard_trips_on_thin_pool() {
+        // 10% of a thin pool's reserves: ~9.1% curve impact plus the 0.3% fee
+        let (reserve, amount_in) = (1_000_000u64, 100_000u64);
+        let amount_out = calculate_output_amount(amount_in, reserve, reserve, 3, 1000, None).unwrap();
+        let impact = calculate_price_impact_bps(amount_in, amount_out, reserve, reserve).unwrap();
+        assert!((930..=940).contains(&impact));
+        assert!(impact > 500);
+        // A small trade in the same pool only pays about the fee
+        let amount_out = calculate_output_amount(1_000, reserve, reserve, 3, 1000, None).unwrap();
+        assert!(calculate_price_impact_bps(1_000, amount_out, reserve, reserve).unwrap() <= 50);
+    }
+
+    #[test]
+    fn test_single_sided_split_matches_pool_ratio() {
+        for (amount_in, reserve_in, reserve_out) in [
+            (1_000_000u64, 1_000_000_000u64, 5_000_000_000u64),
+            (250_000_000, 1_000_000_000, 1_000_000_000),
+            (7_777_777, 123_456_789_000, 987_654_321),
+        ] {
+            let swap_amount = calculate_single_sided_swap_amount(amount_in, reserve_in, 3, 1000).unwrap();
+            let swap_out = calculate_output_amount(swap_amount, reserve_in, reserve_out, 3, 1000, None).unwrap();
+            let (reserve_in, reserve_out) = (reserve_in + swap_amount, reserve_out - swap_out);
+            let supply = 1_000_000_000_000u64;
+            let lp_from_in = (amount_in - swap_amount) as u128 * supply as u128 / reserve_in as u128;
+            let lp_from_out = swap_out as u128 * supply as u128 / reserve_out as u128;
+            // Both sides buy the same share of the pool, up to output rounding
+            let diff = lp_from_in.abs_diff(lp_from_out);
+            assert!(diff * 10_000 <= lp_from_in.max(lp_from_out));
+        }
+    }
+
+    #[test]
+    fn test_single_sided_swaps_about_half() {
+        // Small deposit: just over half is swapped to cover the fee on the swapped half
+        let swap_amount = calculate_single_sided_swap_amount(1_000_000, 1_000_000_000_000, 3, 1000).unwrap();
+        assert!((500_000..=500_800).contains(&swap_amount));
+    }
+
+    #[test]
+    fn test_percent_withdrawals() {
+        let lp = 1_000_003u64;
+        assert_eq!(calculate_lp_tokens_for_percent(lp, 10_000).unwrap(), lp);
+        assert_eq!(calculate_lp_tokens_for_percent(lp, 5_000).unwrap(), 500_001);
+        assert_eq!(calculate_lp_tokens_for_percent(lp, 3_300).unwrap(), 330_000);
+        // 50% twice leaves one unit; a final 100% sweeps it with no dust
+        let rest = lp - 2 * calculate_lp_tokens_for_percent(lp, 5_000).unwrap();
+        assert_eq!(calculate_lp_tokens_for_percent(rest, 10_000).unwrap(), rest);
+        for bps in [0u16, 10_001] {
+            assert_eq!(
+                calculate_lp_tokens_for_percent(lp, bps).unwrap_err(),
+                error!(CustomError::InvalidAmount)
+            );
+        }
+    }
+
+    #[test]
+    fn test_swap_toward_twap_pays_lower_dynamic_fee() {
+        // 30 bps base fee, ±25% for swaps that pull spot toward / away from a TWAP of 100
+        let (twap, below, above) = (100u128, 90u128, 110u128);
+        assert_eq!(dynamic_fee_numerator(below, 95, twap, 30, 2_500, 0, 100), 23);
+        assert_eq!(dynamic_fee_numerator(above, 105, twap, 30, 2_500, 0, 100), 23);
+        assert_eq!(dynamic_fee_numerator(below, 85, twap, 30, 2_500, 0, 100), 37);
+        // Overshooting the TWAP by more than spot was off is no rebalance
+        assert_eq!(dynamic_fee_numerator(below, 115, twap, 30, 2_500, 0, 100), 37);
+        // Disabled
+        assert_eq!(dynamic_fee_numerator(below, 95, twap, 30, 0, 0, 100), 30);
+    }
+
+    #[test]
+    fn test_dynamic_fee_stays_in_band_and_above_protocol_fee() {
+        // A 25..=35 bps band stops the cut at 25 and the raise at 35
+        assert_eq!(dynamic_fee_numerator(90, 95, 100, 30, 2_500, 25, 35), 25);
+        assert_eq!(dynamic_fee_numerator(90, 85, 100, 30, 2_500, 25, 35), 35);
+        // A floor above the ceiling (protocol fee over the band) still wins
+        assert_eq!(dynamic_fee_numerator(90, 95, 100, 30, 2_500, 28, 20), 28);
+    }
+}
@@ -121,6 +121,20 @@ describe("SolRush DEX - Comprehensive MVP Test Suite", () => {
 
     userLpTokenAccount = await getAssociatedTokenAddress(lpTokenMint, wallet.publicKey);
 
+    // Liquidity instructions accrue RUSH rewards, so the config must exist first
+    try {
+      await program.methods
+        .initializeRushToken()
+        .accounts({
+          rushMint: rushMint.publicKey,
+          authority: wallet.publicKey,
+        })
+        .signers([rushMint])
+        .rpc();
+    } catch (e: any) {
+      if (!e.message.includes("already in use")) throw e;
+    }
+
     console.log("Pool PDA:", poolPDA.toBase58());
     console.log("LP Mint PDA:", lpTokenMint.toBase58());
     console.log("\n Setup complete!\n");
@@ -137,7 +151,7 @@ describe("SolRush DEX - Comprehensive MVP Test Suite", () => {
       console.log("   Deposit B:", DEPOSIT_B / 10 ** DECIMALS);
 
       const tx = await program.methods
-        .initializePool()
+        .initializePool(new anchor.BN(0), new anchor.BN(0), new anchor.BN(0))
         .accounts({
           tokenAMint: tokenAMint,
           tokenBMint: tokenBMint,
@@ -158,6 +172,38 @@ describe("SolRush DEX - Comprehensive MVP Test Suite", () => {
       assert.equal(pool.reserveA.toString(), "0");
       assert.equal(pool.reserveB.toString(), "0");
     });
+
+    it("Should reject a limit order on an unfunded pool", async () => {
+      const orderId = new anchor.BN(1);
+      const orderVault = Keypair.generate();
+      try {
+        await program.methods
+          .createLimitOrder(
+            new anchor.BN(10 ** DECIMALS),
+            new anchor.BN(10 ** DECIMALS),
+            new anchor.BN(1),
+            new anchor.BN(1),
+            orderId,
+            new anchor.BN(0),
+            { takeProfit: {} },
+            0,
+            { goodTilCancelled: {} }
+          )
+          .accounts({
+            pool: poolPDA,
+            sellTokenMint: tokenAMint,
+            userTokenIn: userTokenA,
+            userTokenOut: userTokenB,
+            orderVault: orderVault.publicKey,
+            user: wallet.publicKey,
+          })
+          .signers([orderVault])
+          .rpc();
+        assert.fail("Expected PoolNotFunded");
+      } catch (e) {
+        assert.include(e.toString(), "PoolNotFunded");
+      }
+    });
   });
 
   // =========================================================================
@@ -181,6 +227,7 @@ describe("SolRush DEX - Comprehensive MVP Test Suite", () => {
         )
         .accounts({
           pool: poolPDA,
+          rushConfig: rushConfig,
           lpTokenMint: lpTokenMint,
           tokenAVault: tokenAVault.publicKey,
           tokenBVault: tokenBVault.publicKey,
@@ -196,6 +243,72 @@ describe("SolRush DEX - Comprehensive MVP Test Suite", () => {
       const lpBalance = await getAccount(connection, userLpTokenAccount);
       console.log("   LP Tokens:", Number(lpBalance.amount) / 10 ** DECIMALS);
       assert.isTrue(Number(lpBalance.amount) > 0);
+
+      // The first deposit's locked minimum is counted in supply but never minted
+      const liquidity = await program.methods
+        .getPoolLiquidity()
+        .accounts({ pool: poolPDA })
+        .view();
+      assert.equal(liquidity.lockedLiquidity.toNumber(), 1000);
+      assert.equal(
+        liquidity.redeemableLpSupply.toString(),
+        liquidity.totalLpSupply.sub(liquidity.lockedLiquidity).toString()
+      );
+      assert.equal(liquidity.redeemableLpSupply.toString(), lpBalance.amount.toString());
+    });
+
+    it("Should add single-sided liquidity", async () => {
+      const amountIn = 50 * 10 ** DECIMALS;
+      const lpBefore = await getAccount(connection, userLpTokenAccount);
+      const bBefore = await getAccount(connection, userTokenB);
+
+      await program.methods
+        .addLiquiditySingleSided(new anchor.BN(amountIn), true, new anchor.BN(1))
+        .accounts({
+          pool: poolPDA,
+          rushConfig: rushConfig,
+          lpTokenMint: lpTokenMint,
+          tokenAVault: tokenAVault.publicKey,
+          tokenBVault: tokenBVault.publicKey,
+          userTokenA: userTokenA,
+          userTokenB: userTokenB,
+          userLpTokenAccount: userLpTokenAccount,
+          user: wallet.publicKey,
+        })
+        .rpc();
+
+      const lpAfter = await getAccount(connection, userLpTokenAccount);
+      const bAfter = await getAccount(connection, userTokenB);
+      assert.isTrue(lpAfter.amount > lpBefore.amount);
+      // Only token A is taken from the user
+      assert.equal(bAfter.amount.toString(), bBefore.amount.toString());
+    });
+
+    it("Should reject a vault with the wrong mint", async () => {
+      try {
+        await program.methods
+          .addLiquidity(
+            new anchor.BN(10 * 10 ** DECIMALS),
+            new anchor.BN(50 * 10 ** DECIMALS),
+            new anchor.BN(1)
+          )
+          .accounts({
+            pool: poolPDA,
+            rushConfig: rushConfig,
+            lpTokenMint: lpTokenMint,
+            // token B's vault passed in the token A slot
+            tokenAVault: tokenBVault.publicKey,
+            tokenBVault: tokenBVault.publicKey,
+            userTokenA: userTokenB,
+            userTokenB: userTokenB,
+            userLpTokenAccount: userLpTokenAccount,
+            user: wallet.publicKey,
+          })
+          .rpc();
+        assert.fail("Expected the mismatched vault to be rejected");
+      } catch (e: any) {
+        assert.match(e.toString(), /InvalidVault|InvalidMint/);
+      }
     });
   });
 
@@ -213,12 +326,17 @@ describe("SolRush DEX - Comprehensive MVP Test Suite", () => {
 
       const balanceBefore = await getAccount(connection, userTokenB);
 
+      let swapEvent: any;
+      const listener = program.addEventListener("swapExecuted", (event) => {
+        swapEvent = event;
+      });
       const tx = await program.methods
         .swap(
           new anchor.BN(swapAmount),
           new anchor.BN(1),
           true,
-          new anchor.BN(deadline)
+          new anchor.BN(deadline),
+          0
         )
         .accounts({
           pool: poolPDA,
@@ -228,7 +346,9 @@ describe("SolRush DEX - Comprehensive MVP Test Suite", () => {
           userTokenOut: userTokenB,
           user: wallet.publicKey,
         })
-        .rpc();
+        .rpc({ commitment: "confirmed" });
+      await new Promise((resolve) => setTimeout(resolve, 1000));
+      await program.removeEventListener(listener);
 
       console.log("Swap executed! Tx:", tx);
 
@@ -236,6 +356,10 @@ describe("SolRush DEX - Comprehensive MVP Test Suite", () => {
       const received = Number(balanceAfter.amount) - Number(balanceBefore.amount);
       console.log("   Received:", received / 10 ** DECIMALS, "Token B");
       assert.isTrue(received > 0);
+      // The event reports exactly the reserves left on-chain
+      const pool = await program.account.liquidityPool.fetch(poolPDA, "confirmed");
+      assert.isTrue(swapEvent.newReserveA.eq(pool.reserveA));
+      assert.isTrue(swapEvent.newReserveB.eq(pool.reserveB));
     });
 
     it("Should swap Token B to Token A", async () => {
@@ -252,7 +376,8 @@ describe("SolRush DEX - Comprehensive MVP Test Suite", () => {
           new anchor.BN(swapAmount),
           new anchor.BN(1),
           false,
-          new anchor.BN(deadline)
+          new anchor.BN(deadline),
+          0
         )
         .accounts({
           pool: poolPDA,
@@ -271,6 +396,137 @@ describe("SolRush DEX - Comprehensive MVP Test Suite", () => {
       console.log("   Received:", received / 10 ** DECIMALS, "Token A");
       assert.isTrue(received > 0);
     });
+
+    it("Should report accumulated swap fees", async () => {
+      const pool = await program.account.liquidityPool.fetch(poolPDA);
+      assert.isTrue(pool.cumulativeFeesA.gtn(0));
+      assert.isTrue(pool.cumulativeFeesB.gtn(0));
+
+      const fees = await program.methods
+        .getPoolFees()
+        .accounts({ pool: poolPDA, position: userPositionPDA })
+        .view();
+      assert.isTrue(fees.cumulativeFeesA.eq(pool.cumulativeFeesA));
+      assert.isTrue(fees.positionFeesA.lte(fees.cumulativeFeesA));
+      assert.isTrue(fees.positionFeesB.gtn(0));
+    });
+
+    it("Should reject a swap beyond the price-impact cap", async () => {
+      const deadline = Math.floor(Date.now() / 1000) + 3600;
+      // The 0.3% fee alone exceeds a 1 bps cap
+      try {
+        await program.methods
+          .swap(
+            new anchor.BN(10 * 10 ** DECIMALS),
+            new anchor.BN(1),
+            true,
+            new anchor.BN(deadline),
+            1
+          )
+          .accounts({
+            pool: poolPDA,
+            poolVaultIn: tokenAVault.publicKey,
+            poolVaultOut: tokenBVault.publicKey,
+            userTokenIn: userTokenA,
+            userTokenOut: userTokenB,
+            user: wallet.publicKey,
+          })
+          .rpc();
+        assert.fail("Expected PriceImpactTooHigh");
+      } catch (err: any) {
+        assert.include(err.toString(), "PriceImpactTooHigh");
+      }
+    });
+  });
+
+  // =========================================================================
+  // TEST 3b: PROTOCOL FEE BAND
+  // =========================================================================
+
+  describe("3b. Pool Fee Band", () => {
+    before(async () => {
+      try {
+        await program.methods
+          .initializeAmmConfig()
+          .accounts({ authority: wallet.publicKey })
+          .rpc();
+      } catch (e: any) {
+        if (!e.message.includes("already in use")) throw e;
+      }
+      await program.methods
+        .setFeeBounds(10, 100)
+        .accounts({ authority: wallet.publicKey })
+        .rpc();
+    });
+
+    for (const [label, feeBps] of [["below the floor", 5], ["above the ceiling", 150]] as const) {
+      it(`Should reject a pool fee ${label}`, async () => {
+        try {
+          await program.methods
+            .setPoolFee(feeBps)
+            .accounts({ pool: poolPDA, authority: wallet.publicKey })
+            .rpc();
+          assert.fail("Expected InvalidFeeParameters");
+        } catch (err: any) {
+          assert.include(err.toString(), "InvalidFeeParameters");
+        }
+      });
+    }
+
+    it("Should accept an in-band pool fee", async () => {
+      await program.methods
+        .setPoolFee(30)
+        .accounts({ pool: poolPDA, authority: wallet.publicKey })
+        .rpc();
+      const pool = await program.account.liquidityPool.fetch(poolPDA);
+      assert.equal(pool.feeNumerator.toNumber(), 30);
+      assert.equal(pool.feeDenominator.toNumber(), 10_000);
+    });
+  });
+
+  // =========================================================================
+  // TEST 3c: PROTOCOL SLIPPAGE CAP
+  // =========================================================================
+
+  describe("3c. Protocol Slippage Cap", () => {
+    const setCap = (bps: number) =>
+      program.methods
+        .setMaxAllowedSlippage(bps)
+        .accounts({ authority: wallet.publicKey })
+        .rpc();
+    const zeroMinimumSwap = () =>
+      program.methods
+        .swap(
+          new anchor.BN(1 * 10 ** DECIMALS),
+          new anchor.BN(0),
+          true,
+          new anchor.BN(Math.floor(Date.now() / 1000) + 3600),
+          0
+        )
+        .accounts({
+          pool: poolPDA,
+          poolVaultIn: tokenAVault.publicKey,
+          poolVaultOut: tokenBVault.publicKey,
+          userTokenIn: userTokenA,
+          userTokenOut: userTokenB,
+          user: wallet.publicKey,
+        })
+        .rpc();
+
+    it("Should reject a zero-minimum swap while the cap is enabled", async () => {
+      await setCap(100);
+      try {
+        await zeroMinimumSwap();
+        assert.fail("Expected SlippageTooHigh");
+      } catch (err: any) {
+        assert.include(err.toString(), "SlippageTooHigh");
+      }
+    });
+
+    it("Should accept a zero-minimum swap once the cap is disabled", async () => {
+      await setCap(0);
+      await zeroMinimumSwap();
+    });
   });
 
   // =========================================================================
@@ -294,6 +550,7 @@ describe("SolRush DEX - Comprehensive MVP Test Suite", () => {
         )
         .accounts({
           pool: poolPDA,
+          rushConfig: rushConfig,
           lpTokenMint: lpTokenMint,
           tokenAVault: tokenAVault.publicKey,
           tokenBVault: tokenBVault.publicKey,
@@ -306,6 +563,176 @@ describe("SolRush DEX - Comprehensive MVP Test Suite", () => {
 
       console.log("Liquidity removed! Tx:", tx);
     });
+
+    it("Should reject emergency removal on an active pool", async () => {
+      try {
+        await program.methods
+          .emergencyRemoveLiquidity(new anchor.BN(1000))
+          .accounts({
+            pool: poolPDA,
+            rushConfig: rushConfig,
+            lpTokenMint: lpTokenMint,
+            tokenAVault: tokenAVault.publicKey,
+            tokenBVault: tokenBVault.publicKey,
+            userLpTokenAccount: userLpTokenAccount,
+            userTokenA: userTokenA,
+            userTokenB: userTokenB,
+            user: wallet.publicKey,
+          })
+          .rpc();
+        assert.fail("Expected PoolNotPaused");
+      } catch (e: any) {
+        assert.include(e.toString(), "PoolNotPaused");
+      }
+    });
+
+    it("Should allow emergency removal of a locked position while paused", async () => {
+      const setPaused = (isPaused: boolean) =>
+        program.methods
+          .setPoolPaused(isPaused)
+          .accounts({ pool: poolPDA, authority: wallet.publicKey })
+          .rpc();
+
+      await program.methods
+        .lockPosition(new anchor.BN(30))
+        .accounts({ position: userPositionPDA, pool: poolPDA, user: wallet.publicKey })
+        .rpc();
+      await setPaused(true);
+      const before = await getAccount(connection, userLpTokenAccount);
+      const lpToRemove = Math.floor(Number(before.amount) / 20);
+      // No slippage floor: whatever the proportional share yields is accepted
+      await program.methods
+        .emergencyRemoveLiquidity(new anchor.BN(lpToRemove))
+        .accounts({
+          pool: poolPDA,
+          rushConfig: rushConfig,
+          lpTokenMint: lpTokenMint,
+          tokenAVault: tokenAVault.publicKey,
+          tokenBVault: tokenBVault.publicKey,
+          userLpTokenAccount: userLpTokenAccount,
+          userTokenA: userTokenA,
+          userTokenB: userTokenB,
+          user: wallet.publicKey,
+        })
+        .rpc();
+      const after = await getAccount(connection, userLpTokenAccount);
+      assert.equal(Number(before.amount) - Number(after.amount), lpToRemove);
+      // The lock ended with the exit and took its boost with it
+      const position = await program.account.userLiquidityPosition.fetch(userPositionPDA);
+      assert.isAtMost(position.lockUntilTs.toNumber(), Math.floor(Date.now() / 1000) + 60);
+      assert.equal(position.boostMultiplierBps, 0);
+      await setPaused(false);
+    });
+
+    it("Should block swaps but not withdrawals while paused", async () => {
+      const setPaused = (isPaused: boolean) =>
+        program.methods
+          .setPoolPaused(isPaused)
+          .accounts({ pool: poolPDA, authority: wallet.publicKey })
+          .rpc();
+      const deadline = Math.floor(Date.now() / 1000) + 3600;
+
+      await setPaused(true);
+      try {
+        await program.methods
+          .swap(new anchor.BN(10 ** DECIMALS), new anchor.BN(1), true, new anchor.BN(deadline), 0)
+          .accounts({
+            pool: poolPDA,
+            poolVaultIn: tokenAVault.publicKey,
+            poolVaultOut: tokenBVault.publicKey,
+            userTokenIn: userTokenA,
+            userTokenOut: userTokenB,
+            user: wallet.publicKey,
+          })
+          .rpc();
+        assert.fail("Expected PoolPaused");
+      } catch (e) {
+        assert.include(e.toString(), "PoolPaused");
+      }
+
+      const before = await getAccount(connection, userLpTokenAccount);
+      const lpToRemove = Math.floor(Number(before.amount) / 20);
+      await program.methods
+        .removeLiquidity(new anchor.BN(lpToRemove), new anchor.BN(0), new anchor.BN(0))
+        .accounts({
+          pool: poolPDA,
+          rushConfig: rushConfig,
+          lpTokenMint: lpTokenMint,
+          tokenAVault: tokenAVault.publicKey,
+          tokenBVault: tokenBVault.publicKey,
+          userLpTokenAccount: userLpTokenAccount,
+          userTokenA: userTokenA,
+          userTokenB: userTokenB,
+          user: wallet.publicKey,
+        })
+        .rpc();
+      const after = await getAccount(connection, userLpTokenAccount);
+      assert.equal(Number(before.amount) - Number(after.amount), lpToRemove);
+      // The lock ended with the exit and took its boost with it
+      const position = await program.account.userLiquidityPosition.fetch(userPositionPDA);
+      assert.isAtMost(position.lockUntilTs.toNumber(), Math.floor(Date.now() / 1000) + 60);
+      assert.equal(position.boostMultiplierBps, 0);
+      await setPaused(false);
+    });
+
+    it("Should sync reserves after a direct vault transfer", async () => {
+      const airdrop = 7 * 10 ** DECIMALS;
+      const poolBefore = await program.account.liquidityPool.fetch(poolPDA);
+      await mintTo(connection, wallet.payer, tokenAMint, tokenAVault.publicKey, wallet.publicKey, airdrop);
+
+      await program.methods
+        .syncReserves()
+        .accounts({
+          pool: poolPDA,
+          tokenAVault: tokenAVault.publicKey,
+          tokenBVault: tokenBVault.publicKey,
+          authority: wallet.publicKey,
+        })
+        .rpc();
+
+      const poolAfter = await program.account.liquidityPool.fetch(poolPDA);
+      const vaultA = await getAccount(connection, tokenAVault.publicKey);
+      const vaultB = await getAccount(connection, tokenBVault.publicKey);
+      assert.equal(poolAfter.reserveA.toString(), vaultA.amount.toString());
+      assert.equal(poolAfter.reserveB.toString(), vaultB.amount.toString());
+      assert.isTrue(poolAfter.reserveA.sub(poolBefore.reserveA).gten(airdrop));
+    });
+
+    it("Should quote a position's full redemption value", async () => {
+      const liquidityAccounts = {
+        pool: poolPDA,
+        rushConfig: rushConfig,
+        lpTokenMint: lpTokenMint,
+        tokenAVault: tokenAVault.publicKey,
+        tokenBVault: tokenBVault.publicKey,
+        userLpTokenAccount: userLpTokenAccount,
+        userTokenA: userTokenA,
+        userTokenB: userTokenB,
+        user: wallet.publicKey,
+      };
+      const value = await program.methods
+        .getPositionValue()
+        .accounts({ pool: poolPDA, position: userPositionPDA, usdOracle: null })
+        .view();
+      assert.isNull(value.usdValue);
+
+      const aBefore = await getAccount(connection, userTokenA);
+      const bBefore = await getAccount(connection, userTokenB);
+      await program.methods
+        .removeLiquidity(value.lpTokens, new anchor.BN(0), new anchor.BN(0))
+        .accounts(liquidityAccounts)
+        .rpc();
+      const aAfter = await getAccount(connection, userTokenA);
+      const bAfter = await getAccount(connection, userTokenB);
+      assert.equal((aAfter.amount - aBefore.amount).toString(), value.amountA.toString());
+      assert.equal((bAfter.amount - bBefore.amount).toString(), value.amountB.toString());
+
+      // Put the liquidity back for the sections that follow
+      await program.methods
+        .addLiquidity(value.amountA, value.amountB, new anchor.BN(1))
+        .accounts(liquidityAccounts)
+        .rpc();
+    });
   });
 
   // =========================================================================
@@ -320,6 +747,10 @@ describe("SolRush DEX - Comprehensive MVP Test Suite", () => {
       console.log("\n Market Buy...");
       console.log("   Spending:", amount / 10 ** DECIMALS, "Token B");
 
+      let swapEvent: any;
+      const listener = program.addEventListener("swapExecuted", (event) => {
+        swapEvent = event;
+      });
       const tx = await program.methods
         .marketBuy(
           new anchor.BN(amount),
@@ -334,9 +765,14 @@ describe("SolRush DEX - Comprehensive MVP Test Suite", () => {
           poolVaultOut: tokenAVault.publicKey,
           user: wallet.publicKey,
         })
-        .rpc();
+        .rpc({ commitment: "confirmed" });
+      await new Promise((resolve) => setTimeout(resolve, 1000));
+      await program.removeEventListener(listener);
 
       console.log("Market Buy executed! Tx:", tx);
+      // Fees on a market buy are charged in the input token
+      assert.isTrue(swapEvent.feeToken.equals(tokenBMint));
+      assert.equal(swapEvent.isAToB, false);
     });
 
     it("Should execute market sell", async () => {
@@ -347,6 +783,10 @@ describe("SolRush DEX - Comprehensive MVP Test Suite", () => {
       console.log("\n Market Sell...");
       console.log("   Selling:", amount / 10 ** DECIMALS, "Token A");
 
+      let swapEvent: any;
+      const listener = program.addEventListener("swapExecuted", (event) => {
+        swapEvent = event;
+      });
       const tx = await program.methods
         .marketSell(
           new anchor.BN(amount),
@@ -361,9 +801,176 @@ describe("SolRush DEX - Comprehensive MVP Test Suite", () => {
           poolVaultOut: tokenBVault.publicKey,
           user: wallet.publicKey,
         })
-        .rpc();
+        .rpc({ commitment: "confirmed" });
+      await new Promise((resolve) => setTimeout(resolve, 1000));
+      await program.removeEventListener(listener);
 
       console.log("Market Sell executed! Tx:", tx);
+      // Fees on a market sell are charged in the input token
+      assert.isTrue(swapEvent.feeToken.equals(tokenAMint));
+      assert.equal(swapEvent.isAToB, true);
+    });
+  });
+
+  // =========================================================================
+  // TEST 5a: TWO-HOP ROUTING (A → B → C)
+  // =========================================================================
+
+  describe("5a. Two-Hop Routing", () => {
+    let tokenCMint: PublicKey;
+    let userTokenC: PublicKey;
+    let poolBC: PublicKey;
+    let vaultBC_B: Keypair;
+    let vaultBC_C: Keypair;
+
+    before(async () => {
+      tokenCMint = await createMint(connection, wallet.payer, wallet.publicKey, null, DECIMALS);
+      userTokenC = await createAccount(connection, wallet.payer, tokenCMint, wallet.publicKey);
+      await mintTo(connection, wallet.payer, tokenCMint, userTokenC, wallet.publicKey, INITIAL_MINT);
+      [poolBC] = PublicKey.findProgramAddressSync(
+        [Buffer.from("pool"), tokenBMint.toBuffer(), tokenCMint.toBuffer()],
+        program.programId
+      );
+      vaultBC_B = Keypair.generate();
+      vaultBC_C = Keypair.generate();
+      await program.methods
+        .initializePool(new anchor.BN(0), new anchor.BN(500 * 10 ** DECIMALS), new anchor.BN(250 * 10 ** DECIMALS))
+        .accounts({
+          tokenAMint: tokenBMint,
+          tokenBMint: tokenCMint,
+          tokenAVault: vaultBC_B.publicKey,
+          tokenBVault: vaultBC_C.publicKey,
+          authorityTokenA: userTokenB,
+          authorityTokenB: userTokenC,
+          authority: wallet.publicKey,
+        })
+        .signers([vaultBC_B, vaultBC_C])
+        .rpc();
+    });
+
+    const routeAccounts = () => ({
+      firstPool: poolPDA,
+      secondPool: poolBC,
+      userTokenIn: userTokenA,
+      userTokenMid: userTokenB,
+      userTokenOut: userTokenC,
+      firstVaultIn: tokenAVault.publicKey,
+      firstVaultOut: tokenBVault.publicKey,
+      secondVaultIn: vaultBC_B.publicKey,
+      secondVaultOut: vaultBC_C.publicKey,
+      user: wallet.publicKey,
+    });
+
+    it("Should route A to C through both pools", async () => {
+      const amountIn = 5 * 10 ** DECIMALS;
+      const deadline = Math.floor(Date.now() / 1000) + 3600;
+      const swaps: any[] = [];
+      let route: any;
+      const swapListener = program.addEventListener("swapExecuted", (e) => swaps.push(e));
+      const routeListener = program.addEventListener("routeExecuted", (e) => { route = e; });
+      const midBefore = await getAccount(connection, userTokenB);
+      const outBefore = await getAccount(connection, userTokenC);
+
+      await program.methods
+        .swapTwoHop(new anchor.BN(amountIn), new anchor.BN(1), new anchor.BN(deadline))
+        .accounts(routeAccounts())
+        .rpc({ commitment: "confirmed" });
+      await new Promise((resolve) => setTimeout(resolve, 1000));
+      await program.removeEventListener(swapListener);
+      await program.removeEventListener(routeListener);
+
+      // The whole first-hop output is spent by the second hop
+      const midAfter = await getAccount(connection, userTokenB);
+      const outAfter = await getAccount(connection, userTokenC);
+      assert.equal(midAfter.amount.toString(), midBefore.amount.toString());
+      assert.equal(swaps.length, 2);
+      assert.isTrue(swaps[0].amountOut.eq(route.intermediateAmount));
+      assert.isTrue(swaps[1].amountIn.eq(route.intermediateAmount));
+      assert.equal((outAfter.amount - outBefore.amount).toString(), route.amountOut.toString());
+    });
+
+    it("Should enforce only the final minimum", async () => {
+      const deadline = Math.floor(Date.now() / 1000) + 3600;
+      try {
+        await program.methods
+          .swapTwoHop(new anchor.BN(5 * 10 ** DECIMALS), new anchor.BN(1_000 * 10 ** DECIMALS), new anchor.BN(deadline))
+          .accounts(routeAccounts())
+          .rpc();
+        assert.fail("Expected SlippageTooHigh");
+      } catch (e) {
+        assert.include(e.toString(), "SlippageTooHigh");
+      }
+    });
+  });
+
+  // =========================================================================
+  // TEST 5b: FLASH LOANS
+  // =========================================================================
+
+  describe("5b. Flash Loans", () => {
+    it("Should borrow and repay within one transaction", async () => {
+      const [receipt] = PublicKey.findProgramAddressSync(
+        [Buffer.from("flash_loan"), poolPDA.toBuffer()],
+        program.programId
+      );
+      const principal = 10 * 10 ** DECIMALS;
+      const poolBefore = await program.account.liquidityPool.fetch(poolPDA);
+      const vaultBefore = await getAccount(connection, tokenAVault.publicKey);
+
+      const repayIx = await program.methods
+        .flashLoanRepay()
+        .accounts({
+          pool: poolPDA,
+          receipt,
+          vault: tokenAVault.publicKey,
+          borrowerTokenAccount: userTokenA,
+          borrower: wallet.publicKey,
+        })
+        .instruction();
+      await program.methods
+        .flashLoanBorrow(new anchor.BN(principal))
+        .accounts({
+          pool: poolPDA,
+          receipt,
+          vault: tokenAVault.publicKey,
+          borrowerTokenAccount: userTokenA,
+          borrower: wallet.publicKey,
+          instructions: anchor.web3.SYSVAR_INSTRUCTIONS_PUBKEY,
+        })
+        .postInstructions([repayIx])
+        .rpc();
+
+      const poolAfter = await program.account.liquidityPool.fetch(poolPDA);
+      const vaultAfter = await getAccount(connection, tokenAVault.publicKey);
+      const fee = Math.ceil((principal * poolBefore.flashFeeBps) / 10_000);
+      // Vault and reserve both grow by exactly the fee
+      assert.equal(Number(vaultAfter.amount) - Number(vaultBefore.amount), fee);
+      assert.equal(poolAfter.reserveA.sub(poolBefore.reserveA).toNumber(), fee);
+      assert.isFalse(poolAfter.locked);
+      assert.isNull(await connection.getAccountInfo(receipt));
+    });
+
+    it("Should reject a borrow without a repay", async () => {
+      const [receipt] = PublicKey.findProgramAddressSync(
+        [Buffer.from("flash_loan"), poolPDA.toBuffer()],
+        program.programId
+      );
+      try {
+        await program.methods
+          .flashLoanBorrow(new anchor.BN(10 * 10 ** DECIMALS))
+          .accounts({
+            pool: poolPDA,
+            receipt,
+            vault: tokenAVault.publicKey,
+            borrowerTokenAccount: userTokenA,
+            borrower: wallet.publicKey,
+            instructions: anchor.web3.SYSVAR_INSTRUCTIONS_PUBKEY,
+          })
+          .rpc();
+        assert.fail("Expected FlashLoanNotRepaid");
+      } catch (e: any) {
+        assert.include(e.toString(), "FlashLoanNotRepaid");
+      }
     });
   });
 
@@ -375,6 +982,14 @@ describe("SolRush DEX - Comprehensive MVP Test Suite", () => {
     let limitOrderPDA: PublicKey;
     const orderId = new anchor.BN(Date.now());
 
+    before(async function () {
+      // Orders trigger on the pool's 60s TWAP, which needs that much price history
+      this.timeout(90_000);
+      const pool = await program.account.liquidityPool.fetch(poolPDA);
+      const readyAt = (pool.observations[0].timestamp.toNumber() + 60 + 2) * 1000;
+      await new Promise((resolve) => setTimeout(resolve, Math.max(0, readyAt - Date.now())));
+    });
+
     it("Should create a limit order", async () => {
       const sellAmount = 10 * 10 ** DECIMALS;
       const targetPrice = 6 * 10 ** DECIMALS;
@@ -400,7 +1015,11 @@ describe("SolRush DEX - Comprehensive MVP Test Suite", () => {
           new anchor.BN(targetPrice),
           new anchor.BN(minReceive),
           new anchor.BN(7),
-          orderId
+          orderId,
+          new anchor.BN(0),
+          { takeProfit: {} },
+          0,
+          { goodTilCancelled: {} }
         )
         .accounts({
           pool: poolPDA,
@@ -419,8 +1038,46 @@ describe("SolRush DEX - Comprehensive MVP Test Suite", () => {
       console.log("   Status:", JSON.stringify(order.status));
     });
 
+    it("Should let only the owner modify a pending order", async () => {
+      const stranger = Keypair.generate();
+      try {
+        await program.methods
+          .modifyLimitOrder(new anchor.BN(7 * 10 ** DECIMALS), new anchor.BN(60 * 10 ** DECIMALS), new anchor.BN(3))
+          .accounts({ limitOrder: limitOrderPDA, owner: stranger.publicKey })
+          .signers([stranger])
+          .rpc();
+        assert.fail("Expected UnauthorizedOrderOwner");
+      } catch (e) {
+        assert.include(e.toString(), "UnauthorizedOrderOwner");
+      }
+
+      try {
+        await program.methods
+          .modifyLimitOrder(new anchor.BN(0), new anchor.BN(60 * 10 ** DECIMALS), new anchor.BN(3))
+          .accounts({ limitOrder: limitOrderPDA, owner: wallet.publicKey })
+          .rpc();
+        assert.fail("Expected InvalidAmount");
+      } catch (e) {
+        assert.include(e.toString(), "InvalidAmount");
+      }
+
+      const before = await program.account.limitOrder.fetch(limitOrderPDA);
+      await program.methods
+        .modifyLimitOrder(new anchor.BN(7 * 10 ** DECIMALS), new anchor.BN(60 * 10 ** DECIMALS), new anchor.BN(3))
+        .accounts({ limitOrder: limitOrderPDA, owner: wallet.publicKey })
+        .rpc();
+      const after = await program.account.limitOrder.fetch(limitOrderPDA);
+      assert.equal(after.targetPrice.toNumber(), 7 * 10 ** DECIMALS);
+      assert.equal(after.minimumReceive.toNumber(), 60 * 10 ** DECIMALS);
+      assert.isBelow(after.expiresAt.toNumber(), before.expiresAt.toNumber());
+      // Size and pair are fixed
+      assert.equal(after.sellAmount.toString(), before.sellAmount.toString());
+      assert.isTrue(after.sellToken.equals(before.sellToken));
+    });
+
     it("Should cancel the limit order", async () => {
       console.log("\n Cancelling limit order...");
+      const walletBefore = await connection.getBalance(wallet.publicKey);
 
       const tx = await program.methods
         .cancelLimitOrder()
@@ -433,6 +1090,350 @@ describe("SolRush DEX - Comprehensive MVP Test Suite", () => {
         .rpc();
 
       console.log("Order cancelled! Tx:", tx);
+      // Both accounts' rent comes back, well over the transaction fee
+      assert.isNull(await program.account.limitOrder.fetchNullable(limitOrderPDA));
+      assert.isNull(await connection.getAccountInfo(orderVault.publicKey));
+      assert.isAbove(await connection.getBalance(wallet.publicKey), walletBefore);
+    });
+
+    it("Should reject closing an order before it expires", async () => {
+      const expiringId = new anchor.BN(Date.now() + 2);
+      const [expiringPDA] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("limit_order"),
+          poolPDA.toBuffer(),
+          wallet.publicKey.toBuffer(),
+          expiringId.toArrayLike(Buffer, "le", 8)
+        ],
+        program.programId
+      );
+      const expiringVault = Keypair.generate();
+      await program.methods
+        .createLimitOrder(
+          new anchor.BN(10 ** DECIMALS),
+          new anchor.BN(1_000_000 * 10 ** DECIMALS),
+          new anchor.BN(1),
+          new anchor.BN(1),
+          expiringId,
+          new anchor.BN(0),
+          { takeProfit: {} },
+          0,
+          { goodTilCancelled: {} }
+        )
+        .accounts({
+          pool: poolPDA,
+          sellTokenMint: tokenAMint,
+          userTokenIn: userTokenA,
+          userTokenOut: userTokenB,
+          orderVault: expiringVault.publicKey,
+          user: wallet.publicKey,
+        })
+        .signers([expiringVault])
+        .rpc();
+
+      // A stranger can't sweep an order before its expiry
+      const stranger = Keypair.generate();
+      const closeAccounts = {
+        limitOrder: expiringPDA,
+        orderVault: expiringVault.publicKey,
+        ownerTokenIn: userTokenA,
+        owner: wallet.publicKey,
+        caller: stranger.publicKey,
+      };
+      try {
+        await program.methods
+          .closeExpiredOrder()
+          .accounts(closeAccounts)
+          .signers([stranger])
+          .rpc();
+        assert.fail("Expected OrderNotExpired");
+      } catch (e) {
+        assert.include(e.toString(), "OrderNotExpired");
+      }
+
+      // Expiry is at least a day out, so clean up through the owner
+      await program.methods
+        .cancelLimitOrder()
+        .accounts({
+          limitOrder: expiringPDA,
+          orderVault: expiringVault.publicKey,
+          userTokenIn: userTokenA,
+          user: wallet.publicKey,
+        })
+        .rpc();
+    });
+
+    it("Should return rent to the owner and only the tip and reward to the keeper", async () => {
+      const tipOrderId = new anchor.BN(Date.now() + 1);
+      const tip = 5_000;
+      const sellAmount = 1 * 10 ** DECIMALS;
+      const rewardBps = 50;
+      const reward = (sellAmount * rewardBps) / 10_000;
+      const ownerABefore = (await getAccount(connection, userTokenA)).amount;
+      const [tipOrderPDA] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("limit_order"),
+          poolPDA.toBuffer(),
+          wallet.publicKey.toBuffer(),
+          tipOrderId.toArrayLike(Buffer, "le", 8)
+        ],
+        program.programId
+      );
+      const tipVault = Keypair.generate();
+      await program.methods
+        .createLimitOrder(
+          new anchor.BN(sellAmount),
+          new anchor.BN(1), // any pool price is above this
+          new anchor.BN(1),
+          new anchor.BN(7),
+          tipOrderId,
+          new anchor.BN(tip),
+          { takeProfit: {} },
+          rewardBps,
+          { goodTilCancelled: {} }
+        )
+        .accounts({
+          pool: poolPDA,
+          sellTokenMint: tokenAMint,
+          userTokenIn: userTokenA,
+          userTokenOut: userTokenB,
+          orderVault: tipVault.publicKey,
+          user: wallet.publicKey,
+        })
+        .signers([tipVault])
+        .rpc();
+      // The reward is escrowed on top of the sell amount
+      assert.equal(
+        Number(ownerABefore - (await getAccount(connection, userTokenA)).amount),
+        sellAmount + reward
+      );
+
+      // A funded keeper, so the tip lands in an existing account
+      const keeper = Keypair.generate();
+      await connection.confirmTransaction(
+        await connection.requestAirdrop(keeper.publicKey, anchor.web3.LAMPORTS_PER_SOL)
+      );
+      const keeperTokenA = await createAccount(connection, wallet.payer, tokenAMint, keeper.publicKey);
+      const orderRent = await connection.getBalance(tipOrderPDA) - tip;
+      const vaultRent = await connection.getBalance(tipVault.publicKey);
+      const keeperBefore = await connection.getBalance(keeper.publicKey);
+      const ownerBefore = await connection.getBalance(wallet.publicKey);
+      const ownerBBefore = (await getAccount(connection, userTokenB)).amount;
+
+      await program.methods
+        .executeLimitOrder(new anchor.BN(sellAmount))
+        .accounts({
+          pool: poolPDA,
+          limitOrder: tipOrderPDA,
+          orderVault: tipVault.publicKey,
+          poolVaultIn: tokenAVault.publicKey,
+          userTokenOut: userTokenB,
+          poolVaultOut: tokenBVault.publicKey,
+          keeper: keeper.publicKey,
+          keeperTokenAccount: keeperTokenA,
+          owner: wallet.publicKey,
+        })
+        .signers([keeper])
+        .rpc();
+
+      assert.isNull(await program.account.limitOrder.fetchNullable(tipOrderPDA));
+      assert.isNull(await connection.getAccountInfo(tipVault.publicKey));
+      // The wallet pays the transaction fee, so the keeper's gain is exactly the tip
+      assert.equal(await connection.getBalance(keeper.publicKey) - keeperBefore, tip);
+      const ownerGain = await connection.getBalance(wallet.publicKey) - ownerBefore;
+      const txFee = orderRent + vaultRent - ownerGain;
+      assert.isAtLeast(txFee, 0);
+      assert.isBelow(txFee, 100_000, "owner should get back both accounts' rent, less the fee");
+      // The keeper's reward comes out of the escrow, the owner keeps the whole swap output
+      assert.equal(Number((await getAccount(connection, keeperTokenA)).amount), reward);
+      assert.isAbove(Number((await getAccount(connection, userTokenB)).amount - ownerBBefore), 0);
+    });
+
+    it("Should partially fill an IOC order and refund the rest", async () => {
+      const pool = await program.account.liquidityPool.fetch(poolPDA);
+      // A tenth of the reserve at no worse than 95% of spot: only part of it fits
+      const sellAmount = pool.reserveA.divn(10);
+      const minReceive = sellAmount.mul(pool.reserveB).div(pool.reserveA).muln(95).divn(100);
+      const iocId = new anchor.BN(Date.now() + 20);
+      const [iocPDA] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("limit_order"),
+          poolPDA.toBuffer(),
+          wallet.publicKey.toBuffer(),
+          iocId.toArrayLike(Buffer, "le", 8)
+        ],
+        program.programId
+      );
+      const iocVault = Keypair.generate();
+      const ownerABefore = (await getAccount(connection, userTokenA)).amount;
+      const ownerBBefore = (await getAccount(connection, userTokenB)).amount;
+
+      await program.methods
+        .createLimitOrder(
+          sellAmount,
+          new anchor.BN(1),
+          minReceive,
+          new anchor.BN(1),
+          iocId,
+          new anchor.BN(0),
+          { takeProfit: {} },
+          0,
+          { immediateOrCancel: {} }
+        )
+        .accounts({
+          pool: poolPDA,
+          sellTokenMint: tokenAMint,
+          userTokenIn: userTokenA,
+          userTokenOut: userTokenB,
+          orderVault: iocVault.publicKey,
+          poolTokenAVault: tokenAVault.publicKey,
+          poolTokenBVault: tokenBVault.publicKey,
+          user: wallet.publicKey,
+        })
+        .signers([iocVault])
+        .rpc();
+
+      // Nothing rests: the order and its escrow are gone, the unfilled part is back
+      assert.isNull(await program.account.limitOrder.fetchNullable(iocPDA));
+      assert.isNull(await connection.getAccountInfo(iocVault.publicKey));
+      const spentA = ownerABefore - (await getAccount(connection, userTokenA)).amount;
+      assert.isAbove(Number(spentA), 0);
+      assert.isBelow(Number(spentA), sellAmount.toNumber());
+      assert.isAbove(Number((await getAccount(connection, userTokenB)).amount - ownerBBefore), 0);
+    });
+
+    it("Should revert a FOK order the pool can't fill in full", async () => {
+      const pool = await program.account.liquidityPool.fetch(poolPDA);
+      const sellAmount = pool.reserveA.divn(10);
+      const minReceive = sellAmount.mul(pool.reserveB).div(pool.reserveA).muln(95).divn(100);
+      const fokId = new anchor.BN(Date.now() + 21);
+      const [fokPDA] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("limit_order"),
+          poolPDA.toBuffer(),
+          wallet.publicKey.toBuffer(),
+          fokId.toArrayLike(Buffer, "le", 8)
+        ],
+        program.programId
+      );
+      const fokVault = Keypair.generate();
+      const ownerABefore = (await getAccount(connection, userTokenA)).amount;
+      try {
+        await program.methods
+          .createLimitOrder(
+            sellAmount,
+            new anchor.BN(1),
+            minReceive,
+            new anchor.BN(1),
+            fokId,
+            new anchor.BN(0),
+            { takeProfit: {} },
+            0,
+            { fillOrKill: {} }
+          )
+          .accounts({
+            pool: poolPDA,
+            sellTokenMint: tokenAMint,
+            userTokenIn: userTokenA,
+            userTokenOut: userTokenB,
+            orderVault: fokVault.publicKey,
+            poolTokenAVault: tokenAVault.publicKey,
+            poolTokenBVault: tokenBVault.publicKey,
+            user: wallet.publicKey,
+          })
+          .signers([fokVault])
+          .rpc();
+        assert.fail("Expected SlippageTooHigh");
+      } catch (e) {
+        assert.include(e.toString(), "SlippageTooHigh");
+      }
+      // The whole instruction reverted: no order, no escrow taken
+      assert.isNull(await program.account.limitOrder.fetchNullable(fokPDA));
+      assert.equal((await getAccount(connection, userTokenA)).amount, ownerABefore);
+    });
+
+    it("Should execute the triggered orders of a batch and skip the rest", async () => {
+      const sellAmount = 1 * 10 ** DECIMALS;
+      // Two orders any pool price triggers, one far above it
+      const targets = [1, 1, 1_000_000 * 10 ** DECIMALS];
+      const orders = [];
+      for (const [i, target] of targets.entries()) {
+        const id = new anchor.BN(Date.now() + 10 + i);
+        const [pda] = PublicKey.findProgramAddressSync(
+          [
+            Buffer.from("limit_order"),
+            poolPDA.toBuffer(),
+            wallet.publicKey.toBuffer(),
+            id.toArrayLike(Buffer, "le", 8)
+          ],
+          program.programId
+        );
+        const vault = Keypair.generate();
+        await program.methods
+          .createLimitOrder(
+            new anchor.BN(sellAmount),
+            new anchor.BN(target),
+            new anchor.BN(1),
+            new anchor.BN(7),
+            id,
+            new anchor.BN(0),
+            { takeProfit: {} },
+            0,
+            { goodTilCancelled: {} }
+          )
+          .accounts({
+            pool: poolPDA,
+            sellTokenMint: tokenAMint,
+            userTokenIn: userTokenA,
+            userTokenOut: userTokenB,
+            orderVault: vault.publicKey,
+            user: wallet.publicKey,
+          })
+          .signers([vault])
+          .rpc();
+        orders.push({ pda, vault });
+      }
+
+      const listener = program.addEventListener("batchExecuted", (event) => {
+        assert.equal(event.executedCount, 2);
+        assert.equal(event.skippedCount, 1);
+      });
+      await program.methods
+        .executeLimitOrdersBatch()
+        .accounts({
+          pool: poolPDA,
+          poolTokenAVault: tokenAVault.publicKey,
+          poolTokenBVault: tokenBVault.publicKey,
+          keeper: wallet.publicKey,
+          keeperTokenA: userTokenA,
+          keeperTokenB: userTokenB,
+        })
+        .remainingAccounts(
+          orders.flatMap(({ pda, vault }) => [
+            { pubkey: pda, isWritable: true, isSigner: false },
+            { pubkey: vault.publicKey, isWritable: true, isSigner: false },
+            { pubkey: userTokenB, isWritable: true, isSigner: false },
+            { pubkey: wallet.publicKey, isWritable: true, isSigner: false },
+          ])
+        )
+        .rpc();
+      await program.removeEventListener(listener);
+
+      assert.isNull(await program.account.limitOrder.fetchNullable(orders[0].pda));
+      assert.isNull(await program.account.limitOrder.fetchNullable(orders[1].pda));
+      const resting = await program.account.limitOrder.fetch(orders[2].pda);
+      assert.property(resting.status, "pending");
+      assert.equal(resting.sellAmount.toNumber(), sellAmount);
+
+      await program.methods
+        .cancelLimitOrder()
+        .accounts({
+          limitOrder: orders[2].pda,
+          orderVault: orders[2].vault.publicKey,
+          userTokenIn: userTokenA,
+          user: wallet.publicKey,
+        })
+        .rpc();
     });
   });
 
@@ -499,6 +1500,52 @@ describe("SolRush DEX - Comprehensive MVP Test Suite", () => {
 
       console.log("Rewards paused! Tx:", tx);
     });
+
+    it("Should pay two partner reward tokens at their own rates", async () => {
+      const programs = [];
+      for (const rate of [1_000, 100]) {
+        const mint = await createMint(connection, wallet.payer, wallet.publicKey, null, DECIMALS);
+        const funder = await createAccount(connection, wallet.payer, mint, wallet.publicKey);
+        await mintTo(connection, wallet.payer, mint, funder, wallet.payer, rate * 3_600);
+        const [vault] = PublicKey.findProgramAddressSync(
+          [Buffer.from("reward_vault"), poolPDA.toBuffer(), mint.toBuffer()],
+          program.programId
+        );
+        await program.methods
+          .addRewardProgram(new anchor.BN(rate), new anchor.BN(3_600))
+          .accounts({
+            pool: poolPDA,
+            rewardMint: mint,
+            rewardVault: vault,
+            funderTokenAccount: funder,
+            authority: wallet.publicKey,
+          })
+          .rpc();
+        // The whole run is escrowed up front
+        assert.equal(Number((await getAccount(connection, vault)).amount), rate * 3_600);
+        programs.push({ mint, vault, rate });
+      }
+
+      await new Promise((resolve) => setTimeout(resolve, 3000));
+      const claimed = [];
+      for (const { mint, vault } of programs) {
+        const userAccount = await createAccount(connection, wallet.payer, mint, wallet.publicKey, Keypair.generate());
+        await program.methods
+          .claimReward(mint)
+          .accounts({
+            position: userPositionPDA,
+            pool: poolPDA,
+            rewardVault: vault,
+            userRewardAccount: userAccount,
+            user: wallet.publicKey,
+          })
+          .rpc();
+        claimed.push(Number((await getAccount(connection, userAccount)).amount));
+      }
+      // Both accrued over the same seconds, the first at ten times the rate
+      assert.isAbove(claimed[1], 0);
+      assert.isAbove(claimed[0], claimed[1] * 5);
+    });
   });
 
   // =========================================================================
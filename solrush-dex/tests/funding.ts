@@ -3,6 +3,7 @@ import { Program } from "@coral-xyz/anchor";
 import { expect } from "chai";
 import {
   createMint,
+  getAccount,
   getOrCreateAssociatedTokenAccount,
   mintTo,
 } from "@solana/spl-token";
@@ -13,6 +14,7 @@ import {
   findPerpsUserAddress,
   findPerpsPositionAddress,
 } from "../target/types/pda";
+import { seedAndActivatePerpsMarket } from "./perps-setup";
 
 /**
  * Phase 3 — Funding rate tests.
@@ -22,6 +24,9 @@ import {
  *  2. Negative funding: shorts pay longs
  *  3. Funding accumulates over time
  *  4. No funding applied twice (checkpoint guards)
+ *
+ * The oracle stays at 100, so the mark TWAP matches the index and the rate
+ * comes from the open-interest skew: the test's lone position pays it.
  */
 describe("funding (Phase 3)", () => {
   const provider = anchor.AnchorProvider.env();
@@ -66,7 +71,9 @@ describe("funding (Phase 3)", () => {
         sideArg,
         new anchor.BN(size),
         leverage,
-        { market: {} }
+        { market: {} },
+        { cross: {} },
+        false
       )
       .accounts({
         owner: admin.publicKey,
@@ -95,14 +102,18 @@ describe("funding (Phase 3)", () => {
       .rpc();
   }
 
-  /** Call update_perps_funding with a mark price. */
-  async function updateFunding(markPrice: number) {
+  /** Crank update_perps_funding. */
+  async function updateFunding() {
     await program.methods
-      .updatePerpsFunding(new anchor.BN(markPrice))
+      .updatePerpsFunding()
       .accounts({
+        keeper: admin.publicKey,
         global: globalPda,
         market: marketPda,
         oraclePriceAccount: oraclePda,
+        collateralVault: collateralVault.publicKey,
+        keeperAta: userQuoteAta,
+        tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID,
       })
       .rpc();
   }
@@ -196,7 +207,8 @@ describe("funding (Phase 3)", () => {
         10,
         500,
         new anchor.BN(10_000),
-        new anchor.BN(1) // 1-second interval for easy testing
+        new anchor.BN(1), // 1-second interval for easy testing
+        new anchor.BN(0)
       )
       .accounts({
         admin: admin.publicKey,
@@ -212,6 +224,17 @@ describe("funding (Phase 3)", () => {
       })
       .signers([collateralVault])
       .rpc();
+    await seedAndActivatePerpsMarket(program, provider, {
+      global: globalPda,
+      market: marketPda,
+      quoteMint,
+      depositorAta: userQuoteAta,
+    });
+    // 100 bps skew: a one-sided book pays the full 1% max rate
+    await program.methods
+      .setFundingSkew(100)
+      .accounts({ admin: admin.publicKey, global: globalPda, market: marketPda })
+      .rpc();
 
     [positionPda] = findPerpsPositionAddress(
       admin.publicKey,
@@ -262,11 +285,10 @@ describe("funding (Phase 3)", () => {
     // Wait for funding interval to elapse
     await sleep(1500);
 
-    // Update funding: mark = 101 (1% premium over index 100)
-    // premium = (101_000_000 - 100_000_000) * 1_000_000 / 100_000_000 = 10_000
-    // clamped at max 10_000 → funding_rate = 10_000
+    // Update funding: only longs are open, so skew = 100 bps of PRICE_SCALE
+    // = 10_000 → funding_rate = 10_000 (premium is 0 at a flat oracle)
     // cum_funding += 100_000_000 * 10_000 / 1_000_000 = 1_000_000
-    await updateFunding(101_000_000);
+    await updateFunding();
 
     const mkt = await fetchMarket();
     expect(mkt.fundingRateI64.toNumber()).to.equal(10_000);
@@ -279,7 +301,7 @@ describe("funding (Phase 3)", () => {
 
     // The long should have paid funding → net collateral returned is reduced.
     // Before funding: collateral = 10_000_000 at 10x leverage on notional 100M
-    // Funding delta = 1 (base_position) * cum_funding_diff > 0 → collateral reduced
+    // Funding delta = ceil(1 (base_position) * cum_funding_diff / BASE_SCALE) > 0 → collateral reduced
     // We verify the user got less back than their original deposit minus PnL
     // (price unchanged so PnL = 0, but funding was charged)
     expect(posAfter.basePositionI64.toNumber()).to.equal(0, "position fully closed");
@@ -299,11 +321,10 @@ describe("funding (Phase 3)", () => {
     // Wait for funding interval
     await sleep(1500);
 
-    // Update funding with mark < index (negative premium)
-    // mark = 99, index = 100 → premium = (99M - 100M) * 1M / 100M = -10_000
-    // clamped at -10_000 → negative funding rate
+    // Update funding with only shorts open (negative skew)
+    // skew = -10_000 → negative funding rate
     // cum_funding += 100_000_000 * (-10_000) / 1_000_000 = -1_000_000
-    await updateFunding(99_000_000);
+    await updateFunding();
 
     const mkt = await fetchMarket();
     // Funding rate should be negative (note: additive from previous test)
@@ -312,7 +333,7 @@ describe("funding (Phase 3)", () => {
     const expectedNewCum = prevCum - 1_000_000; // = 0
     // cum diff for this position = newCum - position's checkpoint (which was set at open)
     // The short should pay when funding is negative
-    // funding_delta = base_position(-1) * cum_diff
+    // funding_delta = ceil(base_position(-1) * cum_diff / BASE_SCALE)
     // If cum_diff < 0: funding_delta = (-1) * (negative) = positive → short pays
 
     await closePos();
@@ -333,11 +354,11 @@ describe("funding (Phase 3)", () => {
     // Open long: size=1, leverage=10
     await openPos("long", 1, 10);
 
-    // Apply funding 3 times with mark > index
+    // Apply funding 3 times with only the long open
     for (let i = 0; i < 3; i++) {
       await sleep(1500);
-      // mark = 101 each time → +1_000_000 to cum_funding each interval
-      await updateFunding(101_000_000);
+      // skew = 10_000 each time → +1_000_000 to cum_funding each interval
+      await updateFunding();
     }
 
     const mkt = await fetchMarket();
@@ -361,7 +382,7 @@ describe("funding (Phase 3)", () => {
 
     // Apply one round of funding
     await sleep(1500);
-    await updateFunding(101_000_000);
+    await updateFunding();
 
     // Close position — this settles funding once
     await closePos();
@@ -389,10 +410,62 @@ describe("funding (Phase 3)", () => {
   it("5. Funding update rejected if interval not elapsed", async () => {
     // We just called updateFunding. Calling again immediately should fail.
     try {
-      await updateFunding(101_000_000);
+      await updateFunding();
+      expect.fail("should have thrown FundingTooSoon");
+    } catch (err: any) {
+      expect(err.toString()).to.include("FundingTooSoon");
+    }
+  });
+
+  it("6. settle_perps_funding settles a dormant position", async () => {
+    await openPos("long", 1, 10);
+    await sleep(1500);
+    await updateFunding();
+
+    const accounts = { positionOwner: admin.publicKey, market: marketPda, position: positionPda };
+    const pending = await program.methods.getPendingFunding().accounts(accounts).view();
+    expect(pending.toNumber()).to.be.greaterThan(0, "long owes funding");
+    const collBefore = (await fetchPosition()).collateralU64.toNumber();
+
+    await program.methods
+      .settlePerpsFunding()
+      .accounts({ caller: admin.publicKey, ...accounts })
+      .rpc();
+
+    const pos = await fetchPosition();
+    const mkt = await fetchMarket();
+    expect(pos.basePositionI64.toNumber()).to.equal(1, "position untouched");
+    expect(pos.collateralU64.toNumber()).to.equal(collBefore - pending.toNumber());
+    expect(pos.lastFundingI128.toString()).to.equal(mkt.cumulativeFundingI128.toString());
+    const after = await program.methods.getPendingFunding().accounts(accounts).view();
+    expect(after.toNumber()).to.equal(0);
+
+    await closePos();
+  });
+
+  it("7. Funding keeper is rewarded only when the interval elapsed", async () => {
+    await program.methods
+      .setFundingKeeperReward(new anchor.BN(1_000))
+      .accounts({ admin: admin.publicKey, global: globalPda, market: marketPda })
+      .rpc();
+    const keeperBalance = async () =>
+      Number((await getAccount(provider.connection, userQuoteAta)).amount);
+
+    await sleep(1500);
+    const feesBefore = (await fetchMarket()).tradingFeesU64.toNumber();
+    const before = await keeperBalance();
+    await updateFunding();
+    const reward = Math.min(1_000, feesBefore);
+    expect(await keeperBalance()).to.equal(before + reward);
+    expect((await fetchMarket()).tradingFeesU64.toNumber()).to.equal(feesBefore - reward);
+
+    // Too soon: the call fails and pays nothing
+    try {
+      await updateFunding();
       expect.fail("should have thrown FundingTooSoon");
     } catch (err: any) {
       expect(err.toString()).to.include("FundingTooSoon");
     }
+    expect(await keeperBalance()).to.equal(before + reward);
   });
 });
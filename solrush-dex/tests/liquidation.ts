@@ -143,7 +143,8 @@ describe("liquidation", () => {
         10,   // max leverage 10x
         500,  // maintenance margin 5% = 500 bps
         new anchor.BN(10_000),
-        new anchor.BN(3600)
+        new anchor.BN(3600),
+        new anchor.BN(0)
       )
       .accounts({
         admin: admin.publicKey,
@@ -214,10 +215,45 @@ describe("liquidation", () => {
       .rpc();
   });
 
+  it("rejects opening until the market is activated", async () => {
+    // Market starts inactive even though insurance is seeded
+    try {
+      await program.methods
+        .openPerpsPosition({ long: {} }, new anchor.BN(1_000_000), 5, { market: {} }, { cross: {} }, false)
+        .accounts({
+          owner: admin.publicKey,
+          global: globalPda,
+          user: userPda,
+          market: marketPda,
+          oraclePriceAccount: oraclePda,
+          position: positionPda,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .rpc();
+      expect.fail("Expected MarketInactive error");
+    } catch (error: any) {
+      expect(error.toString()).to.include("MarketInactive");
+    }
+
+    // 5 USDC meets the default seed — activate
+    await program.methods
+      .activatePerpsMarket()
+      .accounts({
+        admin: admin.publicKey,
+        global: globalPda,
+        market: marketPda,
+        insuranceVault: insuranceVaultPda,
+      })
+      .rpc();
+
+    const market = await program.account.perpsMarket.fetch(marketPda);
+    expect(market.active).to.equal(true);
+  });
+
   it("rejects liquidation when position is healthy", async () => {
     // Open a long 1 unit @ $100, 5x leverage
     await program.methods
-      .openPerpsPosition({ long: {} }, new anchor.BN(1_000_000), 5, { market: {} })
+      .openPerpsPosition({ long: {} }, new anchor.BN(1_000_000), 5, { market: {} }, { cross: {} }, false)
       .accounts({
         owner: admin.publicKey,
         global: globalPda,
@@ -254,6 +290,23 @@ describe("liquidation", () => {
       expect(error.toString()).to.include("not liquidatable");
     }
 
+    // An open position cannot be swept
+    try {
+      await program.methods
+        .closeLiquidatedPosition()
+        .accounts({
+          caller: liquidator.publicKey,
+          positionOwner: admin.publicKey,
+          market: marketPda,
+          position: positionPda,
+        })
+        .signers([liquidator])
+        .rpc();
+      expect.fail("Expected PositionNotEmpty error");
+    } catch (error: any) {
+      expect(error.toString()).to.include("Position still holds");
+    }
+
     // Clean up — close the position
     await program.methods
       .closePerpsPosition()
@@ -273,7 +326,7 @@ describe("liquidation", () => {
     // Required margin = notional / leverage = (10 * 100) / 10 = 100 USDC = 100_000_000
     // Maintenance margin = notional * 5% = 50 USDC
     await program.methods
-      .openPerpsPosition({ long: {} }, new anchor.BN(10_000_000), 10, { market: {} })
+      .openPerpsPosition({ long: {} }, new anchor.BN(10_000_000), 10, { market: {} }, { cross: {} }, false)
       .accounts({
         owner: admin.publicKey,
         global: globalPda,
@@ -290,6 +343,7 @@ describe("liquidation", () => {
 
     // Record liquidator balance before
     const liqBalanceBefore = (await getAccount(provider.connection, liquidatorQuoteAta)).amount;
+    const ivBefore = await program.account.insuranceVault.fetch(insuranceVaultPda);
 
     // ── Crash price to $91 ── (loss = 10 * (91-100) = -90 USDC)
     // equity = 100 - 90 = 10 USDC
@@ -305,6 +359,21 @@ describe("liquidation", () => {
       })
       .rpc();
 
+    // ── Preview: same computation, nothing moves ──
+    const preview = await program.methods
+      .previewLiquidation()
+      .accounts({
+        global: globalPda,
+        user: userPda,
+        positionOwner: admin.publicKey,
+        market: marketPda,
+        oraclePriceAccount: oraclePda,
+        position: positionPda,
+        insuranceVault: insuranceVaultPda,
+      })
+      .view();
+    expect(preview.liquidatable).to.eq(true);
+
     // ── Liquidate ──
     await program.methods
       .liquidatePosition()
@@ -335,11 +404,21 @@ describe("liquidation", () => {
     // ── Verify liquidator received a fee ──
     const liqBalanceAfter = (await getAccount(provider.connection, liquidatorQuoteAta)).amount;
     expect(Number(liqBalanceAfter)).to.be.greaterThan(Number(liqBalanceBefore));
+    expect(Number(liqBalanceAfter) - Number(liqBalanceBefore)).to.eq(preview.liquidatorFeeU64.toNumber());
+    expect(baseBefore - baseAfter).to.eq(preview.sizeClosedI64.toNumber());
+    expect(positionAfter.basePositionI64.isZero()).to.eq(preview.fullClose);
     console.log(`  Liquidator fee received: ${Number(liqBalanceAfter) - Number(liqBalanceBefore)} lamports`);
 
     // ── Verify insurance vault was updated ──
     const ivAfter = await program.account.insuranceVault.fetch(insuranceVaultPda);
     console.log(`  Insurance vault balance: ${ivAfter.balanceU64.toNumber()}`);
+    // Fee and penalty came out of the position's own collateral: the fund only grew
+    expect(ivAfter.balanceU64.toNumber()).to.be.at.least(ivBefore.balanceU64.toNumber());
+    expect(ivAfter.balanceU64.toNumber() - ivBefore.balanceU64.toNumber()).to.eq(
+      preview.insurancePenaltyU64.toNumber() -
+        preview.badDebtU64.toNumber() -
+        preview.liquidatorFeeFromInsuranceU64.toNumber()
+    );
   });
 
   it("handles bad-debt liquidation and sets emergency flag", async () => {
@@ -359,7 +438,7 @@ describe("liquidation", () => {
     if (posBefore.basePositionI64.toNumber() === 0) {
       // Open a new highly leveraged position
       await program.methods
-        .openPerpsPosition({ long: {} }, new anchor.BN(10_000_000), 10, { market: {} })
+        .openPerpsPosition({ long: {} }, new anchor.BN(10_000_000), 10, { market: {} }, { cross: {} }, false)
         .accounts({
           owner: admin.publicKey,
           global: globalPda,
@@ -422,6 +501,125 @@ describe("liquidation", () => {
     const ivAfter = await program.account.insuranceVault.fetch(insuranceVaultPda);
     console.log(`  Insurance vault after extreme crash: ${ivAfter.balanceU64.toNumber()}`);
     expect(ivAfter.balanceU64.toNumber()).to.eq(0);
+
+    // Insurance paid the bad debt in tokens, so its ATA still matches the
+    // tracked balance and the collateral vault covers every remaining claim
+    const insuranceAta = await getAccount(provider.connection, insuranceVaultAta.publicKey);
+    expect(Number(insuranceAta.amount)).to.eq(ivAfter.balanceU64.toNumber());
+    const vault = await getAccount(provider.connection, collateralVault.publicKey);
+    const user = await program.account.perpsUserAccount.fetch(userPda);
+    expect(Number(vault.amount)).to.be.at.least(
+      user.collateralQuoteU64.toNumber() + marketAfter.fundingPoolU64.toNumber()
+    );
+  });
+
+  it("closes a fully-liquidated position and refunds rent to the owner", async () => {
+    const rent = await provider.connection.getBalance(positionPda);
+    const ownerBefore = await provider.connection.getBalance(admin.publicKey);
+
+    // Permissionless: the liquidator pays the fee, the owner gets the rent
+    await program.methods
+      .closeLiquidatedPosition()
+      .accounts({
+        caller: liquidator.publicKey,
+        positionOwner: admin.publicKey,
+        market: marketPda,
+        position: positionPda,
+      })
+      .signers([liquidator])
+      .rpc();
+
+    expect(await provider.connection.getAccountInfo(positionPda)).to.eq(null);
+    const ownerAfter = await provider.connection.getBalance(admin.publicKey);
+    expect(ownerAfter - ownerBefore).to.eq(rent);
+  });
+
+  it("auto-deleverages a profitable short to cover uncovered bad debt", async () => {
+    // The liquidator takes the other side of the crash: short 1 unit at $5
+    [liquidatorUserPda] = findPerpsUserAddress(liquidator.publicKey, program.programId);
+    const [liquidatorPositionPda] = findPerpsPositionAddress(
+      liquidator.publicKey,
+      marketPda,
+      program.programId
+    );
+    await program.methods
+      .initializePerpsUser()
+      .accounts({
+        owner: liquidator.publicKey,
+        user: liquidatorUserPda,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      })
+      .signers([liquidator])
+      .rpc();
+    await program.methods
+      .depositPerpsCollateral(new anchor.BN(5_000_000))
+      .accounts({
+        owner: liquidator.publicKey,
+        global: globalPda,
+        user: liquidatorUserPda,
+        market: marketPda,
+        userQuoteAta: liquidatorQuoteAta,
+        collateralVault: collateralVault.publicKey,
+        tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID,
+      })
+      .signers([liquidator])
+      .rpc();
+    await program.methods
+      .openPerpsPosition({ short: {} }, new anchor.BN(1_000_000), 5, { market: {} }, { cross: {} }, false)
+      .accounts({
+        owner: liquidator.publicKey,
+        global: globalPda,
+        user: liquidatorUserPda,
+        market: marketPda,
+        oraclePriceAccount: oraclePda,
+        position: liquidatorPositionPda,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      })
+      .signers([liquidator])
+      .rpc();
+
+    // Price keeps falling: the short is now $1 in profit
+    await program.methods
+      .setPerpsOraclePrice(new anchor.BN(4_000_000))
+      .accounts({
+        admin: admin.publicKey,
+        global: globalPda,
+        oracle: oraclePda,
+      })
+      .rpc();
+
+    const marketBefore = await program.account.perpsMarket.fetch(marketPda);
+    expect(marketBefore.emergency).to.eq(true);
+    const debtBefore = marketBefore.uncoveredBadDebtU64.toNumber();
+    expect(debtBefore).to.be.greaterThan(0);
+
+    const adlAccounts = {
+      admin: admin.publicKey,
+      global: globalPda,
+      user: liquidatorUserPda,
+      positionOwner: liquidator.publicKey,
+      market: marketPda,
+      oraclePriceAccount: oraclePda,
+      position: liquidatorPositionPda,
+    };
+    await program.methods.adlPosition().accounts(adlAccounts).rpc();
+
+    const position = await program.account.perpsPosition.fetch(liquidatorPositionPda);
+    expect(position.basePositionI64.toNumber()).to.eq(0);
+
+    // The short's profit went into the hole; the emergency lasts while any is left
+    const marketAfter = await program.account.perpsMarket.fetch(marketPda);
+    const debtAfter = marketAfter.uncoveredBadDebtU64.toNumber();
+    expect(debtAfter).to.be.lessThan(debtBefore);
+    expect(marketAfter.emergency).to.eq(debtAfter > 0);
+
+    // Nothing left to deleverage on a closed position
+    try {
+      await program.methods.adlPosition().accounts(adlAccounts).rpc();
+      expect.fail("Expected AdlIneligiblePosition error");
+    } catch (error: any) {
+      expect(error.toString()).to.include("not a profitable counterparty");
+    }
   });
 
   it("prevents self-liquidation", async () => {
@@ -436,10 +634,11 @@ describe("liquidation", () => {
       .rpc();
 
     // Open a position
-    const pos = await program.account.perpsPosition.fetch(positionPda);
-    if (pos.basePositionI64.toNumber() === 0) {
+    // The previous test closed the account; opening re-creates it
+    const pos = await program.account.perpsPosition.fetchNullable(positionPda);
+    if (!pos || pos.basePositionI64.toNumber() === 0) {
       await program.methods
-        .openPerpsPosition({ long: {} }, new anchor.BN(1_000_000), 5, { market: {} })
+        .openPerpsPosition({ long: {} }, new anchor.BN(1_000_000), 5, { market: {} }, { cross: {} }, false)
         .accounts({
           owner: admin.publicKey,
           global: globalPda,
@@ -492,4 +691,65 @@ describe("liquidation", () => {
         .rpc();
     }
   });
+
+  it("lets only the admin withdraw from the insurance fund", async () => {
+    // Top the fund up past the activation seed the live market keeps in place
+    const seed = (await program.account.perpsMarket.fetch(marketPda)).minInsuranceSeedU64;
+    await program.methods
+      .depositInsurance(seed.add(new anchor.BN(1_000_000)))
+      .accounts({
+        depositor: admin.publicKey,
+        market: marketPda,
+        insuranceVault: insuranceVaultPda,
+        insuranceVaultAta: insuranceVaultAta.publicKey,
+        depositorAta: userQuoteAta,
+        tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+    const withdrawAccounts = (signer: anchor.web3.PublicKey, ata: anchor.web3.PublicKey) => ({
+      admin: signer,
+      global: globalPda,
+      market: marketPda,
+      insuranceVault: insuranceVaultPda,
+      insuranceVaultAta: insuranceVaultAta.publicKey,
+      adminAta: ata,
+      tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID,
+    });
+
+    try {
+      await program.methods
+        .withdrawInsurance(new anchor.BN(1_000_000))
+        .accounts(withdrawAccounts(liquidator.publicKey, liquidatorQuoteAta))
+        .signers([liquidator])
+        .rpc();
+      expect.fail("Expected UnauthorizedAdmin error");
+    } catch (error: any) {
+      expect(error.toString()).to.include("Unauthorized admin");
+    }
+
+    // The fund stays put while bad debt is outstanding
+    if ((await program.account.perpsMarket.fetch(marketPda)).emergency) {
+      try {
+        await program.methods
+          .withdrawInsurance(new anchor.BN(1_000_000))
+          .accounts(withdrawAccounts(admin.publicKey, userQuoteAta))
+          .rpc();
+        expect.fail("Expected MarketEmergency error");
+      } catch (error: any) {
+        expect(error.toString()).to.include("emergency mode");
+      }
+      return;
+    }
+
+    const vaultBefore = await program.account.insuranceVault.fetch(insuranceVaultPda);
+    const adminBefore = (await getAccount(provider.connection, userQuoteAta)).amount;
+    await program.methods
+      .withdrawInsurance(new anchor.BN(1_000_000))
+      .accounts(withdrawAccounts(admin.publicKey, userQuoteAta))
+      .rpc();
+    const vaultAfter = await program.account.insuranceVault.fetch(insuranceVaultPda);
+    const adminAfter = (await getAccount(provider.connection, userQuoteAta)).amount;
+    expect(vaultBefore.balanceU64.sub(vaultAfter.balanceU64).toNumber()).to.equal(1_000_000);
+    expect(Number(adminAfter - adminBefore)).to.equal(1_000_000);
+  });
 });
@@ -0,0 +1,79 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { mintTo } from "@solana/spl-token";
+import { findInsuranceVaultAddress } from "../target/types/pda";
+
+/**
+ * Seed a freshly created perps market's insurance fund with its
+ * `min_insurance_seed` and activate it — markets start inactive and reject
+ * opens and limit orders with `MarketInactive` until then.
+ *
+ * The seed is minted to `depositorAta` first, so the caller's collateral
+ * balance is left untouched.  The provider wallet must be the perps admin and
+ * the quote mint authority.
+ */
+export async function seedAndActivatePerpsMarket(
+  program: Program,
+  provider: anchor.AnchorProvider,
+  accounts: {
+    global: anchor.web3.PublicKey;
+    market: anchor.web3.PublicKey;
+    quoteMint: anchor.web3.PublicKey;
+    depositorAta: anchor.web3.PublicKey;
+  }
+): Promise<{ insuranceVault: anchor.web3.PublicKey; insuranceVaultAta: anchor.web3.PublicKey }> {
+  const admin = provider.wallet as anchor.Wallet;
+  const [insuranceVault] = findInsuranceVaultAddress(accounts.market, program.programId);
+  const insuranceVaultAta = anchor.web3.Keypair.generate();
+
+  await program.methods
+    .initializeInsuranceVault()
+    .accounts({
+      admin: admin.publicKey,
+      global: accounts.global,
+      market: accounts.market,
+      insuranceVault,
+      insuranceVaultAta: insuranceVaultAta.publicKey,
+      quoteMint: accounts.quoteMint,
+      systemProgram: anchor.web3.SystemProgram.programId,
+      tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID,
+      rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+    })
+    .signers([insuranceVaultAta])
+    .rpc();
+
+  const seed = (await program.account.perpsMarket.fetch(accounts.market)).minInsuranceSeedU64 as anchor.BN;
+  if (seed.gtn(0)) {
+    await mintTo(
+      provider.connection,
+      admin.payer,
+      accounts.quoteMint,
+      accounts.depositorAta,
+      admin.publicKey,
+      BigInt(seed.toString())
+    );
+    await program.methods
+      .depositInsurance(seed)
+      .accounts({
+        depositor: admin.publicKey,
+        market: accounts.market,
+        insuranceVault,
+        insuranceVaultAta: insuranceVaultAta.publicKey,
+        depositorAta: accounts.depositorAta,
+        tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+  }
+
+  await program.methods
+    .activatePerpsMarket()
+    .accounts({
+      admin: admin.publicKey,
+      global: accounts.global,
+      market: accounts.market,
+      insuranceVault,
+    })
+    .rpc();
+
+  return { insuranceVault, insuranceVaultAta: insuranceVaultAta.publicKey };
+}
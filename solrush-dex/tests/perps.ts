@@ -13,6 +13,7 @@ import {
   findPerpsUserAddress,
   findPerpsPositionAddress,
 } from "../target/types/pda";
+import { seedAndActivatePerpsMarket } from "./perps-setup";
 
 describe("perps v1", () => {
   const provider = anchor.AnchorProvider.env();
@@ -105,7 +106,7 @@ describe("perps v1", () => {
       .rpc();
 
     await program.methods
-      .createPerpsMarket(Array(32).fill(0), 10, 500, new anchor.BN(10_000), new anchor.BN(3600))
+      .createPerpsMarket(Array(32).fill(0), 10, 500, new anchor.BN(10_000), new anchor.BN(3600), new anchor.BN(0))
       .accounts({
         admin: admin.publicKey,
         global: globalPda,
@@ -120,9 +121,16 @@ describe("perps v1", () => {
       })
       .signers([collateralVault])
       .rpc();
+    await seedAndActivatePerpsMarket(program, provider, {
+      global: globalPda,
+      market: marketPda,
+      quoteMint,
+      depositorAta: userQuoteAta,
+    });
 
     const market = await program.account.perpsMarket.fetch(marketPda);
     expect(market.maxLeverage).to.eq(10);
+    expect(market.active).to.eq(true);
   });
 
   it("deposits collateral, opens, closes, withdraws", async () => {
@@ -149,7 +157,7 @@ describe("perps v1", () => {
       .rpc();
 
     await program.methods
-      .openPerpsPosition({ long: {} }, new anchor.BN(10), 5, { market: {} })
+      .openPerpsPosition({ long: {} }, new anchor.BN(10), 5, { market: {} }, { cross: {} }, false)
       .accounts({
         owner: admin.publicKey,
         global: globalPda,
@@ -183,6 +191,8 @@ describe("perps v1", () => {
         userQuoteAta,
         collateralVault: collateralVault.publicKey,
         tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID,
+        oraclePriceAccount: null,
+        position: null,
       })
       .rpc();
 
@@ -193,7 +203,7 @@ describe("perps v1", () => {
   it("fails with leverage too high", async () => {
     try {
       await program.methods
-        .openPerpsPosition({ long: {} }, new anchor.BN(10), 50, { market: {} })
+        .openPerpsPosition({ long: {} }, new anchor.BN(10), 50, { market: {} }, { cross: {} }, false)
         .accounts({
           owner: admin.publicKey,
           global: globalPda,
@@ -213,7 +223,7 @@ describe("perps v1", () => {
   it("fails with insufficient collateral", async () => {
     try {
       await program.methods
-        .openPerpsPosition({ long: {} }, new anchor.BN(1_000_000), 5, { market: {} })
+        .openPerpsPosition({ long: {} }, new anchor.BN(1_000_000), 5, { market: {} }, { cross: {} }, false)
         .accounts({
           owner: admin.publicKey,
           global: globalPda,
@@ -230,9 +240,9 @@ describe("perps v1", () => {
     }
   });
 
-  it("fails withdrawing while position open", async () => {
+  it("limits withdrawals to margin above IM while a position is open", async () => {
     await program.methods
-      .openPerpsPosition({ long: {} }, new anchor.BN(10), 5, { market: {} })
+      .openPerpsPosition({ long: {} }, new anchor.BN(10), 5, { market: {} }, { cross: {} }, false)
       .accounts({
         owner: admin.publicKey,
         global: globalPda,
@@ -244,22 +254,34 @@ describe("perps v1", () => {
       })
       .rpc();
 
+    const withdrawAccounts = {
+      owner: admin.publicKey,
+      global: globalPda,
+      user: userPda,
+      market: marketPda,
+      userQuoteAta,
+      collateralVault: collateralVault.publicKey,
+      tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID,
+      oraclePriceAccount: oraclePda,
+      position: positionPda,
+    };
+
+    // Free collateral stays withdrawable
+    await program.methods
+      .withdrawPerpsCollateral(new anchor.BN(100))
+      .accounts(withdrawAccounts)
+      .rpc();
+
+    // The freshly opened position holds exactly IM, so nothing beyond free collateral
+    const free = (await program.account.perpsUserAccount.fetch(userPda)).collateralQuoteU64;
     try {
       await program.methods
-        .withdrawPerpsCollateral(new anchor.BN(100))
-        .accounts({
-          owner: admin.publicKey,
-          global: globalPda,
-          user: userPda,
-          market: marketPda,
-          userQuoteAta,
-          collateralVault: collateralVault.publicKey,
-          tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID,
-        })
+        .withdrawPerpsCollateral(free.addn(1))
+        .accounts(withdrawAccounts)
         .rpc();
-      expect.fail("Expected maintenance margin failure");
+      expect.fail("Expected initial margin failure");
     } catch (error: any) {
-      expect(error.toString()).to.include("Maintenance margin violation");
+      expect(error.toString()).to.include("Initial margin violation");
     }
 
     await program.methods
@@ -274,4 +296,226 @@ describe("perps v1", () => {
       })
       .rpc();
   });
+
+  it("blocks opens but allows closes during a trading pause", async () => {
+    const setPause = (tradingPaused: boolean) =>
+      program.methods
+        .setPerpsPause(tradingPaused, true)
+        .accounts({ admin: admin.publicKey, global: globalPda })
+        .rpc();
+    const openAccounts = {
+      owner: admin.publicKey,
+      global: globalPda,
+      user: userPda,
+      market: marketPda,
+      oraclePriceAccount: oraclePda,
+      position: positionPda,
+      systemProgram: anchor.web3.SystemProgram.programId,
+    };
+
+    await program.methods
+      .openPerpsPosition({ long: {} }, new anchor.BN(10), 5, { market: {} }, { cross: {} }, false)
+      .accounts(openAccounts)
+      .rpc();
+    await setPause(true);
+
+    try {
+      await program.methods
+        .openPerpsPosition({ long: {} }, new anchor.BN(10), 5, { market: {} }, { cross: {} }, false)
+        .accounts(openAccounts)
+        .rpc();
+      expect.fail("Expected PerpsPaused");
+    } catch (error: any) {
+      expect(error.toString()).to.include("PerpsPaused");
+    }
+
+    await program.methods
+      .closePerpsPosition()
+      .accounts({
+        owner: admin.publicKey,
+        global: globalPda,
+        user: userPda,
+        market: marketPda,
+        oraclePriceAccount: oraclePda,
+        position: positionPda,
+      })
+      .rpc();
+    const global = await program.account.perpsGlobalState.fetch(globalPda);
+    expect(global.tradingPaused).to.eq(true);
+    expect(global.liquidationsEnabled).to.eq(true);
+
+    await setPause(false);
+  });
+
+  it("fills a perps limit order only once the oracle crosses the limit", async () => {
+    const setPrice = (price: number) =>
+      program.methods
+        .setPerpsOraclePrice(new anchor.BN(price))
+        .accounts({ admin: admin.publicKey, global: globalPda, oracle: oraclePda })
+        .rpc();
+    const orderId = new anchor.BN(1);
+    const [orderPda] = anchor.web3.PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("perps_limit_order"),
+        admin.publicKey.toBuffer(),
+        marketPda.toBuffer(),
+        orderId.toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    const expiry = new anchor.BN(Math.floor(Date.now() / 1000) + 3600);
+
+    const freeBefore = (await program.account.perpsUserAccount.fetch(userPda)).collateralQuoteU64;
+    await program.methods
+      .placePerpsLimitOrder({ long: {} }, new anchor.BN(10), new anchor.BN(90_000), 5, expiry, orderId, false)
+      .accounts({
+        owner: admin.publicKey,
+        global: globalPda,
+        user: userPda,
+        market: marketPda,
+        position: positionPda,
+        order: orderPda,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      })
+      .rpc();
+    const order = await program.account.perpsLimitOrder.fetch(orderPda);
+    const freeAfterPlace = (await program.account.perpsUserAccount.fetch(userPda)).collateralQuoteU64;
+    expect(freeBefore.sub(freeAfterPlace).toString()).to.eq(order.reservedCollateralU64.toString());
+
+    const executeAccounts = {
+      keeper: admin.publicKey,
+      owner: admin.publicKey,
+      global: globalPda,
+      user: userPda,
+      market: marketPda,
+      oraclePriceAccount: oraclePda,
+      position: positionPda,
+      order: orderPda,
+    };
+
+    // Oracle at 100_000 is above the 90_000 buy limit.
+    try {
+      await program.methods.executePerpsLimitOrder().accounts(executeAccounts).rpc();
+      expect.fail("Expected PriceConditionNotMet");
+    } catch (error: any) {
+      expect(error.toString()).to.include("PriceConditionNotMet");
+    }
+
+    await setPrice(89_000);
+    await program.methods.executePerpsLimitOrder().accounts(executeAccounts).rpc();
+    const position = await program.account.perpsPosition.fetch(positionPda);
+    expect(position.basePositionI64.toNumber()).to.eq(10);
+    expect(position.entryPriceI64.toNumber()).to.eq(89_000);
+    expect(await provider.connection.getAccountInfo(orderPda)).to.eq(null);
+
+    await program.methods
+      .closePerpsPosition()
+      .accounts({
+        owner: admin.publicKey,
+        global: globalPda,
+        user: userPda,
+        market: marketPda,
+        oraclePriceAccount: oraclePda,
+        position: positionPda,
+      })
+      .rpc();
+    await setPrice(100_000);
+  });
+
+  it("closes a position through its take-profit trigger", async () => {
+    const setPrice = (price: number) =>
+      program.methods
+        .setPerpsOraclePrice(new anchor.BN(price))
+        .accounts({ admin: admin.publicKey, global: globalPda, oracle: oraclePda })
+        .rpc();
+
+    await program.methods
+      .openPerpsPosition({ long: {} }, new anchor.BN(10), 5, { market: {} }, { cross: {} }, false)
+      .accounts({
+        owner: admin.publicKey,
+        global: globalPda,
+        user: userPda,
+        market: marketPda,
+        oraclePriceAccount: oraclePda,
+        position: positionPda,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      })
+      .rpc();
+    await program.methods
+      .setPositionTriggers(new anchor.BN(110_000), new anchor.BN(90_000))
+      .accounts({ owner: admin.publicKey, market: marketPda, position: positionPda })
+      .rpc();
+
+    const triggerAccounts = {
+      keeper: admin.publicKey,
+      global: globalPda,
+      user: userPda,
+      positionOwner: admin.publicKey,
+      market: marketPda,
+      oraclePriceAccount: oraclePda,
+      position: positionPda,
+      collateralVault: collateralVault.publicKey,
+      keeperAta: userQuoteAta,
+      tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID,
+    };
+    try {
+      await program.methods.executePositionTrigger().accounts(triggerAccounts).rpc();
+      expect.fail("Expected TriggerNotMet");
+    } catch (error: any) {
+      expect(error.toString()).to.include("TriggerNotMet");
+    }
+
+    await setPrice(111_000);
+    await program.methods.executePositionTrigger().accounts(triggerAccounts).rpc();
+    const position = await program.account.perpsPosition.fetch(positionPda);
+    expect(position.basePositionI64.toNumber()).to.eq(0);
+    expect(position.takeProfitPriceI64.toNumber()).to.eq(0);
+    expect(position.stopLossPriceI64.toNumber()).to.eq(0);
+    await setPrice(100_000);
+  });
+
+  it("closes half a long at a profit and keeps the entry price", async () => {
+    const setPrice = (price: number) =>
+      program.methods
+        .setPerpsOraclePrice(new anchor.BN(price))
+        .accounts({ admin: admin.publicKey, global: globalPda, oracle: oraclePda })
+        .rpc();
+    const positionAccounts = {
+      owner: admin.publicKey,
+      global: globalPda,
+      user: userPda,
+      market: marketPda,
+      oraclePriceAccount: oraclePda,
+      position: positionPda,
+    };
+
+    await program.methods
+      .openPerpsPosition({ long: {} }, new anchor.BN(10), 5, { market: {} }, { cross: {} }, false)
+      .accounts({ ...positionAccounts, systemProgram: anchor.web3.SystemProgram.programId })
+      .rpc();
+    const opened = await program.account.perpsPosition.fetch(positionPda);
+    const userBefore = await program.account.perpsUserAccount.fetch(userPda);
+
+    await setPrice(110_000);
+    await program.methods
+      .closePerpsPositionPartial(new anchor.BN(5))
+      .accounts(positionAccounts)
+      .rpc();
+
+    const position = await program.account.perpsPosition.fetch(positionPda);
+    const userAfter = await program.account.perpsUserAccount.fetch(userPda);
+    expect(position.basePositionI64.toNumber()).to.eq(5);
+    expect(position.entryPriceI64.toString()).to.eq(opened.entryPriceI64.toString());
+    expect(userAfter.positionsCountU8).to.eq(userBefore.positionsCountU8);
+    // Half the collateral back plus 5 × (110_000 − 100_000) of profit
+    const expectedCredit = opened.collateralU64.divn(2).addn(5 * 10_000);
+    expect(userAfter.collateralQuoteU64.sub(userBefore.collateralQuoteU64).toString())
+      .to.eq(expectedCredit.toString());
+
+    await program.methods
+      .closePerpsPositionPartial(new anchor.BN(5))
+      .accounts(positionAccounts)
+      .rpc();
+    await setPrice(100_000);
+  });
 });
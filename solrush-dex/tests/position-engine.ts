@@ -13,6 +13,7 @@ import {
   findPerpsUserAddress,
   findPerpsPositionAddress,
 } from "../target/types/pda";
+import { seedAndActivatePerpsMarket } from "./perps-setup";
 
 /**
  * Phase 2 – Position Engine integration tests.
@@ -60,7 +61,7 @@ describe("position-engine (Phase 2)", () => {
   async function openPos(side: "long" | "short", size: number, leverage: number) {
     const sideArg = side === "long" ? { long: {} } : { short: {} };
     await program.methods
-      .openPerpsPosition(sideArg, new anchor.BN(size), leverage, { market: {} })
+      .openPerpsPosition(sideArg, new anchor.BN(size), leverage, { market: {} }, { cross: {} }, false)
       .accounts({
         owner: admin.publicKey,
         global: globalPda,
@@ -151,7 +152,7 @@ describe("position-engine (Phase 2)", () => {
 
     // Create market (max leverage = 10, maintenance margin = 500 bps = 5%)
     await program.methods
-      .createPerpsMarket(Array(32).fill(0), 10, 500, new anchor.BN(10_000), new anchor.BN(3600))
+      .createPerpsMarket(Array(32).fill(0), 10, 500, new anchor.BN(10_000), new anchor.BN(3600), new anchor.BN(0))
       .accounts({
         admin: admin.publicKey,
         global: globalPda,
@@ -166,6 +167,12 @@ describe("position-engine (Phase 2)", () => {
       })
       .signers([collateralVault])
       .rpc();
+    await seedAndActivatePerpsMarket(program, provider, {
+      global: globalPda,
+      market: marketPda,
+      quoteMint,
+      depositorAta: userQuoteAta,
+    });
 
     // Derive position PDA (needs marketPda)
     [positionPda] = findPerpsPositionAddress(admin.publicKey, marketPda, program.programId);
@@ -284,7 +291,9 @@ describe("position-engine-v2 (Phase 2)", () => {
         side === "long" ? { long: {} } : { short: {} },
         new anchor.BN(size),
         leverage,
-        { market: {} }
+        { market: {} },
+        { cross: {} },
+        false
       )
       .accounts({
         owner: admin.publicKey,
@@ -350,7 +359,7 @@ describe("position-engine-v2 (Phase 2)", () => {
       .rpc();
 
     // Create market (max leverage 10, maintenance margin 500 bps)
-    await program.methods.createPerpsMarket(Array(32).fill(0), 10, 500, new anchor.BN(10_000), new anchor.BN(3600))
+    await program.methods.createPerpsMarket(Array(32).fill(0), 10, 500, new anchor.BN(10_000), new anchor.BN(3600), new anchor.BN(0))
       .accounts({
         admin: admin.publicKey, global: globalPda, baseMint, quoteMint,
         oraclePriceAccount: oraclePda, market: marketPda,
@@ -361,6 +370,12 @@ describe("position-engine-v2 (Phase 2)", () => {
       })
       .signers([collateralVault])
       .rpc();
+    await seedAndActivatePerpsMarket(program, provider, {
+      global: globalPda,
+      market: marketPda,
+      quoteMint,
+      depositorAta: userQuoteAta,
+    });
 
     [positionPda] = findPerpsPositionAddress(admin.publicKey, marketPda, program.programId);
 
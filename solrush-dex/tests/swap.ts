@@ -94,7 +94,7 @@ describe("Swap", () => {
     );
 
     await program.methods
-      .initializePool()
+      .initializePool(new anchor.BN(0), new anchor.BN(0), new anchor.BN(0))
       .accounts({
         pool: poolAccount,
         tokenAMint: tokenA.publicKey,